@@ -0,0 +1,251 @@
+use crate::{Spell, Shape, HasVolume, boolean_logic, spelltranslator, saver::Config, spell_error::SpellError, DEFAULT_EFFICIENCY_LEVEL, ENERGY_CONSIDERATION_LEVEL};
+use crate::codes::{attributecodes::*, componentcodes::*, opcodes::*};
+
+/// What casting the ready sections of a spell is expected to need
+#[derive(Debug, PartialEq)]
+pub struct BudgetReport {
+    pub energy_used: f64,
+    pub peak_control: f64,
+    /// The section that went over budget and why, if one did
+    pub exceeded: Option<(String, SpellError)>
+}
+
+/// Keeps track of a spell's energy and shape while stepping through its components without casting them
+struct BudgetSimulation {
+    energy: f64,
+    shape: Option<Shape>,
+    charge_to_shape: bool,
+    max_control: f64,
+    energy_used: f64,
+    peak_control: f64
+}
+
+impl BudgetSimulation {
+    /// Steps through a section. Returns why the section went over budget if it did
+    fn simulate_section(&mut self, instructions: &[u64], config: &Config, estimate_base_energy: &mut impl FnMut(u64, &[Option<u64>], f64, bool) -> Result<f64, SpellError>) -> Result<Option<SpellError>, SpellError> {
+        let mut instructions_iter = instructions.iter();
+        while let Some(&bits) = instructions_iter.next() {
+            match bits {
+                END_OF_SCOPE => {},
+                COMPONENT => {
+                    let is_delay = instructions_iter.as_slice().first() == Some(&DELAY);
+                    if let Some(reason) = self.simulate_component(&mut instructions_iter, config, estimate_base_energy)? {
+                        return Ok(Some(reason))
+                    }
+                    // What comes after a delay doesn't run when the section does
+                    if is_delay {
+                        break
+                    }
+                },
+                // Which way an if statement goes can't be known ahead of time, so assume its contents always run. While loops are assumed to run once
+                IF | WHILE => Spell::skip_logic(&mut instructions_iter)?,
+                _ => return Err(SpellError::InvalidInstructions("Not valid opcode"))
+            }
+        }
+        Ok(None)
+    }
+
+    fn simulate_component<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>, config: &Config, estimate_base_energy: &mut impl FnMut(u64, &[Option<u64>], f64, bool) -> Result<f64, SpellError>) -> Result<Option<SpellError>, SpellError> {
+        let component_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+        let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code)?;
+
+        // Parameters worked out while the spell is running are None
+        let mut parameters: Vec<Option<u64>> = Vec::new();
+        while parameters.len() < number_of_component_parameters {
+            match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
+                TRUE => parameters.push(Some(TRUE)),
+                FALSE => parameters.push(Some(FALSE)),
+                NUMBER_LITERAL => parameters.push(Some(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?)),
+                COMPONENT => {
+                    // A vector fills one parameter for each of its values
+                    let parameter_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+                    Spell::skip_component_parameters(parameter_code, instructions_iter)?;
+                    parameters.extend(vec![None; Spell::get_parameter_width(parameter_code)?]);
+                },
+                EXPRESSION => {
+                    Spell::skip_logic(instructions_iter)?;
+                    parameters.push(None);
+                },
+                _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
+            }
+        }
+
+        // The shape is worked out first as it decides how much energy take_shape needs
+        let new_shape = match (component_code, &parameters[..]) {
+            (TAKE_SHAPE, &[Some(shape_code), Some(size_1), Some(size_2), Some(size_3)]) => {
+                match Shape::from_code(f64::from_bits(shape_code) as u64, f64::from_bits(size_1), f64::from_bits(size_2), f64::from_bits(size_3), config.minimum_shape_dimension) {
+                    Ok(shape) => Some(shape),
+                    Err(reason) => return Ok(Some(reason))
+                }
+            },
+            _ => None
+        };
+
+        let base_energy = match new_shape {
+            Some(ref shape) => Spell::get_shape_energy(shape, self.energy, self.charge_to_shape, config.energy_to_volume),
+            None => estimate_base_energy(component_code, &parameters, self.energy, self.charge_to_shape)?
+        };
+        let energy_needed = Spell::get_energy_needed(base_energy, DEFAULT_EFFICIENCY_LEVEL, config.efficiency_increase_rate);
+        if energy_needed > self.energy {
+            return Ok(Some(SpellError::NotEnoughEnergy))
+        }
+        self.energy -= energy_needed;
+        self.energy_used += energy_needed;
+
+        match (component_code, new_shape) {
+            (_, Some(shape)) => {
+                // Spells give back energy they don't need for their shape, but asking the caster for more energy isn't counted
+                if self.charge_to_shape {
+                    self.energy = self.energy.min(shape.get_volume() / config.energy_to_volume);
+                }
+                self.shape = Some(shape);
+            },
+            (UNDO_SHAPE, _) | (UNDO_FORM, _) => self.shape = None,
+            _ => {}
+        }
+
+        let control = self.energy + self.shape.map_or(0.0, |shape| Spell::get_shape_control(&shape, self.energy, config.energy_to_volume));
+        self.peak_control = self.peak_control.max(control);
+        if control > self.max_control {
+            return Ok(Some(SpellError::NotEnoughControl))
+        }
+
+        Ok(None)
+    }
+}
+
+/// Reads charge_to_shape from the about section, which defaults to true
+fn get_charge_to_shape(attributes: &[u64]) -> Result<bool, SpellError> {
+    let mut charge_to_shape = true;
+    let mut attributes_iter = attributes.iter();
+    while let Some(&attribute) = attributes_iter.next() {
+        match attribute {
+            COLOR => for _ in 0..3 {
+                attributes_iter.next().ok_or(SpellError::InvalidInstructions("Invalid data: There should be three color values"))?;
+            },
+            CHARGE_TO_SHAPE => charge_to_shape = boolean_logic::num_to_bool(*attributes_iter.next().ok_or(SpellError::InvalidInstructions("Expected boolean after charge_to_shape"))?)?,
+            TRANSPARENCY => { attributes_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after transparency"))?; },
+            NAME | DESCRIPTION => { spelltranslator::decode_string(attributes_iter.by_ref().copied())?; },
+            _ => return Err(SpellError::InvalidInstructions("Invalid attribute"))
+        }
+    }
+    Ok(charge_to_shape)
+}
+
+/// Splits instructions into each section's opcode and the instructions in it
+pub fn split_sections(instructions: &[u64]) -> Result<Vec<(u64, Vec<u64>)>, SpellError> {
+    let mut sections: Vec<(u64, Vec<u64>)> = Vec::new();
+    let mut instructions_iter = instructions.iter();
+    while let Some(&instruction) = instructions_iter.next() {
+        match instruction {
+            NUMBER_LITERAL => {
+                let number = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?;
+                if let Some((_, section_instructions)) = sections.last_mut() {
+                    section_instructions.extend([NUMBER_LITERAL, number]);
+                }
+            },
+            WHEN_CREATED_SECTION..=REPEAT_FOR_SECTION => sections.push((instruction, Vec::new())),
+            CHILD_SECTION => break, // The child section belongs to the spells spawn_child makes, not this one
+            _ => if let Some((_, section_instructions)) = sections.last_mut() {
+                section_instructions.push(instruction);
+            }
+        }
+    }
+    Ok(sections)
+}
+
+/// Steps through the when_created and `repeat n times` sections in the order a spell runs them, for a spell cast with `max_energy`.
+/// Stops at the first section that runs out of energy or needs more than `max_control`. Components other than take_shape are costed by `estimate_base_energy`,
+/// given the component, its parameters with None for those only known while the spell is running, and the spell's energy and charge_to_shape at that point
+pub fn check_within_budget(instructions: &[u64], max_energy: f64, max_control: f64, config: &Config, mut estimate_base_energy: impl FnMut(u64, &[Option<u64>], f64, bool) -> Result<f64, SpellError>) -> Result<BudgetReport, SpellError> {
+    let sections = split_sections(instructions)?;
+
+    let mut charge_to_shape = true;
+    let mut ready_sections: Vec<(String, usize, &[u64])> = Vec::new();
+    let mut ready_loops: Vec<(String, usize, &[u64])> = Vec::new();
+    for (section, section_instructions) in sections.iter() {
+        match (*section, &section_instructions[..]) {
+            (WHEN_CREATED_SECTION, section_instructions) => ready_sections.push((String::from("when_created"), 1, section_instructions)),
+            (REPEAT_TIMES_SECTION, &[NUMBER_LITERAL, iterations, ref section_instructions @ ..]) => {
+                let iterations = f64::from_bits(iterations) as usize;
+                ready_loops.push((format!("repeat {} times", iterations), iterations, section_instructions));
+            },
+            (REPEAT_TIMES_SECTION, _) => return Err(SpellError::InvalidInstructions("Expected number of times to repeat")),
+            (ABOUT_SECTION, attributes) => charge_to_shape = get_charge_to_shape(attributes)?,
+            _ => {}
+        }
+    }
+    ready_sections.extend(ready_loops);
+
+    let mut simulation = BudgetSimulation { energy: max_energy, shape: None, charge_to_shape, max_control, energy_used: 0.0, peak_control: max_energy };
+    let mut exceeded = if max_energy > max_control { Some((String::from("when_created"), SpellError::NotEnoughControl)) } else { None };
+
+    'sections: for (name, iterations, section_instructions) in ready_sections {
+        for _ in 0..iterations {
+            // The spell perishes once it runs low on energy
+            if exceeded.is_some() || simulation.energy < ENERGY_CONSIDERATION_LEVEL {
+                break 'sections
+            }
+            if let Some(reason) = simulation.simulate_section(section_instructions, config, &mut estimate_base_energy)? {
+                exceeded = Some((name, reason));
+                break 'sections
+            }
+        }
+    }
+
+    Ok(BudgetReport { energy_used: simulation.energy_used, peak_control: simulation.peak_control, exceeded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spelltranslator::parse_spell;
+    use crate::component_functions::APPLY_TO_SPELL_COEFFICIENT;
+
+    /// Stands in for the component functions, which need the engine. Costs give_velocity like a velocity of 1 and everything else as free
+    fn estimate_base_energy(component_code: u64, _parameters: &[Option<u64>], energy: f64, _charge_to_shape: bool) -> Result<f64, SpellError> {
+        Ok(if component_code == GIVE_VELOCITY { energy / APPLY_TO_SPELL_COEFFICIENT } else { 0.0 })
+    }
+
+    #[test]
+    fn spell_within_budget_passes() {
+        let instructions = parse_spell("when_created:\ngive_velocity(1, 0, 0)\ntake_shape(0, 0.1, 0, 0)", None).unwrap();
+        let report = check_within_budget(&instructions, 10.0, 20.0, &Config::default(), estimate_base_energy).unwrap();
+
+        assert_eq!(report.exceeded, None);
+        assert!((report.energy_used - 10.0 / APPLY_TO_SPELL_COEFFICIENT * 16.0).abs() < 1e-9);
+        assert_eq!(report.peak_control, 10.0);
+    }
+
+    #[test]
+    fn oversized_shape_exceeds_control_budget() {
+        let instructions = parse_spell("when_created:\ngive_velocity(1, 0, 0)\nrepeat 2 times:\ntake_shape(0, 1, 0, 0)", None).unwrap();
+        let report = check_within_budget(&instructions, 10.0, 20.0, &Config::default(), estimate_base_energy).unwrap();
+
+        assert_eq!(report.exceeded, Some((String::from("repeat 2 times"), SpellError::NotEnoughControl)));
+        assert!(report.peak_control > 20.0);
+    }
+
+    #[test]
+    fn components_after_a_delay_are_not_counted() {
+        let instructions = parse_spell("when_created:
+delay(1)
+give_velocity(1, 0, 0)
+repeat 3 times:
+give_velocity(1, 0, 0)
+delay(1)
+give_velocity(1, 0, 0)", None).unwrap();
+        let report = check_within_budget(&instructions, 10.0, 20.0, &Config::default(), estimate_base_energy).unwrap();
+
+        // Only the give_velocity before the delay in each time around the loop is counted
+        let mut energy = 10.0;
+        let mut energy_used = 0.0;
+        for _ in 0..3 {
+            let energy_needed = Spell::get_energy_needed(energy / APPLY_TO_SPELL_COEFFICIENT, DEFAULT_EFFICIENCY_LEVEL, Config::default().efficiency_increase_rate);
+            energy -= energy_needed;
+            energy_used += energy_needed;
+        }
+        assert_eq!(report.exceeded, None);
+        assert!((report.energy_used - energy_used).abs() < 1e-9);
+    }
+}
@@ -3,3 +3,4 @@ pub mod opcodes;
 pub mod attributecodes;
 pub mod component_specific_codes;
 pub mod datatypes;
+pub mod damagetypes;
@@ -1,2 +1,7 @@
 pub const COLOR: u64 = 0; // colour/color [r: float, g: float, b: float]
 pub const CHARGE_TO_SHAPE: u64 = 1;
+// Strings are stored as UTF-8 bytes packed big-endian into u64s, eight bytes at a time with the last one padded with zeros, followed by a 0.
+// Strings can't contain null characters, so none of the packed u64s are small enough to be mistaken for opcodes or the end of the string
+pub const NAME: u64 = 2; // name "string"
+pub const DESCRIPTION: u64 = 3; // description "string"
+pub const TRANSPARENCY: u64 = 4; // transparency float, from 0 (invisible) to 1 (opaque)
@@ -1,3 +1,7 @@
 // Shapes
 pub const SPHERE: u64 = 0;
 pub const CUBE: u64 = 1;
+pub const CYLINDER: u64 = 2;
+pub const CONE: u64 = 3;
+pub const CAPSULE: u64 = 4;
+pub const PYRAMID: u64 = 5;
@@ -6,12 +6,58 @@ pub const RECHARGE_TO: u64 = 3; // recharge_to(energy: float)
 pub const ANCHOR: u64 = 4; // anchor()
 pub const UNDO_ANCHOR: u64 = 5; // undo_anchor()
 pub const PERISH: u64 = 6; // perish()
-pub const TAKE_SHAPE: u64 = 7; // take_shape(shape: integer)
+pub const TAKE_SHAPE: u64 = 7; // take_shape(shape: integer, size_1: float, size_2: float, size_3: float)
 pub const UNDO_SHAPE: u64 = 8; // undo_shape()
+pub const FRAGMENT_ON_WALL: u64 = 9; // fragment_on_wall(count: float, speed: float)
+pub const HOMING: u64 = 10; // homing(turn_rate: float)
+pub const SET_LIFETIME: u64 = 11; // set_lifetime(seconds: float)
+pub const FADE_COLOR_TO: u64 = 12; // fade_color_to(r: float, g: float, b: float, duration: float)
+pub const BOUNCE: u64 = 13; // bounce(energy_loss: float)
+pub const RESET_TIMER: u64 = 14; // reset_timer()
+pub const FAMILIAR: u64 = 15; // familiar(follow_distance: float)
+pub const SPLIT: u64 = 16; // split(count: float)
+pub const SET_DIRECTION: u64 = 17; // set_direction(x: float, y: float, z: float), makes forward point along the world direction given
+pub const ORBIT: u64 = 18; // orbit(center_x: float, center_y: float, center_z: float, angular_speed: float), circles the center, which is relative to the spell
+pub const SET_COLOR: u64 = 19; // set_color(r: float, g: float, b: float), each channel is clamped between 0 and 1
+pub const PIERCE: u64 = 20; // pierce(count: float), the spell perishes once it has damaged count different magical entities. A count of 0 or less is treated as 1
+pub const DELAY: u64 = 21; // delay(seconds: float), the instructions after it wait until seconds of spell time have passed
+pub const SET_DECAY: u64 = 22; // set_decay(rate: float), the fraction of its energy the spell loses every second
+pub const SPAWN_CHILD: u64 = 23; // spawn_child(energy_fraction: float), makes a spell running the child section with that fraction of the spell's energy
+pub const ANCHOR_FOLLOW: u64 = 24; // anchor_follow(distance: float), holds the spell distance in front of its caster until undo_anchor
+pub const HOLLOW_SHAPE: u64 = 25; // hollow_shape(thickness: float), makes the visible sphere or cube a shell thickness thick
 
 // logic components
 pub const MOVING: u64 = 1000; // moving() returns boolean
 pub const GET_TIME: u64 = 1001; // get_time() returns float
+pub const HIT_COUNT: u64 = 1002; // hit_count() returns float
+pub const GET_ENERGY: u64 = 1003; // get_energy() returns float
+pub const CASTER_ALIVE: u64 = 1004; // caster_alive() returns boolean
+pub const LIFETIME: u64 = 1005; // lifetime() returns float
+pub const CHANCE_RAMP: u64 = 1006; // chance_ramp(start_probability: float, end_probability: float, ramp_duration: float) returns boolean
+pub const CASTER_ENERGY_SELECTED: u64 = 1007; // caster_energy_selected() returns float
+pub const CASTER_ENERGY_CHARGED: u64 = 1008; // caster_energy_charged() returns float
+pub const GET_SPEED: u64 = 1009; // get_speed() returns float, the length of the spell's velocity before it's rotated by original_direction (same as moving)
+pub const GET_RANDOM: u64 = 1010; // get_random() returns float, from 0 up to but not including 1
+pub const TARGET_HEALTH: u64 = 1011; // target_health() returns float, the health of the nearest overlapping magical entity that didn't cast the spell, or 0 if there isn't one
+pub const TARGET_ENERGY: u64 = 1012; // target_energy() returns float, the energy charged of the nearest overlapping magical entity that didn't cast the spell, or 0 if there isn't one
+pub const CASTER_FORWARD: u64 = 1013; // caster_forward() returns vector, the direction the caster is facing in the spell's own directions, so it can fill the three parameters of give_velocity
+pub const GET_NEAREST_ENTITY_DIRECTION: u64 = 1014; // get_nearest_entity_direction() returns vector, the direction to the nearest overlapping magical entity that didn't cast the spell in the spell's own directions, or 0, 0, 0 if there isn't one
+pub const GET_OVERLAPPING_ENTITY_COUNT: u64 = 1015; // get_overlapping_entity_count() returns float, how many overlapping magical entities the spell would damage, or 0 if there aren't any
+pub const CASTER_FOCUS: u64 = 1016; // caster_focus() returns float, the caster's focus from 0 to 2 when the spell was cast
 
 // power components
 pub const SET_DAMAGE: u64 = 2000; // set_damage()
+pub const SET_LIFESTEAL: u64 = 2001; // set_lifesteal(fraction: float)
+pub const BEAM: u64 = 2002; // beam(damage_per_second: float, max_length: float)
+pub const CLEAVE: u64 = 2003; // cleave(damage: float, arc_angle: float, range: float)
+pub const SET_FACTION: u64 = 2004; // set_faction(faction: float)
+pub const MARK_VULNERABLE: u64 = 2005; // mark_vulnerable(multiplier: float, duration: float)
+pub const HEAL: u64 = 2006; // heal(amount: float, heal_owner: boolean)
+pub const TRAP: u64 = 2007; // trap(arm_delay: float, trigger_radius: float, damage: float)
+pub const SIPHON_TO_CASTER: u64 = 2008; // siphon_to_caster(rate: float)
+pub const APPLY_RADIAL_FORCE: u64 = 2009; // apply_radial_force(strength: float, radius: float), positive strength pushes away and negative strength pulls in
+pub const SET_TYPED_DAMAGE: u64 = 2010; // set_typed_damage(amount: float, damage_type: float), damage types are in damagetypes.rs
+pub const ABSORB: u64 = 2011; // absorb(), drains energy from overlapping spells cast by someone else
+pub const SET_DAMAGE_FALLOFF: u64 = 2012; // set_damage_falloff(start_distance: float, end_distance: float)
+pub const APPLY_STUN: u64 = 2013; // apply_stun(seconds: float), overlapping magical entities the spell targets can't cast spells or move themselves for that long
+pub const PUSH_SPELLS: u64 = 2014; // push_spells(strength: float), speeds up overlapping spells cast by someone else in the direction the spell is going
@@ -0,0 +1,6 @@
+// Damage types, given to set_typed_damage
+pub const UNTYPED: u64 = 0;
+pub const FIRE: u64 = 1;
+pub const ICE: u64 = 2;
+pub const LIGHTNING: u64 = 3;
+pub const POISON: u64 = 4;
@@ -5,6 +5,7 @@ pub const FALSE: u64 = 101;
 pub const NUMBER_LITERAL: u64 = 102;
 pub const COMPONENT: u64 = 103;
 pub const ANY: u64 = 104;
+pub const EXPRESSION: u64 = 105;
 
 pub const AND: u64 = 200;
 pub const OR: u64 = 201;
@@ -14,15 +15,34 @@ pub const XOR: u64 = 203;
 pub const EQUALS: u64 = 300;
 pub const GREATER_THAN: u64 = 301;
 pub const LESSER_THAN: u64 = 302;
+pub const GREATER_THAN_OR_EQUAL: u64 = 303;
+pub const LESSER_THAN_OR_EQUAL: u64 = 304;
 
 pub const IF: u64 = 400;
+pub const WHILE: u64 = 401;
 
 pub const WHEN_CREATED_SECTION: u64 = 500;
 pub const REPEAT_SECTION: u64 = 501;
 pub const ABOUT_SECTION: u64 = 502;
+pub const REPEAT_TIMES_SECTION: u64 = 503;
+pub const ON_COLLISION_SECTION: u64 = 504;
+pub const REPEAT_FOR_SECTION: u64 = 505; // Followed by how often it runs like REPEAT_SECTION, then how many seconds it runs for
+pub const CHILD_SECTION: u64 = 506; // Everything after it is the instructions of the spells made by spawn_child
 
 pub const MULTIPLY: u64 = 600;
 pub const DIVIDE: u64 = 601;
 pub const ADD: u64 = 602;
 pub const SUBTRACT: u64 = 603;
 pub const POWER: u64 = 604;
+pub const MODULO: u64 = 605;
+
+pub const SQRT: u64 = 700;
+pub const ABS: u64 = 701;
+pub const MIN: u64 = 702;
+pub const MAX: u64 = 703;
+pub const FLOOR: u64 = 704;
+pub const CEIL: u64 = 705;
+pub const ROUND: u64 = 706;
+pub const SIN: u64 = 707;
+pub const COS: u64 = 708;
+pub const CLAMP: u64 = 709;
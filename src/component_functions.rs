@@ -1,173 +1,852 @@
 use godot::prelude::*;
 
-use crate::{Spell, codes::opcodes::*, codes::component_specific_codes::*, Shape, Sphere, Cube, HasShape};
+use crate::{Spell, spell_error::SpellError, SET_DIRECTION_ENERGY_COST, SET_DECAY_ENERGY_COST, STUN_ENERGY_PER_SECOND, codes::{opcodes::*, damagetypes::UNTYPED}, Shape, Sphere, Beam, Fragmentation, Homing, ColorFade, Bounce, Pierce, DamageFalloff, VulnerabilityMark, Familiar, AnchorFollow, Trap, RadialForce, Orbit, HasShape};
 
-const APPLY_TO_SPELL_COEFFICIENT: f64 = 70.0;
+pub const APPLY_TO_SPELL_COEFFICIENT: f64 = 70.0;
 
 // Utility:
 
-pub fn give_velocity(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn give_velocity(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     let x_speed: f32 = f64::from_bits(parameters[0]) as f32;
     let y_speed: f32 = f64::from_bits(parameters[1]) as f32;
     let z_speed: f32 = f64::from_bits(parameters[2]) as f32;
     if should_execute {
         let new_velocity = spell.velocity + Vector3 {x: x_speed, y: y_speed, z: z_speed };
         spell.velocity = new_velocity;
-        return None
+        return Ok(None)
     }
 
-    return Some(vec![f64::to_bits(spell.energy * ((x_speed * x_speed + y_speed * y_speed + z_speed * z_speed) as f64).sqrt() / APPLY_TO_SPELL_COEFFICIENT)])
+    return Ok(Some(vec![f64::to_bits(spell.energy * ((x_speed * x_speed + y_speed * y_speed + z_speed * z_speed) as f64).sqrt() / APPLY_TO_SPELL_COEFFICIENT)]))
 }
 
-pub fn take_form(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn take_form(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     let form_code = f64::from_bits(parameters[0]) as u64;
 
     if !should_execute {
-        return Some(vec![f64::to_bits(spell.config.forms.get(&form_code).expect("Expected form code to map to a form").energy_required)])
+        return Ok(Some(vec![f64::to_bits(spell.config.forms.get(&form_code).expect("Expected form code to map to a form").energy_required)]))
     }
 
     spell.set_form(form_code);
-    return None
+    return Ok(None)
 }
 
-pub fn undo_form(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn undo_form(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![0])
+        return Ok(Some(vec![0]))
     }
 
     spell.undo_form();
-    return None
+    return Ok(None)
 }
 
-pub fn recharge_to(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn recharge_to(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
     let energy_wanted = f64::from_bits(parameters[0]);
 
-    if spell.energy >= energy_wanted {
-        return None
-    }
-
-    spell.energy_requested = energy_wanted - spell.energy;
+    spell.request_energy(energy_wanted);
 
-    return None
+    return Ok(None)
 }
 
-pub fn anchor(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn anchor(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
     if spell.anchored_to != None {
-        return None
+        return Ok(None)
     }
 
     spell.anchor();
 
-    return None
+    return Ok(None)
 }
 
-pub fn undo_anchor(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn undo_anchor(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
     spell.undo_anchor();
 
-    return None
+    return Ok(None)
 }
 
-pub fn perish(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn perish(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
     spell.perish();
 
-    return None
+    return Ok(None)
 }
 
-pub fn take_shape(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
-    if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
-    }
-
-    spell.undo_form();
-
+pub fn take_shape(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     let shape_num = f64::from_bits(parameters[0]) as u64;
     let size_1 = f64::from_bits(parameters[1]);
     let size_2 = f64::from_bits(parameters[2]);
     let size_3 = f64::from_bits(parameters[3]);
 
-    let shape = match shape_num {
-        SPHERE => Shape::Sphere(Sphere { radius: size_1 }),
-        CUBE => Shape::Cube(Cube { x: size_1, y: size_2, z: size_3 }),
-        _ => panic!("Not a valid shape")
-    };
+    let shape = Shape::from_code(shape_num, size_1, size_2, size_3, spell.config.minimum_shape_dimension)?;
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(Spell::get_shape_energy(&shape, spell.energy, spell.charge_to_shape, spell.config.energy_to_volume))]))
+    }
+
+    spell.undo_form();
 
     spell.shape = Some(shape);
     spell.handle_charge_to_shape();
     spell.set_shape(shape);
 
-    return None
+    return Ok(None)
 }
 
-pub fn undo_shape(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn undo_shape(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
     spell.undo_form();
 
-    spell.set_shape(Shape::Sphere(Sphere::from_volume(spell.get_natural_volume(spell.energy))));
+    spell.set_shape(Shape::Sphere(Sphere::from_volume(Spell::get_natural_volume(spell.energy, spell.config.energy_to_volume))));
+
+    return Ok(None)
+}
+
+pub fn fragment_on_wall(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let count = f64::from_bits(parameters[0]);
+    let speed = f64::from_bits(parameters[1]);
+
+    if count < 1.0 {
+        spell.fragmentation = None;
+        return Ok(None)
+    }
+
+    spell.fragmentation = Some(Fragmentation { count: count as usize, speed });
+
+    return Ok(None)
+}
+
+pub fn homing(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let turn_rate = f64::from_bits(parameters[0]);
+
+    if turn_rate <= 0.0 {
+        spell.homing = None;
+        return Ok(None)
+    }
+
+    spell.homing = Some(Homing { turn_rate });
+
+    return Ok(None)
+}
+
+pub fn set_lifetime(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let seconds = f64::from_bits(parameters[0]);
+
+    let spawn_time = match spell.spawn_time {
+        Some(spawn_time) => spawn_time,
+        None => panic!("Time wasn't created")
+    };
+
+    // Replaces any previous lifetime rather than adding to it
+    spell.expiry_time = Some(Spell::get_expiry_time(spawn_time, seconds));
+
+    return Ok(None)
+}
+
+pub fn fade_color_to(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let target_color = Color {
+        r: f64::from_bits(parameters[0]) as f32,
+        g: f64::from_bits(parameters[1]) as f32,
+        b: f64::from_bits(parameters[2]) as f32,
+        a: spell.color.a
+    };
+    let duration = f64::from_bits(parameters[3]);
+
+    spell.color_fade = Some(ColorFade { start_color: spell.color, target_color, duration, elapsed: 0.0 });
+
+    return Ok(None)
+}
+
+/// Changes the spell's color straight away by updating the material of the current shape, so it's cheap enough to use every frame
+pub fn set_color(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let red = f64::from_bits(parameters[0]);
+    let green = f64::from_bits(parameters[1]);
+    let blue = f64::from_bits(parameters[2]);
+    if red.is_nan() || green.is_nan() || blue.is_nan() {
+        godot_warn!("Color channels must be numbers");
+        return Ok(None)
+    }
+
+    // Setting a color replaces any fade in progress, otherwise the fade would overwrite it next frame
+    spell.color_fade = None;
+    let color = Spell::get_clamped_color(red, green, blue, spell.color.a);
+    spell.set_spell_color(color);
+
+    return Ok(None)
+}
+
+/// Makes the spell perish once it has damaged `count` different magical entities, including any it has already damaged
+pub fn pierce(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let count = f64::from_bits(parameters[0]);
+    if count.is_nan() {
+        godot_warn!("Pierce count must be a number");
+        return Ok(None)
+    }
+
+    spell.pierce = Some(Pierce::from_count(count));
+
+    return Ok(None)
+}
+
+/// Puts off the instructions after this component until `seconds` of spell time have passed. If the spell perishes first, they never run
+pub fn delay(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let seconds = f64::from_bits(parameters[0]);
+    if !seconds.is_finite() || seconds < 0.0 {
+        godot_warn!("Couldn't delay: seconds must be a number that's at least 0");
+        return Ok(None)
+    }
+
+    spell.delay_requested = Some(seconds);
+
+    return Ok(None)
+}
+
+pub fn bounce(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let energy_loss = f64::from_bits(parameters[0]).clamp(0.0, 1.0);
+
+    spell.bounce = Some(Bounce { energy_loss });
+
+    return Ok(None)
+}
+
+/// Makes `get_time` count from now. Doesn't affect `lifetime`
+pub fn reset_timer(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.clock.reset();
+
+    return Ok(None)
+}
+
+/// Makes the spell trail its caster instead of moving by itself, which stops it being anchored
+pub fn familiar(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let follow_distance = f64::from_bits(parameters[0]).max(0.0);
+
+    spell.undo_anchor();
+    spell.velocity = Vector3::ZERO;
+    spell.familiar = Some(Familiar { follow_distance });
+
+    return Ok(None)
+}
+
+/// Holds the spell `distance` in front of its caster every frame, wherever they aim, until `undo_anchor` lets it go
+pub fn anchor_follow(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let distance = f64::from_bits(parameters[0]);
+
+    if !distance.is_finite() {
+        return Err(SpellError::BadParameterType("Distance must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.undo_anchor();
+    spell.familiar = None;
+    spell.anchor_follow = Some(AnchorFollow { distance: distance.max(0.0), direction: spell.original_direction * Vector3::FORWARD });
+
+    return Ok(None)
+}
+
+pub fn hollow_shape(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let thickness = f64::from_bits(parameters[0]);
+
+    if thickness.is_nan() {
+        return Err(SpellError::BadParameterType("Thickness must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.hollow_thickness = Some(Spell::get_clamped_hollow_thickness(thickness));
+    spell.refresh_shape();
+
+    return Ok(None)
+}
+
+/// Splits the spell into `count` spells at the start of the next physics frame
+pub fn split(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let count = f64::from_bits(parameters[0]);
+
+    if count.is_nan() {
+        return Err(SpellError::BadParameterType("Split count must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(Spell::get_split_cost(count))]))
+    }
+
+    if count < 2.0 {
+        return Ok(None)
+    }
 
-    return None
+    spell.split_count = Some(count as usize);
+
+    return Ok(None)
+}
+
+/// Turns the spell so that velocity given to it goes relative to the world direction `(x, y, z)` instead of the direction it was cast in
+pub fn set_direction(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let direction = Vector3::new(f64::from_bits(parameters[0]) as f32, f64::from_bits(parameters[1]) as f32, f64::from_bits(parameters[2]) as f32);
+
+    let basis = Spell::get_direction_basis(direction).ok_or(SpellError::BadParameterType("Direction can't be a zero vector"))?;
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(SET_DIRECTION_ENERGY_COST)]))
+    }
+
+    spell.original_direction = basis;
+
+    return Ok(None)
+}
+
+pub fn set_decay(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let energy_lose_rate = f64::from_bits(parameters[0]);
+
+    if energy_lose_rate.is_nan() {
+        return Err(SpellError::BadParameterType("Decay rate must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(SET_DECAY_ENERGY_COST)]))
+    }
+
+    spell.energy_lose_rate = Spell::get_clamped_energy_lose_rate(energy_lose_rate);
+
+    return Ok(None)
+}
+
+/// Spawns a spell running the child section at the start of the next physics frame, giving it `energy_fraction` of the spell's energy then
+pub fn spawn_child(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let energy_fraction = f64::from_bits(parameters[0]);
+
+    Spell::check_child_energy_fraction(energy_fraction)?;
+
+    // Costs are also estimated by spells that aren't the one being cast, so the child section is only needed once it runs
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    if spell.child_instructions.is_empty() {
+        godot_warn!("Couldn't spawn child: spell has no child section");
+        return Ok(None)
+    }
+
+    spell.children_requested.push(energy_fraction);
+
+    return Ok(None)
+}
+
+pub fn orbit(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let offset = Vector3::new(f64::from_bits(parameters[0]) as f32, f64::from_bits(parameters[1]) as f32, f64::from_bits(parameters[2]) as f32);
+    let angular_speed = f64::from_bits(parameters[3]);
+
+    if !should_execute {
+        // Costs the same as giving the spell the speed it moves around the orbit at
+        return Ok(Some(vec![f64::to_bits(spell.energy * Orbit::get_radius(offset) * angular_speed.abs() / APPLY_TO_SPELL_COEFFICIENT)]))
+    }
+
+    let center = spell.base().get_global_position() + spell.original_direction * offset;
+    let axis = (spell.original_direction * Vector3::UP).normalized();
+    spell.orbit = Some(Orbit { center, axis, angular_speed: angular_speed as f32 });
+
+    return Ok(None)
 }
 
 // Logic:
 
-pub fn get_time(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn get_time(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![NUMBER_LITERAL, f64::to_bits(0.1)]) // TODO: Should be set in config
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.clock.elapsed)]))
+}
+
+/// Returns how many seconds it has been since the spell was created, which isn't reset by `reset_timer`
+pub fn lifetime(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let current_time = match spell.time {
+        Some(ref time) => time.get_ticks_msec(),
+        None => panic!("Time wasn't created")
+    };
+
+    let spawn_time = match spell.spawn_time {
+        Some(spawn_time) => spawn_time,
+        None => panic!("Time wasn't created")
+    };
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(Spell::get_seconds_between(spawn_time, current_time))]))
+}
+
+/// Returns true with a chance that goes from `start_probability` to `end_probability` as the spell gets older, reaching `end_probability` after `ramp_duration` seconds
+pub fn chance_ramp(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let start_probability = f64::from_bits(parameters[0]);
+    let end_probability = f64::from_bits(parameters[1]);
+    let ramp_duration = f64::from_bits(parameters[2]);
+
     let current_time = match spell.time {
-        Some(ref ms) => ms,
+        Some(ref time) => time.get_ticks_msec(),
         None => panic!("Time wasn't created")
     };
 
-    let start_time = match spell.start_time {
-        Some(ref ms) => ms,
+    let spawn_time = match spell.spawn_time {
+        Some(spawn_time) => spawn_time,
         None => panic!("Time wasn't created")
     };
 
-    return Some(vec![NUMBER_LITERAL, f64::to_bits((current_time.get_ticks_msec() - start_time) as f64 / 1000.0)])
+    let age = Spell::get_seconds_between(spawn_time, current_time);
+    let probability = Spell::get_ramped_probability(start_probability, end_probability, ramp_duration, age);
+
+    if spell.rng.next_f64() < probability {
+        return Ok(Some(vec![TRUE]))
+    } else {
+        return Ok(Some(vec![FALSE]))
+    }
 }
 
-pub fn moving(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+/// Returns the fraction of its charged energy the caster put into the spell when it was cast
+pub fn caster_energy_selected(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.caster_energy_selected)]))
+}
+
+/// Returns how much energy the caster currently has charged
+pub fn caster_energy_charged(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    // The caster can't be bound while it's casting the spell, but the spell was given the caster's energy charged then
+    let energy_charged = match (spell.cast_direction, &spell.caster) {
+        (None, Some(caster)) if caster.is_instance_valid() => caster.bind().get_energy_charged(),
+        _ => spell.caster_energy_charged
+    };
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(energy_charged)]))
+}
+
+/// Returns the caster's focus when the spell was cast
+pub fn caster_focus(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.caster_focus)]))
+}
+
+/// Returns the length of the spell's own velocity. Rotating by `original_direction` doesn't change the length, so this matches the speed `moving` compares against
+pub fn get_speed(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.velocity.length() as f64)]))
+}
+
+pub fn get_random(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.rng.next_f64())]))
+}
+
+pub fn target_health(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let health = spell.get_nearest_overlapping_target().map_or(0.0, |magical_entity| magical_entity.bind().get_health());
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(health)]))
+}
+
+pub fn target_energy(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let energy_charged = spell.get_nearest_overlapping_target().map_or(0.0, |magical_entity| magical_entity.bind().get_energy_charged());
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(energy_charged)]))
+}
+
+/// Returns the way the caster is facing as three floats in the spell's own directions, so `give_velocity(caster_forward())` sends the spell that way
+pub fn caster_forward(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    // The caster can't be bound while it's casting the spell, but the spell was given its direction then
+    let caster_direction = match (spell.cast_direction, &spell.caster) {
+        (Some(cast_direction), _) => cast_direction,
+        (None, Some(caster)) if caster.is_instance_valid() => caster.bind().get_original_direction(),
+        _ => spell.original_direction
+    };
+
+    let forward = Spell::get_local_forward(spell.original_direction, caster_direction);
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(forward.x as f64), NUMBER_LITERAL, f64::to_bits(forward.y as f64), NUMBER_LITERAL, f64::to_bits(forward.z as f64)]))
+}
+
+/// Returns the direction to the nearest magical entity inside the spell as three floats in the spell's own directions, so `give_velocity(get_nearest_entity_direction())` sends the spell towards it
+pub fn get_nearest_entity_direction(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let direction = match spell.get_nearest_overlapping_target() {
+        Some(magical_entity) => Spell::get_local_direction_to(spell.original_direction, spell.base().get_global_position(), magical_entity.get_global_position()),
+        None => Vector3::ZERO
+    };
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(direction.x as f64), NUMBER_LITERAL, f64::to_bits(direction.y as f64), NUMBER_LITERAL, f64::to_bits(direction.z as f64)]))
+}
+
+pub fn hit_count(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.entities_hit.len() as f64)]))
+}
+
+/// Returns the energy the spell has left, which includes energy spent by components earlier in the same section
+pub fn get_energy(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.energy)]))
+}
+
+pub fn get_overlapping_entity_count(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    return Ok(Some(vec![NUMBER_LITERAL, f64::to_bits(spell.get_overlapping_target_count() as f64)]))
+}
+
+pub fn caster_alive(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    match spell.caster {
+        Some(ref caster) if caster.is_instance_valid() => return Ok(Some(vec![TRUE])),
+        _ => return Ok(Some(vec![FALSE]))
+    }
+}
+
+pub fn moving(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     // Static energy return
     if !should_execute {
-        return Some(vec![f64::to_bits(0.1)]) // TODO: Adjust energy requirements
+        return Ok(Some(vec![f64::to_bits(0.1)])) // TODO: Adjust energy requirements
     }
 
     let parameter_speed = f64::from_bits(parameters[0]);
 
     if (spell.velocity.x.powi(2) + spell.velocity.y.powi(2) + spell.velocity.z.powi(2)).sqrt() >= parameter_speed as f32 {
-        return Some(vec![TRUE])
+        return Ok(Some(vec![TRUE]))
     } else {
-        return Some(vec![FALSE])
+        return Ok(Some(vec![FALSE]))
     }
 }
 
 // Power:
-pub fn set_damage(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Option<Vec<u64>> {
+pub fn set_damage(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
     if !should_execute {
-        return Some(vec![f64::to_bits(0.0)])
+        return Ok(Some(vec![f64::to_bits(0.0)]))
     }
 
-    spell.damage = f64::from_bits(parameters[0]);
+    // Negative damage would heal, which is what `heal` is for
+    spell.damage = f64::from_bits(parameters[0]).max(0.0);
+    spell.damage_type = UNTYPED;
+
+    return Ok(None)
+}
+
+/// Like `set_damage`, but magical entities resist the damage depending on its type
+pub fn set_typed_damage(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.damage = f64::from_bits(parameters[0]).max(0.0);
+    spell.damage_type = f64::from_bits(parameters[1]) as u64;
+
+    return Ok(None)
+}
+
+pub fn cleave(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let damage = f64::from_bits(parameters[0]);
+    let arc_angle = f64::from_bits(parameters[1]);
+    let range = f64::from_bits(parameters[2]);
+
+    if damage <= 0.0 || range <= 0.0 {
+        return Ok(None)
+    }
+
+    spell.cleave(damage, arc_angle, range);
+
+    return Ok(None)
+}
+
+/// Changes which magical entities the spell treats as enemies. The spell still won't damage the magical entity that owns it
+pub fn set_faction(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.faction = f64::from_bits(parameters[0]) as i64;
+
+    return Ok(None)
+}
+
+/// Magical entities the spell damages from now on take `multiplier` times as much damage from anything for `duration` seconds
+pub fn mark_vulnerable(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let multiplier = f64::from_bits(parameters[0]);
+    let duration = f64::from_bits(parameters[1]);
+
+    if multiplier <= 0.0 || duration <= 0.0 {
+        spell.vulnerability_mark = None;
+        return Ok(None)
+    }
+
+    spell.vulnerability_mark = Some(VulnerabilityMark { multiplier, duration });
+
+    return Ok(None)
+}
+
+/// Stuns the magical entities the spell targets for `seconds` while they overlap it. A duration of 0 or less stops the spell stunning
+pub fn apply_stun(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let seconds = f64::from_bits(parameters[0]);
+
+    if seconds.is_nan() {
+        return Err(SpellError::BadParameterType("Stun duration must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(seconds.max(0.0) * STUN_ENERGY_PER_SECOND)]))
+    }
+
+    spell.stun_duration = if seconds > 0.0 { Some(seconds) } else { None };
+
+    return Ok(None)
+}
+
+/// Heals the magical entities the spell touches by `amount` every frame, split between them. The caster is only healed if `heal_owner` is true
+pub fn heal(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.heal = f64::from_bits(parameters[0]).max(0.0);
+    spell.heal_owner = parameters[1] == TRUE;
+
+    return Ok(None)
+}
+
+/// Drains `rate` energy per second from each target the spell overlaps and gives it to the caster's charged energy
+pub fn siphon_to_caster(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.siphon_rate = f64::from_bits(parameters[0]).max(0.0);
+
+    return Ok(None)
+}
+
+/// Makes the spell's damage fall from full at `start_distance` from the caster to nothing at `end_distance`. Past `end_distance` the spell deals no damage but doesn't perish
+pub fn set_damage_falloff(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let start_distance = f64::from_bits(parameters[0]);
+    let end_distance = f64::from_bits(parameters[1]);
+    if start_distance.is_nan() || end_distance.is_nan() {
+        godot_warn!("Damage falloff distances must be numbers");
+        return Ok(None)
+    }
+
+    spell.damage_falloff = Some(DamageFalloff { start_distance: start_distance.max(0.0), end_distance: end_distance.max(0.0) });
+
+    return Ok(None)
+}
+
+/// Makes the spell drain energy from overlapping spells that weren't cast by its caster
+pub fn absorb(spell: &mut Spell, _parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.absorbing = true;
+
+    return Ok(None)
+}
+
+/// Makes the spell speed up overlapping spells cast by someone else by `strength` every second in the direction it's going. The energy is paid every frame for each spell pushed
+pub fn push_spells(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let strength = f64::from_bits(parameters[0]);
+
+    if strength.is_nan() {
+        return Err(SpellError::BadParameterType("Push strength must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.spell_push = if strength > 0.0 { Some(strength) } else { None };
+
+    return Ok(None)
+}
+
+pub fn apply_radial_force(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let strength = f64::from_bits(parameters[0]);
+    let radius = f64::from_bits(parameters[1]);
+
+    if strength == 0.0 || radius <= 0.0 {
+        spell.radial_force = None;
+        return Ok(None)
+    }
+
+    spell.radial_force = Some(RadialForce { strength, radius });
+
+    return Ok(None)
+}
+
+pub fn set_lifesteal(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    spell.lifesteal = f64::from_bits(parameters[0]).clamp(0.0, 1.0);
+
+    return Ok(None)
+}
+
+pub fn beam(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    let damage_per_second = f64::from_bits(parameters[0]);
+    let max_length = f64::from_bits(parameters[1]);
+
+    if max_length.is_nan() {
+        return Err(SpellError::BadParameterType("Beam length must be a number"))
+    }
+
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(Beam::get_energy_cost(max_length))]))
+    }
+
+    if max_length <= 0.0 {
+        return Ok(None)
+    }
+
+    spell.beam = Some(Beam { damage_per_second: damage_per_second.max(0.0), max_length });
+
+    return Ok(None)
+}
+
+/// Turns the spell into a trap that stays where it is, arms after `arm_delay` seconds, and then goes off when a target comes within `trigger_radius`
+pub fn trap(spell: &mut Spell, parameters: &[u64], should_execute: bool) -> Result<Option<Vec<u64>>, SpellError> {
+    if !should_execute {
+        return Ok(Some(vec![f64::to_bits(0.0)]))
+    }
+
+    let arm_delay = f64::from_bits(parameters[0]);
+    let trigger_radius = f64::from_bits(parameters[1]);
+    let damage = f64::from_bits(parameters[2]);
+
+    if trigger_radius <= 0.0 || damage <= 0.0 {
+        return Ok(None)
+    }
+
+    let now = match spell.time {
+        Some(ref time) => time.get_ticks_msec(),
+        None => panic!("Time wasn't created")
+    };
+
+    spell.undo_anchor();
+    spell.familiar = None;
+    spell.velocity = Vector3::ZERO;
+    spell.trap = Some(Trap { armed_time: Spell::get_expiry_time(now, arm_delay), trigger_radius, damage });
 
-    return None
+    return Ok(None)
 }
@@ -1,20 +1,31 @@
 use lazy_static::lazy_static;
 use serde_json::json;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::f64::consts::{PI, E};
+use std::time::Instant;
 
 // Godot imports
 use godot::prelude::*;
 use godot::classes::Time;
 use godot::classes::Area3D;
+use godot::classes::RigidBody3D;
 use godot::classes::IArea3D;
 use godot::classes::CollisionShape3D;
 use godot::classes::SphereShape3D;
 use godot::classes::BoxShape3D;
+use godot::classes::CylinderShape3D;
+use godot::classes::ConvexPolygonShape3D;
+use godot::classes::CapsuleShape3D;
+use godot::classes::CapsuleMesh;
 use godot::classes::CsgSphere3D;
 use godot::classes::CsgBox3D;
+use godot::classes::CsgCylinder3D;
+use godot::classes::CsgMesh3D;
+use godot::classes::PhysicsRayQueryParameters3D;
+use godot::classes::PhysicsShapeQueryParameters3D;
 use godot::classes::CsgPrimitive3D;
+use godot::classes::csg_shape_3d::Operation;
 use godot::classes::Shape3D;
 use godot::classes::StandardMaterial3D;
 use godot::classes::base_material_3d::Transparency;
@@ -26,13 +37,19 @@ mod component_functions;
 mod magical_entity;
 mod saver;
 mod codes;
+mod budget;
+mod validator;
+mod spell_error;
 
 use saver::{Config, godot_json_saver};
+use spell_error::SpellError;
 use magical_entity::MagicalEntity;
 use codes::componentcodes::*;
 use codes::attributecodes::*;
 use codes::opcodes::*;
 use codes::datatypes::*;
+use codes::component_specific_codes::*;
+use codes::damagetypes::*;
 
 /// How often spells instructions should be processed. The specified frequency is multiplied by this constant.
 const PROCESS_FREQUENCY: usize = 1; // TODO: Test changing this doesn't break anything
@@ -40,26 +57,142 @@ const PROCESS_FREQUENCY: usize = 1; // TODO: Test changing this doesn't break an
 /// When a spell has energy below this level it is discarded as being insignificant
 pub const ENERGY_CONSIDERATION_LEVEL: f64 = 0.1;
 
-/// Used to control how fast efficiency increases with each cast
+// The constants below that are marked as defaults can be changed in config.toml
+
+/// Used to control how fast efficiency increases with each cast. Default for `efficiency_increase_rate`
 const EFFICIENCY_INCREASE_RATE: f64 = 15.0;
 
-/// Used to control how fast energy is lost passively over time. Is a fraction of total spell energy
+/// The efficiency level of a component that hasn't been cast before
+const DEFAULT_EFFICIENCY_LEVEL: f64 = 1.0;
+
+/// Used to control how fast energy is lost passively over time. Is a fraction of total spell energy. Default for `energy_lose_rate`
 const ENERGY_LOSE_RATE: f64 = 0.05;
 
+/// How much the caster's focus changes the energy lose rate of the spells they cast. At 0.5, a caster with a focus of 2 casts spells that lose energy half as fast. Default for `focus_stability`
+const FOCUS_STABILITY: f64 = 0.5;
+
+/// The energy an anchored spell loses every frame for each unit of mass of the magical entity it's anchored to. Default for `mass_movement_cost`
 const MASS_MOVEMENT_COST: f64 = 0.5;
 
-/// Used to determin how Transparent the default spell is. 0 = fully transparent, 1 = opaque
+/// The mass used for surmounting the anchor resistance of nodes that aren't magical entities or rigid bodies, and for pushing magical entities without a mass
+const DEFAULT_ANCHOR_MASS: f64 = 1.0;
+
+/// The physics layer spells are on, and the layers of the bodies they detect, unless `set_collision_profile` is used
+const DEFAULT_SPELL_COLLISION_LAYER: u32 = 1;
+const DEFAULT_SPELL_COLLISION_MASK: u32 = 1;
+
+/// Used to determin how Transparent the default spell is. 0 = fully transparent, 1 = opaque. Default for `spell_transparency`
 const SPELL_TRANSPARENCY: f32 = 0.9;
 
+/// How much charging energy slows down as energy charged fills up. At 0, magical entities charge at the same rate until they're full, and higher numbers
+/// charge more slowly the fuller they are, so they settle below full. Default for `energy_charge_curve`
+const ENERGY_CHARGE_CURVE: f64 = 0.0;
+
+/// The most instructions a section of a spell can run each time it runs, so that spells with deeply nested loops can't stall the game. Default for `instruction_budget`
+const INSTRUCTION_BUDGET: usize = 100_000;
+
 /// The frequency at which the radius of a spell is updated (if the shape isn't set). If the number was five, it would update every five physics frames
 const RADIUS_UPDATE_RATE: usize = 5;
 
-/// A number used in the conversion of energy to volume, if changed effects the size of spells
+/// A number used in the conversion of energy to volume, if changed effects the size of spells. Default for `energy_to_volume`
 const ENERGY_TO_VOLUME: f64 = 0.0005;
 
 /// In the format (rings, radial segments). Determins the detail on the visible sphere of spells.
 const CSG_SPHERE_DETAIL: (i32, i32) = (18, 20);
 
+/// The number of sides on the visible cylinder of spells
+const CSG_CYLINDER_SIDES: i32 = 16;
+
+/// The radius of the cylinder used by beams
+const BEAM_RADIUS: f64 = 0.1;
+
+/// Energy `beam` costs to cast for every unit of its max length
+const BEAM_ENERGY_PER_LENGTH: f64 = 0.1;
+
+/// How far away from a wall fragments are created so that they don't start inside it
+const FRAGMENT_OFFSET: f32 = 0.05;
+
+/// The smallest any dimension of a shape can be, which stops shapes with no volume being made. Also the default smallest dimension `take_shape` accepts
+const MINIMUM_SHAPE_DIMENSION: f64 = 0.01;
+
+/// The thinnest the shell of a hollow spell can be, so that the inner shape doesn't cut through the outer one
+const MINIMUM_HOLLOW_THICKNESS: f64 = 0.05;
+
+/// Spells and magical entities with this faction treat everything as an enemy
+pub const NO_FACTION: i64 = 0;
+
+/// The most times a spell can bounce in a single frame, which stops spells getting stuck in walls
+const MAX_BOUNCES_PER_FRAME: usize = 4;
+
+/// How far a spell is moved away from a wall it bounced off
+const BOUNCE_OFFSET: f32 = 0.05;
+
+/// Energy a bouncing spell uses for every unit of speed it loses when it bounces
+const BOUNCE_ENERGY_PER_SPEED_LOST: f64 = 0.1;
+
+/// How quickly a familiar catches up to where it should be
+const FAMILIAR_FOLLOW_RATE: f64 = 5.0;
+
+/// Energy a familiar uses every second to keep following its caster
+const FAMILIAR_ENERGY_PER_SECOND: f64 = 0.1;
+
+/// Energy a spell uses every second to stay held in front of its caster
+const ANCHOR_FOLLOW_ENERGY_PER_SECOND: f64 = 0.2;
+
+/// Homing spells without a detection radius look for targets within this radius. Default for `homing_detection_radius`
+const HOMING_DETECTION_RADIUS: f32 = 20.0;
+
+/// Energy a homing spell uses for every radian it turns
+const HOMING_ENERGY_PER_RADIAN: f64 = 1.0;
+
+/// The angle in radians between the outermost spells made by splitting
+const SPLIT_SPREAD_ANGLE: f32 = 0.5;
+
+/// The most spells `split` can split a spell into at once
+const MAX_SPLIT_COUNT: usize = 16;
+
+/// Energy `split` costs for every extra spell it makes
+const SPLIT_ENERGY_PER_SPELL: f64 = 0.5;
+
+/// How many times spells made by `spawn_child` can spawn children of their own, which stops spells spawning each other forever
+const MAX_CHILD_GENERATION: usize = 3;
+
+/// Energy a spell spends for each unit of energy it siphons to its caster
+const SIPHON_ENERGY_COST: f64 = 0.5;
+
+/// The fraction of its own energy an absorbing spell draws out of each overlapping spell every second
+const ABSORB_RATE: f64 = 0.5;
+
+/// The most of its energy a spell can lose to an absorbing spell in one frame
+const MAX_ABSORB_FRACTION: f64 = 0.5;
+
+/// The most times a while loop can run its contents before the spell gives up and perishes, which stops spells freezing the game
+const MAX_WHILE_ITERATIONS: usize = 10_000;
+
+/// The most times a `repeat n times` section can run, which is kept the same as the most a while loop can
+const MAX_REPEAT_TIMES: usize = MAX_WHILE_ITERATIONS;
+
+/// Energy `set_direction` costs, whatever direction it's given
+const SET_DIRECTION_ENERGY_COST: f64 = 0.05;
+
+/// Energy `set_decay` costs, whatever rate it's given
+const SET_DECAY_ENERGY_COST: f64 = 0.01;
+
+/// Energy `apply_stun` costs for every second it stuns for
+const STUN_ENERGY_PER_SECOND: f64 = 0.5;
+
+/// The highest energy lose rate `set_decay` can give a spell, which is all of its energy every second
+const MAX_ENERGY_LOSE_RATE: f64 = 1.0;
+
+/// Energy a spell spends for every unit of speed its radial force gives magical entities
+const RADIAL_FORCE_ENERGY_COST: f64 = 0.1;
+
+/// Energy a spell spends for every unit of speed `push_spells` gives other spells
+const SPELL_PUSH_ENERGY_COST: f64 = 0.1;
+
+/// Magical entities closer than this to the center of a radial force are pushed as if they were this far away, so the force never becomes infinite
+const MINIMUM_RADIAL_FORCE_DISTANCE: f64 = 0.5;
+
 /// The default colour of spells
 const DEFAULT_COLOR: CustomColor = CustomColor { r: 0.1, g: 0.0, b: 0.9 };
 
@@ -67,6 +200,11 @@ const DEFAULT_COLOR: CustomColor = CustomColor { r: 0.1, g: 0.0, b: 0.9 };
 const SPELL_COLLISION_SHAPE_NAME: &'static str = "spell_collision_shape";
 const SPELL_SHAPE_NAME: &'static str = "spell_shape";
 const SPELL_CSG_SHAPE_NAME: &'static str = "spell_csg_shape";
+const SPELL_CSG_HOLLOW_NAME: &'static str = "spell_csg_hollow";
+const SPELL_DETECTION_AREA_NAME: &'static str = "spell_detection_area";
+/// The name a detection area is given while it waits to be freed, so it isn't mistaken for the spell's current one
+const SPELL_REMOVED_DETECTION_AREA_NAME: &'static str = "spell_removed_detection_area";
+const SPELL_DETECTION_SHAPE_NAME: &'static str = "spell_detection_shape";
 const FORM_NAME: &'static str = "form";
 
 #[derive(Serialize, Deserialize)]
@@ -77,13 +215,14 @@ struct CustomColor {
 }
 
 impl CustomColor {
-    pub fn into_spell_color(self) -> Color {
-        Color { r: self.r, g: self.g, b: self.b, a: SPELL_TRANSPARENCY }
+    pub fn into_spell_color(self, transparency: f32) -> Color {
+        Color { r: self.r, g: self.g, b: self.b, a: transparency }
     }
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 struct ComponentCatalogue {
+    #[serde(serialize_with = "godot_json_saver::serialize_sorted")]
     pub component_catalogue: HashMap<u64, Vec<Vec<u64>>>
 }
 
@@ -91,41 +230,114 @@ impl ComponentCatalogue {
     fn new() -> Self {
         ComponentCatalogue { component_catalogue: HashMap::new() }
     }
+
+    /// Allows every component with any parameters. Goes through `COMPONENT_TO_FUNCTION_MAP` so new components are included too
+    fn add_all_components(&mut self) {
+        for (&component_code, (_, parameter_types, _)) in COMPONENT_TO_FUNCTION_MAP.iter() {
+            Spell::add_component_to_component_catalogue(component_code, vec![vec!["ANY"]; parameter_types.len()], self);
+        }
+    }
+
+    fn contains(&self, component_code: u64) -> bool {
+        self.component_catalogue.contains_key(&component_code)
+    }
 }
 
 enum ReturnType {
     Float,
     Boolean,
+    /// Three floats, which fill three float parameters when the component is used as a parameter
+    Vector,
     None
 }
 
+impl ReturnType {
+    /// How many values a component gives back
+    fn get_arity(&self) -> usize {
+        match self {
+            ReturnType::Float | ReturnType::Boolean => 1,
+            ReturnType::Vector => 3,
+            ReturnType::None => 0
+        }
+    }
+}
+
 const COMPONENT_0_ARGS: &[u64] = &[FLOAT, FLOAT, FLOAT];
 const COMPONENT_1_ARGS: &[u64] = &[FLOAT];
 const COMPONENT_2_ARGS: &[u64] = &[];
 const COMPONENT_7_ARGS: &[u64] = &[FLOAT, FLOAT, FLOAT, FLOAT];
+const COMPONENT_9_ARGS: &[u64] = &[FLOAT, FLOAT];
+const COMPONENT_2006_ARGS: &[u64] = &[FLOAT, BOOLEAN];
 
 lazy_static! {
     /// Maps component bytecode to functions and its parameter datatypes and its return type.
     /// Component_bytecode -> (function, parameter types represented by u64, return type of the function for if statements).
-    static ref COMPONENT_TO_FUNCTION_MAP: HashMap<u64, (fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, &'static[u64], ReturnType)> = {
+    /// Functions return an error when they can't be cast with the parameters they're given, which stops the spell with that error.
+    static ref COMPONENT_TO_FUNCTION_MAP: HashMap<u64, (fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, &'static[u64], ReturnType)> = {
         let mut component_map = HashMap::new();
         // Utility:
-        component_map.insert(GIVE_VELOCITY, (component_functions::give_velocity as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_0_ARGS, ReturnType::None));
-        component_map.insert(TAKE_FORM, (component_functions::take_form as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_1_ARGS, ReturnType::None));
-        component_map.insert(UNDO_FORM, (component_functions::undo_form as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::None));
-        component_map.insert(RECHARGE_TO, (component_functions::recharge_to as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_1_ARGS, ReturnType::None));
-        component_map.insert(ANCHOR, (component_functions::anchor as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::None));
-        component_map.insert(UNDO_ANCHOR, (component_functions::undo_anchor as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::None));
-        component_map.insert(PERISH, (component_functions::perish as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::None));
-        component_map.insert(TAKE_SHAPE, (component_functions::take_shape as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_7_ARGS, ReturnType::None));
-        component_map.insert(UNDO_SHAPE, (component_functions::undo_shape as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(GIVE_VELOCITY, (component_functions::give_velocity as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::None));
+        component_map.insert(TAKE_FORM, (component_functions::take_form as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(UNDO_FORM, (component_functions::undo_form as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(RECHARGE_TO, (component_functions::recharge_to as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(ANCHOR, (component_functions::anchor as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(UNDO_ANCHOR, (component_functions::undo_anchor as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(PERISH, (component_functions::perish as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(TAKE_SHAPE, (component_functions::take_shape as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_7_ARGS, ReturnType::None));
+        component_map.insert(UNDO_SHAPE, (component_functions::undo_shape as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(FRAGMENT_ON_WALL, (component_functions::fragment_on_wall as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(HOMING, (component_functions::homing as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_LIFETIME, (component_functions::set_lifetime as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(FADE_COLOR_TO, (component_functions::fade_color_to as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_7_ARGS, ReturnType::None));
+        component_map.insert(BOUNCE, (component_functions::bounce as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(RESET_TIMER, (component_functions::reset_timer as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(FAMILIAR, (component_functions::familiar as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SPLIT, (component_functions::split as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_DIRECTION, (component_functions::set_direction as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::None));
+        component_map.insert(ORBIT, (component_functions::orbit as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_7_ARGS, ReturnType::None));
+        component_map.insert(SET_COLOR, (component_functions::set_color as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::None));
+        component_map.insert(PIERCE, (component_functions::pierce as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(DELAY, (component_functions::delay as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_DECAY, (component_functions::set_decay as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(ANCHOR_FOLLOW, (component_functions::anchor_follow as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(HOLLOW_SHAPE, (component_functions::hollow_shape as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SPAWN_CHILD, (component_functions::spawn_child as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
 
         // Logic:
-        component_map.insert(MOVING, (component_functions::moving as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_1_ARGS, ReturnType::Boolean));
-        component_map.insert(GET_TIME, (component_functions::get_time as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(MOVING, (component_functions::moving as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::Boolean));
+        component_map.insert(GET_TIME, (component_functions::get_time as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(HIT_COUNT, (component_functions::hit_count as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(GET_ENERGY, (component_functions::get_energy as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(CASTER_ALIVE, (component_functions::caster_alive as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Boolean));
+        component_map.insert(LIFETIME, (component_functions::lifetime as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(CHANCE_RAMP, (component_functions::chance_ramp as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::Boolean));
+        component_map.insert(CASTER_ENERGY_SELECTED, (component_functions::caster_energy_selected as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(CASTER_ENERGY_CHARGED, (component_functions::caster_energy_charged as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(CASTER_FOCUS, (component_functions::caster_focus as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(GET_SPEED, (component_functions::get_speed as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(GET_RANDOM, (component_functions::get_random as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(TARGET_HEALTH, (component_functions::target_health as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(TARGET_ENERGY, (component_functions::target_energy as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
+        component_map.insert(CASTER_FORWARD, (component_functions::caster_forward as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Vector));
+        component_map.insert(GET_NEAREST_ENTITY_DIRECTION, (component_functions::get_nearest_entity_direction as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Vector));
+        component_map.insert(GET_OVERLAPPING_ENTITY_COUNT, (component_functions::get_overlapping_entity_count as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::Float));
 
         // Power:
-        component_map.insert(SET_DAMAGE, (component_functions::set_damage as fn(&mut Spell, &[u64], bool) -> Option<Vec<u64>>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_DAMAGE, (component_functions::set_damage as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_LIFESTEAL, (component_functions::set_lifesteal as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(BEAM, (component_functions::beam as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(CLEAVE, (component_functions::cleave as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::None));
+        component_map.insert(SET_FACTION, (component_functions::set_faction as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(MARK_VULNERABLE, (component_functions::mark_vulnerable as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(HEAL, (component_functions::heal as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2006_ARGS, ReturnType::None));
+        component_map.insert(TRAP, (component_functions::trap as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_0_ARGS, ReturnType::None));
+        component_map.insert(SIPHON_TO_CASTER, (component_functions::siphon_to_caster as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(SET_TYPED_DAMAGE, (component_functions::set_typed_damage as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(APPLY_RADIAL_FORCE, (component_functions::apply_radial_force as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(ABSORB, (component_functions::absorb as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_2_ARGS, ReturnType::None));
+        component_map.insert(SET_DAMAGE_FALLOFF, (component_functions::set_damage_falloff as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_9_ARGS, ReturnType::None));
+        component_map.insert(APPLY_STUN, (component_functions::apply_stun as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
+        component_map.insert(PUSH_SPELLS, (component_functions::push_spells as fn(&mut Spell, &[u64], bool) -> Result<Option<Vec<u64>>, SpellError>, COMPONENT_1_ARGS, ReturnType::None));
 
         return component_map
     };
@@ -134,15 +346,80 @@ lazy_static! {
 #[derive(Clone, Copy, Deserialize)]
 enum Shape {
     Sphere(Sphere),
-    Cube(Cube)
+    Cube(Cube),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Pyramid(Pyramid)
 }
 
 impl HasVolume for Shape {
     fn get_volume(&self) -> f64 {
         match self {
             Self::Sphere(sphere) => sphere.get_volume(),
-            Self::Cube(cube) => cube.get_volume()
+            Self::Cube(cube) => cube.get_volume(),
+            Self::Cylinder(cylinder) => cylinder.get_volume(),
+            Self::Cone(cone) => cone.get_volume(),
+            Self::Capsule(capsule) => capsule.get_volume(),
+            Self::Pyramid(pyramid) => pyramid.get_volume()
+        }
+    }
+}
+
+impl Shape {
+    /// Makes a shape from the parameters of `take_shape`, rejecting shapes with a dimension smaller than `minimum_dimension`
+    fn from_code(shape_code: u64, size_1: f64, size_2: f64, size_3: f64, minimum_dimension: f64) -> Result<Self, SpellError> {
+        let shape = match shape_code {
+            SPHERE => Shape::Sphere(Sphere { radius: size_1 }),
+            CUBE => Shape::Cube(Cube { x: size_1, y: size_2, z: size_3 }),
+            CYLINDER => Shape::Cylinder(Cylinder { radius: size_1, height: size_2 }),
+            CONE => Shape::Cone(Cone { radius: size_1, height: size_2 }),
+            CAPSULE => Shape::Capsule(Capsule { radius: size_1, height: size_2 }),
+            PYRAMID => Shape::Pyramid(Pyramid { base_length: size_1, base_width: size_2, height: size_3 }),
+            _ => return Err(SpellError::InvalidShape("Not a valid shape"))
+        };
+        if shape.get_smallest_dimension() < minimum_dimension {
+            return Err(SpellError::InvalidShape("Shape has a dimension smaller than the minimum"))
+        }
+        Ok(shape.with_minimum_dimensions())
+    }
+
+    fn get_smallest_dimension(&self) -> f64 {
+        match self {
+            Self::Sphere(sphere) => sphere.radius,
+            Self::Cube(cube) => cube.x.min(cube.y).min(cube.z),
+            Self::Cylinder(cylinder) => cylinder.radius.min(cylinder.height),
+            Self::Cone(cone) => cone.radius.min(cone.height),
+            // A capsule without a middle is a sphere, so only the radius has a minimum
+            Self::Capsule(capsule) => capsule.radius,
+            Self::Pyramid(pyramid) => pyramid.base_length.min(pyramid.base_width).min(pyramid.height)
+        }
+    }
+
+    /// Returns the shape with every dimension at least `MINIMUM_SHAPE_DIMENSION`
+    fn with_minimum_dimensions(self) -> Self {
+        match self {
+            Self::Sphere(sphere) => Self::Sphere(Sphere { radius: sphere.radius.max(MINIMUM_SHAPE_DIMENSION) }),
+            Self::Cube(cube) => Self::Cube(Cube { x: cube.x.max(MINIMUM_SHAPE_DIMENSION), y: cube.y.max(MINIMUM_SHAPE_DIMENSION), z: cube.z.max(MINIMUM_SHAPE_DIMENSION) }),
+            Self::Cylinder(cylinder) => Self::Cylinder(Cylinder { radius: cylinder.radius.max(MINIMUM_SHAPE_DIMENSION), height: cylinder.height.max(MINIMUM_SHAPE_DIMENSION) }),
+            Self::Cone(cone) => Self::Cone(Cone { radius: cone.radius.max(MINIMUM_SHAPE_DIMENSION), height: cone.height.max(MINIMUM_SHAPE_DIMENSION) }),
+            Self::Capsule(capsule) => Self::Capsule(Capsule { radius: capsule.radius.max(MINIMUM_SHAPE_DIMENSION), height: capsule.height.max(0.0) }),
+            Self::Pyramid(pyramid) => Self::Pyramid(Pyramid { base_length: pyramid.base_length.max(MINIMUM_SHAPE_DIMENSION), base_width: pyramid.base_width.max(MINIMUM_SHAPE_DIMENSION), height: pyramid.height.max(MINIMUM_SHAPE_DIMENSION) })
+        }
+    }
+
+    /// Returns the shape cut out of the middle of a hollow spell to leave a shell `thickness` thick, or None if the shape can't be hollowed or is too thin to have a middle.
+    /// Only spheres and cubes can be hollowed
+    fn get_hollow_inner(&self, thickness: f64) -> Option<Self> {
+        let inner = match self {
+            Self::Sphere(sphere) => Self::Sphere(Sphere { radius: sphere.radius - thickness }),
+            Self::Cube(cube) => Self::Cube(Cube { x: cube.x - 2.0 * thickness, y: cube.y - 2.0 * thickness, z: cube.z - 2.0 * thickness }),
+            _ => return None
+        };
+        if inner.get_smallest_dimension() < MINIMUM_SHAPE_DIMENSION {
+            return None
         }
+        Some(inner)
     }
 }
 
@@ -180,6 +457,96 @@ impl HasVolume for Cube {
     }
 }
 
+/// The height of a cylinder goes along the spell's forward direction
+#[derive(Clone, Copy, Deserialize)]
+struct Cylinder {
+    radius: f64,
+    height: f64
+}
+
+impl HasVolume for Cylinder {
+    fn get_volume(&self) -> f64 {
+        PI * self.radius.powi(2) * self.height
+    }
+}
+
+/// The height of a cone goes along the spell's forward direction, with the point at the back and the base at the front
+#[derive(Clone, Copy, Deserialize)]
+struct Cone {
+    radius: f64,
+    height: f64
+}
+
+impl Cone {
+    /// Returns the corners of a collision shape that matches a cone with `sides` sides, pointing up and centered like `CsgCylinder3D` cones
+    fn get_collision_points(&self, sides: i32) -> Vec<Vector3> {
+        let half_height = self.height as f32 / 2.0;
+        let mut points = vec![Vector3::new(0.0, half_height, 0.0)];
+        points.extend((0..sides).map(|side| {
+            let angle = std::f32::consts::TAU * side as f32 / sides as f32;
+            Vector3::new(self.radius as f32 * angle.cos(), -half_height, self.radius as f32 * angle.sin())
+        }));
+        points
+    }
+}
+
+impl HasVolume for Cone {
+    fn get_volume(&self) -> f64 {
+        PI * self.radius.powi(2) * self.height / 3.0
+    }
+}
+
+/// A cylinder with a hemisphere on each end. `height` is the length of the cylinder between the hemispheres and goes along the spell's forward direction
+#[derive(Clone, Copy, Deserialize)]
+struct Capsule {
+    radius: f64,
+    height: f64
+}
+
+impl Capsule {
+    /// Returns the length from the tip of one hemisphere to the other, which is the height Godot capsules use
+    fn get_total_height(&self) -> f64 {
+        self.height + 2.0 * self.radius
+    }
+}
+
+impl HasVolume for Capsule {
+    fn get_volume(&self) -> f64 {
+        PI * self.radius.powi(2) * self.height + (4.0 / 3.0) * PI * self.radius.powi(3)
+    }
+}
+
+/// Like a cone, the height of a pyramid goes along the spell's forward direction with the point at the back.
+/// `base_length` goes along the spell's right direction and `base_width` along its up direction
+#[derive(Clone, Copy, Deserialize)]
+struct Pyramid {
+    base_length: f64,
+    base_width: f64,
+    height: f64
+}
+
+impl Pyramid {
+    /// Returns the point and the four corners of the base, pointing up and centered like `CsgCylinder3D` cones
+    fn get_collision_points(&self) -> Vec<Vector3> {
+        let half_length = self.base_length as f32 / 2.0;
+        let half_width = self.base_width as f32 / 2.0;
+        let half_height = self.height as f32 / 2.0;
+        vec![
+            Vector3::new(0.0, half_height, 0.0),
+            Vector3::new(half_length, -half_height, half_width),
+            Vector3::new(-half_length, -half_height, half_width),
+            Vector3::new(-half_length, -half_height, -half_width),
+            Vector3::new(half_length, -half_height, -half_width)
+        ]
+    }
+}
+
+impl HasVolume for Pyramid {
+    fn get_volume(&self) -> f64 {
+        self.base_length * self.base_width * self.height / 3.0
+    }
+}
+
 trait HasVolume {
     fn get_volume(&self) -> f64;
 }
@@ -189,16 +556,253 @@ trait HasShape {
     fn set_visibility(&mut self, visible: bool);
 }
 
+/// A beam stretches from the caster in the direction they're facing and continuously damages what's inside it
+#[derive(Clone, Copy)]
+struct Beam {
+    damage_per_second: f64,
+    max_length: f64
+}
+
+impl Beam {
+    /// The damage the beam deals to a target over a frame of `delta` seconds, which is never more than it takes to kill them
+    fn get_frame_damage(&self, delta: f64, energy_to_kill: f64) -> f64 {
+        (self.damage_per_second * delta).min(energy_to_kill)
+    }
+
+    /// The energy casting a beam that reaches up to `max_length` costs. A longer beam costs more
+    fn get_energy_cost(max_length: f64) -> f64 {
+        max_length.max(0.0) * BEAM_ENERGY_PER_LENGTH
+    }
+}
+
+/// When a spell with fragmentation hits a wall it splits into `count` fragments moving at `speed`
+#[derive(Clone, Copy)]
+struct Fragmentation {
+    count: usize,
+    speed: f64
+}
+
+/// A homing spell turns towards the nearest magical entity it doesn't belong to by up to `turn_rate` radians per second
+#[derive(Clone, Copy)]
+struct Homing {
+    turn_rate: f64
+}
+
+/// A bouncing spell reflects off walls, losing `energy_loss` of its speed each time
+#[derive(Clone, Copy)]
+struct Bounce {
+    energy_loss: f64
+}
+
+/// A piercing spell perishes once it has damaged `limit` different magical entities. An entity that stays inside the spell only counts once
+#[derive(Clone, Copy)]
+struct Pierce {
+    limit: usize
+}
+
+impl Pierce {
+    /// Makes a pierce from the count given to `pierce`. Counts below 1 still let the spell hit one entity before it perishes
+    fn from_count(count: f64) -> Self {
+        Pierce { limit: count.max(1.0) as usize }
+    }
+
+    fn is_used_up(&self, entities_hit: usize) -> bool {
+        entities_hit >= self.limit
+    }
+}
+
+/// A spell with damage falloff deals full damage up to `start_distance` from its caster, then less and less until it deals none at `end_distance`
+#[derive(Clone, Copy)]
+struct DamageFalloff {
+    start_distance: f64,
+    end_distance: f64
+}
+
+impl DamageFalloff {
+    /// Returns what the spell's damage is multiplied by at `distance` from its caster. When the end isn't past the start, damage stops all at once at the start
+    fn get_multiplier(&self, distance: f64) -> f64 {
+        if distance <= self.start_distance {
+            1.0
+        } else if distance >= self.end_distance {
+            0.0
+        } else {
+            1.0 - (distance - self.start_distance) / (self.end_distance - self.start_distance)
+        }
+    }
+}
+
+/// Magical entities hit by a spell with a vulnerability mark take `multiplier` times as much damage for `duration` seconds
+#[derive(Clone, Copy)]
+struct VulnerabilityMark {
+    multiplier: f64,
+    duration: f64
+}
+
+/// A familiar trails its caster, staying `follow_distance` away from them
+#[derive(Clone, Copy)]
+struct Familiar {
+    follow_distance: f64
+}
+
+/// A held spell stays `distance` in front of its caster, wherever they aim. `direction` is the way it was last held out, which it flies off in when it's let go of
+#[derive(Clone, Copy)]
+struct AnchorFollow {
+    distance: f64,
+    direction: Vector3
+}
+
+/// A trap stays where it is and does nothing until `armed_time`. Once armed, it deals `damage` to every target within `trigger_radius` as soon as one comes close enough, then perishes
+#[derive(Clone, Copy)]
+struct Trap {
+    armed_time: u64,
+    trigger_radius: f64,
+    damage: f64
+}
+
+impl Trap {
+    fn is_armed(&self, now: u64) -> bool {
+        now >= self.armed_time
+    }
+
+    /// Whether the trap goes off at `now` given how far away each target is
+    fn should_trigger(&self, now: u64, target_distances: &[f64]) -> bool {
+        self.is_armed(now) && target_distances.iter().any(|&distance| distance <= self.trigger_radius)
+    }
+}
+
+/// Every physics frame, pushes targets within `radius` away from the spell, or pulls them in if `strength` is negative
+#[derive(Clone, Copy)]
+struct RadialForce {
+    strength: f64,
+    radius: f64
+}
+
+impl RadialForce {
+    /// Returns the velocity given over `delta` seconds to a target `offset` from the center, when the strength is split between `number_of_targets`
+    fn get_velocity(&self, offset: Vector3, number_of_targets: usize, delta: f64) -> Vector3 {
+        let distance = (offset.length() as f64).max(MINIMUM_RADIAL_FORCE_DISTANCE);
+        let speed = self.strength / number_of_targets as f64 / distance * delta;
+        offset.normalized_or_zero() * speed as f32
+    }
+}
+
+/// Moves the spell in a circle around `center`, turning `angular_speed` radians a second about `axis`
+#[derive(Clone, Copy)]
+struct Orbit {
+    center: Vector3,
+    axis: Vector3,
+    angular_speed: f32
+}
+
+impl Orbit {
+    /// Returns where a spell at `position` is after orbiting for `delta` seconds
+    fn get_next_position(&self, position: Vector3, delta: f32) -> Vector3 {
+        self.center + Basis::from_axis_angle(self.axis, self.angular_speed * delta) * (position - self.center)
+    }
+
+    /// The radius of an orbit around a center `offset` from the spell in the spell's own directions, where the spell turns about its up direction
+    fn get_radius(offset: Vector3) -> f64 {
+        (offset.x as f64).hypot(offset.z as f64)
+    }
+}
+
+/// Fades the spell's color from `start_color` to `target_color` over `duration` seconds
+#[derive(Clone, Copy)]
+struct ColorFade {
+    start_color: Color,
+    target_color: Color,
+    duration: f64,
+    elapsed: f64
+}
+
+impl ColorFade {
+    fn get_color(&self) -> Color {
+        let weight = if self.duration <= 0.0 { 1.0 } else { (self.elapsed / self.duration).clamp(0.0, 1.0) as f32 };
+        Color {
+            r: self.start_color.r + (self.target_color.r - self.start_color.r) * weight,
+            g: self.start_color.g + (self.target_color.g - self.start_color.g) * weight,
+            b: self.start_color.b + (self.target_color.b - self.start_color.b) * weight,
+            a: self.start_color.a + (self.target_color.a - self.start_color.a) * weight
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
 /// A process is a set of instructions used in the method `physics_process`. A process keeps track of when it should run using a counter.
+/// Processes from `repeat for` sections also keep track of how many seconds of spell time they have left
+#[derive(Clone)]
 struct Process {
     counter: usize,
     frequency: usize,
+    time_remaining: Option<f64>,
+    instructions: Vec<u64>
+}
+
+/// A counted loop is a set of instructions that runs a fixed number of times when the spell is created, after the `when_created` section
+struct CountedLoop {
+    iterations: usize,
+    instructions: Vec<u64>
+}
+
+/// The instructions that come after a `delay`, which run once `remaining` seconds of spell time have passed.
+/// A delay in the delayed instructions is counted from when they run, so delays one after another add up
+#[derive(Clone)]
+struct DelayedInstructions {
+    remaining: f64,
     instructions: Vec<u64>
 }
 
+impl DelayedInstructions {
+    fn new(seconds: f64, instructions: Vec<u64>) -> Self {
+        DelayedInstructions { remaining: seconds, instructions }
+    }
+
+    /// Puts `instructions` aside for `seconds`, unless the same instructions are already waiting. This stops a delay in a section that runs every frame piling up a new wait every frame,
+    /// so what comes after it runs at most once each time the delay finishes
+    fn schedule(delayed_instructions: &mut Vec<DelayedInstructions>, seconds: f64, instructions: &[u64]) {
+        if delayed_instructions.iter().any(|delayed| delayed.instructions == instructions) {
+            return
+        }
+        delayed_instructions.push(DelayedInstructions::new(seconds, instructions.to_vec()));
+    }
+
+    /// Moves every delay along by `seconds` and removes the instructions that are due, in the order they were delayed
+    fn take_due(delayed_instructions: &mut Vec<DelayedInstructions>, seconds: f64) -> Vec<Vec<u64>> {
+        let mut due = Vec::new();
+        delayed_instructions.retain_mut(|delayed| {
+            delayed.remaining -= seconds;
+            if delayed.remaining <= 0.0 {
+                due.push(std::mem::take(&mut delayed.instructions));
+                false
+            } else {
+                true
+            }
+        });
+        due
+    }
+}
+
 impl Process {
     fn new(frequency: usize, instructions: Vec<u64>) -> Self {
-        Process { counter: 0, frequency, instructions}
+        Process { counter: 0, frequency, time_remaining: None, instructions}
+    }
+
+    fn with_duration(self, seconds: f64) -> Self {
+        Process { time_remaining: Some(seconds), ..self }
+    }
+
+    fn count_down(&mut self, seconds: f64) {
+        if let Some(ref mut time_remaining) = self.time_remaining {
+            *time_remaining -= seconds;
+        }
+    }
+
+    /// A process with no time left never runs again, so a duration of 0 means it never runs at all
+    fn is_over(&self) -> bool {
+        self.time_remaining.is_some_and(|time_remaining| time_remaining <= 0.0)
     }
 
     fn increment(&mut self) {
@@ -210,6 +814,84 @@ impl Process {
     }
 }
 
+/// A small seeded random number generator (SplitMix64) so that a spell's randomness can be repeated by using the same seed
+#[derive(Clone, Copy)]
+struct SpellRng {
+    state: u64
+}
+
+impl SpellRng {
+    fn new(seed: u64) -> Self {
+        SpellRng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number from 0 up to but not including 1
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Counts the seconds `get_time` gives, which pass `scale` times as fast as real seconds
+#[derive(Clone, Copy)]
+struct SpellClock {
+    elapsed: f64,
+    scale: f64
+}
+
+impl SpellClock {
+    fn new() -> Self {
+        SpellClock { elapsed: 0.0, scale: 1.0 }
+    }
+
+    fn advance(&mut self, delta: f64) {
+        self.elapsed += delta * self.scale;
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+/// Debug only: Measures how long the spell virtual machine takes to run in microseconds. Does nothing unless enabled
+#[derive(Default)]
+struct VmTimer {
+    enabled: bool,
+    accumulated_time_us: Option<u64>,
+    last_time_us: u64
+}
+
+impl VmTimer {
+    fn start(&self) -> Option<Instant> {
+        if self.enabled {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    fn stop(&mut self, start: Option<Instant>) {
+        if let Some(start) = start {
+            let elapsed_time_us = start.elapsed().as_micros() as u64;
+            self.accumulated_time_us = Some(self.accumulated_time_us.unwrap_or(0) + elapsed_time_us);
+        }
+    }
+
+    /// Makes the time accumulated since the last call the last time, as long as the virtual machine was run in between
+    fn finish(&mut self) {
+        if let Some(accumulated_time_us) = self.accumulated_time_us.take() {
+            self.last_time_us = accumulated_time_us;
+        }
+    }
+}
+
 struct MMSpellbook;
 
 #[gdextension]
@@ -225,6 +907,8 @@ struct Spell {
     color: Color,
     shape: Option<Shape>,
     charge_to_shape: bool,
+    spell_name: String,
+    spell_description: String,
     counter: usize,
     #[export]
     energy_lose_rate: f64,
@@ -232,58 +916,192 @@ struct Spell {
     component_catalogue: ComponentCatalogue,
     check_component_return_value: bool,
     ready_instructions: Vec<u64>,
+    ready_loops: Vec<CountedLoop>,
     process_instructions: Vec<Process>,
+    /// Runs every frame the spell overlaps a body that isn't its owner
+    collision_instructions: Vec<u64>,
     component_efficiency_levels: HashMap<u64, f64>,
 
+    /// The magical entity that cast the spell
+    caster: Option<Gd<MagicalEntity>>,
+    /// Spells only damage magical entities of other factions
+    faction: i64,
+    /// The fraction of its charged energy the caster chose to put into the spell
+    caster_energy_selected: f64,
+    /// The caster's charged energy when it last gave the spell energy, for while the caster is still bound from casting the spell
+    caster_energy_charged: f64,
+    /// The caster's focus when the spell was cast
+    caster_focus: f64,
+    /// The direction the spell was cast in, kept while the ready sections run as the caster is still bound from casting the spell then
+    cast_direction: Option<Basis>,
+
     // Component fields
     damage: f64,
-    energy_requested: f64,
+    /// The type of `damage`, from damagetypes.rs
+    damage_type: u64,
+    damage_falloff: Option<DamageFalloff>,
+    /// Where the spell was created, which damage falloff measures from once the caster is gone
+    cast_position: Vector3,
+    heal: f64,
+    heal_owner: bool,
+    /// Energy per second drained from each overlapping target and given to the caster
+    siphon_rate: f64,
+    /// How many seconds magical entities the spell targets are stunned for while they overlap it
+    stun_duration: Option<f64>,
+    /// Whether the spell drains energy from overlapping spells cast by someone else
+    absorbing: bool,
+    /// Speed per second given to overlapping spells cast by someone else in the direction the spell is going
+    spell_push: Option<f64>,
+    radial_force: Option<RadialForce>,
+    orbit: Option<Orbit>,
+    lifesteal: f64,
+    /// The energy the spell wants its caster to top it up to this frame. `recharge_to` and charging to a shape both set it, so the caster only gives what's missing once
+    energy_wanted: f64,
     original_direction: Basis,
     velocity: Vector3,
     time: Option<Gd<Time>>,
-    start_time: Option<u64>,
+    clock: SpellClock,
+    /// Time in milliseconds the spell was created at. Unlike `clock` this is never reset or scaled
+    spawn_time: Option<u64>,
+    /// Time in milliseconds at which the spell perishes
+    expiry_time: Option<u64>,
     form_set: bool,
-    anchored_to: Option<Gd<MagicalEntity>>,
+    anchored_to: Option<Gd<Node3D>>,
+    collision_layer: u32,
+    collision_mask: u32,
+    /// How far area effects look for magical entities, instead of using the spell's shape
+    detection_radius: Option<f32>,
+    beam: Option<Beam>,
+    fragmentation: Option<Fragmentation>,
+    homing: Option<Homing>,
+    color_fade: Option<ColorFade>,
+    bounce: Option<Bounce>,
+    pierce: Option<Pierce>,
+    vulnerability_mark: Option<VulnerabilityMark>,
+    familiar: Option<Familiar>,
+    anchor_follow: Option<AnchorFollow>,
+    /// How thick the shell of the spell's visible shape is when `hollow_shape` has been cast
+    hollow_thickness: Option<f64>,
+    trap: Option<Trap>,
+    /// How many spells the spell turns into at the start of the next physics frame
+    split_count: Option<usize>,
+    /// The instructions in the child section, which spells made by `spawn_child` run
+    child_instructions: Vec<u64>,
+    /// How many spells this spell was spawned through. 0 for spells that weren't made by `spawn_child`
+    generation: usize,
+    /// The fraction of energy to give each child spawned at the start of the next physics frame
+    children_requested: Vec<f64>,
+    /// Set by `delay` so that the virtual machine can put off the rest of the instructions it's running
+    delay_requested: Option<f64>,
+    delayed_instructions: Vec<DelayedInstructions>,
+    /// Instance ids of the magical entities that this spell has damaged
+    entities_hit: HashSet<InstanceId>,
+
+    vm_timer: VmTimer,
+    /// How many instructions the section that's running has run so far
+    instructions_run: usize,
+    /// If set before the spell is ready, the spell's randomness is the same every time
+    random_seed: Option<u64>,
+    rng: SpellRng,
 }
 
 #[godot_api]
 impl IArea3D for Spell {
     fn init(base: Base<Area3D>) -> Self {
+        let config = Config::get_config().unwrap_or_else(|error| {
+            godot_warn!("{}", error);
+            Config::default()
+        });
         Self {
             base,
             energy: 0.0,
-            color: DEFAULT_COLOR.into_spell_color(),
+            color: DEFAULT_COLOR.into_spell_color(config.spell_transparency),
             shape: None,
             charge_to_shape: true,
+            spell_name: String::new(),
+            spell_description: String::new(),
             counter: 0,
-            energy_lose_rate: ENERGY_LOSE_RATE,
-            config: Config::get_config().unwrap_or_else(|error| {
-                godot_warn!("{}", error);
-                Config::default()
-            }),
+            energy_lose_rate: config.energy_lose_rate,
+            config,
             component_catalogue: ComponentCatalogue::new(),
             check_component_return_value: true,
             ready_instructions: Vec::new(),
+            ready_loops: Vec::new(),
             process_instructions: Vec::new(),
+            collision_instructions: Vec::new(),
             component_efficiency_levels: HashMap::new(),
 
+            caster: None,
+            faction: NO_FACTION,
+            caster_energy_selected: 0.0,
+            caster_energy_charged: 0.0,
+            caster_focus: 1.0,
+            cast_direction: None,
+
             // Component fields
             damage: 0.0,
-            energy_requested: 0.0,
+            damage_type: UNTYPED,
+            damage_falloff: None,
+            cast_position: Vector3::ZERO,
+            heal: 0.0,
+            heal_owner: false,
+            siphon_rate: 0.0,
+            stun_duration: None,
+            absorbing: false,
+            spell_push: None,
+            radial_force: None,
+            orbit: None,
+            lifesteal: 0.0,
+            energy_wanted: 0.0,
             original_direction: Basis::default(),
             velocity: Vector3::new(0.0, 0.0, 0.0),
             time: None,
-            start_time: None,
+            clock: SpellClock::new(),
+            spawn_time: None,
+            expiry_time: None,
             form_set: false,
             anchored_to: None,
+            collision_layer: DEFAULT_SPELL_COLLISION_LAYER,
+            collision_mask: DEFAULT_SPELL_COLLISION_MASK,
+            detection_radius: None,
+            beam: None,
+            fragmentation: None,
+            homing: None,
+            color_fade: None,
+            bounce: None,
+            pierce: None,
+            vulnerability_mark: None,
+            familiar: None,
+            anchor_follow: None,
+            hollow_thickness: None,
+            trap: None,
+            split_count: None,
+            child_instructions: Vec::new(),
+            generation: 0,
+            children_requested: Vec::new(),
+            delay_requested: None,
+            delayed_instructions: Vec::new(),
+            entities_hit: HashSet::new(),
+
+            vm_timer: VmTimer::default(),
+            instructions_run: 0,
+            random_seed: None,
+            rng: SpellRng::new(0),
         }
     }
 
     fn ready(&mut self) {
+        let (collision_layer, collision_mask) = (self.collision_layer, self.collision_mask);
+        self.base_mut().set_collision_layer(collision_layer);
+        self.base_mut().set_collision_mask(collision_mask);
+        self.cast_position = self.base().get_global_position();
+
         // Starting time
         self.time = Some(Time::singleton());
         if let Some(ref time) = self.time {
-            self.start_time = Some(time.get_ticks_msec());
+            self.spawn_time = Some(time.get_ticks_msec());
+            let seed = self.random_seed.unwrap_or_else(|| time.get_ticks_usec() ^ self.base().instance_id().to_i64() as u64);
+            self.rng = SpellRng::new(seed);
         } else {
             panic!("Time not available")
         }
@@ -292,13 +1110,30 @@ impl IArea3D for Spell {
             self.perish();
         }
 
-        self.update_natural_shape();
+        // Spells split from a shaped spell start with its shape
+        self.refresh_shape();
 
         // Execute the spell and get the result
         let spell_result = {
             let instructions = std::mem::take(&mut self.ready_instructions);
-            let result = self.spell_virtual_machine(&instructions);
+            self.cast_direction = Some(self.original_direction);
+            let start = self.vm_timer.start();
+            let mut result = self.spell_virtual_machine(&instructions);
             self.ready_instructions = instructions;
+
+            // The counted loops run in the same go as when_created, so they share its instruction budget and each time around one counts as an instruction
+            let ready_loops = std::mem::take(&mut self.ready_loops);
+            if result.is_ok() {
+                result = Spell::run_counted_loops(self, &ready_loops, |spell| spell.energy >= ENERGY_CONSIDERATION_LEVEL, |spell, instructions| {
+                    Spell::count_instruction(&mut spell.instructions_run, spell.config.instruction_budget)?;
+                    spell.run_instructions(instructions)
+                });
+            }
+            self.ready_loops = ready_loops;
+            self.cast_direction = None;
+
+            self.vm_timer.stop(start);
+            self.vm_timer.finish();
             result
         };
 
@@ -315,37 +1150,131 @@ impl IArea3D for Spell {
     }
 
     fn physics_process(&mut self, delta: f64) {
-        // Handle velocity
-        if let Some(ref mut anchored_to) = self.anchored_to {
-            let direction = (self.original_direction * self.velocity).normalized_or_zero();
-            let mut bound_parent = anchored_to.bind_mut();
-            bound_parent.give_external_velocity(direction * self.velocity.length());
-            self.velocity = Vector3::ZERO;
-        } else {
-            let f32_delta: f32 = delta as f32;
-            let previous_position = self.base_mut().get_global_position();
-            let direction = (self.original_direction * self.velocity).normalized_or_zero();
-            let new_position = previous_position + direction * self.velocity.length() * f32_delta;
-            self.base_mut().set_global_position(new_position);
+        // Perish if the spell's lifetime is over
+        if let (Some(expiry_time), Some(ref time)) = (self.expiry_time, &self.time) {
+            if time.get_ticks_msec() >= expiry_time {
+                self.perish();
+                return
+            }
         }
 
-        // Reduces energy due to anchor if there is one
-        if !self.surmount_anchor_resistance() {
-            self.perish();
-            return
+        self.clock.advance(delta);
+
+        // Handle split. Splitting waits until now because the caster is bound while the spell is made
+        if let Some(count) = self.split_count.take() {
+            self.split(count);
         }
 
-        // Handle instructions
-        let mut instructions = std::mem::take(&mut self.process_instructions);
-        for process in instructions.iter_mut() {
-            process.increment();
+        // Handle spawn child, which waits for the same reason
+        for energy_fraction in std::mem::take(&mut self.children_requested) {
+            self.spawn_child(energy_fraction);
+        }
 
-            if !process.should_run() { continue };
+        // Handle color fade
+        if self.color_fade.is_some() {
+            self.update_color_fade(delta);
+        }
 
-            match self.spell_virtual_machine(&process.instructions) {
-                Ok(()) => {},
-                Err(_) => self.perish()
-            }
+        // Handle trap
+        if self.trap.is_some() && !self.update_trap() {
+            return
+        }
+
+        // Handle homing
+        if self.homing.is_some() && self.anchored_to.is_none() && !self.update_homing(delta) {
+            return
+        }
+
+        // Handle velocity
+        if self.trap.is_some() {
+            // Traps stay where they were placed
+            self.velocity = Vector3::ZERO;
+        } else if self.familiar.is_some() {
+            if !self.update_familiar(delta) {
+                return
+            }
+        } else if self.anchor_follow.is_some() {
+            if !self.update_anchor_follow(delta) {
+                return
+            }
+        } else if let Some(anchored_to) = self.anchored_to.clone() {
+            if anchored_to.is_instance_valid() {
+                // Only magical entities can be pushed by the spells anchored to them, other nodes are just followed
+                if let Ok(mut magical_entity) = anchored_to.clone().try_cast::<MagicalEntity>() {
+                    let direction = (self.original_direction * self.velocity).normalized_or_zero();
+                    let mut bind_magical_entity = magical_entity.bind_mut();
+                    let push = Spell::get_anchored_push(direction * self.velocity.length(), bind_magical_entity.get_mass());
+                    bind_magical_entity.give_external_velocity(push);
+                }
+                self.velocity = Vector3::ZERO;
+                self.base_mut().set_global_position(anchored_to.get_global_position());
+            }
+        } else if let Some(orbit) = self.orbit {
+            // Orbiting replaces moving with velocity
+            let position = self.base().get_global_position();
+            self.base_mut().set_global_position(orbit.get_next_position(position, delta as f32));
+        } else {
+            let f32_delta: f32 = delta as f32;
+            let previous_position = self.base_mut().get_global_position();
+            let direction = (self.original_direction * self.velocity).normalized_or_zero();
+            let mut new_position = previous_position + direction * self.velocity.length() * f32_delta;
+
+            // Fragments if the spell would move through a wall
+            if let Some(fragmentation) = self.fragmentation {
+                if let Some(collision) = self.intersect_ray(previous_position, new_position) {
+                    if Spell::is_wall(&collision) {
+                        let impact_position = collision.get("position").map_or(new_position, |position| position.to::<Vector3>());
+                        let normal = collision.get("normal").map_or(-direction, |normal| normal.to::<Vector3>());
+                        self.fragment(fragmentation, impact_position, normal);
+                        return
+                    }
+                }
+            }
+
+            // Reflects off walls in the way
+            if let Some(bounce) = self.bounce {
+                match self.move_with_bounce(bounce, previous_position, f32_delta) {
+                    Some(position) => new_position = position,
+                    None => return
+                }
+            }
+
+            self.base_mut().set_global_position(new_position);
+        }
+
+        // Handle beam
+        if self.beam.is_some() && !self.update_beam(delta) {
+            return
+        }
+
+        // Reduces energy due to anchor if there is one
+        if !self.surmount_anchor_resistance() {
+            self.perish();
+            return
+        }
+
+        // Handle delayed instructions. These are only checked here, so they never run in the same frame they were delayed in
+        if !self.delayed_instructions.is_empty() && !self.run_delayed_instructions(delta) {
+            return
+        }
+
+        // Handle instructions. Timed processes count down in spell time, so they run for longer when the time scale is lowered
+        let mut instructions = std::mem::take(&mut self.process_instructions);
+        instructions.retain(|process| !process.is_over());
+        for process in instructions.iter_mut() {
+            process.increment();
+            process.count_down(delta * self.clock.scale);
+
+            if !process.should_run() { continue };
+
+            let start = self.vm_timer.start();
+            let result = self.spell_virtual_machine(&process.instructions);
+            self.vm_timer.stop(start);
+
+            match result {
+                Ok(()) => {},
+                Err(_) => self.perish()
+            }
 
             // Check if spell should be deleted due to lack of energy
             if self.energy < ENERGY_CONSIDERATION_LEVEL {
@@ -354,87 +1283,135 @@ impl IArea3D for Spell {
         }
         self.process_instructions = instructions;
 
-        // Deal damage
-        if self.damage != 0.0 && self.anchored_to == None {
-            let mut objects = self.base().get_overlapping_bodies();
+        // Handle collision instructions, which run after the repeat sections
+        if !self.collision_instructions.is_empty() && self.is_colliding() {
+            let instructions = std::mem::take(&mut self.collision_instructions);
+            let start = self.vm_timer.start();
+            let result = self.spell_virtual_machine(&instructions);
+            self.vm_timer.stop(start);
+            self.collision_instructions = instructions;
+
+            match result {
+                Ok(()) => {},
+                Err(_) => self.perish()
+            }
 
-            for area in self.base().get_overlapping_areas().iter_shared() {
-                objects.push(&area.upcast());
+            if self.energy < ENERGY_CONSIDERATION_LEVEL {
+                self.perish();
             }
+        }
+        self.vm_timer.finish();
 
-            let mut number_of_magical_entities: usize = 0;
+        // Deal damage. Traps only deal damage when they go off
+        let spell_damage = self.get_effective_damage();
+        if spell_damage != 0.0 && self.anchored_to == None && self.trap.is_none() {
+            let objects = self.get_overlapping_objects();
 
-            for object in objects.iter_shared() {
-                if let Ok(magical_entity_object) = object.clone().try_cast::<MagicalEntity>() {
-                    let bind_magical_entity = magical_entity_object.bind();
-                    if !bind_magical_entity.owns_spell(self.to_gd()) {
-                        number_of_magical_entities += 1;
-                    }
-                } else if let Ok(spell) = object.clone().try_cast::<Spell>() {
-                    let self_parent = self.to_gd().get_parent();
-                    let spell_parent = spell.get_parent();
+            // Damage is only split between magical entities. Spells clash at full damage
+            let number_of_magical_entities = self.count_targets(&objects);
 
-                    // If either spell has no parent, or they have different parents, proceed with damage
-                    if self_parent.is_none() || spell_parent.is_none() || self_parent != spell_parent {
-                        number_of_magical_entities += 1;
-                    }
-                }
-            }
+            let instance_id = self.base().instance_id();
 
             for object in objects.iter_shared() {
                 if let Ok(mut magical_entity_object) = object.clone().try_cast::<MagicalEntity>() {
+                    let magical_entity_id = magical_entity_object.instance_id();
                     let mut bind_magical_entity = magical_entity_object.bind_mut();
-                    if !bind_magical_entity.owns_spell(self.to_gd()) {
+                    if self.is_target(&bind_magical_entity) {
+                        // A deflected spell doesn't damage anything else that frame
+                        if bind_magical_entity.use_deflect_charge() {
+                            self.deflect(&mut bind_magical_entity, magical_entity_object.clone());
+                            break
+                        }
+
+                        self.entities_hit.insert(magical_entity_id);
+
                         // Damage is split among magical_entities
-                        let damage = self.damage / number_of_magical_entities as f64;
+                        let damage = spell_damage / number_of_magical_entities as f64;
 
                         // Code ensures energy used is at max the magic_entities health and that if it can't do damage specified it does as much of that damage as it can before destroying itself
                         let possible_damage = damage.min(bind_magical_entity.get_energy_to_kill());
 
                         if self.energy - possible_damage < ENERGY_CONSIDERATION_LEVEL {
-                            bind_magical_entity.take_damage(self.energy);
+                            bind_magical_entity.take_damage(self.energy, self.damage_type);
+                            self.apply_lifesteal(self.energy);
                             self.perish();
                             return;
                         }
 
                         self.energy -= possible_damage;
 
-                        bind_magical_entity.take_damage(possible_damage);
-                    }
-                } else if let Ok(mut spell) = object.clone().try_cast::<Spell>() {
-                    let self_parent = self.to_gd().get_parent();
-                    let spell_parent = spell.get_parent();
-
-                    // If either spell has no parent, or they have different parents, proceed with damage
-                    if self_parent.is_none() || spell_parent.is_none() || self_parent != spell_parent {
-                        let mut spell_bind = spell.bind_mut();
-
-                        let damage = self.damage / number_of_magical_entities as f64;
+                        bind_magical_entity.take_damage(possible_damage, self.damage_type);
+                        self.apply_vulnerability(&mut bind_magical_entity);
+                        self.apply_lifesteal(possible_damage);
 
-                        let possible_damage = damage.min(spell_bind.energy);
-
-                        if self.energy - possible_damage < ENERGY_CONSIDERATION_LEVEL {
-                            spell_bind.take_damage(self.energy);
+                        if self.is_done_piercing() {
                             self.perish();
                             return;
                         }
+                    }
+                } else if let Ok(mut spell) = object.clone().try_cast::<Spell>() {
+                    let spell_id = spell.instance_id();
+                    let mut spell_bind = spell.bind_mut();
+                    if !self.is_clashing_with(&spell_bind) {
+                        continue
+                    }
 
-                        self.energy -= possible_damage;
+                    // When both spells deal damage, only the one with the lower instance id works out the clash so that it only happens once a frame
+                    if spell_bind.can_clash() && spell_id < instance_id {
+                        continue
+                    }
+
+                    let other_damage = if spell_bind.can_clash() { spell_bind.get_effective_damage() } else { 0.0 };
+                    let (energy_lost, other_energy_lost) = Spell::get_clash_losses(self.energy, spell_damage, spell_bind.energy, other_damage);
+                    spell_bind.take_damage(other_energy_lost);
+                    self.energy -= energy_lost;
 
-                        spell_bind.take_damage(possible_damage);
+                    if self.energy < ENERGY_CONSIDERATION_LEVEL {
+                        self.perish();
+                        return;
                     }
                 }
             }
         }
 
+        // Heal
+        if self.heal != 0.0 && self.anchored_to == None {
+            self.heal_overlapping_entities();
+        }
+
+        // Stun
+        if self.stun_duration.is_some() && self.anchored_to == None && !self.stun_overlapping_entities(delta) {
+            return
+        }
+
+        // Siphon energy to the caster
+        if self.siphon_rate > 0.0 && !self.siphon_to_caster(delta) {
+            return
+        }
+
+        // Absorb energy from overlapping spells
+        if self.absorbing && !self.absorb_overlapping_spells(delta) {
+            return
+        }
+
+        // Push or pull nearby magical entities
+        if self.radial_force.is_some() && !self.apply_radial_force(delta) {
+            return
+        }
+
+        // Push overlapping spells
+        if self.spell_push.is_some() && !self.push_overlapping_spells(delta) {
+            return
+        }
+
         // Handle energy lose
-        self.energy -= self.energy * self.energy_lose_rate * delta;
+        self.energy = Spell::get_decayed_energy(self.energy, self.energy_lose_rate, delta);
 
         // Makes the energy to the spell match the energy needed for the shape
         self.handle_charge_to_shape();
 
         // Decreases the radius of the sphere if form isn't set
-        if self.shape.is_none() && self.anchored_to.is_none() && self.counter == 0 {
+        if self.shape.is_none() && self.anchored_to.is_none() && self.beam.is_none() && self.counter == 0 {
             self.update_natural_shape();
         }
 
@@ -449,150 +1426,396 @@ impl IArea3D for Spell {
 
 
 impl Spell {
-    fn spell_virtual_machine(&mut self, instructions: &[u64]) -> Result<(), &'static str> {
+    /// Runs a section, or the part of one left after a delay, with a fresh instruction budget
+    fn spell_virtual_machine(&mut self, instructions: &[u64]) -> Result<(), SpellError> {
+        self.instructions_run = 0;
+        self.run_instructions(instructions)
+    }
+
+    fn run_instructions(&mut self, instructions: &[u64]) -> Result<(), SpellError> {
         let mut instructions_iter = instructions.iter();
         while let Some(&bits) = instructions_iter.next() {
+            Spell::count_instruction(&mut self.instructions_run, self.config.instruction_budget)?;
             match bits {
                 END_OF_SCOPE => {}, // 0 = end of scope, if reached naturely, move on
                 COMPONENT => { // 103 = component
                     self.execute_component(&mut instructions_iter)?;
+
+                    // Everything after a delay is put aside to run later. Inside a while loop, only the rest of that time around the loop is put aside
+                    if let Some(seconds) = self.delay_requested.take() {
+                        DelayedInstructions::schedule(&mut self.delayed_instructions, seconds, instructions_iter.as_slice());
+                        return Ok(())
+                    }
                 },
                 IF => { // 400 = if statement
-                    let mut rpn_stack: Vec<u64> = Vec::new();
-                    while let Some(&if_bits) = instructions_iter.next() {
-                        match if_bits {
-                            END_OF_SCOPE => break,
-                            TRUE | FALSE => rpn_stack.push(if_bits), // true and false
-                            NUMBER_LITERAL => rpn_stack.extend(vec![NUMBER_LITERAL, *instructions_iter.next().expect("Expected following value")]), // if 102, next bits are a number literal
-                            COMPONENT => rpn_stack.extend(self.execute_component(&mut instructions_iter)?), // Component
-                            AND => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::and).unwrap_or_else(|err| panic!("{}", err)), // And statement
-                            OR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::or).unwrap_or_else(|err| panic!("{}", err)), // Or statement
-                            NOT => { // Not statement
-                                let bool_one = rpn_stack.pop().expect("Expected value to compare");
-                                rpn_stack.push(boolean_logic::not(bool_one).unwrap_or_else(|err| panic!("{}", err)));
-                            },
-                            XOR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::xor).unwrap_or_else(|err| panic!("{}", err)), // Xor statement
-                            EQUALS => { // Equals statement
-                                let argument_two = rpn_stack.pop().expect("Expected value to compare");
-                                let opcode_or_bool = rpn_stack.pop().expect("Expected value to compare");
-                                if opcode_or_bool == NUMBER_LITERAL {
-                                    let argument_one = f64::from_bits(rpn_stack.pop().expect("Expected value to compare"));
-                                    let _ = rpn_stack.pop().expect("Expected number literal opcode");
-                                    if argument_one == f64::from_bits(argument_two) {
-                                        rpn_stack.push(TRUE);
-                                    } else {
-                                        rpn_stack.push(FALSE);
-                                    }
-                                } else {
-                                    if opcode_or_bool == argument_two {
-                                        rpn_stack.push(TRUE);
-                                    } else {
-                                        rpn_stack.push(FALSE);
-                                    }
-                                }
-                            },
-                            GREATER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a > b).unwrap_or_else(|err| panic!("{}", err)), // Greater than
-                            LESSER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a < b).unwrap_or_else(|err| panic!("{}", err)), // Lesser than
-                            MULTIPLY => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a * b).unwrap_or_else(|err| panic!("{}", err)), // Multiply
-                            DIVIDE => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a / b).unwrap_or_else(|err| panic!("{}", err)), // Divide
-                            ADD => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a + b).unwrap_or_else(|err| panic!("{}", err)), // Add
-                            SUBTRACT => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a - b).unwrap_or_else(|err| panic!("{}", err)), // Subtract
-                            POWER => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a.powf(b)).unwrap_or_else(|err| panic!("{}", err)), // Power
-                            _ => panic!("Opcode doesn't exist")
-                        };
-                    }
-                    match rpn_stack.pop().expect("Expected final bool") {
+                    let mut rpn_stack = self.evaluate_logic(&mut instructions_iter)?;
+                    match rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected final bool"))? {
                         TRUE => {}, // if true, execute by going back into normal loop
-                        FALSE => { // if false, skip to the end of scope
-                            let mut skip_amount: usize = 1;
-                            while let Some(&skipping_bits) = instructions_iter.next() {
-                                match skipping_bits {
-                                    END_OF_SCOPE => skip_amount -= 1, // If end of scope
-                                    NUMBER_LITERAL => _ = instructions_iter.next(), // Ignores number literals
-                                    COMPONENT => {
-                                        self.skip_component(&mut instructions_iter);
-                                    }
-                                    IF => skip_amount += 2, // Ignore next two end of scopes because if statements have two end of scopes
-                                    _ => {}
-                                }
-                                if skip_amount == 0 {
-                                    break;
-                                }
-                            }
-                        }
-                        _ => panic!("Expected bool")
+                        FALSE => Spell::skip_scope(&mut instructions_iter)?, // if false, skip to the end of scope
+                        _ => return Err(SpellError::BadParameterType("Expected bool"))
                     };
                 },
-                _ => panic!("Not valid opcode")
+                WHILE => {
+                    // The condition is evaluated again before each time the contents run, so the contents are run as their own instructions
+                    let condition = instructions_iter.as_slice();
+                    Spell::skip_logic(&mut instructions_iter)?;
+                    let contents_and_rest = instructions_iter.as_slice();
+                    Spell::skip_scope(&mut instructions_iter)?;
+                    let contents = &contents_and_rest[..contents_and_rest.len() - instructions_iter.as_slice().len()];
+
+                    Spell::run_while_loop(self, |spell| {
+                        match spell.evaluate_logic(&mut condition.iter())?.pop().ok_or(SpellError::InvalidInstructions("Expected final bool"))? {
+                            TRUE => Ok(true),
+                            FALSE => Ok(false),
+                            _ => Err(SpellError::BadParameterType("Expected bool"))
+                        }
+                    }, |spell| spell.run_instructions(contents))?;
+                },
+                _ => return Err(SpellError::InvalidInstructions("Not valid opcode"))
             }
         }
         Ok(())
     }
 
-    fn skip_component<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>) {
-        let component_code = instructions_iter.next().expect("Expected component");
-        let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code);
-        for _ in 0..number_of_component_parameters {
-            let parameter = *instructions_iter.next().expect("Expected parameter");
-            match parameter {
-                TRUE | FALSE => {},
-                NUMBER_LITERAL => _ = *instructions_iter.next().expect("Expected number after number literal opcode"),
-                COMPONENT => _ = self.execute_component(instructions_iter),
-                _ => panic!("Invalid parameter skipped")
+    /// Counts an instruction against the budget, failing once more than `instruction_budget` have run
+    fn count_instruction(instructions_run: &mut usize, instruction_budget: usize) -> Result<(), SpellError> {
+        *instructions_run += 1;
+        if *instructions_run > instruction_budget {
+            return Err(SpellError::InstructionBudgetExceeded)
+        }
+        Ok(())
+    }
+
+    /// Runs the delayed instructions that are due after `delta` seconds, which pass as fast as `get_time` does. Returns false if the spell perished
+    fn run_delayed_instructions(&mut self, delta: f64) -> bool {
+        let due = DelayedInstructions::take_due(&mut self.delayed_instructions, delta * self.clock.scale);
+
+        for instructions in due {
+            let start = self.vm_timer.start();
+            let result = self.spell_virtual_machine(&instructions);
+            self.vm_timer.stop(start);
+
+            if result.is_err() || self.energy < ENERGY_CONSIDERATION_LEVEL {
+                self.perish();
+                return false
+            }
+        }
+        true
+    }
+
+    /// Evaluates RPN logic up to the end of scope and returns what is left on the stack
+    fn evaluate_logic<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, SpellError> {
+        let tokens = Spell::read_logic(instructions_iter)?;
+        Spell::evaluate_rpn(&tokens, self, |spell, component| {
+            let component_return = spell.execute_component(&mut component.iter())?;
+            if Spell::split_return_values(&component_return)?.len() > 1 {
+                return Err(SpellError::BadParameterType("Vectors can only be used as the parameters of components"))
+            }
+            Ok(component_return)
+        })
+    }
+
+    /// Splits RPN logic up to the end of scope into its values and operators. Components are kept whole, along with their parameters
+    fn read_logic<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<Vec<u64>>, SpellError> {
+        let mut tokens: Vec<Vec<u64>> = Vec::new();
+        while let Some(&bits) = instructions_iter.next() {
+            match bits {
+                END_OF_SCOPE => break,
+                NUMBER_LITERAL => tokens.push(vec![NUMBER_LITERAL, *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected following value"))?]),
+                COMPONENT => {
+                    let mut token = vec![COMPONENT];
+                    Spell::skip_component(&mut instructions_iter.by_ref().inspect(|&&component_bits| token.push(component_bits)))?;
+                    tokens.push(token);
+                },
+                _ => tokens.push(vec![bits])
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Returns how many values an opcode in RPN logic takes off the stack
+    fn get_operand_count(opcode: u64) -> usize {
+        match opcode {
+            TRUE | FALSE | NUMBER_LITERAL | COMPONENT => 0,
+            NOT | SQRT | ABS | FLOOR | CEIL | ROUND | SIN | COS => 1,
+            CLAMP => 3,
+            _ => 2
+        }
+    }
+
+    /// For each token that starts the right side of an `and` or `or`, returns the index of that operator and the operator itself
+    fn get_short_circuits(tokens: &[Vec<u64>]) -> Result<Vec<Option<(usize, u64)>>, SpellError> {
+        let mut short_circuits: Vec<Option<(usize, u64)>> = vec![None; tokens.len()];
+        // The index of the first token of the value that each token finishes
+        let mut starts: Vec<usize> = Vec::with_capacity(tokens.len());
+        for (index, token) in tokens.iter().enumerate() {
+            let mut start = index;
+            let mut right_start = index;
+            for operand in 0..Spell::get_operand_count(token[0]) {
+                start = starts[start.checked_sub(1).ok_or(SpellError::InvalidInstructions("Expected value to compare"))?];
+                if operand == 0 {
+                    right_start = start;
+                }
+            }
+            if matches!(token[0], AND | OR) {
+                short_circuits[right_start] = Some((index, token[0]));
+            }
+            starts.push(start);
+        }
+        Ok(short_circuits)
+    }
+
+    /// Evaluates tokens from `read_logic`, using `execute_component` to get the value of each component.
+    /// The right side of an `and` isn't evaluated when the left side is false, and the right side of an `or` isn't evaluated when the left side is true, so the components there aren't cast and don't use energy
+    fn evaluate_rpn<T>(tokens: &[Vec<u64>], state: &mut T, mut execute_component: impl FnMut(&mut T, &[u64]) -> Result<Vec<u64>, SpellError>) -> Result<Vec<u64>, SpellError> {
+        let short_circuits = Spell::get_short_circuits(tokens)?;
+        let mut rpn_stack: Vec<u64> = Vec::new();
+        let mut index = 0;
+        while let Some(token) = tokens.get(index) {
+            if let Some((operator_index, operator)) = short_circuits[index] {
+                let deciding_value = if operator == AND { FALSE } else { TRUE };
+                // The left side is already on the stack and is the result, so the right side and the operator are skipped
+                if rpn_stack.last() == Some(&deciding_value) {
+                    index = operator_index + 1;
+                    continue
+                }
+            }
+            index += 1;
+
+            match token[0] {
+                TRUE | FALSE => rpn_stack.push(token[0]), // true and false
+                NUMBER_LITERAL => rpn_stack.extend_from_slice(token), // Number literal
+                COMPONENT => rpn_stack.extend(execute_component(state, &token[1..])?), // Component
+                AND => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::and)?, // And statement
+                OR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::or)?, // Or statement
+                NOT => { // Not statement
+                    let bool_one = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                    rpn_stack.push(boolean_logic::not(bool_one)?);
+                },
+                XOR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::xor)?, // Xor statement
+                EQUALS => { // Equals statement
+                    let argument_two = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                    let opcode_or_bool = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                    if opcode_or_bool == NUMBER_LITERAL {
+                        let argument_one = f64::from_bits(rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?);
+                        let _ = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected number literal opcode"))?;
+                        if argument_one == f64::from_bits(argument_two) {
+                            rpn_stack.push(TRUE);
+                        } else {
+                            rpn_stack.push(FALSE);
+                        }
+                    } else {
+                        if opcode_or_bool == argument_two {
+                            rpn_stack.push(TRUE);
+                        } else {
+                            rpn_stack.push(FALSE);
+                        }
+                    }
+                },
+                GREATER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a > b)?, // Greater than
+                LESSER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a < b)?, // Lesser than
+                GREATER_THAN_OR_EQUAL => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a >= b)?, // Greater than or equal
+                LESSER_THAN_OR_EQUAL => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a <= b)?, // Lesser than or equal
+                MULTIPLY => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a * b)?, // Multiply
+                DIVIDE => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a / b)?, // Divide
+                MODULO => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a % b)?, // Remainder, which is NaN when dividing by zero
+                ADD => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a + b)?, // Add
+                SUBTRACT => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a - b)?, // Subtract
+                POWER => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a.powf(b))?, // Power
+                SQRT => rpn_operations::unary_maths_operation(&mut rpn_stack, rpn_operations::square_root)?, // Square root
+                ABS => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.abs()))?, // Absolute value
+                FLOOR => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.floor()))?, // Round down
+                CEIL => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.ceil()))?, // Round up
+                ROUND => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.round()))?, // Round to nearest, halves away from zero
+                SIN => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.sin()))?, // Sine, in radians
+                COS => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.cos()))?, // Cosine, in radians
+                MIN => rpn_operations::maths_operation(&mut rpn_stack, f64::min)?, // Minimum
+                MAX => rpn_operations::maths_operation(&mut rpn_stack, f64::max)?, // Maximum
+                CLAMP => rpn_operations::ternary_maths_operation(&mut rpn_stack, |value, low, high| value.max(low).min(high))?, // Clamp, which gives high when low is bigger than high rather than panicking like f64::clamp
+                _ => return Err(SpellError::InvalidInstructions("Opcode doesn't exist"))
             };
         }
+        Ok(rpn_stack)
+    }
+
+    fn skip_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+        let component_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+        Spell::skip_component_parameters(component_code, instructions_iter)
+    }
+
+    fn skip_component_parameters<'a>(component_code: &u64, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+        let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code)?;
+        let mut parameter_number = 0;
+        while parameter_number < number_of_component_parameters {
+            parameter_number += Spell::skip_parameter(instructions_iter)?;
+        }
+        Ok(())
+    }
+
+    /// Moves past one parameter and returns how many of the component's parameters it fills
+    fn skip_parameter<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<usize, SpellError> {
+        match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
+            TRUE | FALSE => {},
+            NUMBER_LITERAL => _ = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?,
+            COMPONENT => {
+                let component_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+                Spell::skip_component_parameters(component_code, instructions_iter)?;
+                return Spell::get_parameter_width(component_code)
+            },
+            EXPRESSION => Spell::skip_logic(instructions_iter)?,
+            _ => return Err(SpellError::InvalidInstructions("Invalid parameter skipped"))
+        };
+        Ok(1)
+    }
+
+    /// Moves past the contents of an if statement or while loop, up to and including its end of scope
+    fn skip_scope<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+        let mut skip_amount: usize = 1;
+        while let Some(&skipping_bits) = instructions_iter.next() {
+            match skipping_bits {
+                END_OF_SCOPE => skip_amount -= 1, // If end of scope
+                NUMBER_LITERAL => _ = instructions_iter.next(), // Ignores number literals
+                COMPONENT => {
+                    Spell::skip_component(instructions_iter)?;
+                }
+                IF | WHILE => skip_amount += 2, // Ignore next two end of scopes because if statements and while loops have two end of scopes
+                _ => {}
+            }
+            if skip_amount == 0 {
+                return Ok(())
+            }
+        }
+        Err(SpellError::InvalidInstructions("Expected end of scope"))
+    }
+
+    /// Runs the instructions of each `repeat n times` loop as many times as it says, in order. Each iteration pays for its own components,
+    /// so the loops stop as soon as `has_energy` is false, leaving the spell to perish part way through, or as soon as an iteration fails
+    fn run_counted_loops<T>(state: &mut T, counted_loops: &[CountedLoop], mut has_energy: impl FnMut(&mut T) -> bool, mut contents: impl FnMut(&mut T, &[u64]) -> Result<(), SpellError>) -> Result<(), SpellError> {
+        for counted_loop in counted_loops {
+            for _ in 0..counted_loop.iterations {
+                if !has_energy(state) {
+                    return Ok(())
+                }
+                contents(state, &counted_loop.instructions)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `contents` for as long as `condition` is true, giving up once it has run `MAX_WHILE_ITERATIONS` times
+    fn run_while_loop<T>(state: &mut T, mut condition: impl FnMut(&mut T) -> Result<bool, SpellError>, mut contents: impl FnMut(&mut T) -> Result<(), SpellError>) -> Result<(), SpellError> {
+        let mut iterations: usize = 0;
+        while condition(state)? {
+            if iterations == MAX_WHILE_ITERATIONS {
+                return Err(SpellError::TooManyIterations)
+            }
+            contents(state)?;
+            iterations += 1;
+        }
+        Ok(())
+    }
+
+    /// Moves past RPN logic up to the end of scope without evaluating it
+    fn skip_logic<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+        while let Some(&bits) = instructions_iter.next() {
+            match bits {
+                END_OF_SCOPE => break,
+                NUMBER_LITERAL => _ = instructions_iter.next(),
+                COMPONENT => Spell::skip_component(instructions_iter)?,
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
-    fn execute_component<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, &'static str> {
-        let component_code = instructions_iter.next().expect("Expected component");
-        let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code);
+    fn execute_component<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, SpellError> {
+        let component_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+        let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code)?;
         let mut parameters: Vec<u64> = Vec::new();
-        for parameter_number in 0..number_of_component_parameters {
-            let parameter = *instructions_iter.next().expect("Expected parameter");
+        let mut parameter_number = 0;
+        while parameter_number < number_of_component_parameters {
+            let parameter = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))?;
             match parameter {
                 TRUE | FALSE => parameters.push(parameter),
                 NUMBER_LITERAL => {
                     parameters.push(parameter);
-                    parameters.push(*instructions_iter.next().expect("Expected number after number literal opcode"));
+                    parameters.push(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?);
                 },
                 COMPONENT => {
                     let component_return = self.execute_component(instructions_iter)?;
+                    // A vector fills one parameter for each of its values
+                    let values = Spell::split_return_values(&component_return)?;
+                    if values.is_empty() {
+                        return Err(SpellError::InvalidInstructions("Expected return from component"))
+                    } else if parameter_number + values.len() > number_of_component_parameters {
+                        return Err(SpellError::InvalidInstructions("Component returned more values than there are parameters left"))
+                    }
                     // Checks if component return is an allowed parameter as it can't be known at compile time
                     if self.check_component_return_value {
-                        let allowed_parameters_list: &Vec<Vec<u64>> = self.component_catalogue.component_catalogue.get(&component_code.to_godot()).ok_or("Component isn't in component catalogue")?;
-                        Spell::check_if_parameter_allowed(&component_return, &allowed_parameters_list[parameter_number])?;
+                        let allowed_parameters_list: &Vec<Vec<u64>> = self.component_catalogue.component_catalogue.get(&component_code.to_godot()).ok_or(SpellError::NotAllowed("Component isn't in component catalogue"))?;
+                        for (offset, value) in values.iter().enumerate() {
+                            Spell::check_if_parameter_allowed(value, &allowed_parameters_list[parameter_number + offset])?;
+                        }
                     }
                     parameters.extend(component_return);
+                    parameter_number += values.len();
+                    continue
+                },
+                EXPRESSION => {
+                    let expression_result = self.evaluate_logic(instructions_iter)?;
+                    match expression_result[..] {
+                        [TRUE] | [FALSE] | [NUMBER_LITERAL, _] => {},
+                        _ => return Err(SpellError::InvalidInstructions("Expression should result in a single value"))
+                    }
+                    // Checks if expression result is an allowed parameter as it can't be known at compile time
+                    if self.check_component_return_value {
+                        let allowed_parameters_list: &Vec<Vec<u64>> = self.component_catalogue.component_catalogue.get(&component_code.to_godot()).ok_or(SpellError::NotAllowed("Component isn't in component catalogue"))?;
+                        Spell::check_if_parameter_allowed(&expression_result, &allowed_parameters_list[parameter_number])?;
+                    }
+                    parameters.extend(expression_result);
                 },
-                _ => panic!("Invalid parameter")
+                _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
             }
+            parameter_number += 1;
         }
 
         return self.call_component(component_code, parameters)
     }
 
-    fn call_component(&mut self, component_code: &u64, parameters: Vec<u64>) -> Result<Vec<u64>, &'static str> {
+    /// Splits what a component returned into its separate values, such as the three floats of a vector
+    fn split_return_values(component_return: &[u64]) -> Result<Vec<Vec<u64>>, SpellError> {
+        let mut values: Vec<Vec<u64>> = Vec::new();
+        let mut return_iter = component_return.iter();
+        while let Some(&bits) = return_iter.next() {
+            match bits {
+                TRUE | FALSE => values.push(vec![bits]),
+                NUMBER_LITERAL => values.push(vec![NUMBER_LITERAL, *return_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?]),
+                _ => return Err(SpellError::BadParameterType("Invalid return: isn't float or boolean"))
+            }
+        }
+        Ok(values)
+    }
+
+    fn call_component(&mut self, component_code: &u64, parameters: Vec<u64>) -> Result<Vec<u64>, SpellError> {
         // Removes number literal opcodes
         let mut compressed_parameters: Vec<u64> = Vec::new();
         let mut parameter_iter = parameters.iter();
         while let Some(&parameter) = parameter_iter.next() {
             match parameter {
-                NUMBER_LITERAL => compressed_parameters.push(*parameter_iter.next().expect("Expected parameter after number literal opcode")),
+                NUMBER_LITERAL => compressed_parameters.push(*parameter_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter after number literal opcode"))?),
                 TRUE | FALSE => compressed_parameters.push(parameter),
-                _ => panic!("Invalid parameter: isn't float or boolean")
+                _ => return Err(SpellError::BadParameterType("Invalid parameter: isn't float or boolean"))
             }
         }
 
         // Getting component cast count
         if let Some((function, _, _)) = COMPONENT_TO_FUNCTION_MAP.get(&component_code) {
-            let mut component_efficiency_level = self.component_efficiency_levels.entry(*component_code).or_insert(1.0).clone();
+            let mut component_efficiency_level = self.component_efficiency_levels.entry(*component_code).or_insert(DEFAULT_EFFICIENCY_LEVEL).clone();
 
             // Getting energy required
-            if let Some(base_energy_bits) = function(self, &compressed_parameters, false) {
-                let base_energy = f64::from_bits(*base_energy_bits.first().expect("Expected energy useage return"));
-                // Getting efficiency from component_efficiency_level
-                let efficiency = component_efficiency_level / (component_efficiency_level + EFFICIENCY_INCREASE_RATE);
-                let energy_needed = base_energy / efficiency;
+            if let Some(base_energy_bits) = function(self, &compressed_parameters, false)? {
+                let base_energy = f64::from_bits(*base_energy_bits.first().ok_or(SpellError::Internal("Expected energy useage return"))?);
+                let energy_needed = Spell::get_energy_needed(base_energy, component_efficiency_level, self.config.efficiency_increase_rate);
                 if self.energy >= energy_needed {
                     self.energy -= energy_needed;
 
@@ -604,35 +1827,157 @@ impl Spell {
                     // Emit signal to say component has been cast
                     self.emit_component_cast(*component_code, efficiency_increase);
 
-                    if let Some(value) = function(self, &compressed_parameters, true) {
+                    if let Some(value) = function(self, &compressed_parameters, true)? {
                         return Ok(value)
                     } else {
                         return Ok(Vec::new())
                     }
                 } else {
-                    return Err("Not enough energy")
+                    return Err(SpellError::NotEnoughEnergy)
                 }
             } else {
-                panic!("Function should return base_energy when should_execute is false")
+                return Err(SpellError::Internal("Function should return base_energy when should_execute is false"))
             }
         } else {
-            panic!("Component does not exist")
+            return Err(SpellError::UnknownComponent("Component does not exist"))
+        }
+    }
+
+    /// Adds up the energy the when_created and `repeat n times` sections of `instructions` ask for, without casting anything.
+    /// Each component is costed with the spell's current energy, and efficiency levels go up between components like they would when casting
+    fn estimate_ready_cost(&mut self, instructions: &[u64]) -> Result<f64, SpellError> {
+        let mut efficiency_levels = self.component_efficiency_levels.clone();
+        let mut energy_needed = 0.0;
+        for (section, section_instructions) in budget::split_sections(instructions)? {
+            match (section, &section_instructions[..]) {
+                (WHEN_CREATED_SECTION, section_instructions) => energy_needed += self.estimate_section_cost(section_instructions, &mut efficiency_levels)?,
+                (REPEAT_TIMES_SECTION, &[NUMBER_LITERAL, iterations, ref section_instructions @ ..]) => {
+                    let energy_available = self.energy - energy_needed;
+                    energy_needed += Spell::estimate_loop_cost(f64::from_bits(iterations) as usize, energy_available, || self.estimate_section_cost(section_instructions, &mut efficiency_levels))?;
+                },
+                (REPEAT_TIMES_SECTION, _) => return Err(SpellError::InvalidInstructions("Expected number of times to repeat")),
+                _ => {}
+            }
+        }
+        Ok(energy_needed)
+    }
+
+    /// Adds up the cost of up to `iterations` times around a `repeat n times` loop. Like when casting, it stops once the spell would have run out of energy,
+    /// and once a time around costs nothing, since efficiency only makes the rest cheaper. This keeps huge counts from running forever
+    fn estimate_loop_cost(iterations: usize, energy_available: f64, mut estimate_iteration: impl FnMut() -> Result<f64, SpellError>) -> Result<f64, SpellError> {
+        let mut energy_needed = 0.0;
+        for _ in 0..iterations {
+            if energy_needed > energy_available {
+                break
+            }
+            let iteration_cost = estimate_iteration()?;
+            if iteration_cost <= 0.0 {
+                break
+            }
+            energy_needed += iteration_cost;
+        }
+        Ok(energy_needed)
+    }
+
+    /// Which way an if statement goes can't be known ahead of time, so its contents are always counted as that's the most it can cost. While loops are counted once.
+    /// Nothing after a delay is counted, as it doesn't run when the section does
+    fn estimate_section_cost(&mut self, instructions: &[u64], efficiency_levels: &mut HashMap<u64, f64>) -> Result<f64, SpellError> {
+        let mut energy_needed = 0.0;
+        let mut instructions_iter = instructions.iter();
+        while let Some(&bits) = instructions_iter.next() {
+            match bits {
+                END_OF_SCOPE => {},
+                COMPONENT => {
+                    let is_delay = instructions_iter.as_slice().first() == Some(&DELAY);
+                    energy_needed += self.estimate_component_cost(&mut instructions_iter, efficiency_levels)?;
+                    if is_delay {
+                        break
+                    }
+                },
+                IF | WHILE => Spell::skip_logic(&mut instructions_iter)?,
+                _ => return Err(SpellError::InvalidInstructions("Not valid opcode"))
+            }
+        }
+        Ok(energy_needed)
+    }
+
+    /// Reads a component and its parameters, then estimates it with `estimate_base_energy` and applies its efficiency like `call_component` does
+    fn estimate_component_cost<'a>(&mut self, instructions_iter: &mut impl Iterator<Item = &'a u64>, efficiency_levels: &mut HashMap<u64, f64>) -> Result<f64, SpellError> {
+        let component_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+        let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code)?;
+
+        // Parameters only known while the spell is running are None
+        let mut parameters: Vec<Option<u64>> = Vec::new();
+        while parameters.len() < number_of_component_parameters {
+            match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
+                NUMBER_LITERAL => parameters.push(Some(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?)),
+                parameter @ (TRUE | FALSE) => parameters.push(Some(parameter)),
+                COMPONENT => {
+                    // A vector fills one parameter for each of its values
+                    let parameter_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+                    Spell::skip_component_parameters(parameter_code, instructions_iter)?;
+                    parameters.extend(vec![None; Spell::get_parameter_width(parameter_code)?]);
+                },
+                EXPRESSION => {
+                    Spell::skip_logic(instructions_iter)?;
+                    parameters.push(None);
+                },
+                _ => return Err(SpellError::BadParameterType("Invalid parameter: isn't float or boolean"))
+            }
+        }
+
+        let base_energy = self.estimate_base_energy(component_code, &parameters)?;
+
+        let efficiency_level = efficiency_levels.entry(component_code).or_insert(DEFAULT_EFFICIENCY_LEVEL);
+        let energy_needed = Spell::get_energy_needed(base_energy, *efficiency_level, self.config.efficiency_increase_rate);
+        *efficiency_level += base_energy;
+        Ok(energy_needed)
+    }
+
+    /// Runs the part of a component function that returns its base energy, with the spell as it is now. Parameters that are None are only known while the spell is running,
+    /// so they're estimated as zero or false. This is also how `validate_within_budget` costs components, so estimates only come from the component functions themselves
+    fn estimate_base_energy(&mut self, component_code: u64, parameters: &[Option<u64>]) -> Result<f64, SpellError> {
+        let (function, parameter_types, _) = COMPONENT_TO_FUNCTION_MAP.get(&component_code).ok_or(SpellError::UnknownComponent("Component does not exist"))?;
+
+        let has_unknown_parameters = parameters.contains(&None);
+        let parameters: Vec<u64> = parameters.iter().zip(parameter_types.iter()).map(|(&parameter, &parameter_type)| {
+            parameter.unwrap_or(if parameter_type == BOOLEAN { FALSE } else { f64::to_bits(0.0) })
+        }).collect();
+
+        match function(self, &parameters, false) {
+            Ok(Some(base_energy_bits)) => Ok(f64::from_bits(*base_energy_bits.first().ok_or(SpellError::Internal("Expected energy useage return"))?)),
+            Ok(None) => Err(SpellError::Internal("Function should return base_energy when should_execute is false")),
+            // A guessed parameter can be one the component rejects, which says nothing about what it costs when the spell runs
+            Err(_) if has_unknown_parameters => Ok(0.0),
+            Err(error) => Err(error)
         }
     }
 
+    /// Returns the energy needed to cast a component that costs `base_energy`, which goes down as the component's efficiency level goes up
+    fn get_energy_needed(base_energy: f64, component_efficiency_level: f64, efficiency_increase_rate: f64) -> f64 {
+        let efficiency = component_efficiency_level / (component_efficiency_level + efficiency_increase_rate);
+        base_energy / efficiency
+    }
+
     fn emit_component_cast(&mut self, component_code: u64, efficiency_increase: f64) {
         self.base_mut().emit_signal("component_cast", &[Variant::from(component_code), Variant::from(efficiency_increase)]);
     }
 
-    fn get_number_of_component_parameters(component_code: &u64) -> usize {
+    fn get_number_of_component_parameters(component_code: &u64) -> Result<usize, SpellError> {
         if let Some((_, number_of_parameters, _)) = COMPONENT_TO_FUNCTION_MAP.get(&component_code) {
-            return number_of_parameters.len()
+            return Ok(number_of_parameters.len())
         } else {
-            panic!("Component doesn't exist")
+            return Err(SpellError::UnknownComponent("Component doesn't exist"))
         }
     }
 
-    fn check_if_parameter_allowed(parameter: &Vec<u64>, allowed_values: &Vec<u64>) -> Result<(), &'static str> {
+    /// Returns how many parameters a component fills when it's used as a parameter
+    fn get_parameter_width(component_code: &u64) -> Result<usize, SpellError> {
+        let (_, _, return_type) = COMPONENT_TO_FUNCTION_MAP.get(component_code).ok_or(SpellError::UnknownComponent("Component doesn't exist"))?;
+        Ok(return_type.get_arity().max(1))
+    }
+
+    fn check_if_parameter_allowed(parameter: &Vec<u64>, allowed_values: &Vec<u64>) -> Result<(), SpellError> {
         let mut allowed_iter = allowed_values.iter();
         match parameter[0] {
             TRUE => {
@@ -656,11 +2001,11 @@ impl Spell {
                     }
                     let start_float_range = match value {
                         NUMBER_LITERAL => f64::from_bits(*allowed_iter.next().expect("Expected value after number literal")),
-                        _ => return Err("Invalid type: Expected float")
+                        _ => return Err(SpellError::BadParameterType("Invalid type: Expected float"))
                     };
                     let stop_float_range = match *allowed_iter.next().expect("Expected range of numbers") {
                         NUMBER_LITERAL => f64::from_bits(*allowed_iter.next().expect("Expected value after number literal")),
-                        _ => return Err("Invalid type: Expected float")
+                        _ => return Err(SpellError::BadParameterType("Invalid type: Expected float"))
                     };
                     let range = start_float_range..=stop_float_range;
                     if range.contains(&f64::from_bits(parameter[1])) {
@@ -668,44 +2013,61 @@ impl Spell {
                     }
                 }
             },
-            _ => return Err("Invalid parameter type")
+            _ => return Err(SpellError::BadParameterType("Invalid parameter type"))
         };
-        return Err("Parameter not allowed")
+        return Err(SpellError::NotAllowed("Parameter not allowed"))
     }
 
     /// Checks if the magical entity has access to the component and can cast it with the given parameters. Doesn't check the return of components that are parameters.
-    fn check_allowed_to_cast_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>, component_catalogue: &ComponentCatalogue) -> Result<(), &'static str> {
-        let component_code = *instructions_iter.next().expect("Expected component code"); // Get component num to work out how many parameters to skip
-        let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code);
-        let allowed_parameters_list: &Vec<Vec<u64>> = component_catalogue.component_catalogue.get(&component_code.to_godot()).ok_or("Component isn't in component catalogue")?;
+    /// Returns how many parameters the component fills when it's used as a parameter
+    fn check_allowed_to_cast_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>, component_catalogue: &ComponentCatalogue) -> Result<usize, SpellError> {
+        let component_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?; // Get component num to work out how many parameters to skip
+        let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code)?;
+        let allowed_parameters_list: &Vec<Vec<u64>> = component_catalogue.component_catalogue.get(&component_code.to_godot()).ok_or(SpellError::NotAllowed("Component isn't in component catalogue"))?;
 
-        for index in 0..number_of_component_parameters {
-            let parameter = match *instructions_iter.next().expect("Expected parameter") {
+        let mut index = 0;
+        while index < number_of_component_parameters {
+            let parameter = match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
                 TRUE => vec![TRUE],
                 FALSE => vec![FALSE],
-                NUMBER_LITERAL => vec![NUMBER_LITERAL, *instructions_iter.next().expect("Expected parameter")],
+                NUMBER_LITERAL => vec![NUMBER_LITERAL, *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?],
                 COMPONENT => {
-                    _ = instructions_iter.next();
+                    index += Spell::check_allowed_to_cast_component(instructions_iter, component_catalogue)?;
+                    continue
+                },
+                EXPRESSION => {
+                    // Components in the expression still need to be allowed, but the value can't be known until the spell runs
+                    while let Some(&bits) = instructions_iter.next() {
+                        match bits {
+                            END_OF_SCOPE => break,
+                            NUMBER_LITERAL => _ = instructions_iter.next(),
+                            COMPONENT => _ = Spell::check_allowed_to_cast_component(instructions_iter, component_catalogue)?,
+                            _ => {}
+                        }
+                    }
+                    index += 1;
                     continue
                 },
-                _ => panic!("Invalid parameter")
+                _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
             };
-            Spell::check_if_parameter_allowed(&parameter, &allowed_parameters_list[index])?;
+            let allowed_values = allowed_parameters_list.get(index).ok_or(SpellError::NotAllowed("Parameter isn't in component catalogue"))?;
+            Spell::check_if_parameter_allowed(&parameter, allowed_values)?;
+            index += 1;
         }
-        return Ok(())
+        return Spell::get_parameter_width(&component_code)
     }
 
-    fn internal_check_allowed_to_cast(instructions: Vec<u64>, component_catalogue: &ComponentCatalogue) -> Result<(), &'static str> {
+    fn internal_check_allowed_to_cast(instructions: Vec<u64>, component_catalogue: &ComponentCatalogue) -> Result<(), SpellError> {
         let mut instructions_iter = instructions.iter();
         let mut section: Option<u64> = None;
         while let Some(&bits) = instructions_iter.next() {
-            if section.is_some_and(|x| x == ABOUT_SECTION) && !(WHEN_CREATED_SECTION..=ABOUT_SECTION).contains(&bits)  {
+            if section.is_some_and(|x| x == ABOUT_SECTION) && !(WHEN_CREATED_SECTION..=CHILD_SECTION).contains(&bits)  {
                 continue;
             }
             match bits {
                 NUMBER_LITERAL => _ = instructions_iter.next(),
                 COMPONENT => _ = Spell::check_allowed_to_cast_component(&mut instructions_iter, &component_catalogue)?,
-                WHEN_CREATED_SECTION..=ABOUT_SECTION => {
+                WHEN_CREATED_SECTION..=CHILD_SECTION => {
                     section = Some(bits)
                 },
                 _ => {}
@@ -714,6 +2076,27 @@ impl Spell {
         return Ok(())
     }
 
+    /// Returns the code of every component in the ready and process sections, including ones inside if statements and parameters
+    fn get_component_codes(instructions: &[u64]) -> Vec<u64> {
+        let mut component_codes: Vec<u64> = Vec::new();
+        let mut instructions_iter = instructions.iter();
+        let mut section: Option<u64> = None;
+        while let Some(&bits) = instructions_iter.next() {
+            if section.is_some_and(|x| x == ABOUT_SECTION) && !(WHEN_CREATED_SECTION..=CHILD_SECTION).contains(&bits)  {
+                continue;
+            }
+            match bits {
+                NUMBER_LITERAL => _ = instructions_iter.next(),
+                COMPONENT => if let Some(&component_code) = instructions_iter.next() {
+                    component_codes.push(component_code);
+                },
+                WHEN_CREATED_SECTION..=CHILD_SECTION => section = Some(bits),
+                _ => {}
+            }
+        }
+        component_codes
+    }
+
     fn add_component_to_component_catalogue(component_code: u64, parameter_restrictions: Vec<Vec<&str>>, component_catalogue: &mut ComponentCatalogue) {
         let mut parsed_parameter_restrictions: Vec<Vec<u64>> = Vec::new();
         let mut index = 0;
@@ -747,8 +2130,12 @@ impl Spell {
         component_catalogue.component_catalogue.insert(component_code, parsed_parameter_restrictions);
     }
 
-    /// Gives a spell instance its instructions, used to avoid json translation
-    fn internal_set_instructions(&mut self, instructions: Vec<u64>) {
+    /// Gives a spell instance its instructions, used to avoid json translation. Instructions can come from saves, so malformed ones are an error rather than a panic
+    fn internal_set_instructions(&mut self, instructions: Vec<u64>) -> Result<(), SpellError> {
+        // Everything after the child section is the child's own spell
+        let (instructions, child_instructions) = Spell::split_child_section(&instructions);
+        self.child_instructions = child_instructions.to_vec();
+
         let mut section_instructions: Vec<u64> = Vec::new();
         let mut last_section: u64 = 0;
         let mut instructions_iter = instructions.iter();
@@ -756,23 +2143,11 @@ impl Spell {
             match instruction {
                 NUMBER_LITERAL => { // Number literal
                     section_instructions.push(instruction);
-                    let something = *instructions_iter.next().expect("Expected number after literal opcode");
+                    let something = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?;
                     section_instructions.push(something);
                 },
-                WHEN_CREATED_SECTION..=ABOUT_SECTION => {
-                    match last_section {
-                        END_OF_SCOPE => {},
-                        WHEN_CREATED_SECTION => self.ready_instructions = section_instructions.clone(),
-                        REPEAT_SECTION => {
-                            section_instructions.remove(0); // Removes the number literal opcode
-                            self.process_instructions.push(Process::new(f64::from_bits(section_instructions.remove(0)) as usize * PROCESS_FREQUENCY, section_instructions.clone()))
-                        },
-                        ABOUT_SECTION => {
-                            self.set_about_section(section_instructions.clone())
-                        },
-                        _ => panic!("Invalid section")
-                    }
-
+                WHEN_CREATED_SECTION..=REPEAT_FOR_SECTION => {
+                    self.set_section(last_section, &section_instructions)?;
                     section_instructions.clear();
                     last_section = instruction;
                 },
@@ -781,41 +2156,72 @@ impl Spell {
         }
 
         // Match the finial section
-        match last_section {
-            END_OF_SCOPE => {},
-            WHEN_CREATED_SECTION => self.ready_instructions = section_instructions.clone(),
-            REPEAT_SECTION => {
-                section_instructions.remove(0);
-                self.process_instructions.push(Process::new(f64::from_bits(section_instructions.remove(0)) as usize, section_instructions.clone()))
+        self.set_section(last_section, &section_instructions)
+    }
+
+    /// Splits instructions into the spell's own sections and the child section after them, without its opcode. The child section is empty if there isn't one
+    fn split_child_section(instructions: &[u64]) -> (&[u64], &[u64]) {
+        let mut index = 0;
+        while let Some(&instruction) = instructions.get(index) {
+            match instruction {
+                NUMBER_LITERAL => index += 1, // The number after a number literal opcode can have the same bits as the child section
+                CHILD_SECTION => return (&instructions[..index], &instructions[index + 1..]),
+                _ => {}
+            }
+            index += 1;
+        }
+        (instructions, &[])
+    }
+
+    /// Stores the instructions of one section, without its opcode, where the spell runs them from
+    fn set_section(&mut self, section: u64, section_instructions: &[u64]) -> Result<(), SpellError> {
+        match (section, section_instructions) {
+            (END_OF_SCOPE, _) => {},
+            (WHEN_CREATED_SECTION, section_instructions) => self.ready_instructions = section_instructions.to_vec(),
+            (REPEAT_SECTION, &[NUMBER_LITERAL, frequency, ref section_instructions @ ..]) => {
+                self.process_instructions.push(Process::new(f64::from_bits(frequency) as usize * PROCESS_FREQUENCY, section_instructions.to_vec()))
+            },
+            (REPEAT_TIMES_SECTION, &[NUMBER_LITERAL, iterations, ref section_instructions @ ..]) => {
+                self.ready_loops.push(CountedLoop { iterations: f64::from_bits(iterations) as usize, instructions: section_instructions.to_vec() })
             },
-            ABOUT_SECTION => {
-                self.set_about_section(section_instructions.clone())
+            (REPEAT_FOR_SECTION, &[NUMBER_LITERAL, frequency, NUMBER_LITERAL, duration, ref section_instructions @ ..]) => {
+                let frequency = f64::from_bits(frequency) as usize * PROCESS_FREQUENCY;
+                self.process_instructions.push(Process::new(frequency, section_instructions.to_vec()).with_duration(f64::from_bits(duration)))
             },
-            _ => panic!("Invalid section")
+            (REPEAT_SECTION | REPEAT_TIMES_SECTION | REPEAT_FOR_SECTION, _) => return Err(SpellError::InvalidInstructions("Expected number literal after repeat section")),
+            (ON_COLLISION_SECTION, section_instructions) => self.collision_instructions = section_instructions.to_vec(),
+            (ABOUT_SECTION, attributes) => self.set_about_section(attributes)?,
+            _ => return Err(SpellError::InvalidInstructions("Invalid section"))
         }
+        Ok(())
     }
 
-    fn set_about_section(&mut self, attributes: Vec<u64>) {
-        let mut codes = attributes.into_iter();
+    fn set_about_section(&mut self, attributes: &[u64]) -> Result<(), SpellError> {
+        let mut codes = attributes.iter().copied();
         while let Some(code) = codes.next() {
             match code {
                 COLOR => { // Set colour
-                    match match vec![codes.next(), codes.next(), codes.next()].into_iter().collect::<Option<Vec<u64>>>(){ // Transpose vec of option into option of vec
-                        Some(colour_vector) => colour_vector,
-                        None => panic!("Invalid data: There should be three color values")
-                    }.into_iter()
-                    .map(|x| f64::from_bits(x) as f32)
-                    .collect::<Vec<f32>>()[..] {
-                        [red, green, blue] => self.color = Color{r: red, g: green, b: blue, a: SPELL_TRANSPARENCY},
-                        _ => panic!("Failed to parse colors")
-                    }
+                    let mut next_color = || codes.next().map(|x| f64::from_bits(x) as f32).ok_or(SpellError::InvalidInstructions("Invalid data: There should be three color values"));
+                    let (red, green, blue) = (next_color()?, next_color()?, next_color()?);
+                    // Keeps the transparency, which can be set by the transparency attribute before or after the color
+                    self.color = Color{r: red, g: green, b: blue, a: self.color.a};
                 }
                 CHARGE_TO_SHAPE => {
-                    self.charge_to_shape = boolean_logic::num_to_bool(codes.next().expect("Expected boolean after charge_to_shape")).unwrap_or_else(|err| panic!("{err}"));
+                    self.charge_to_shape = boolean_logic::num_to_bool(codes.next().ok_or(SpellError::InvalidInstructions("Expected boolean after charge_to_shape"))?)?;
                 }
-                _ => panic!("Invalid attribute")
+                TRANSPARENCY => {
+                    self.color.a = Spell::get_clamped_transparency(f64::from_bits(codes.next().ok_or(SpellError::InvalidInstructions("Expected number after transparency"))?));
+                }
+                NAME => {
+                    self.spell_name = spelltranslator::decode_string(codes.by_ref())?;
+                }
+                DESCRIPTION => {
+                    self.spell_description = spelltranslator::decode_string(codes.by_ref())?;
+                }
+                _ => return Err(SpellError::InvalidInstructions("Invalid attribute"))
             }
         }
+        Ok(())
     }
 
     fn translate_instructions(instructions_json: &GString) -> Vec<u64> {
@@ -831,463 +2237,3368 @@ impl Spell {
         self.base_mut().queue_free();
     }
 
-    fn anchor(&mut self) {
-        let parent = match self.base().get_parent() {
-            Some(node) => node.cast::<MagicalEntity>(),
-            None => panic!("Expected parent node")
-        };
-        let distance = (self.base().get_global_position() - parent.get_global_position()).length() as f64;
-        if Sphere::get_radius_from_volume(self.get_natural_volume(self.energy)) >= distance {
-            self.base_mut().set_position(parent.get_global_position());
-            self.anchored_to = Some(parent);
-            self.base_mut().set_as_top_level(false);
-            self.set_visibility(false);
+    /// Returns a fraction of the damage dealt to the caster as health
+    fn apply_lifesteal(&mut self, damage_dealt: f64) {
+        if self.lifesteal <= 0.0 {
+            return
         }
-    }
 
-    fn undo_anchor(&mut self) {
-        self.base_mut().set_as_top_level(true);
-        let position = self.base().get_global_position();
-        match self.anchored_to {
-            Some(ref mut magical_entity) => magical_entity.set_position(position),
-            None => return
-        }
-        self.anchored_to = None;
-        if !self.form_set {
-            self.set_visibility(true);
+        let health_returned = Spell::get_lifesteal_health(damage_dealt, self.lifesteal);
+
+        if let Some(ref mut caster) = self.caster {
+            if caster.is_instance_valid() {
+                caster.bind_mut().restore_health(health_returned);
+            }
         }
     }
 
-    fn surmount_anchor_resistance(&mut self) -> bool {
-        let mut spell_owned = false;
+    /// Returns the health a spell with `lifesteal` gives back to its caster for dealing `damage_dealt`
+    fn get_lifesteal_health(damage_dealt: f64, lifesteal: f64) -> f64 {
+        (damage_dealt * lifesteal).max(0.0)
+    }
 
-        let magical_entity_option = std::mem::take(&mut self.anchored_to);
+    /// Moves a familiar towards where it should be behind its caster. Returns false if the spell perished
+    fn update_familiar(&mut self, delta: f64) -> bool {
+        let familiar = match self.familiar {
+            Some(familiar) => familiar,
+            None => return true
+        };
 
-        if let Some(ref magical_entity) = magical_entity_option {
-            let bind_magical_entity = magical_entity.bind();
-            spell_owned = bind_magical_entity.owns_spell(self.to_gd())
+        let caster_position = match self.caster {
+            Some(ref caster) if caster.is_instance_valid() => caster.get_global_position(),
+            _ => {
+                self.perish();
+                return false
+            }
+        };
+
+        let energy_needed = FAMILIAR_ENERGY_PER_SECOND * delta;
+        if self.energy - energy_needed < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
         }
+        self.energy -= energy_needed;
 
-        self.anchored_to = magical_entity_option;
+        let position = self.base().get_global_position();
+        let new_position = Spell::get_familiar_position(position, caster_position, familiar.follow_distance, FAMILIAR_FOLLOW_RATE * delta);
+        self.base_mut().set_global_position(new_position);
 
-        if let Some(ref mut magical_entity) = self.anchored_to {
-            let mut bind_magical_entity = magical_entity.bind_mut();
+        return true
+    }
 
-            // Surmounting magical entity's charged energy
-            if !spell_owned {
-                let energy_charged = bind_magical_entity.get_energy_charged();
-                if self.energy >= energy_charged {
-                    bind_magical_entity.set_energy_charged(0.0);
-                    self.energy -= energy_charged;
-                } else {
-                    bind_magical_entity.set_energy_charged(energy_charged - self.energy);
-                    self.energy = 0.0;
-                    return false
-                }
-            }
+    /// Returns where a familiar at `position` moves to. It moves `weight` of the way towards the point `follow_distance` away from its caster on the side it's already on
+    fn get_familiar_position(position: Vector3, caster_position: Vector3, follow_distance: f64, weight: f64) -> Vector3 {
+        let mut direction_from_caster = (position - caster_position).normalized_or_zero();
+        if direction_from_caster == Vector3::ZERO {
+            direction_from_caster = Vector3::BACK;
+        }
+
+        let target_position = caster_position + direction_from_caster * follow_distance as f32;
+        position + (target_position - position) * weight.clamp(0.0, 1.0) as f32
+    }
 
-            // Surmounting magical entity's mass
-            self.energy -= bind_magical_entity.get_mass() * MASS_MOVEMENT_COST;
+    /// Holds the spell in front of its caster. Returns false if the spell perished
+    fn update_anchor_follow(&mut self, delta: f64) -> bool {
+        let anchor_follow = match self.anchor_follow {
+            Some(anchor_follow) => anchor_follow,
+            None => return true
+        };
 
-            if !(self.energy > 0.0) {
+        let (caster_position, caster_direction) = match self.caster {
+            Some(ref caster) if caster.is_instance_valid() => (caster.get_global_position(), caster.bind().get_original_direction()),
+            _ => {
+                self.perish();
                 return false
             }
+        };
+
+        let energy_needed = ANCHOR_FOLLOW_ENERGY_PER_SECOND * delta;
+        if self.energy - energy_needed < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
         }
+        self.energy -= energy_needed;
+
+        let (position, direction) = Spell::get_anchor_follow_position(caster_position, caster_direction, anchor_follow.distance);
+        self.base_mut().set_global_position(position);
+        self.anchor_follow = Some(AnchorFollow { direction, ..anchor_follow });
 
         return true
     }
 
-    fn set_form(&mut self, form_code: u64) {
-        if self.form_set {
-            self.undo_form();
+    /// Returns where a spell held `distance` in front of a caster aiming along `caster_direction` goes, and the way it's held out from them
+    fn get_anchor_follow_position(caster_position: Vector3, caster_direction: Basis, distance: f64) -> (Vector3, Vector3) {
+        let direction = (caster_direction * Vector3::FORWARD).normalized_or_zero();
+        (caster_position + direction * distance as f32, direction)
+    }
+
+    /// Lets go of a held spell, turning it to fly the way it was last held out at the speed it has
+    fn release_anchor_follow(&mut self, anchor_follow: AnchorFollow) {
+        if let Some(basis) = Spell::get_direction_basis(anchor_follow.direction) {
+            self.original_direction = basis;
+            self.velocity = Vector3::FORWARD * self.velocity.length();
         }
-        let form_config = self.config.forms.get(&form_code).expect("Expected form code to map to a form");
+    }
 
-        let scene: Gd<PackedScene> = load(&form_config.path);
+    /// Splits the spell's healing among the magical entities it overlaps. The spell only uses energy for health that is actually restored
+    fn heal_overlapping_entities(&mut self) {
+        let bodies = self.get_detected_bodies();
+        let mut magical_entities: Vec<Gd<MagicalEntity>> = Vec::new();
+
+        for body in bodies.iter_shared() {
+            if let Ok(magical_entity) = body.try_cast::<MagicalEntity>() {
+                let is_heal_target = {
+                    let bind_magical_entity = magical_entity.bind();
+                    Spell::is_heal_target_of(self.faction, self.heal_owner, bind_magical_entity.owns_spell(self.to_gd()), bind_magical_entity.get_faction())
+                };
+                if is_heal_target {
+                    magical_entities.push(magical_entity);
+                }
+            }
+        }
 
-        let shape = form_config.shape;
+        if magical_entities.is_empty() {
+            return
+        }
 
-        self.form_set = true;
-        self.shape = Some(shape);
-        self.handle_charge_to_shape();
-        self.set_shape(shape);
-        self.set_visibility(false);
-        let mut instantiated_scene = scene.instantiate().expect("Expected to be able to create scene").cast::<Node3D>();
-        instantiated_scene.set_name(FORM_NAME);
-        instantiated_scene.set_basis(self.original_direction);
-        self.base_mut().add_child(&instantiated_scene);
+        let heal = self.heal / magical_entities.len() as f64;
+
+        for mut magical_entity in magical_entities {
+            let possible_heal = heal.min(self.energy);
+            let health_restored = magical_entity.bind_mut().restore_health(possible_heal);
+            self.energy -= health_restored;
+
+            if self.energy < ENERGY_CONSIDERATION_LEVEL {
+                self.perish();
+                return
+            }
+        }
     }
 
-    fn undo_form(&mut self) {
-        if self.form_set == false {
-            return
+    /// Stuns the magical entities the spell targets that it overlaps. The stun is applied again every frame, so it lasts until they've been out of the spell for the duration.
+    /// Holding each one costs `STUN_ENERGY_PER_SECOND`. Returns false if the spell perished
+    fn stun_overlapping_entities(&mut self, delta: f64) -> bool {
+        let duration = match self.stun_duration {
+            Some(duration) => duration,
+            None => return true
+        };
+
+        let mut magical_entities: Vec<Gd<MagicalEntity>> = Vec::new();
+        for body in self.get_detected_bodies().iter_shared() {
+            if let Ok(magical_entity) = body.try_cast::<MagicalEntity>() {
+                if self.is_target(&magical_entity.bind()) {
+                    magical_entities.push(magical_entity);
+                }
+            }
         }
-        self.form_set = false;
-        let form: Gd<Node> = self.base_mut().get_node_as(FORM_NAME);
-        form.free();
-        self.shape = None;
-        self.update_natural_shape();
-        if self.anchored_to == None {
-            self.set_visibility(true);
+
+        let affordable_stuns = Spell::get_affordable_stun_count(self.energy, magical_entities.len(), delta);
+        for mut magical_entity in magical_entities.iter().take(affordable_stuns).cloned() {
+            magical_entity.bind_mut().stun(duration);
+        }
+        self.energy -= affordable_stuns as f64 * STUN_ENERGY_PER_SECOND * delta;
+
+        if affordable_stuns < magical_entities.len() {
+            self.perish();
+            return false
         }
+
+        true
     }
 
-    fn handle_charge_to_shape(&mut self) {
-        if self.charge_to_shape {
-            match self.shape {
-                Some(ref shape) => {
-                    let energy_needed = self.get_natural_energy(shape.get_volume());
-                    if energy_needed > self.energy {
-                        self.energy_requested = energy_needed - self.energy;
-                    } else {
-                        self.energy = energy_needed;
-                    }
-                },
-                None => {}
-            }
+    /// Returns how many of `targets` a spell with `energy` can hold stunned for `delta` seconds before it runs too low on energy to keep going
+    fn get_affordable_stun_count(energy: f64, targets: usize, delta: f64) -> usize {
+        let energy_per_stun = STUN_ENERGY_PER_SECOND * delta;
+        if energy_per_stun <= 0.0 {
+            return targets
         }
+        let affordable_stuns = ((energy - ENERGY_CONSIDERATION_LEVEL) / energy_per_stun).floor().max(0.0);
+        (affordable_stuns as usize).min(targets)
     }
 
-    fn update_natural_shape(&mut self) {
-        self.set_shape(Shape::Sphere(Sphere::from_volume(self.get_natural_volume(self.energy))));
+    /// A spell heals its owner only if `heal_owner` is set. Spells with a faction only heal magical entities of the same faction, and spells without one heal everyone
+    fn is_heal_target_of(spell_faction: i64, heal_owner: bool, owns_spell: bool, magical_entity_faction: i64) -> bool {
+        if owns_spell {
+            return heal_owner
+        }
+        spell_faction == NO_FACTION || !Spell::is_enemy_faction(spell_faction, magical_entity_faction)
     }
 
-    fn get_control_needed_for_shape(&self, shape_option: Option<Shape>) -> f64 {
-        let shape = match shape_option {
-            Some(ref shape) => shape,
-            None => return 0.0
-        };
-        let volume_multiplier = shape.get_volume() / self.get_natural_volume(self.energy);
-        (E.powf(volume_multiplier - 1.0) + E.powf((1.0 / volume_multiplier) - 1.0) - 2.0) * self.energy
+    /// Returns the bodies inside the spell's detection radius if it has one, otherwise the bodies overlapping its shape
+    fn get_detected_bodies(&self) -> Array<Gd<Node3D>> {
+        match self.base().try_get_node_as::<Area3D>(SPELL_DETECTION_AREA_NAME) {
+            Some(detection_area) => detection_area.get_overlapping_bodies(),
+            None => self.base().get_overlapping_bodies()
+        }
     }
 
-    fn get_control_needed(&self) -> f64 {
-        self.energy + self.get_control_needed_for_shape(self.shape)
+    /// Returns the radius of the detection area for a requested radius, or None if the spell should go back to using its shape
+    fn get_detection_area_radius(radius: f32) -> Option<f32> {
+        if radius > 0.0 && radius.is_finite() {
+            Some(radius)
+        } else {
+            None
+        }
     }
 
-    fn get_natural_volume(&self, energy: f64) -> f64 {
-        energy * ENERGY_TO_VOLUME
+    /// Returns the nearest magical entity overlapping the spell that doesn't own it. When several overlap, only the nearest is returned
+    fn get_nearest_overlapping_target(&self) -> Option<Gd<MagicalEntity>> {
+        let mut targets: Vec<Gd<MagicalEntity>> = self.get_detected_bodies().iter_shared()
+            .filter_map(|body| body.try_cast::<MagicalEntity>().ok())
+            .filter(|magical_entity| !magical_entity.bind().owns_spell(self.to_gd()))
+            .collect();
+        let positions: Vec<Vector3> = targets.iter().map(|magical_entity| magical_entity.get_global_position()).collect();
+        Spell::get_nearest_index(self.base().get_global_position(), &positions).map(|index| targets.swap_remove(index))
     }
 
-    // get_natural_energy is the inverse function of get_natural_volume
-    fn get_natural_energy(&self, volume: f64) -> f64 {
-        volume / ENERGY_TO_VOLUME
+    /// Returns the index of the position closest to `position`, or None if there aren't any
+    fn get_nearest_index(position: Vector3, positions: &[Vector3]) -> Option<usize> {
+        positions.iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.distance_squared_to(position).total_cmp(&b.distance_squared_to(position)))
+            .map(|(index, _)| index)
     }
-}
 
-impl HasShape for Spell {
-    fn set_shape(&mut self, shape: Shape) {
-        // Collision shape
-        let mut collision_shape_exists = false;
+    /// Returns the direction from `position` to `target_position` in the spell's own directions, so giving the spell velocity that way moves it towards the target
+    fn get_local_direction_to(original_direction: Basis, position: Vector3, target_position: Vector3) -> Vector3 {
+        original_direction.inverse() * (target_position - position).normalized_or_zero()
+    }
 
-        let mut collision_shape = match self.base().try_get_node_as::<CollisionShape3D>(SPELL_COLLISION_SHAPE_NAME) {
-            Some(collision_shape) => {
-                collision_shape_exists = true;
-                collision_shape
-            },
-            None => {
-                let mut collision_shape = CollisionShape3D::new_alloc();
-                collision_shape.set_name(SPELL_COLLISION_SHAPE_NAME);
-                collision_shape
+    /// Whether the spell overlaps any body other than the magical entity that owns it
+    fn is_colliding(&self) -> bool {
+        self.base().get_overlapping_bodies().iter_shared().any(|body| {
+            match body.try_cast::<MagicalEntity>() {
+                Ok(magical_entity) => !magical_entity.bind().owns_spell(self.to_gd()),
+                Err(_) => true
             }
-        };
+        })
+    }
 
-        match self.base().try_get_node_as::<CsgPrimitive3D>(SPELL_CSG_SHAPE_NAME) {
-            Some(csg) => csg.free(),
-            None => {}
+    /// Drains energy from overlapping targets and adds it to the caster's charged energy. Returns false if the spell perished
+    fn siphon_to_caster(&mut self, delta: f64) -> bool {
+        let mut caster = match self.caster {
+            Some(ref caster) if caster.is_instance_valid() => caster.clone(),
+            _ => return true
         };
 
-        // Material
-        let mut csg_material = StandardMaterial3D::new_gd();
+        let mut targets: Vec<Gd<MagicalEntity>> = Vec::new();
+        for body in self.get_detected_bodies().iter_shared() {
+            if let Ok(magical_entity) = body.try_cast::<MagicalEntity>() {
+                if self.is_target(&magical_entity.bind()) {
+                    targets.push(magical_entity);
+                }
+            }
+        }
 
-        // Player defined material properties
-        csg_material.set_albedo(self.color);
+        let energies_to_kill: Vec<f64> = targets.iter().map(|target| target.bind().get_energy_to_kill()).collect();
+        let (drained, energy_siphoned, energy_cost) = Spell::get_siphon_transfer(self.siphon_rate, delta, &energies_to_kill);
 
-        // Constant material properties
-        csg_material.set_transparency(Transparency::ALPHA); // Transparency type
-        csg_material.set_feature(Feature::EMISSION, true); // Allows spell to emit light
-        csg_material.set_emission(self.color); // Chooses what light to emit
+        for (mut target, drained) in targets.into_iter().zip(drained) {
+            target.bind_mut().take_damage(drained, self.damage_type);
+        }
 
-        match shape {
-            Shape::Sphere(sphere) => {
-                // Creating sphere shape
-                let mut shape = SphereShape3D::new_gd();
-                shape.set_name(SPELL_SHAPE_NAME);
-                shape.set_radius(sphere.radius as f32);
-                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+        if energy_siphoned <= 0.0 {
+            return true
+        }
 
-                // Creating visual representation of spell in godot
-                let mut csg_sphere = CsgSphere3D::new_alloc();
-                csg_sphere.set_name(SPELL_CSG_SHAPE_NAME);
-                csg_sphere.set_rings(CSG_SPHERE_DETAIL.0);
-                csg_sphere.set_radial_segments(CSG_SPHERE_DETAIL.1);
-                csg_sphere.set_radius(sphere.radius as f32);
-                csg_sphere.set_material(&csg_material);
-                self.base_mut().add_child(&csg_sphere.upcast::<Node>());
-            },
-            Shape::Cube(cube) => {
-                // Creating box shape
-                let mut shape = BoxShape3D::new_gd();
-                shape.set_name(SPELL_SHAPE_NAME);
-                let box_size = Vector3 { x: cube.x as f32, y: cube.y as f32, z: cube.z as f32 };
-                shape.set_size(box_size);
-                collision_shape.set_shape(&shape.upcast::<Shape3D>());
-                collision_shape.set_basis(self.original_direction);
+        caster.bind_mut().increase_energy_charged(energy_siphoned);
 
-                // Creating visual representation of spell in godot
-                let mut csg_box = CsgBox3D::new_alloc();
-                csg_box.set_name(SPELL_CSG_SHAPE_NAME);
-                csg_box.set_size(box_size);
-                csg_box.set_material(&csg_material);
-                csg_box.set_basis(self.original_direction);
-                self.base_mut().add_child(&csg_box.upcast::<Node>());
+        self.energy -= energy_cost;
+        if self.energy < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
+        }
+
+        true
+    }
+
+    /// Moves energy out of overlapping spells cast by someone else and into this spell. Returns false if the spell perished
+    fn absorb_overlapping_spells(&mut self, delta: f64) -> bool {
+        let instance_id = self.base().instance_id();
+
+        for area in self.base().get_overlapping_areas().iter_shared() {
+            let mut spell = match area.try_cast::<Spell>() {
+                Ok(spell) => spell,
+                Err(_) => continue
+            };
+            let spell_id = spell.instance_id();
+
+            let mut spell_bind = spell.bind_mut();
+            if self.has_same_caster(&spell_bind) {
+                continue
             }
-        };
 
-        if !collision_shape_exists {
-            self.base_mut().add_child(&collision_shape.upcast::<Node>());
+            // When both spells absorb, only one of them moves the energy between them so it isn't moved twice
+            if spell_bind.absorbing && spell_id < instance_id {
+                continue
+            }
+
+            let absorbed = Spell::get_absorbed_energy(self.energy, spell_bind.energy, spell_bind.absorbing, delta);
+            spell_bind.take_damage(absorbed);
+            self.energy += absorbed;
+        }
+
+        if self.energy < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
         }
+
+        true
     }
 
-    fn set_visibility(&mut self, visible: bool) {
-        let mut csg: Gd<CsgPrimitive3D> = self.base_mut().get_node_as(SPELL_CSG_SHAPE_NAME);
-        csg.set_visible(visible);
+    /// Sends the spell back the way it came and makes the magical entity that deflected it its owner. A spell that isn't moving can't be sent back, so it only loses its damage for the frame
+    fn deflect(&mut self, deflector: &mut MagicalEntity, deflector_gd: Gd<MagicalEntity>) {
+        let velocity = match Spell::get_deflected_velocity(self.velocity) {
+            Some(velocity) => velocity,
+            None => return
+        };
+        self.velocity = velocity;
+
+        let spell = self.to_gd();
+        if let Some(mut caster) = self.caster.take() {
+            // The deflector is already bound
+            if caster.is_instance_valid() && caster != deflector_gd {
+                caster.bind_mut().remove_spell_cast(&spell);
+            }
+        }
+        deflector.add_spell_cast(spell);
+        self.faction = deflector.get_faction();
+        self.caster = Some(deflector_gd);
     }
-}
 
-#[godot_api]
-impl Spell {
-    /// Reduces the energy of the spell by `damage`
-    #[func]
-    fn take_damage(&mut self, damage: f64) {
-        self.energy -= damage;
+    /// Returns the velocity of a spell after it's deflected, or None if it isn't moving
+    fn get_deflected_velocity(velocity: Vector3) -> Option<Vector3> {
+        if velocity == Vector3::ZERO {
+            return None
+        }
+        Some(-velocity)
     }
 
-    #[func]
-    fn set_original_direction(&mut self, original_direction: Basis) {
-        self.original_direction = original_direction
+    /// Whether both spells were cast by the same magical entity. Spells without a caster aren't counted as having the same caster as anything
+    fn has_same_caster(&self, other: &Spell) -> bool {
+        matches!((&self.caster, &other.caster), (Some(caster), Some(other_caster)) if caster == other_caster)
     }
 
-    #[func]
-    fn get_original_direction(&self) -> Basis {
-        self.original_direction
+    /// Returns how much energy an absorbing spell takes from another spell over `delta` seconds, which is negative when the other spell absorbs more back.
+    /// Swapping the spells gives the same amount the other way, and neither loses more than `MAX_ABSORB_FRACTION` of its energy at once, so two absorbing spells can't pass energy back and forth without limit
+    fn get_absorbed_energy(energy: f64, other_energy: f64, other_absorbing: bool, delta: f64) -> f64 {
+        let absorbed = energy * ABSORB_RATE * delta;
+        let absorbed_back = if other_absorbing { other_energy * ABSORB_RATE * delta } else { 0.0 };
+        (absorbed - absorbed_back).max(-energy * MAX_ABSORB_FRACTION).min(other_energy * MAX_ABSORB_FRACTION)
     }
 
-    /// Checks instructions against the component catalogue to see if the player is allowed to cast all components in the spell and with the parameters entered
-    #[func]
-    fn check_allowed_to_cast(instructions_json: GString, component_catalogue_path: GString) -> Dictionary {
-        let component_catalogue: ComponentCatalogue = godot_json_saver::from_path(&component_catalogue_path.to_string()).unwrap();
-        let (allowed_to_cast, denial_reason) = match Spell::internal_check_allowed_to_cast(Spell::translate_instructions(&instructions_json), &component_catalogue) {
-            Ok(_) => (true, ""),
-            Err(error_message) => (false, error_message)
+    /// Pushes targets within the radial force's radius away from the spell, or from the caster if the spell is anchored. Returns false if the spell perished
+    fn apply_radial_force(&mut self, delta: f64) -> bool {
+        let radial_force = match self.radial_force {
+            Some(radial_force) => radial_force,
+            None => return true
         };
-        return dict! {"allowed_to_cast": allowed_to_cast, "denial_reason": denial_reason}
-    }
 
-    #[func]
-    fn add_component(&mut self, component: GString) {
-        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
-        let number_of_parameters = Spell::get_number_of_component_parameters(&component_code);
-        let mut parameter_restrictions: Vec<Vec<&str>> = Vec::new();
-        for _ in 0..number_of_parameters {
-            parameter_restrictions.push(vec!["ANY"]);
+        let center = match (&self.anchored_to, &self.caster) {
+            (Some(_), Some(caster)) if caster.is_instance_valid() => caster.get_global_position(),
+            _ => self.base().get_global_position()
+        };
+
+        let targets = self.get_magical_entities_within_from(center, Spell::get_area_radius(radial_force.radius as f32, self.detection_radius));
+        if targets.is_empty() {
+            return true
         }
-        Spell::add_component_to_component_catalogue(component_code, parameter_restrictions, &mut self.component_catalogue);
-    }
 
-    #[func]
-    fn remove_component(&mut self, component: GString) {
-        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
-        self.component_catalogue.component_catalogue.remove(&component_code);
+        // Like damage, the strength is split between every target
+        let velocities: Vec<Vector3> = targets.iter()
+            .map(|target| radial_force.get_velocity(target.get_global_position() - center, targets.len(), delta))
+            .collect();
+        let energy_needed: f64 = velocities.iter().map(|velocity| velocity.length() as f64).sum::<f64>() * RADIAL_FORCE_ENERGY_COST;
+
+        // Spells without enough energy push as hard as they can before perishing
+        let fraction_applied = if energy_needed > self.energy { self.energy / energy_needed } else { 1.0 };
+        for (mut target, velocity) in targets.into_iter().zip(velocities) {
+            target.bind_mut().give_external_velocity(velocity * fraction_applied as f32);
+        }
+
+        self.energy -= energy_needed * fraction_applied;
+        if self.energy < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
+        }
+
+        true
     }
 
-    #[func]
-    fn add_restricted_component(&mut self, component: GString, parameter_restrictions: GString) {
-        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
-        let string_parameter_restrictions = parameter_restrictions.to_string();
-        let parameter_restrictions: Vec<Vec<&str>> = serde_json::from_str(&string_parameter_restrictions).expect("Couldn't parse JSON");
-        Spell::add_component_to_component_catalogue(component_code, parameter_restrictions, &mut self.component_catalogue);
+    /// Speeds up overlapping spells cast by someone else in the direction the spell is going. Spells anchored to something aren't pushed, since their velocity moves what they're anchored to. Returns false if the spell perished
+    fn push_overlapping_spells(&mut self, delta: f64) -> bool {
+        let strength = match self.spell_push {
+            Some(strength) => strength,
+            None => return true
+        };
+
+        // Spells that aren't moving push in the direction they were cast
+        let mut direction = (self.original_direction * self.velocity).normalized_or_zero();
+        if direction == Vector3::ZERO {
+            direction = (self.original_direction * Vector3::FORWARD).normalized_or_zero();
+        }
+
+        let instance_id = self.base().instance_id();
+        let mut targets: Vec<Gd<Spell>> = Vec::new();
+        for area in self.base().get_overlapping_areas().iter_shared() {
+            let spell = match area.try_cast::<Spell>() {
+                Ok(spell) => spell,
+                Err(_) => continue
+            };
+
+            // The spell itself is already bound, so binding it again would panic
+            if spell.instance_id() == instance_id {
+                continue
+            }
+
+            let can_be_pushed = {
+                let spell_bind = spell.bind();
+                !self.has_same_caster(&spell_bind) && spell_bind.anchored_to.is_none()
+            };
+            if can_be_pushed {
+                targets.push(spell);
+            }
+        }
+
+        if targets.is_empty() {
+            return true
+        }
+
+        // Every spell gets the full push, so the energy needed grows with how many are pushed
+        let push = Spell::get_spell_push(direction, strength, delta);
+        let energy_needed = push.length() as f64 * targets.len() as f64 * SPELL_PUSH_ENERGY_COST;
+
+        // Spells without enough energy push as hard as they can before perishing
+        let fraction_applied = if energy_needed > self.energy { self.energy / energy_needed } else { 1.0 };
+        for mut target in targets {
+            let mut target_bind = target.bind_mut();
+            target_bind.velocity = Spell::get_pushed_velocity(target_bind.original_direction, target_bind.velocity, push * fraction_applied as f32);
+        }
+
+        self.energy -= energy_needed * fraction_applied;
+        if self.energy < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
+        }
+
+        true
     }
 
-    #[func]
-    fn set_efficiency_levels(&mut self, efficiency_levels_bytecode_json: GString) {
-        let json_string = efficiency_levels_bytecode_json.to_string();
+    /// Returns the velocity, in world space, that `push_spells` adds to another spell over `delta` seconds
+    fn get_spell_push(direction: Vector3, strength: f64, delta: f64) -> Vector3 {
+        direction.normalized_or_zero() * (strength * delta) as f32
+    }
 
-        match serde_json::from_str(&json_string) {
-            Ok(serde_json::Value::Object(efficiency_levels_object)) => {
-                let mut temp_hashmap: HashMap<u64, f64> = HashMap::new();
-                for (key, value) in efficiency_levels_object {
-                    if let (Ok(parsed_key), Some(parsed_value)) = (key.parse::<u64>(), value.as_f64()) {
-                        temp_hashmap.insert(parsed_key, parsed_value);
-                    }
-                }
-                self.component_efficiency_levels = temp_hashmap;
-            },
-            Ok(_) => panic!("Invalid: Must be dictionary"),
-            Err(_) => panic!("Invalid Json")
+    /// Adds a push in world space to the velocity of a spell, which is relative to the direction it was cast in
+    fn get_pushed_velocity(original_direction: Basis, velocity: Vector3, push: Vector3) -> Vector3 {
+        velocity + Spell::get_local_velocity(original_direction, push)
+    }
+
+    /// Turns a velocity in world space into the spell's own frame, which is what `velocity` and `get_velocity` use
+    fn get_local_velocity(original_direction: Basis, world_velocity: Vector3) -> Vector3 {
+        original_direction.inverse() * world_velocity
+    }
+
+    /// Returns what siphoning at `rate` takes from each target with `energies_to_kill` over `delta` seconds, how much that gives the caster,
+    /// and the energy the spell pays to move it, which is `SIPHON_ENERGY_COST` for each unit moved
+    fn get_siphon_transfer(rate: f64, delta: f64, energies_to_kill: &[f64]) -> (Vec<f64>, f64, f64) {
+        let drained: Vec<f64> = energies_to_kill.iter().map(|&energy_to_kill| Spell::get_siphoned_energy(rate, delta, energy_to_kill)).collect();
+        let energy_siphoned: f64 = drained.iter().sum();
+        (drained, energy_siphoned, energy_siphoned * SIPHON_ENERGY_COST)
+    }
+
+    /// Returns how much energy draining at `rate` per second takes from a target over `delta` seconds, which can't be more than the target has
+    fn get_siphoned_energy(rate: f64, delta: f64, energy_to_kill: f64) -> f64 {
+        (rate * delta).min(energy_to_kill).max(0.0)
+    }
+
+    /// Makes a magical entity the spell hit take more damage for a while if the spell has a vulnerability mark
+    fn apply_vulnerability(&self, magical_entity: &mut MagicalEntity) {
+        if let Some(vulnerability_mark) = self.vulnerability_mark {
+            magical_entity.mark_vulnerable(vulnerability_mark.multiplier, vulnerability_mark.duration);
         }
     }
 
-    #[func]
-    fn get_bytecode_efficiency_levels(efficiency_levels_json: GString) -> GString {
-        let json_string = efficiency_levels_json.to_string();
+    /// Stretches the spell from the caster to either the beam's max length or whatever it hits first, then damages the magical entities inside it. Returns false if the spell perished
+    fn update_beam(&mut self, delta: f64) -> bool {
+        let beam = match self.beam {
+            Some(beam) => beam,
+            None => return true
+        };
 
-        match serde_json::from_str(&json_string) {
-            Ok(serde_json::Value::Object(efficiency_levels_object)) => {
-                let mut return_hashmap: HashMap<u64, f64> = HashMap::new();
-                for (key, value) in efficiency_levels_object {
-                    if let (Some(parsed_key), Some(parsed_value)) = (spelltranslator::get_component_num(&key), value.as_f64()) {
-                        return_hashmap.insert(parsed_key, parsed_value);
-                    }
+        let caster = match self.caster {
+            Some(ref caster) if caster.is_instance_valid() => caster.clone(),
+            _ => {
+                self.perish();
+                return false
+            }
+        };
+
+        let start = caster.get_global_position();
+        self.original_direction = caster.bind().get_original_direction();
+        let direction = (self.original_direction * Vector3::FORWARD).normalized_or_zero();
+
+        // Shortens the beam to whatever it hits first. The beam goes slightly into what it hits so that it overlaps it
+        let mut length = beam.max_length;
+        if let Some(position) = self.intersect_ray(start, start + direction * beam.max_length as f32).and_then(|collision| collision.get("position")) {
+            length = ((position.to::<Vector3>() - start).length() as f64 + BEAM_RADIUS).min(beam.max_length);
+        }
+
+        self.base_mut().set_global_position(start + direction * (length / 2.0) as f32);
+        self.set_beam_length(length);
+
+        // Damage the magical entities inside the beam
+        let bodies = self.base().get_overlapping_bodies();
+        for body in bodies.iter_shared() {
+            if let Ok(mut magical_entity) = body.try_cast::<MagicalEntity>() {
+                // Damage falloff is measured to each target, since the beam reaches from the caster to whatever it hits
+                let distance = self.get_distance_to_caster(magical_entity.get_global_position());
+                let magical_entity_id = magical_entity.instance_id();
+                let mut bind_magical_entity = magical_entity.bind_mut();
+                if !self.is_target(&bind_magical_entity) {
+                    continue
                 }
-                let json_object: serde_json::Value = json!(return_hashmap);
-                GString::from(json_object.to_string())
+
+                self.entities_hit.insert(magical_entity_id);
+
+                let frame_damage = Spell::get_damage_after_falloff(beam.get_frame_damage(delta, f64::INFINITY), self.damage_falloff, distance);
+                let possible_damage = frame_damage.min(bind_magical_entity.get_energy_to_kill());
+
+                if self.energy - possible_damage < ENERGY_CONSIDERATION_LEVEL {
+                    bind_magical_entity.take_damage(self.energy, self.damage_type);
+                    self.apply_lifesteal(self.energy);
+                    self.perish();
+                    return false
+                }
+
+                self.energy -= possible_damage;
+
+                bind_magical_entity.take_damage(possible_damage, self.damage_type);
+                self.apply_vulnerability(&mut bind_magical_entity);
+                self.apply_lifesteal(possible_damage);
+
+                if self.is_done_piercing() {
+                    self.perish();
+                    return false
+                }
+            }
+        }
+
+        return true
+    }
+
+    /// Changes the length of the beam's cylinder and turns it with the caster. The beam changes every frame, so the cylinder is only made again if it isn't there yet
+    fn set_beam_length(&mut self, length: f64) {
+        let collision_shape = self.base().try_get_node_as::<CollisionShape3D>(SPELL_COLLISION_SHAPE_NAME);
+        let cylinder_shape = collision_shape.as_ref().and_then(|collision_shape| collision_shape.get_shape()).and_then(|shape| shape.try_cast::<CylinderShape3D>().ok());
+        let csg_cylinder = self.base().try_get_node_as::<CsgCylinder3D>(SPELL_CSG_SHAPE_NAME);
+
+        match (collision_shape, cylinder_shape, csg_cylinder) {
+            (Some(mut collision_shape), Some(mut cylinder_shape), Some(mut csg_cylinder)) => {
+                // The same rotation `set_shape` gives cylinders
+                let cylinder_basis = self.original_direction * Basis::from_axis_angle(Vector3::RIGHT, -std::f32::consts::FRAC_PI_2);
+                cylinder_shape.set_height(length as f32);
+                collision_shape.set_basis(cylinder_basis);
+                csg_cylinder.set_height(length as f32);
+                csg_cylinder.set_basis(cylinder_basis);
             },
-            Ok(_) => panic!("Invalid: Must be dictionary"),
-            Err(_) => panic!("Invalid Json")
+            _ => self.set_shape(Shape::Cylinder(Cylinder { radius: BEAM_RADIUS, height: length }))
         }
     }
 
-    /// Takes instructions in the format of a json list which can be obtained from the output of the method `get_bytecode_instructions`. The instructions are called once the spell is put in the scene tree
-    #[func]
-    fn set_instructions(&mut self, instructions_json: GString) {
-        self.internal_set_instructions(Spell::translate_instructions(&instructions_json));
+    /// Moves the color fade along by `delta` seconds and removes it once it's finished
+    fn update_color_fade(&mut self, delta: f64) {
+        let mut color_fade = match self.color_fade {
+            Some(color_fade) => color_fade,
+            None => return
+        };
+
+        color_fade.elapsed += delta;
+        self.set_spell_color(color_fade.get_color());
+
+        if color_fade.is_finished() {
+            self.color_fade = None;
+        } else {
+            self.color_fade = Some(color_fade);
+        }
     }
 
-    /// Takes in spell instructions in string format and returns a dictionary containing `instructions` (a json list), `successful` (a boolean) and `error_message` (a string)
-    #[func]
-    fn get_bytecode_instructions(instructions_json: GString) -> Dictionary {
-        // Returns a dictionary of the instructions and successful
-        let config = saver::Config::get_config().unwrap_or_else(|err| {
-            godot_warn!("Config.toml couldn't be opened, so custom_translation can't be used: {err}");
-            Config::default()
-        });
+    /// Returns the color with each channel clamped between 0 and 1. The alpha is left as it is
+    fn get_clamped_color(red: f64, green: f64, blue: f64, alpha: f32) -> Color {
+        Color {
+            r: red.clamp(0.0, 1.0) as f32,
+            g: green.clamp(0.0, 1.0) as f32,
+            b: blue.clamp(0.0, 1.0) as f32,
+            a: alpha
+        }
+    }
 
-        let (instructions, successful, error_message) = match spelltranslator::parse_spell(&instructions_json.to_string(), Some(config.custom_translation)) {
-            Ok(succesful_instructions) => (succesful_instructions, true, GString::new()),
-            Err(error) => (Vec::new(), false, GString::from(error))
-        };
-        return dict!{"instructions": GString::from(serde_json::to_string(&instructions).expect("Failed to parse instructions into json")), "successful": successful, "error_message": error_message}
+    /// Keeps a transparency from the about section between 0 (invisible) and 1 (opaque)
+    fn get_clamped_transparency(transparency: f64) -> f32 {
+        transparency.clamp(0.0, 1.0) as f32
     }
 
-    #[func]
-    fn set_check_component_return_value(&mut self, boolean: bool) {
-        self.check_component_return_value = boolean;
+    /// Changes the spell's color, including the color of the spell's current shape
+    fn set_spell_color(&mut self, color: Color) {
+        self.color = color;
+
+        let material = self.base().try_get_node_as::<CsgPrimitive3D>(SPELL_CSG_SHAPE_NAME)
+            .and_then(|csg| csg.get_material())
+            .and_then(|material| material.try_cast::<StandardMaterial3D>().ok());
+
+        if let Some(mut material) = material {
+            material.set_albedo(color);
+            material.set_emission(color);
+        }
     }
 
-    #[func]
-    fn get_check_component_return_value(&self) -> bool {
-        self.check_component_return_value
+    /// Returns how many seconds have passed between `since` and `now`, which are both in milliseconds
+    fn get_seconds_between(since: u64, now: u64) -> f64 {
+        now.saturating_sub(since) as f64 / 1000.0
     }
 
-    /// Once `connect_player()` is called, whenever a component is cast, the provided node's `increase_component_efficiency` method will be called
-    #[func]
-    fn connect_player(&mut self, player: Gd<Node>) {
-        let update_function = player.callable("increase_component_efficiency");
-        self.base_mut().connect("component_cast", &update_function);
+    /// Returns the chance of something happening for a spell that is `age` seconds old, going from `start_probability` to `end_probability` over `ramp_duration` seconds
+    fn get_ramped_probability(start_probability: f64, end_probability: f64, ramp_duration: f64, age: f64) -> f64 {
+        let progress = if ramp_duration <= 0.0 { 1.0 } else { (age / ramp_duration).clamp(0.0, 1.0) };
+        (start_probability + (end_probability - start_probability) * progress).clamp(0.0, 1.0)
     }
 
-    #[signal]
-    fn component_cast(component_code: u64, efficiency_increase: f64);
-}
+    /// Returns the time in milliseconds that a spell started at `start_time` should perish if it lives for `seconds`
+    fn get_expiry_time(start_time: u64, seconds: f64) -> u64 {
+        start_time + (seconds.max(0.0) * 1000.0) as u64
+    }
 
-mod boolean_logic { // 100 = true, 101 = false
-    use super::{TRUE, FALSE};
+    /// Turns the spell's velocity towards the nearest target. Returns false if the spell perished
+    fn update_homing(&mut self, delta: f64) -> bool {
+        let homing = match self.homing {
+            Some(homing) => homing,
+            None => return true
+        };
 
-    pub fn and(a: u64, b: u64) -> Result<u64, &'static str> {
-        match (a, b) {
-            (TRUE, TRUE) => Ok(TRUE),
-            (TRUE, FALSE) => Ok(FALSE),
-            (FALSE, TRUE) => Ok(FALSE),
-            (FALSE, FALSE) => Ok(FALSE),
-            _ => Err("Boolean logic can only compare booleans")
+        if self.velocity == Vector3::ZERO {
+            return true
+        }
+
+        let target_position = match self.find_nearest_target() {
+            Some(target_position) => target_position,
+            None => return true
+        };
+
+        // Velocity is relative to the original direction so the target direction has to be too
+        let world_target_direction = (target_position - self.base().get_global_position()).normalized_or_zero();
+        let target_direction = self.original_direction.inverse() * world_target_direction;
+
+        let (axis, angle) = match Spell::get_homing_turn(self.velocity, target_direction, (homing.turn_rate * delta) as f32) {
+            Some(turn) => turn,
+            None => return true
+        };
+        let energy_needed = angle as f64 * HOMING_ENERGY_PER_RADIAN;
+
+        if self.energy - energy_needed < ENERGY_CONSIDERATION_LEVEL {
+            self.perish();
+            return false
         }
+
+        self.energy -= energy_needed;
+        self.velocity = self.velocity.rotated(axis, angle);
+
+        return true
     }
 
-    pub fn or(a: u64, b: u64) -> Result<u64, &'static str> {
-        match (a, b) {
-            (TRUE, TRUE) => Ok(TRUE),
-            (TRUE, FALSE) => Ok(TRUE),
-            (FALSE, TRUE) => Ok(TRUE),
-            (FALSE, FALSE) => Ok(FALSE),
-            _ => Err("Boolean logic can only compare booleans")
+    /// The axis to rotate `velocity` around and the angle to turn it by to point it towards `target_direction`, turning no more than `max_angle`. None if there's no way to turn
+    fn get_homing_turn(velocity: Vector3, target_direction: Vector3, max_angle: f32) -> Option<(Vector3, f32)> {
+        let axis = velocity.cross(target_direction).normalized_or_zero();
+        if axis == Vector3::ZERO {
+            return None
         }
+
+        Some((axis, velocity.angle_to(target_direction).min(max_angle)))
     }
 
-    pub fn xor(a: u64, b: u64) -> Result<u64, &'static str> {
-        match (a, b) {
-            (TRUE, TRUE) => Ok(FALSE),
-            (TRUE, FALSE) => Ok(TRUE),
-            (FALSE, TRUE) => Ok(TRUE),
-            (FALSE, FALSE) => Ok(FALSE),
-            _ => Err("Boolean logic can only compare booleans")
+    /// Returns the global position of the nearest magical entity within the spell's detection radius, or `homing_detection_radius` if it doesn't have one, that the spell targets
+    fn find_nearest_target(&self) -> Option<Vector3> {
+        let radius = self.detection_radius.unwrap_or(self.config.homing_detection_radius);
+        let target_positions = self.get_magical_entities_within(radius).iter().map(|magical_entity| magical_entity.get_global_position()).collect::<Vec<Vector3>>();
+        Spell::get_nearest_position(self.base().get_global_position(), &target_positions)
+    }
+
+    fn get_nearest_position(position: Vector3, target_positions: &[Vector3]) -> Option<Vector3> {
+        let mut nearest: Option<(f32, Vector3)> = None;
+        for &target_position in target_positions {
+            let distance = (target_position - position).length();
+            if nearest.map_or(true, |(nearest_distance, _)| distance < nearest_distance) {
+                nearest = Some((distance, target_position));
+            }
         }
+
+        nearest.map(|(_, target_position)| target_position)
     }
 
-    pub fn not(a: u64) -> Result<u64, &'static str> {
-        match a {
-            TRUE => Ok(FALSE),
-            FALSE => Ok(TRUE),
-            _ => Err("Not can only be used on booleans")
+    /// A spell targets magical entities of enemy factions. A spell never targets the magical entity that owns it, even if its faction was changed
+    fn is_target(&self, magical_entity: &MagicalEntity) -> bool {
+        Spell::is_target_of(self.faction, magical_entity.owns_spell(self.to_gd()), magical_entity.get_faction())
+    }
+
+    fn is_target_of(spell_faction: i64, owns_spell: bool, magical_entity_faction: i64) -> bool {
+        !owns_spell && Spell::is_enemy_faction(spell_faction, magical_entity_faction)
+    }
+
+    /// Returns the bodies and areas the spell is overlapping
+    fn get_overlapping_objects(&self) -> Array<Gd<Node3D>> {
+        let mut objects = self.base().get_overlapping_bodies();
+
+        for area in self.base().get_overlapping_areas().iter_shared() {
+            objects.push(&area.upcast());
         }
+
+        objects
     }
 
-    pub fn bool_to_num(boolean: bool) -> u64 {
-        match boolean {
-            true => TRUE,
-            false => FALSE
+    /// Returns how many of `objects` are magical entities the spell targets
+    fn count_targets(&self, objects: &Array<Gd<Node3D>>) -> usize {
+        let magical_entities = objects.iter_shared().filter_map(|object| object.try_cast::<MagicalEntity>().ok()).map(|magical_entity| {
+            let bind_magical_entity = magical_entity.bind();
+            (bind_magical_entity.owns_spell(self.to_gd()), bind_magical_entity.get_faction())
+        });
+        Spell::count_targets_among(self.faction, magical_entities)
+    }
+
+    /// Counts the magical entities, given as whether each owns the spell and its faction, that a spell of `spell_faction` targets
+    fn count_targets_among(spell_faction: i64, magical_entities: impl Iterator<Item = (bool, i64)>) -> usize {
+        magical_entities.filter(|&(owns_spell, magical_entity_faction)| Spell::is_target_of(spell_faction, owns_spell, magical_entity_faction)).count()
+    }
+
+    /// Returns how many magical entities the spell targets that it's overlapping
+    pub fn get_overlapping_target_count(&self) -> usize {
+        self.count_targets(&self.get_overlapping_objects())
+    }
+
+    /// Spells clash with spells of enemy factions that weren't cast by the same magical entity
+    fn is_clashing_with(&self, other: &Spell) -> bool {
+        !self.has_same_caster(other) && Spell::is_enemy_faction(self.faction, other.faction)
+    }
+
+    /// Whether the spell deals damage to the spells it clashes with
+    fn can_clash(&self) -> bool {
+        self.get_effective_damage() != 0.0 && self.anchored_to == None && self.trap.is_none()
+    }
+
+    /// Returns the spell's damage after damage falloff
+    fn get_effective_damage(&self) -> f64 {
+        Spell::get_damage_after_falloff(self.damage, self.damage_falloff, self.get_distance_to_caster(self.base().get_global_position()))
+    }
+
+    /// Returns what's left of `damage` dealt `distance` from the caster once damage falloff is applied
+    fn get_damage_after_falloff(damage: f64, damage_falloff: Option<DamageFalloff>, distance: f64) -> f64 {
+        match damage_falloff {
+            Some(damage_falloff) => damage * damage_falloff.get_multiplier(distance),
+            None => damage
         }
     }
 
-    pub fn num_to_bool(num: u64) -> Result<bool, &'static str> {
-        match num {
-            TRUE => Ok(true),
-            FALSE => Ok(false),
-            _ => Err("Invalid number: Cannot translate to boolean")
+    /// Returns how far `position` is from the spell's caster, or from where the spell was cast if the caster is gone
+    fn get_distance_to_caster(&self, position: Vector3) -> f64 {
+        let caster_position = match self.caster {
+            Some(ref caster) if caster.is_instance_valid() => caster.get_global_position(),
+            _ => self.cast_position
+        };
+        position.distance_to(caster_position) as f64
+    }
+
+    /// Returns the energy two clashing spells lose in a frame. Each loses the other's damage, but never more energy than it has, so a spell with more damage breaks through a weaker one and two of the same spell destroy each other
+    fn get_clash_losses(energy: f64, damage: f64, other_energy: f64, other_damage: f64) -> (f64, f64) {
+        (other_damage.min(energy).max(0.0), damage.min(other_energy).max(0.0))
+    }
+
+    /// Magical entities and spells without a faction are enemies of everyone
+    fn is_enemy_faction(spell_faction: i64, magical_entity_faction: i64) -> bool {
+        spell_faction == NO_FACTION || magical_entity_faction == NO_FACTION || spell_faction != magical_entity_faction
+    }
+
+    /// Returns how far an area effect like `cleave`, a trap or a radial force reaches when it's given `radius`. A bigger detection radius lets it reach further
+    fn get_area_radius(radius: f32, detection_radius: Option<f32>) -> f32 {
+        match detection_radius {
+            Some(detection_radius) => radius.max(detection_radius),
+            None => radius
         }
     }
-}
 
-mod rpn_operations {
-    use super::{NUMBER_LITERAL, TRUE, FALSE};
+    /// Returns every magical entity within `radius` of the spell that the spell targets
+    fn get_magical_entities_within(&self, radius: f32) -> Vec<Gd<MagicalEntity>> {
+        self.get_magical_entities_within_from(self.base().get_global_position(), radius)
+    }
 
-    pub fn binary_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), &'static str>
-    where
-        T: FnOnce(u64, u64) -> Result<u64, &'static str>
-    {
-        let bool_two = rpn_stack.pop().ok_or_else(|| "Expected value to compare")?;
-        let bool_one = rpn_stack.pop().ok_or_else(|| "Expected value to compare")?;
-        match operation(bool_one, bool_two) {
-            Ok(num) => rpn_stack.push(num),
-            Err(err) => return Err(err)
+    /// Returns every magical entity within `radius` of `center` that the spell targets
+    fn get_magical_entities_within_from(&self, center: Vector3, radius: f32) -> Vec<Gd<MagicalEntity>> {
+        let mut magical_entities = Vec::new();
+
+        let mut space_state = match self.base().get_world_3d().and_then(|world| world.get_direct_space_state()) {
+            Some(space_state) => space_state,
+            None => return magical_entities
         };
-        Ok(())
+
+        let mut detection_shape = SphereShape3D::new_gd();
+        detection_shape.set_radius(radius);
+
+        let mut query = PhysicsShapeQueryParameters3D::new_gd();
+        query.set_shape(&detection_shape.upcast::<Shape3D>());
+        query.set_transform(Transform3D::new(Basis::IDENTITY, center));
+
+        for collision in space_state.intersect_shape(&query).iter_shared() {
+            let magical_entity = match collision.get("collider").and_then(|collider| collider.try_to::<Gd<MagicalEntity>>().ok()) {
+                Some(magical_entity) => magical_entity,
+                None => continue
+            };
+
+            if !self.is_target(&magical_entity.bind()) {
+                continue
+            }
+
+            magical_entities.push(magical_entity);
+        }
+
+        magical_entities
     }
 
-    pub fn compare_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), &'static str>
-    where
-        T: FnOnce(f64, f64) -> bool
-    {
-        let argument_two = f64::from_bits(rpn_stack.pop().ok_or_else(|| "Expected value to compare")?);
-        let _ = rpn_stack.pop().ok_or_else(|| "Expected number literal opcode")?;
-        let argument_one = f64::from_bits(rpn_stack.pop().ok_or_else(|| "Expected value to compare")?);
-        let _ = rpn_stack.pop().ok_or_else(|| "Expected number literal opcode")?;
-        match operation(argument_one, argument_two) {
-            true => rpn_stack.push(TRUE),
-            false => rpn_stack.push(FALSE)
-        };
-        Ok(())
+    /// Deals `damage` to every magical entity within `range` that is inside an arc of `arc_angle` radians centered on the direction the spell is going
+    fn cleave(&mut self, damage: f64, arc_angle: f64, range: f64) {
+        let position = self.base().get_global_position();
+
+        // Spells that aren't moving cleave in the direction they were cast
+        let mut forward = (self.original_direction * self.velocity).normalized_or_zero();
+        if forward == Vector3::ZERO {
+            forward = (self.original_direction * Vector3::FORWARD).normalized_or_zero();
+        }
+
+        for mut magical_entity in self.get_magical_entities_within(Spell::get_area_radius(range as f32, self.detection_radius)) {
+            if !Spell::is_within_arc(forward, magical_entity.get_global_position() - position, arc_angle) {
+                continue
+            }
+
+            if !self.damage_magical_entity(&mut magical_entity, damage) {
+                return
+            }
+        }
     }
 
-    pub fn maths_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), &'static str>
-    where
-        T: FnOnce(f64, f64) -> f64
-    {
-        let argument_two = f64::from_bits(rpn_stack.pop().ok_or_else(|| "Expected value to compare")?);
-        let _ = rpn_stack.pop().ok_or_else(|| "Expected number literal opcode")?;
-        let argument_one = f64::from_bits(rpn_stack.pop().ok_or_else(|| "Expected value to compare")?);
-        let _ = rpn_stack.pop().ok_or_else(|| "Expected number literal opcode")?;
-        rpn_stack.extend(vec![NUMBER_LITERAL, f64::to_bits(operation(argument_one, argument_two))]);
-        Ok(())
+    /// Deals as much of `damage` to `magical_entity` as the spell's energy allows, weakened by damage falloff at the spell's distance from its caster. Returns false if the spell perished
+    fn damage_magical_entity(&mut self, magical_entity: &mut Gd<MagicalEntity>, damage: f64) -> bool {
+        self.entities_hit.insert(magical_entity.instance_id());
+        let damage = Spell::get_damage_after_falloff(damage, self.damage_falloff, self.get_distance_to_caster(self.base().get_global_position()));
+
+        let mut bind_magical_entity = magical_entity.bind_mut();
+        let possible_damage = damage.min(bind_magical_entity.get_energy_to_kill());
+
+        if self.energy - possible_damage < ENERGY_CONSIDERATION_LEVEL {
+            bind_magical_entity.take_damage(self.energy, self.damage_type);
+            self.apply_lifesteal(self.energy);
+            self.perish();
+            return false
+        }
+
+        self.energy -= possible_damage;
+
+        bind_magical_entity.take_damage(possible_damage, self.damage_type);
+        self.apply_vulnerability(&mut bind_magical_entity);
+        self.apply_lifesteal(possible_damage);
+
+        if self.is_done_piercing() {
+            self.perish();
+            return false
+        }
+        true
+    }
+
+    /// Returns true if the spell has a pierce and has damaged as many different magical entities as it allows
+    fn is_done_piercing(&self) -> bool {
+        self.pierce.is_some_and(|pierce| pierce.is_used_up(self.entities_hit.len()))
+    }
+
+    /// Sets the trap off if it's armed and a target is close enough. Returns false if the spell perished
+    fn update_trap(&mut self) -> bool {
+        let trap = match self.trap {
+            Some(trap) => trap,
+            None => return true
+        };
+
+        let now = match self.time {
+            Some(ref time) => time.get_ticks_msec(),
+            None => panic!("Time wasn't created")
+        };
+
+        if !trap.is_armed(now) {
+            return true
+        }
+
+        let position = self.base().get_global_position();
+        let targets = self.get_magical_entities_within(Spell::get_area_radius(trap.trigger_radius as f32, self.detection_radius));
+        let target_distances: Vec<f64> = targets.iter().map(|target| position.distance_to(target.get_global_position()) as f64).collect();
+
+        if !trap.should_trigger(now, &target_distances) {
+            return true
+        }
+
+        for mut target in targets {
+            if !self.damage_magical_entity(&mut target, trap.damage) {
+                return false
+            }
+        }
+
+        self.perish();
+        false
+    }
+
+    /// Whether `offset` points within an arc of `arc_angle` radians centered on `forward`. Something directly on top of the spell counts as inside the arc
+    fn is_within_arc(forward: Vector3, offset: Vector3, arc_angle: f64) -> bool {
+        if offset == Vector3::ZERO {
+            return true
+        }
+
+        forward.angle_to(offset) as f64 <= arc_angle / 2.0
+    }
+
+    /// Moves the spell from `start` for `delta` seconds, reflecting its velocity off any walls in the way. Returns where the spell ends up, or None if the spell perished
+    fn move_with_bounce(&mut self, bounce: Bounce, start: Vector3, delta: f32) -> Option<Vector3> {
+        let mut position = start;
+        let mut time_remaining = delta;
+
+        for _ in 0..MAX_BOUNCES_PER_FRAME {
+            let world_velocity = self.original_direction * self.velocity;
+            let target_position = position + world_velocity * time_remaining;
+
+            let collision = match self.intersect_ray(position, target_position) {
+                Some(collision) if Spell::is_wall(&collision) => collision,
+                _ => return Some(target_position)
+            };
+
+            let impact_position = collision.get("position").map_or(target_position, |position| position.to::<Vector3>());
+            let normal = collision.get("normal").map_or(-world_velocity.normalized_or_zero(), |normal| normal.to::<Vector3>()).normalized_or_zero();
+
+            // Time left after reaching the wall
+            let distance = world_velocity.length() * time_remaining;
+            if distance > 0.0 {
+                time_remaining *= 1.0 - ((impact_position - position).length() / distance).min(1.0);
+            }
+
+            // Reflects the velocity about the wall's normal and slows it down
+            let reflected_velocity = world_velocity - normal * 2.0 * world_velocity.dot(normal);
+            let speed_lost = world_velocity.length() as f64 * bounce.energy_loss;
+            let energy_needed = speed_lost * BOUNCE_ENERGY_PER_SPEED_LOST;
+
+            if self.energy - energy_needed < ENERGY_CONSIDERATION_LEVEL {
+                self.perish();
+                return None
+            }
+
+            self.energy -= energy_needed;
+            self.velocity = self.original_direction.inverse() * (reflected_velocity * (1.0 - bounce.energy_loss) as f32);
+
+            // Moves the spell out of the wall so it doesn't hit it again
+            position = impact_position + normal * BOUNCE_OFFSET;
+        }
+
+        Some(position)
+    }
+
+    /// Casts a ray between two global positions and returns information about the first thing it hit, if anything
+    fn intersect_ray(&self, from: Vector3, to: Vector3) -> Option<Dictionary> {
+        let mut space_state = self.base().get_world_3d()?.get_direct_space_state()?;
+        let query = PhysicsRayQueryParameters3D::create(from, to)?;
+        let collision = space_state.intersect_ray(&query);
+        if collision.is_empty() {
+            None
+        } else {
+            Some(collision)
+        }
+    }
+
+    /// A wall is anything a ray can hit that isn't a magical entity
+    fn is_wall(collision: &Dictionary) -> bool {
+        match collision.get("collider").and_then(|collider| collider.try_to::<Gd<Node>>().ok()) {
+            Some(collider) => collider.try_cast::<MagicalEntity>().is_err(),
+            None => false
+        }
+    }
+
+    /// Splits the spell's energy between fragments that scatter away from the surface that was hit, then perishes
+    fn fragment(&mut self, fragmentation: Fragmentation, impact_position: Vector3, normal: Vector3) {
+        let count = Spell::get_fragment_count(fragmentation.count, self.energy);
+        if count == 0 {
+            self.perish();
+            return
+        }
+        let fragment_energy = self.energy / count as f64;
+
+        let normal = normal.normalized_or_zero();
+        let mut parent = self.base().get_parent();
+        for direction in Spell::get_fragment_directions(normal, count) {
+            let mut fragment = Spell::new_alloc();
+            fragment.set_as_top_level(true);
+
+            {
+                let mut fragment_bind = fragment.bind_mut();
+                fragment_bind.set_energy(fragment_energy);
+                fragment_bind.set_color(self.color);
+                fragment_bind.component_catalogue = self.component_catalogue.clone();
+                fragment_bind.component_efficiency_levels = self.component_efficiency_levels.clone();
+                fragment_bind.damage = self.damage;
+                fragment_bind.damage_type = self.damage_type;
+                fragment_bind.caster = self.caster.clone();
+                fragment_bind.faction = self.faction;
+                fragment_bind.caster_energy_selected = self.caster_energy_selected;
+                fragment_bind.caster_energy_charged = self.caster_energy_charged;
+                fragment_bind.caster_focus = self.caster_focus;
+                fragment_bind.velocity = direction * fragmentation.speed as f32;
+            }
+
+            fragment.set_position(impact_position + normal * FRAGMENT_OFFSET);
+
+            if let Some(ref mut parent) = parent {
+                parent.add_child(&fragment);
+            }
+
+            if let Some(ref mut caster) = self.caster {
+                if caster.is_instance_valid() {
+                    caster.bind_mut().add_spell_cast(fragment);
+                }
+            }
+        }
+
+        self.energy = 0.0;
+        self.perish();
+    }
+
+    /// Fragments with too little energy would perish straight away, so a spell makes fewer of them the less energy it has
+    fn get_fragment_count(count: usize, energy: f64) -> usize {
+        count.min((energy / ENERGY_CONSIDERATION_LEVEL) as usize)
+    }
+
+    /// Fragments are spread evenly in a cone around the normal of the surface, each 45 degrees from it
+    fn get_fragment_directions(normal: Vector3, count: usize) -> Vec<Vector3> {
+        let normal = normal.normalized_or_zero();
+        let mut tangent = normal.cross(Vector3::UP).normalized_or_zero();
+        if tangent == Vector3::ZERO {
+            tangent = Vector3::RIGHT;
+        }
+        let bitangent = normal.cross(tangent);
+
+        (0..count).map(|index| {
+            let angle = std::f32::consts::TAU * index as f32 / count as f32;
+            (normal + tangent * angle.cos() + bitangent * angle.sin()).normalized_or_zero()
+        }).collect()
+    }
+
+    /// Turns the spell into `count` spells with the same repeat sections, sharing its energy and fanned out around the direction it's going
+    fn split(&mut self, count: usize) {
+        // The clones go next to the spell, so there's nothing to split into without a parent
+        let mut parent = match self.base().get_parent() {
+            Some(parent) => parent,
+            None => {
+                godot_warn!("Couldn't split: the spell has no parent");
+                return
+            }
+        };
+
+        let energies = Spell::get_split_energies(count, self.energy);
+        if energies.len() <= 1 {
+            return
+        }
+        let clone_energy = energies[0];
+        let angles = Spell::get_split_angles(energies.len(), SPLIT_SPREAD_ANGLE);
+
+        let position = self.base().get_global_position();
+        for &angle in angles.iter().skip(1) {
+            let mut clone = Spell::new_alloc();
+            clone.set_as_top_level(true);
+
+            {
+                let mut clone_bind = clone.bind_mut();
+                clone_bind.set_energy(clone_energy);
+                clone_bind.set_color(self.color);
+                clone_bind.color_fade = self.color_fade;
+                clone_bind.shape = self.shape;
+                clone_bind.charge_to_shape = self.charge_to_shape;
+                clone_bind.hollow_thickness = self.hollow_thickness;
+                clone_bind.energy_lose_rate = self.energy_lose_rate;
+                clone_bind.component_catalogue = self.component_catalogue.clone();
+                clone_bind.check_component_return_value = self.check_component_return_value;
+                clone_bind.process_instructions = self.process_instructions.clone();
+                clone_bind.collision_instructions = self.collision_instructions.clone();
+                clone_bind.delayed_instructions = self.delayed_instructions.clone();
+                clone_bind.component_efficiency_levels = self.component_efficiency_levels.clone();
+                clone_bind.caster = self.caster.clone();
+                clone_bind.faction = self.faction;
+                clone_bind.caster_energy_selected = self.caster_energy_selected;
+                clone_bind.caster_energy_charged = self.caster_energy_charged;
+                clone_bind.caster_focus = self.caster_focus;
+                clone_bind.damage = self.damage;
+                clone_bind.damage_type = self.damage_type;
+                clone_bind.heal = self.heal;
+                clone_bind.heal_owner = self.heal_owner;
+                clone_bind.siphon_rate = self.siphon_rate;
+                clone_bind.stun_duration = self.stun_duration;
+                clone_bind.absorbing = self.absorbing;
+                clone_bind.spell_push = self.spell_push;
+                clone_bind.radial_force = self.radial_force;
+                clone_bind.collision_layer = self.collision_layer;
+                clone_bind.collision_mask = self.collision_mask;
+                clone_bind.lifesteal = self.lifesteal;
+                clone_bind.original_direction = self.original_direction;
+                clone_bind.velocity = self.velocity.rotated(Vector3::UP, angle);
+                clone_bind.expiry_time = self.expiry_time;
+                clone_bind.fragmentation = self.fragmentation;
+                clone_bind.homing = self.homing;
+                clone_bind.bounce = self.bounce;
+                clone_bind.pierce = self.pierce;
+                clone_bind.damage_falloff = self.damage_falloff;
+                clone_bind.vulnerability_mark = self.vulnerability_mark;
+                clone_bind.familiar = self.familiar;
+                clone_bind.trap = self.trap;
+                if let Some(detection_radius) = self.detection_radius {
+                    clone_bind.set_detection_radius(detection_radius);
+                }
+            }
+
+            clone.set_position(position);
+            parent.add_child(&clone);
+
+            if let Some(ref mut caster) = self.caster {
+                if caster.is_instance_valid() {
+                    caster.bind_mut().add_spell_cast(clone);
+                }
+            }
+        }
+
+        self.energy = clone_energy;
+        self.velocity = self.velocity.rotated(Vector3::UP, angles[0]);
+    }
+
+    /// Makes a spell that runs the child section with `energy_fraction` of this spell's energy, which this spell loses
+    fn spawn_child(&mut self, energy_fraction: f64) {
+        let child_energy = match Spell::get_child_energy(self.energy, energy_fraction, self.generation) {
+            Some(child_energy) => child_energy,
+            None => return
+        };
+
+        // Without a parent the child could never be added to the scene, so it isn't made and the spell keeps its energy
+        let mut parent = match self.base().get_parent() {
+            Some(parent) => parent,
+            None => {
+                godot_warn!("Couldn't spawn child: the spell has no parent to add it to");
+                return
+            }
+        };
+
+        let mut child = Spell::new_alloc();
+        child.set_as_top_level(true);
+
+        let set_instructions_result = {
+            let mut child_bind = child.bind_mut();
+            child_bind.set_energy(child_energy);
+            child_bind.set_color(self.color);
+            child_bind.energy_lose_rate = self.energy_lose_rate;
+            child_bind.component_catalogue = self.component_catalogue.clone();
+            child_bind.check_component_return_value = self.check_component_return_value;
+            child_bind.component_efficiency_levels = self.component_efficiency_levels.clone();
+            let set_instructions_result = child_bind.internal_set_instructions(self.child_instructions.clone());
+            child_bind.original_direction = self.original_direction;
+            child_bind.caster = self.caster.clone();
+            child_bind.faction = self.faction;
+            child_bind.caster_energy_selected = self.caster_energy_selected;
+            child_bind.caster_energy_charged = self.caster_energy_charged;
+            child_bind.caster_focus = self.caster_focus;
+            child_bind.collision_layer = self.collision_layer;
+            child_bind.collision_mask = self.collision_mask;
+            child_bind.generation = self.generation + 1;
+            set_instructions_result
+        };
+
+        if let Err(error) = set_instructions_result {
+            godot_warn!("Couldn't spawn child: {}", error);
+            child.free();
+            return
+        }
+
+        child.set_position(self.base().get_global_position());
+        parent.add_child(&child);
+
+        if let Some(ref mut caster) = self.caster {
+            if caster.is_instance_valid() {
+                caster.bind_mut().add_spell_cast(child);
+            }
+        }
+
+        self.energy -= child_energy;
+    }
+
+    /// A child can be given more than none and no more than all of the spell's energy
+    fn check_child_energy_fraction(energy_fraction: f64) -> Result<(), SpellError> {
+        if !(energy_fraction > 0.0 && energy_fraction <= 1.0) {
+            return Err(SpellError::BadParameterType("Energy fraction must be more than 0 and no more than 1"))
+        }
+        Ok(())
+    }
+
+    /// The energy a child spawned with `energy_fraction` gets, or None if it would be too weak to survive or the spell is too many generations down to spawn children
+    fn get_child_energy(energy: f64, energy_fraction: f64, generation: usize) -> Option<f64> {
+        if generation >= MAX_CHILD_GENERATION {
+            return None
+        }
+        let child_energy = energy * energy_fraction.clamp(0.0, 1.0);
+        (child_energy >= ENERGY_CONSIDERATION_LEVEL).then_some(child_energy)
+    }
+
+    /// Caps how many spells a spell splits into at `MAX_SPLIT_COUNT`, and so that none of them have too little energy to survive
+    fn get_split_count(count: usize, energy: f64) -> usize {
+        count.min(MAX_SPLIT_COUNT).min((energy / ENERGY_CONSIDERATION_LEVEL) as usize)
+    }
+
+    /// Returns the energy of each spell a spell with `energy` splits into when asked for `count`, which share it evenly
+    fn get_split_energies(count: usize, energy: f64) -> Vec<f64> {
+        let count = Spell::get_split_count(count, energy);
+        vec![energy / count as f64; count]
+    }
+
+    /// The energy `split` costs to split into `count` spells. Splitting into more than `MAX_SPLIT_COUNT` costs the same as `MAX_SPLIT_COUNT`, since that's all it makes
+    fn get_split_cost(count: f64) -> f64 {
+        if count < 2.0 {
+            return 0.0
+        }
+        (count.min(MAX_SPLIT_COUNT as f64).floor() - 1.0) * SPLIT_ENERGY_PER_SPELL
+    }
+
+    /// Returns the angle each of `count` split spells is turned by, spread evenly across `spread_angle` and centered on the original direction
+    fn get_split_angles(count: usize, spread_angle: f32) -> Vec<f32> {
+        if count <= 1 {
+            return vec![0.0; count]
+        }
+        (0..count).map(|index| spread_angle * (index as f32 / (count - 1) as f32 - 0.5)).collect()
+    }
+
+    /// Anchors the spell to its parent if the parent is close enough
+    fn anchor(&mut self) {
+        let parent = match self.base().get_parent().map(|node| node.try_cast::<Node3D>()) {
+            Some(Ok(parent)) => parent,
+            _ => {
+                godot_warn!("Couldn't anchor: the spell's parent isn't a Node3D");
+                return
+            }
+        };
+        let distance = (self.base().get_global_position() - parent.get_global_position()).length() as f64;
+        if Sphere::get_radius_from_volume(Spell::get_natural_volume(self.energy, self.config.energy_to_volume)) >= distance {
+            self.anchor_to_node(parent);
+        }
+    }
+
+    /// Makes the spell follow `target` every frame until the anchor is undone or `target` is freed
+    fn anchor_to_node(&mut self, target: Gd<Node3D>) {
+        self.base_mut().set_global_position(target.get_global_position());
+        self.anchor_follow = None;
+        self.anchored_to = Some(target);
+        self.set_visibility(false);
+    }
+
+    fn undo_anchor(&mut self) {
+        if let Some(anchor_follow) = self.anchor_follow.take() {
+            self.release_anchor_follow(anchor_follow);
+        }
+        if self.anchored_to.take().is_none() {
+            return
+        }
+        if !self.form_set {
+            self.set_visibility(true);
+        }
+    }
+
+    fn surmount_anchor_resistance(&mut self) -> bool {
+        let anchored_to = match self.anchored_to {
+            Some(ref anchored_to) => anchored_to.clone(),
+            None => return true
+        };
+
+        // The anchor is let go of if what it's anchored to is freed
+        if !anchored_to.is_instance_valid() {
+            self.undo_anchor();
+            return true
+        }
+
+        let mass = match anchored_to.try_cast::<MagicalEntity>() {
+            Ok(mut magical_entity) => {
+                let spell_owned = magical_entity.bind().owns_spell(self.to_gd());
+                let mut bind_magical_entity = magical_entity.bind_mut();
+
+                // Surmounting magical entity's charged energy
+                if !spell_owned {
+                    let energy_charged = bind_magical_entity.get_energy_charged();
+                    if self.energy >= energy_charged {
+                        bind_magical_entity.set_energy_charged(0.0);
+                        self.energy -= energy_charged;
+                    } else {
+                        bind_magical_entity.set_energy_charged(energy_charged - self.energy);
+                        self.energy = 0.0;
+                        return false
+                    }
+                }
+
+                bind_magical_entity.get_mass()
+            },
+            // Other nodes don't have energy charged to resist with
+            Err(node) => match node.try_cast::<RigidBody3D>() {
+                Ok(rigid_body) => rigid_body.get_mass() as f64,
+                Err(_) => DEFAULT_ANCHOR_MASS
+            }
+        };
+
+        // Surmounting the mass of what the spell is anchored to
+        self.energy -= mass * self.config.mass_movement_cost;
+
+        return self.energy > 0.0
+    }
+
+    /// Returns the velocity an anchored spell gives the magical entity it's anchored to. The spell pushes with the impulse it would give something with
+    /// a mass of 1, so heavier magical entities move less. Magical entities without a mass set are pushed as if they had `DEFAULT_ANCHOR_MASS`
+    fn get_anchored_push(velocity: Vector3, mass: f64) -> Vector3 {
+        let mass = if mass > 0.0 { mass } else { DEFAULT_ANCHOR_MASS };
+        velocity / mass as f32
+    }
+
+    fn set_form(&mut self, form_code: u64) {
+        if self.form_set {
+            self.undo_form();
+        }
+        let form_config = self.config.forms.get(&form_code).expect("Expected form code to map to a form");
+
+        let scene: Gd<PackedScene> = load(&form_config.path);
+
+        let shape = form_config.shape;
+
+        self.form_set = true;
+        self.shape = Some(shape);
+        self.handle_charge_to_shape();
+        self.set_shape(shape);
+        self.set_visibility(false);
+        let mut instantiated_scene = scene.instantiate().expect("Expected to be able to create scene").cast::<Node3D>();
+        instantiated_scene.set_name(FORM_NAME);
+        instantiated_scene.set_basis(self.original_direction);
+        self.base_mut().add_child(&instantiated_scene);
+    }
+
+    fn undo_form(&mut self) {
+        if self.form_set == false {
+            return
+        }
+        self.form_set = false;
+        let form: Gd<Node> = self.base_mut().get_node_as(FORM_NAME);
+        form.free();
+        self.shape = None;
+        self.update_natural_shape();
+        if self.anchored_to == None {
+            self.set_visibility(true);
+        }
+    }
+
+    fn handle_charge_to_shape(&mut self) {
+        if self.charge_to_shape {
+            match self.shape {
+                Some(ref shape) => {
+                    let energy_needed = Spell::get_natural_energy(shape.get_volume(), self.config.energy_to_volume);
+                    (self.energy, self.energy_wanted) = Spell::get_shape_charge(self.energy, energy_needed);
+                },
+                None => {}
+            }
+        }
+    }
+
+    /// Returns the energy a spell charged to its shape keeps and the energy it wants topping up to. Energy over what the shape needs is lost.
+    /// The shape decides how much energy the spell has, so it replaces anything `recharge_to` wanted instead of adding to it
+    fn get_shape_charge(energy: f64, energy_needed: f64) -> (f64, f64) {
+        (energy.min(energy_needed), energy_needed)
+    }
+
+    /// Asks the caster to top the spell up to `energy_wanted`. Asking more than once before the caster gives it energy asks for the most any of them wanted, rather than adding them up
+    fn request_energy(&mut self, energy_wanted: f64) {
+        self.energy_wanted = self.energy_wanted.max(energy_wanted);
+    }
+
+    /// Called by the caster once a frame. Gives the spell what it's missing of the energy it wants, up to `energy_available`, and returns how much was given.
+    /// What's missing is worked out now rather than when it was asked for, so energy the spell gained or lost in between isn't counted twice
+    pub fn fulfil_energy_request(&mut self, energy_available: f64) -> f64 {
+        let energy_given = Spell::get_energy_given(self.energy, self.energy_wanted, energy_available);
+        self.energy += energy_given;
+        self.energy_wanted = 0.0;
+        energy_given
+    }
+
+    fn get_energy_given(energy: f64, energy_wanted: f64, energy_available: f64) -> f64 {
+        (energy_wanted - energy).clamp(0.0, energy_available.max(0.0))
+    }
+
+    /// Keeps a thickness given by `hollow_shape` at least `MINIMUM_HOLLOW_THICKNESS`
+    fn get_clamped_hollow_thickness(thickness: f64) -> f64 {
+        thickness.max(MINIMUM_HOLLOW_THICKNESS)
+    }
+
+    /// Remakes the spell's shape, such as after it's been hollowed. Nothing changes while a form or anchor hides the shape or while it's a beam,
+    /// and the change shows the next time the shape is made
+    fn refresh_shape(&mut self) {
+        if self.form_set || self.anchored_to.is_some() || self.beam.is_some() {
+            return
+        }
+        match self.shape {
+            Some(shape) => self.set_shape(shape),
+            None => self.update_natural_shape()
+        }
+    }
+
+    fn update_natural_shape(&mut self) {
+        self.set_shape(Shape::Sphere(Sphere::from_volume(Spell::get_natural_volume(self.energy, self.config.energy_to_volume))));
+    }
+
+    fn get_control_needed_for_shape(&self, shape_option: Option<Shape>) -> f64 {
+        let shape = match shape_option {
+            Some(ref shape) => shape,
+            None => return 0.0
+        };
+        Spell::get_shape_control(shape, self.energy, self.config.energy_to_volume)
+    }
+
+    /// Returns the extra control needed to hold a spell with `energy` in `shape`, which grows the further the shape is from the spell's natural volume
+    fn get_shape_control(shape: &Shape, energy: f64, energy_to_volume: f64) -> f64 {
+        let volume_multiplier = shape.get_volume() / (energy * energy_to_volume);
+        (E.powf(volume_multiplier - 1.0) + E.powf((1.0 / volume_multiplier) - 1.0) - 2.0) * energy
+    }
+
+    /// Returns the energy needed to take `shape`, which is the control needed to hold it with the energy the spell will have afterwards.
+    /// Spells charged to their shape always fit it, so only spells that don't charge to their shape pay for distorting it
+    fn get_shape_energy(shape: &Shape, energy: f64, charge_to_shape: bool, energy_to_volume: f64) -> f64 {
+        let energy = if charge_to_shape { shape.get_volume() / energy_to_volume } else { energy };
+        Spell::get_shape_control(shape, energy, energy_to_volume)
+    }
+
+    fn get_control_needed(&self) -> f64 {
+        self.energy + self.get_control_needed_for_shape(self.shape)
+    }
+
+    fn get_natural_volume(energy: f64, energy_to_volume: f64) -> f64 {
+        energy * energy_to_volume
+    }
+
+    // get_natural_energy is the inverse function of get_natural_volume
+    fn get_natural_energy(volume: f64, energy_to_volume: f64) -> f64 {
+        volume / energy_to_volume
+    }
+
+    /// The radius of the sphere a spell with `energy` takes when it hasn't been given a shape
+    fn get_natural_radius(energy: f64, energy_to_volume: f64) -> f64 {
+        Sphere::get_radius_from_volume(Spell::get_natural_volume(energy.max(0.0), energy_to_volume))
+    }
+}
+
+impl HasShape for Spell {
+    fn set_shape(&mut self, shape: Shape) {
+        // Collision shape
+        let mut collision_shape_exists = false;
+
+        let mut collision_shape = match self.base().try_get_node_as::<CollisionShape3D>(SPELL_COLLISION_SHAPE_NAME) {
+            Some(collision_shape) => {
+                collision_shape_exists = true;
+                collision_shape
+            },
+            None => {
+                let mut collision_shape = CollisionShape3D::new_alloc();
+                collision_shape.set_name(SPELL_COLLISION_SHAPE_NAME);
+                collision_shape
+            }
+        };
+
+        match self.base().try_get_node_as::<CsgPrimitive3D>(SPELL_CSG_SHAPE_NAME) {
+            Some(csg) => csg.free(),
+            None => {}
+        };
+
+        // Material
+        let mut csg_material = StandardMaterial3D::new_gd();
+
+        // Player defined material properties
+        csg_material.set_albedo(self.color);
+
+        // Constant material properties
+        csg_material.set_transparency(Transparency::ALPHA); // Transparency type
+        csg_material.set_feature(Feature::EMISSION, true); // Allows spell to emit light
+        csg_material.set_emission(self.color); // Chooses what light to emit
+
+        match shape {
+            Shape::Sphere(sphere) => {
+                // Creating sphere shape
+                let mut shape = SphereShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                shape.set_radius(sphere.radius as f32);
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+
+                // Creating visual representation of spell in godot
+                let mut csg_sphere = CsgSphere3D::new_alloc();
+                csg_sphere.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_sphere.set_rings(CSG_SPHERE_DETAIL.0);
+                csg_sphere.set_radial_segments(CSG_SPHERE_DETAIL.1);
+                csg_sphere.set_radius(sphere.radius as f32);
+                csg_sphere.set_material(&csg_material);
+                self.base_mut().add_child(&csg_sphere.upcast::<Node>());
+            },
+            Shape::Cube(cube) => {
+                // Creating box shape
+                let mut shape = BoxShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                let box_size = Vector3 { x: cube.x as f32, y: cube.y as f32, z: cube.z as f32 };
+                shape.set_size(box_size);
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+                collision_shape.set_basis(self.original_direction);
+
+                // Creating visual representation of spell in godot
+                let mut csg_box = CsgBox3D::new_alloc();
+                csg_box.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_box.set_size(box_size);
+                csg_box.set_material(&csg_material);
+                csg_box.set_basis(self.original_direction);
+                self.base_mut().add_child(&csg_box.upcast::<Node>());
+            },
+            Shape::Cylinder(cylinder) => {
+                // Rotates the cylinder so that its height goes along the spell's forward direction
+                let cylinder_basis = self.original_direction * Basis::from_axis_angle(Vector3::RIGHT, -std::f32::consts::FRAC_PI_2);
+
+                // Creating cylinder shape
+                let mut shape = CylinderShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                shape.set_radius(cylinder.radius as f32);
+                shape.set_height(cylinder.height as f32);
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+                collision_shape.set_basis(cylinder_basis);
+
+                // Creating visual representation of spell in godot
+                let mut csg_cylinder = CsgCylinder3D::new_alloc();
+                csg_cylinder.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_cylinder.set_radius(cylinder.radius as f32);
+                csg_cylinder.set_height(cylinder.height as f32);
+                csg_cylinder.set_sides(CSG_CYLINDER_SIDES);
+                csg_cylinder.set_material(&csg_material);
+                csg_cylinder.set_basis(cylinder_basis);
+                self.base_mut().add_child(&csg_cylinder.upcast::<Node>());
+            },
+            Shape::Cone(cone) => {
+                // Cones point up, so this turns the point towards the back and the base towards the spell's forward direction
+                let cone_basis = self.original_direction * Basis::from_axis_angle(Vector3::RIGHT, std::f32::consts::FRAC_PI_2);
+
+                // Creating cone shape. A cylinder collider would hit things outside the taper, so the collider is the cone's corners
+                let mut shape = ConvexPolygonShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                shape.set_points(&PackedVector3Array::from(&cone.get_collision_points(CSG_CYLINDER_SIDES)[..]));
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+                collision_shape.set_basis(cone_basis);
+
+                // Creating visual representation of spell in godot
+                let mut csg_cone = CsgCylinder3D::new_alloc();
+                csg_cone.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_cone.set_cone(true);
+                csg_cone.set_radius(cone.radius as f32);
+                csg_cone.set_height(cone.height as f32);
+                csg_cone.set_sides(CSG_CYLINDER_SIDES);
+                csg_cone.set_material(&csg_material);
+                csg_cone.set_basis(cone_basis);
+                self.base_mut().add_child(&csg_cone.upcast::<Node>());
+            },
+            Shape::Capsule(capsule) => {
+                // Rotates the capsule so that its height goes along the spell's forward direction
+                let capsule_basis = self.original_direction * Basis::from_axis_angle(Vector3::RIGHT, -std::f32::consts::FRAC_PI_2);
+                let total_height = capsule.get_total_height() as f32;
+
+                // Creating capsule shape
+                let mut shape = CapsuleShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                shape.set_radius(capsule.radius as f32);
+                shape.set_height(total_height);
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+                collision_shape.set_basis(capsule_basis);
+
+                // Creating visual representation of spell in godot. There's no CSG capsule, so a capsule mesh is used instead
+                let mut mesh = CapsuleMesh::new_gd();
+                mesh.set_radius(capsule.radius as f32);
+                mesh.set_height(total_height);
+                mesh.set_radial_segments(CSG_SPHERE_DETAIL.1);
+                mesh.set_rings(CSG_SPHERE_DETAIL.0);
+
+                let mut csg_capsule = CsgMesh3D::new_alloc();
+                csg_capsule.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_capsule.set_mesh(&mesh);
+                csg_capsule.set_material(&csg_material);
+                csg_capsule.set_basis(capsule_basis);
+                self.base_mut().add_child(&csg_capsule.upcast::<Node>());
+            },
+            Shape::Pyramid(pyramid) => {
+                // Pyramids point up, so this turns the point towards the back like cones
+                let pyramid_basis = self.original_direction * Basis::from_axis_angle(Vector3::RIGHT, std::f32::consts::FRAC_PI_2);
+
+                // Creating pyramid shape from its point and the corners of its base
+                let mut shape = ConvexPolygonShape3D::new_gd();
+                shape.set_name(SPELL_SHAPE_NAME);
+                shape.set_points(&PackedVector3Array::from(&pyramid.get_collision_points()[..]));
+                collision_shape.set_shape(&shape.upcast::<Shape3D>());
+                collision_shape.set_basis(pyramid_basis);
+
+                // Creating visual representation of spell in godot. A four sided cone has a square base with its corners on the axes,
+                // so it's turned to line its sides up with the axes, then stretched from a 1 by 1 by 1 pyramid to the pyramid's size
+                let stretch = Basis::from_scale(Vector3::new(pyramid.base_length as f32, pyramid.height as f32, pyramid.base_width as f32));
+                let mut csg_pyramid = CsgCylinder3D::new_alloc();
+                csg_pyramid.set_name(SPELL_CSG_SHAPE_NAME);
+                csg_pyramid.set_cone(true);
+                csg_pyramid.set_radius(std::f32::consts::FRAC_1_SQRT_2);
+                csg_pyramid.set_height(1.0);
+                csg_pyramid.set_sides(4);
+                csg_pyramid.set_material(&csg_material);
+                csg_pyramid.set_basis(pyramid_basis * stretch * Basis::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_4));
+                self.base_mut().add_child(&csg_pyramid.upcast::<Node>());
+            }
+        };
+
+        // Hollowing only changes what can be seen. The collider stays the whole outer shape, so a hollow spell still hits what's inside its shell
+        let inner = self.hollow_thickness.and_then(|thickness| shape.get_hollow_inner(thickness));
+        if let (Some(inner), Some(mut csg)) = (inner, self.base().try_get_node_as::<CsgPrimitive3D>(SPELL_CSG_SHAPE_NAME)) {
+            // Children of a CSG node with the subtraction operation are cut out of it. The inner shape is inside the outer one, so it uses the outer shape's rotation
+            let csg_hollow: Option<Gd<CsgPrimitive3D>> = match inner {
+                Shape::Sphere(sphere) => {
+                    let mut csg_sphere = CsgSphere3D::new_alloc();
+                    csg_sphere.set_rings(CSG_SPHERE_DETAIL.0);
+                    csg_sphere.set_radial_segments(CSG_SPHERE_DETAIL.1);
+                    csg_sphere.set_radius(sphere.radius as f32);
+                    csg_sphere.set_material(&csg_material);
+                    Some(csg_sphere.upcast())
+                },
+                Shape::Cube(cube) => {
+                    let mut csg_box = CsgBox3D::new_alloc();
+                    csg_box.set_size(Vector3 { x: cube.x as f32, y: cube.y as f32, z: cube.z as f32 });
+                    csg_box.set_material(&csg_material);
+                    Some(csg_box.upcast())
+                },
+                // `get_hollow_inner` only gives back spheres and cubes
+                _ => None
+            };
+            if let Some(mut csg_hollow) = csg_hollow {
+                csg_hollow.set_name(SPELL_CSG_HOLLOW_NAME);
+                csg_hollow.set_operation(Operation::SUBTRACTION);
+                csg.add_child(&csg_hollow.upcast::<Node>());
+            }
+        }
+
+        if !collision_shape_exists {
+            self.base_mut().add_child(&collision_shape.upcast::<Node>());
+        }
+    }
+
+    fn set_visibility(&mut self, visible: bool) {
+        let mut csg: Gd<CsgPrimitive3D> = self.base_mut().get_node_as(SPELL_CSG_SHAPE_NAME);
+        csg.set_visible(visible);
+    }
+}
+
+#[godot_api]
+impl Spell {
+    /// Reduces the energy of the spell by `damage`
+    #[func]
+    fn take_damage(&mut self, damage: f64) {
+        self.energy -= damage;
+    }
+
+    /// Returns the energy left after losing `energy_lose_rate` of it every second for `delta` seconds
+    fn get_decayed_energy(energy: f64, energy_lose_rate: f64, delta: f64) -> f64 {
+        energy - energy * energy_lose_rate * delta
+    }
+
+    /// Keeps an energy lose rate given by `set_decay` between 0 and `MAX_ENERGY_LOSE_RATE`
+    fn get_clamped_energy_lose_rate(energy_lose_rate: f64) -> f64 {
+        energy_lose_rate.clamp(0.0, MAX_ENERGY_LOSE_RATE)
+    }
+
+    /// Returns the basis that turns forward to face `direction`, or None if `direction` has no length
+    fn get_direction_basis(direction: Vector3) -> Option<Basis> {
+        let direction = direction.normalized_or_zero();
+        if direction == Vector3::ZERO || !direction.is_finite() {
+            return None
+        }
+
+        let axis = Vector3::FORWARD.cross(direction);
+        if axis.length() > 1e-6 {
+            return Some(Basis::from_axis_angle(axis.normalized(), Vector3::FORWARD.angle_to(direction)))
+        }
+
+        // Directions along the forward axis can't be turned to around an axis from the cross product
+        if direction.dot(Vector3::FORWARD) > 0.0 {
+            Some(Basis::IDENTITY)
+        } else {
+            Some(Basis::from_axis_angle(Vector3::UP, std::f32::consts::PI))
+        }
+    }
+
+    /// Returns the way `caster_direction` faces as seen from a spell facing `original_direction`, which is what the spell's velocity needs to be to go that way
+    fn get_local_forward(original_direction: Basis, caster_direction: Basis) -> Vector3 {
+        original_direction.inverse() * (caster_direction * Vector3::FORWARD)
+    }
+
+    #[func]
+    fn set_original_direction(&mut self, original_direction: Basis) {
+        self.original_direction = original_direction
+    }
+
+    #[func]
+    fn get_original_direction(&self) -> Basis {
+        self.original_direction
+    }
+
+    /// Returns the name set in the spell's about section, or an empty string if it doesn't have one
+    #[func]
+    fn get_spell_name(&self) -> GString {
+        GString::from(self.spell_name.as_str())
+    }
+
+    /// Returns the description set in the spell's about section, or an empty string if it doesn't have one
+    #[func]
+    fn get_spell_description(&self) -> GString {
+        GString::from(self.spell_description.as_str())
+    }
+
+    /// Debug only: Returns the spell's velocity before it's rotated by the original direction
+    #[func]
+    fn get_velocity(&self) -> Vector3 {
+        self.velocity
+    }
+
+    /// Debug only: Sets the spell's velocity directly without using any energy, unlike the `give_velocity` component
+    #[func]
+    fn set_velocity_debug(&mut self, velocity: Vector3) {
+        self.velocity = velocity
+    }
+
+    /// Returns the energy the spell currently has. On anchored spells this drops every frame as the anchor's resistance is overcome
+    #[func]
+    fn get_current_energy(&self) -> f64 {
+        self.energy
+    }
+
+    /// Returns the control the spell takes up from its caster. Anchored spells still take up their caster's control
+    #[func]
+    fn get_control_cost(&self) -> f64 {
+        self.get_control_needed()
+    }
+
+    /// Makes `get_time` count `scale` seconds for every real second, for slowing down or pausing spells. Negative scales are treated as 0
+    #[func]
+    fn set_time_scale(&mut self, scale: f64) {
+        self.clock.scale = scale.max(0.0);
+    }
+
+    #[func]
+    fn get_time_scale(&self) -> f64 {
+        self.clock.scale
+    }
+
+    /// Anchors the spell to any node, which it follows until `undo_anchor` is cast or the node is freed. Only magical entities are pushed by the spell's velocity
+    #[func]
+    fn anchor_to(&mut self, node: Gd<Node3D>) {
+        self.anchor_to_node(node);
+    }
+
+    /// Sets the physics layer the spell is on and the layers of the bodies it can hit, so spells can ignore some geometry or only affect some groups.
+    /// The mask doesn't affect anchoring, as anchored spells work on the entity they're anchored to directly and don't hit the bodies overlapping them
+    #[func]
+    fn set_collision_profile(&mut self, layer: u32, mask: u32) {
+        self.collision_layer = layer;
+        self.collision_mask = mask;
+        self.base_mut().set_collision_layer(layer);
+        self.base_mut().set_collision_mask(mask);
+        if let Some(mut detection_area) = self.base().try_get_node_as::<Area3D>(SPELL_DETECTION_AREA_NAME) {
+            detection_area.set_collision_mask(mask);
+        }
+    }
+
+    /// Makes healing, siphoning, homing and components like `target_health` find magical entities within `radius` of the spell, however big or small its shape is.
+    /// `cleave`, traps and radial forces reach at least as far. Damage is otherwise still only dealt to what the spell's shape touches. A radius of 0 or less goes back to using the spell's shape
+    #[func]
+    fn set_detection_radius(&mut self, radius: f32) {
+        let detection_area = self.base().try_get_node_as::<Area3D>(SPELL_DETECTION_AREA_NAME);
+        self.detection_radius = Spell::get_detection_area_radius(radius);
+        let radius = match self.detection_radius {
+            Some(radius) => radius,
+            None => {
+                // The area might be in the middle of a physics callback, so it's freed at the end of the frame instead of straight away
+                if let Some(mut detection_area) = detection_area {
+                    detection_area.set_name(SPELL_REMOVED_DETECTION_AREA_NAME);
+                    detection_area.queue_free();
+                }
+                return
+            }
+        };
+
+        let mut detection_area = match detection_area {
+            Some(detection_area) => detection_area,
+            None => {
+                let mut detection_area = Area3D::new_alloc();
+                detection_area.set_name(SPELL_DETECTION_AREA_NAME);
+                // Nothing collides with the detection area, it only looks for bodies
+                detection_area.set_collision_layer(0);
+                detection_area.set_monitorable(false);
+
+                let mut collision_shape = CollisionShape3D::new_alloc();
+                collision_shape.set_name(SPELL_DETECTION_SHAPE_NAME);
+                detection_area.add_child(&collision_shape);
+                self.base_mut().add_child(&detection_area);
+                detection_area
+            }
+        };
+        detection_area.set_collision_mask(self.collision_mask);
+
+        let mut shape = SphereShape3D::new_gd();
+        shape.set_radius(radius);
+        if let Some(mut collision_shape) = detection_area.try_get_node_as::<CollisionShape3D>(SPELL_DETECTION_SHAPE_NAME) {
+            collision_shape.set_shape(&shape.upcast::<Shape3D>());
+        }
+    }
+
+    /// Makes the spell's randomness the same every time it's cast. Calling it after the spell is added to the scene tree restarts the sequence from the seed
+    #[func]
+    fn set_random_seed(&mut self, seed: i64) {
+        self.random_seed = Some(seed as u64);
+        self.rng = SpellRng::new(seed as u64);
+    }
+
+    /// Debug only: Enables measuring how long the spell's instructions take to run, which is read with `get_last_vm_time_us`
+    #[func]
+    fn set_debug_vm_timing(&mut self, enabled: bool) {
+        self.vm_timer.enabled = enabled
+    }
+
+    /// Debug only: Returns how many microseconds the instructions took the last time they ran, added up over every section that ran in the same frame
+    #[func]
+    fn get_last_vm_time_us(&self) -> u64 {
+        self.vm_timer.last_time_us
+    }
+
+    /// Checks instructions against the component catalogue to see if the player is allowed to cast all components in the spell and with the parameters entered
+    #[func]
+    fn check_allowed_to_cast(instructions_json: GString, component_catalogue_path: GString) -> Dictionary {
+        let component_catalogue: ComponentCatalogue = godot_json_saver::from_path(&component_catalogue_path.to_string()).unwrap();
+        let (allowed_to_cast, denial_reason) = match Spell::internal_check_allowed_to_cast(Spell::translate_instructions(&instructions_json), &component_catalogue) {
+            Ok(_) => (true, ""),
+            Err(error) => (false, error.get_message())
+        };
+        return dict! {"allowed_to_cast": allowed_to_cast, "denial_reason": denial_reason}
+    }
+
+    #[func]
+    fn add_component(&mut self, component: GString) {
+        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
+        let number_of_parameters = Spell::get_number_of_component_parameters(&component_code).expect("Component doesn't exist");
+        let mut parameter_restrictions: Vec<Vec<&str>> = Vec::new();
+        for _ in 0..number_of_parameters {
+            parameter_restrictions.push(vec!["ANY"]);
+        }
+        Spell::add_component_to_component_catalogue(component_code, parameter_restrictions, &mut self.component_catalogue);
+    }
+
+    #[func]
+    fn remove_component(&mut self, component: GString) {
+        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
+        self.component_catalogue.component_catalogue.remove(&component_code);
+    }
+
+    #[func]
+    fn add_restricted_component(&mut self, component: GString, parameter_restrictions: GString) {
+        let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
+        let string_parameter_restrictions = parameter_restrictions.to_string();
+        let parameter_restrictions: Vec<Vec<&str>> = serde_json::from_str(&string_parameter_restrictions).expect("Couldn't parse JSON");
+        Spell::add_component_to_component_catalogue(component_code, parameter_restrictions, &mut self.component_catalogue);
+    }
+
+    #[func]
+    fn set_efficiency_levels(&mut self, efficiency_levels_bytecode_json: GString) {
+        let json_string = efficiency_levels_bytecode_json.to_string();
+
+        match serde_json::from_str(&json_string) {
+            Ok(serde_json::Value::Object(efficiency_levels_object)) => {
+                let mut temp_hashmap: HashMap<u64, f64> = HashMap::new();
+                for (key, value) in efficiency_levels_object {
+                    if let (Ok(parsed_key), Some(parsed_value)) = (key.parse::<u64>(), value.as_f64()) {
+                        temp_hashmap.insert(parsed_key, parsed_value);
+                    }
+                }
+                self.component_efficiency_levels = temp_hashmap;
+            },
+            Ok(_) => panic!("Invalid: Must be dictionary"),
+            Err(_) => panic!("Invalid Json")
+        }
+    }
+
+    #[func]
+    fn get_bytecode_efficiency_levels(efficiency_levels_json: GString) -> GString {
+        let json_string = efficiency_levels_json.to_string();
+
+        match serde_json::from_str(&json_string) {
+            Ok(serde_json::Value::Object(efficiency_levels_object)) => {
+                let mut return_hashmap: HashMap<u64, f64> = HashMap::new();
+                for (key, value) in efficiency_levels_object {
+                    if let (Some(parsed_key), Some(parsed_value)) = (spelltranslator::get_component_num(&key), value.as_f64()) {
+                        return_hashmap.insert(parsed_key, parsed_value);
+                    }
+                }
+                let json_object: serde_json::Value = json!(return_hashmap);
+                GString::from(json_object.to_string())
+            },
+            Ok(_) => panic!("Invalid: Must be dictionary"),
+            Err(_) => panic!("Invalid Json")
+        }
+    }
+
+    /// Takes instructions in the format of a json list which can be obtained from the output of the method `get_bytecode_instructions`. The instructions are called once the spell is put in the scene tree.
+    /// Invalid instructions are rejected with a warning, leaving the spell without any
+    #[func]
+    fn set_instructions(&mut self, instructions_json: GString) {
+        let instructions = Spell::translate_instructions(&instructions_json);
+        if let Err(error) = validator::validate_instructions(&instructions) {
+            godot_warn!("Couldn't set instructions: {}", error);
+            return
+        }
+        if let Err(error) = self.internal_set_instructions(instructions) {
+            godot_warn!("Couldn't set instructions: {}", error);
+        }
+    }
+
+    /// Takes in spell instructions in string format and returns a dictionary containing `instructions` (a json list), `successful` (a boolean), `error_message` (a string)
+    /// and `error_code` (an integer saying what kind of error it was, or 0 if there wasn't one)
+    #[func]
+    fn get_bytecode_instructions(instructions_json: GString) -> Dictionary {
+        // Returns a dictionary of the instructions and successful
+        let config = saver::Config::get_config().unwrap_or_else(|err| {
+            godot_warn!("Config.toml couldn't be opened, so custom_translation and component_aliases can't be used: {err}");
+            Config::default()
+        });
+
+        let (instructions, successful, error_message, error_code) = match spelltranslator::parse_spell(&instructions_json.to_string(), Some(&config)) {
+            Ok(succesful_instructions) => (succesful_instructions, true, GString::new(), 0),
+            Err(error) => (Vec::new(), false, GString::from(error.to_string()), error.message.get_code())
+        };
+        return dict!{"instructions": GString::from(serde_json::to_string(&instructions).expect("Failed to parse instructions into json")), "successful": successful, "error_message": error_message, "error_code": error_code}
+    }
+
+    /// Estimates what the when_created and `repeat n times` sections of a spell cast with `max_energy` cost and how much control they need at most.
+    /// Returns a dictionary containing `within_budget` (a boolean), `energy_used` (a float), `peak_control` (a float), `offending_section` (a string) and `reason` (a string)
+    #[func]
+    fn validate_within_budget(instructions_json: GString, max_energy: f64, max_control: f64) -> Dictionary {
+        let config = Config::get_config().unwrap_or_else(|err| {
+            godot_warn!("{err}");
+            Config::default()
+        });
+
+        // Components are costed by their own functions, which need a spell to run on. The spell is never added to the scene, so it doesn't cast anything
+        let mut spell = Spell::new_alloc();
+        let result = {
+            let mut spell_bind = spell.bind_mut();
+            budget::check_within_budget(&Spell::translate_instructions(&instructions_json), max_energy, max_control, &config, |component_code, parameters, energy, charge_to_shape| {
+                spell_bind.energy = energy;
+                spell_bind.charge_to_shape = charge_to_shape;
+                spell_bind.estimate_base_energy(component_code, parameters)
+            })
+        };
+        spell.free();
+
+        match result {
+            Ok(report) => {
+                let (within_budget, offending_section, reason) = match report.exceeded {
+                    Some((section, reason)) => (false, section, reason.get_message()),
+                    None => (true, String::new(), "")
+                };
+                dict! {"within_budget": within_budget, "energy_used": report.energy_used, "peak_control": report.peak_control, "offending_section": GString::from(offending_section), "reason": reason}
+            },
+            Err(error) => dict! {"within_budget": false, "energy_used": 0.0, "peak_control": 0.0, "offending_section": "", "reason": error.get_message()}
+        }
+    }
+
+    /// Returns the energy casting the spell would use straight away, which is the when_created and `repeat n times` sections, using the spell's energy and efficiency levels.
+    /// Nothing about the spell is changed. `repeat` and `on_collision` sections aren't included as they cost energy every time they run. Returns 0 if the instructions are invalid
+    #[func]
+    fn estimate_spell_cost(&mut self, instructions_json: GString) -> f64 {
+        match self.estimate_ready_cost(&Spell::translate_instructions(&instructions_json)) {
+            Ok(energy_needed) => energy_needed,
+            Err(error) => {
+                godot_warn!("Couldn't estimate spell cost: {}", error);
+                0.0
+            }
+        }
+    }
+
+    /// Returns the radius a spell cast with `energy` would have before it's given a shape, without making the spell
+    #[func]
+    fn preview_natural_radius(energy: f64) -> f64 {
+        let config = Config::get_config().unwrap_or_else(|err| {
+            godot_warn!("{err}");
+            Config::default()
+        });
+        Spell::get_natural_radius(energy, config.energy_to_volume)
+    }
+
+    /// Returns the extra control a spell with `energy` would need to hold the shape given, using the same shape codes and sizes as `take_shape`.
+    /// Returns 0 if the shape is invalid or the energy isn't above 0
+    #[func]
+    fn preview_shape_control_cost(energy: f64, shape_code: u64, size_1: f64, size_2: f64, size_3: f64) -> f64 {
+        let config = Config::get_config().unwrap_or_else(|err| {
+            godot_warn!("{err}");
+            Config::default()
+        });
+        if !(energy > 0.0) {
+            return 0.0
+        }
+        match Shape::from_code(shape_code, size_1, size_2, size_3, config.minimum_shape_dimension) {
+            Ok(shape) => Spell::get_shape_control(&shape, energy, config.energy_to_volume),
+            Err(error) => {
+                godot_warn!("Couldn't preview shape control cost: {}", error);
+                0.0
+            }
+        }
+    }
+
+    /// Turns the json list of instructions from `get_bytecode_instructions` into a short code that can be shared
+    #[func]
+    fn export_bytecode(instructions_json: GString) -> GString {
+        GString::from(saver::spell_code::encode(&Spell::translate_instructions(&instructions_json)))
+    }
+
+    /// Turns a code from `export_bytecode` back into a json list of instructions that can be given to `set_instructions`. Returns an empty string if the code is invalid
+    #[func]
+    fn import_bytecode(code: GString) -> GString {
+        let instructions = saver::spell_code::decode(&code.to_string())
+            .and_then(|instructions| validator::validate_instructions(&instructions).map(|_| instructions).map_err(|error| error.get_message()));
+        match instructions {
+            Ok(instructions) => GString::from(serde_json::to_string(&instructions).expect("Failed to parse instructions into json")),
+            Err(error) => {
+                godot_warn!("{error}");
+                GString::new()
+            }
+        }
+    }
+
+    /// Takes in a json list of instructions and returns a dictionary containing `source` (spell code that parses back into the instructions), `successful` (a boolean) and `error_message` (a string)
+    #[func]
+    fn decompile_instructions(instructions_json: GString) -> Dictionary {
+        let (source, successful, error_message) = match spelltranslator::decompile_instructions(&Spell::translate_instructions(&instructions_json)) {
+            Ok(source) => (GString::from(source), true, GString::new()),
+            Err(error) => (GString::new(), false, GString::from(error.get_message()))
+        };
+        return dict!{"source": source, "successful": successful, "error_message": error_message}
+    }
+
+    #[func]
+    fn set_check_component_return_value(&mut self, boolean: bool) {
+        self.check_component_return_value = boolean;
+    }
+
+    #[func]
+    fn get_check_component_return_value(&self) -> bool {
+        self.check_component_return_value
+    }
+
+    /// Once `connect_player()` is called, whenever a component is cast, the provided node's `increase_component_efficiency` method will be called, along with its `start_component_cooldown` method if it has one
+    #[func]
+    fn connect_player(&mut self, player: Gd<Node>) {
+        let update_function = player.callable("increase_component_efficiency");
+        self.base_mut().connect("component_cast", &update_function);
+        // Players that don't use cooldowns don't need the method, and connecting to one that's missing would error every cast
+        if player.has_method("start_component_cooldown") {
+            let cooldown_function = player.callable("start_component_cooldown");
+            self.base_mut().connect("component_cast", &cooldown_function);
+        }
+    }
+
+    #[signal]
+    fn component_cast(component_code: u64, efficiency_increase: f64);
+}
+
+mod boolean_logic { // 100 = true, 101 = false
+    use super::{TRUE, FALSE, SpellError};
+
+    pub fn and(a: u64, b: u64) -> Result<u64, SpellError> {
+        match (a, b) {
+            (TRUE, TRUE) => Ok(TRUE),
+            (TRUE, FALSE) => Ok(FALSE),
+            (FALSE, TRUE) => Ok(FALSE),
+            (FALSE, FALSE) => Ok(FALSE),
+            _ => Err(SpellError::BadParameterType("Boolean logic can only compare booleans"))
+        }
+    }
+
+    pub fn or(a: u64, b: u64) -> Result<u64, SpellError> {
+        match (a, b) {
+            (TRUE, TRUE) => Ok(TRUE),
+            (TRUE, FALSE) => Ok(TRUE),
+            (FALSE, TRUE) => Ok(TRUE),
+            (FALSE, FALSE) => Ok(FALSE),
+            _ => Err(SpellError::BadParameterType("Boolean logic can only compare booleans"))
+        }
+    }
+
+    pub fn xor(a: u64, b: u64) -> Result<u64, SpellError> {
+        match (a, b) {
+            (TRUE, TRUE) => Ok(FALSE),
+            (TRUE, FALSE) => Ok(TRUE),
+            (FALSE, TRUE) => Ok(TRUE),
+            (FALSE, FALSE) => Ok(FALSE),
+            _ => Err(SpellError::BadParameterType("Boolean logic can only compare booleans"))
+        }
+    }
+
+    pub fn not(a: u64) -> Result<u64, SpellError> {
+        match a {
+            TRUE => Ok(FALSE),
+            FALSE => Ok(TRUE),
+            _ => Err(SpellError::BadParameterType("Not can only be used on booleans"))
+        }
+    }
+
+    pub fn bool_to_num(boolean: bool) -> u64 {
+        match boolean {
+            true => TRUE,
+            false => FALSE
+        }
+    }
+
+    pub fn num_to_bool(num: u64) -> Result<bool, SpellError> {
+        match num {
+            TRUE => Ok(true),
+            FALSE => Ok(false),
+            _ => Err(SpellError::BadParameterType("Invalid number: Cannot translate to boolean"))
+        }
+    }
+}
+
+mod rpn_operations {
+    use super::{NUMBER_LITERAL, TRUE, FALSE, SpellError};
+
+    pub fn binary_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), SpellError>
+    where
+        T: FnOnce(u64, u64) -> Result<u64, SpellError>
+    {
+        let bool_two = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?;
+        let bool_one = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?;
+        match operation(bool_one, bool_two) {
+            Ok(num) => rpn_stack.push(num),
+            Err(err) => return Err(err)
+        };
+        Ok(())
+    }
+
+    pub fn compare_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), SpellError>
+    where
+        T: FnOnce(f64, f64) -> bool
+    {
+        let argument_two = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        let argument_one = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        match operation(argument_one, argument_two) {
+            true => rpn_stack.push(TRUE),
+            false => rpn_stack.push(FALSE)
+        };
+        Ok(())
+    }
+
+    pub fn maths_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), SpellError>
+    where
+        T: FnOnce(f64, f64) -> f64
+    {
+        let argument_two = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        let argument_one = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to compare"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        rpn_stack.extend(vec![NUMBER_LITERAL, f64::to_bits(operation(argument_one, argument_two))]);
+        Ok(())
+    }
+
+    pub fn ternary_maths_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), SpellError>
+    where
+        T: FnOnce(f64, f64, f64) -> f64
+    {
+        let argument_three = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to use"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        let argument_two = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to use"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        let argument_one = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to use"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        rpn_stack.extend(vec![NUMBER_LITERAL, f64::to_bits(operation(argument_one, argument_two, argument_three))]);
+        Ok(())
+    }
+
+    pub fn unary_maths_operation<T>(rpn_stack: &mut Vec<u64>, operation: T) -> Result<(), SpellError>
+    where
+        T: FnOnce(f64) -> Result<f64, SpellError>
+    {
+        let argument = f64::from_bits(rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected value to use"))?);
+        let _ = rpn_stack.pop().ok_or_else(|| SpellError::InvalidInstructions("Expected number literal opcode"))?;
+        rpn_stack.extend(vec![NUMBER_LITERAL, f64::to_bits(operation(argument)?)]);
+        Ok(())
+    }
+
+    pub fn square_root(number: f64) -> Result<f64, SpellError> {
+        if number < 0.0 {
+            return Err(SpellError::Maths("Cannot take the square root of a negative number"))
+        }
+        Ok(number.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_fade_interpolates() {
+        let mut color_fade = ColorFade {
+            start_color: Color { r: 0.0, g: 1.0, b: 0.2, a: SPELL_TRANSPARENCY },
+            target_color: Color { r: 1.0, g: 0.0, b: 0.6, a: SPELL_TRANSPARENCY },
+            duration: 2.0,
+            elapsed: 0.5
+        };
+
+        let color = color_fade.get_color();
+        assert!((color.r - 0.25).abs() < 1e-6);
+        assert!((color.g - 0.75).abs() < 1e-6);
+        assert!((color.b - 0.3).abs() < 1e-6);
+        assert!((color.a - SPELL_TRANSPARENCY).abs() < 1e-6);
+        assert!(!color_fade.is_finished());
+
+        color_fade.elapsed = 3.0;
+        let color = color_fade.get_color();
+        assert!((color.r - 1.0).abs() < 1e-6);
+        assert!(color_fade.is_finished());
+    }
+
+    #[test]
+    fn timed_processes_stop_when_their_time_is_up() {
+        let mut process = Process::new(1, Vec::new()).with_duration(0.05);
+        assert!(!process.is_over());
+        process.count_down(0.03);
+        assert!(!process.is_over());
+        process.count_down(0.03);
+        assert!(process.is_over());
+
+        // A duration of 0 is over before it runs once
+        assert!(Process::new(1, Vec::new()).with_duration(0.0).is_over());
+
+        // Normal repeat sections never stop
+        let mut process = Process::new(1, Vec::new());
+        process.count_down(1000.0);
+        assert!(!process.is_over());
+    }
+
+    #[test]
+    fn all_components_are_allowed_after_adding_all() {
+        let mut component_catalogue = ComponentCatalogue::new();
+        assert!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT, PERISH], &component_catalogue).is_err());
+
+        component_catalogue.add_all_components();
+        for (&component_code, (_, parameter_types, _)) in COMPONENT_TO_FUNCTION_MAP.iter() {
+            assert!(component_catalogue.contains(component_code));
+
+            let mut instructions = vec![WHEN_CREATED_SECTION, COMPONENT, component_code];
+            for &parameter_type in parameter_types.iter() {
+                match parameter_type {
+                    BOOLEAN => instructions.push(TRUE),
+                    _ => instructions.extend([NUMBER_LITERAL, f64::to_bits(2.5)])
+                }
+            }
+            assert_eq!(Spell::internal_check_allowed_to_cast(instructions, &component_catalogue), Ok(()));
+        }
+    }
+
+    #[test]
+    fn truncated_instructions_are_not_allowed_to_cast() {
+        let mut component_catalogue = ComponentCatalogue::new();
+        component_catalogue.add_all_components();
+
+        // Saved spells can be cut short, which is an error rather than a panic
+        assert_eq!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT], &component_catalogue), Err(SpellError::InvalidInstructions("Expected component")));
+        assert_eq!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0], &component_catalogue), Err(SpellError::InvalidInstructions("Expected parameter")));
+        assert_eq!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL], &component_catalogue), Err(SpellError::InvalidInstructions("Expected number after number literal opcode")));
+        assert_eq!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, 12345], &component_catalogue), Err(SpellError::InvalidInstructions("Invalid parameter")));
+    }
+
+    #[test]
+    fn saving_a_catalogue_twice_gives_the_same_json() {
+        // Each catalogue's map hashes differently, so the order they're filled in doesn't carry over to the json
+        let mut first_catalogue = ComponentCatalogue::new();
+        first_catalogue.add_all_components();
+        let mut second_catalogue = ComponentCatalogue::new();
+        second_catalogue.add_all_components();
+
+        let saved = godot_json_saver::to_json(first_catalogue.clone()).unwrap();
+        assert_eq!(saved, godot_json_saver::to_json(first_catalogue).unwrap());
+        assert_eq!(saved, godot_json_saver::to_json(second_catalogue).unwrap());
+
+        let loaded: ComponentCatalogue = godot_json_saver::from_json(&saved).unwrap();
+        assert_eq!(godot_json_saver::to_json(loaded).unwrap(), saved);
+    }
+
+    #[test]
+    fn delayed_instructions_wait_for_their_time() {
+        let mut delayed_instructions = vec![
+            DelayedInstructions::new(0.5, vec![COMPONENT, PERISH]),
+            DelayedInstructions::new(0.2, vec![COMPONENT, UNDO_SHAPE])
+        ];
+
+        // Nothing is due until time has passed
+        assert!(DelayedInstructions::take_due(&mut delayed_instructions, 0.0).is_empty());
+        assert_eq!(DelayedInstructions::take_due(&mut delayed_instructions, 0.25), vec![vec![COMPONENT, UNDO_SHAPE]]);
+        assert!(DelayedInstructions::take_due(&mut delayed_instructions, 0.2).is_empty());
+        assert_eq!(DelayedInstructions::take_due(&mut delayed_instructions, 0.05), vec![vec![COMPONENT, PERISH]]);
+        assert!(delayed_instructions.is_empty());
+    }
+
+    #[test]
+    fn delays_in_repeat_sections_dont_pile_up() {
+        // A repeat section reaches the same delay every frame, but only starts waiting again once the last wait is over
+        let mut delayed_instructions = Vec::new();
+        for _ in 0..60 {
+            DelayedInstructions::schedule(&mut delayed_instructions, 1.0, &[COMPONENT, PERISH]);
+        }
+        assert_eq!(delayed_instructions.len(), 1);
+
+        // Different instructions still wait separately
+        DelayedInstructions::schedule(&mut delayed_instructions, 1.0, &[COMPONENT, UNDO_SHAPE]);
+        assert_eq!(delayed_instructions.len(), 2);
+
+        assert_eq!(DelayedInstructions::take_due(&mut delayed_instructions, 1.0).len(), 2);
+        DelayedInstructions::schedule(&mut delayed_instructions, 1.0, &[COMPONENT, PERISH]);
+        assert_eq!(delayed_instructions.len(), 1);
+    }
+
+    #[test]
+    fn pierce_counts_each_entity_once() {
+        let pierce = Pierce::from_count(3.0);
+        let mut entities_hit: HashSet<u64> = HashSet::new();
+
+        // The same entity staying inside the spell for several frames only counts once
+        for _ in 0..5 {
+            entities_hit.insert(1);
+        }
+        assert!(!pierce.is_used_up(entities_hit.len()));
+
+        entities_hit.insert(2);
+        assert!(!pierce.is_used_up(entities_hit.len()));
+        entities_hit.insert(3);
+        assert!(pierce.is_used_up(entities_hit.len()));
+
+        // A count of 0 still hits one entity before perishing
+        assert!(!Pierce::from_count(0.0).is_used_up(0));
+        assert!(Pierce::from_count(0.0).is_used_up(1));
+        assert!(Pierce::from_count(-2.0).is_used_up(1));
+    }
+
+    #[test]
+    fn set_color_clamps_channels() {
+        let color = Spell::get_clamped_color(1.5, 0.25, -2.0, SPELL_TRANSPARENCY);
+        assert_eq!((color.r, color.g, color.b), (1.0, 0.25, 0.0));
+        assert_eq!(color.a, SPELL_TRANSPARENCY);
+    }
+
+    #[test]
+    fn about_transparency_is_clamped() {
+        assert_eq!(Spell::get_clamped_transparency(0.5), 0.5);
+        assert_eq!(Spell::get_clamped_transparency(1.5), 1.0);
+        assert_eq!(Spell::get_clamped_transparency(-0.5), 0.0);
+
+        // Changing the color afterwards keeps the transparency
+        let color = Spell::get_clamped_color(1.0, 0.0, 0.0, Spell::get_clamped_transparency(0.3));
+        assert_eq!(color.a, 0.3);
+    }
+
+    #[test]
+    fn shapes_have_minimum_dimensions() {
+        let cylinder = Shape::Cylinder(Cylinder { radius: 0.0, height: 2.0 }).with_minimum_dimensions();
+        assert_eq!(cylinder.get_volume(), PI * MINIMUM_SHAPE_DIMENSION.powi(2) * 2.0);
+
+        let cylinder = Shape::Cylinder(Cylinder { radius: 1.0, height: 2.0 }).with_minimum_dimensions();
+        assert_eq!(cylinder.get_volume(), PI * 2.0);
+
+        let cube = Shape::Cube(Cube { x: -1.0, y: 1.0, z: 1.0 }).with_minimum_dimensions();
+        assert_eq!(cube.get_volume(), MINIMUM_SHAPE_DIMENSION);
+    }
+
+    #[test]
+    fn cones_are_a_third_of_a_cylinder() {
+        let cone = Shape::Cone(Cone { radius: 2.0, height: 3.0 });
+        assert!((cone.get_volume() - 4.0 * PI).abs() < 1e-9);
+        assert!((cone.get_volume() * 3.0 - Shape::Cylinder(Cylinder { radius: 2.0, height: 3.0 }).get_volume()).abs() < 1e-9);
+
+        assert!(Shape::from_code(CONE, 0.5, 0.001, 0.0, MINIMUM_SHAPE_DIMENSION).is_err());
+        assert!(Shape::from_code(CONE, 0.5, 2.0, 0.0, MINIMUM_SHAPE_DIMENSION).is_ok());
+    }
+
+    #[test]
+    fn capsules_are_a_cylinder_and_a_sphere() {
+        let capsule = Shape::Capsule(Capsule { radius: 1.0, height: 2.0 });
+        assert!((capsule.get_volume() - (2.0 * PI + (4.0 / 3.0) * PI)).abs() < 1e-9);
+        assert!((capsule.get_volume() - Shape::Cylinder(Cylinder { radius: 1.0, height: 2.0 }).get_volume() - Shape::Sphere(Sphere { radius: 1.0 }).get_volume()).abs() < 1e-9);
+        assert_eq!(Capsule { radius: 1.0, height: 2.0 }.get_total_height(), 4.0);
+
+        // Only the radius has a minimum, as a capsule without a middle is still a sphere
+        assert!(Shape::from_code(CAPSULE, 0.001, 2.0, 0.0, MINIMUM_SHAPE_DIMENSION).is_err());
+        assert!(Shape::from_code(CAPSULE, 0.5, 0.0, 0.0, MINIMUM_SHAPE_DIMENSION).is_ok());
+        match Shape::Capsule(Capsule { radius: 0.0, height: -1.0 }).with_minimum_dimensions() {
+            Shape::Capsule(capsule) => assert_eq!((capsule.radius, capsule.height), (MINIMUM_SHAPE_DIMENSION, 0.0)),
+            _ => panic!("Expected a capsule")
+        }
+    }
+
+    #[test]
+    fn pyramids_are_a_third_of_a_box() {
+        let pyramid = Shape::Pyramid(Pyramid { base_length: 2.0, base_width: 3.0, height: 4.0 });
+        assert!((pyramid.get_volume() - 8.0).abs() < 1e-9);
+        assert!((pyramid.get_volume() * 3.0 - Shape::Cube(Cube { x: 2.0, y: 3.0, z: 4.0 }).get_volume()).abs() < 1e-9);
+
+        assert!(Shape::from_code(PYRAMID, 1.0, 1.0, 0.001, MINIMUM_SHAPE_DIMENSION).is_err());
+        assert!(Shape::from_code(PYRAMID, 1.0, 2.0, 3.0, MINIMUM_SHAPE_DIMENSION).is_ok());
+
+        let points = Pyramid { base_length: 2.0, base_width: 4.0, height: 6.0 }.get_collision_points();
+        assert_eq!(points[0], Vector3::new(0.0, 3.0, 0.0));
+        for corner in &points[1..] {
+            assert_eq!((corner.x.abs(), corner.y, corner.z.abs()), (1.0, -3.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn cone_collider_stays_inside_the_taper() {
+        let cone = Cone { radius: 2.0, height: 4.0 };
+        let points = cone.get_collision_points(CSG_CYLINDER_SIDES);
+        assert_eq!(points.len(), CSG_CYLINDER_SIDES as usize + 1);
+        assert_eq!(points[0], Vector3::new(0.0, 2.0, 0.0));
+
+        // Every corner of the base is on the edge of the cone's base
+        for point in &points[1..] {
+            assert!((point.y + 2.0).abs() < 1e-6);
+            assert!((Vector2::new(point.x, point.z).length() - 2.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn take_shape_rejects_thin_shapes() {
+        assert!(Shape::from_code(CUBE, 0.001, 0.001, 10000.0, MINIMUM_SHAPE_DIMENSION).is_err());
+        assert!(Shape::from_code(CYLINDER, 0.5, 0.05, 0.0, 0.1).is_err());
+        // The third size isn't used by spheres, so it isn't checked
+        assert!(Shape::from_code(SPHERE, 0.5, 0.0, 0.0, MINIMUM_SHAPE_DIMENSION).is_ok());
+    }
+
+    #[test]
+    fn distorted_shapes_cost_energy() {
+        let energy = 10.0;
+        let natural_shape = Shape::Sphere(Sphere::from_volume(energy * ENERGY_TO_VOLUME));
+        assert!(Spell::get_shape_energy(&natural_shape, energy, false, ENERGY_TO_VOLUME) < 1e-9);
+
+        let larger_shape = Shape::Sphere(Sphere::from_volume(2.0 * energy * ENERGY_TO_VOLUME));
+        let much_larger_shape = Shape::Sphere(Sphere::from_volume(3.0 * energy * ENERGY_TO_VOLUME));
+        assert!(Spell::get_shape_energy(&larger_shape, energy, false, ENERGY_TO_VOLUME) > 0.0);
+        assert!(Spell::get_shape_energy(&much_larger_shape, energy, false, ENERGY_TO_VOLUME) > Spell::get_shape_energy(&larger_shape, energy, false, ENERGY_TO_VOLUME));
+
+        // Charging to the shape changes the spell's energy to fit it
+        assert!(Spell::get_shape_energy(&much_larger_shape, energy, true, ENERGY_TO_VOLUME) < 1e-9);
+    }
+
+    #[test]
+    fn shape_charged_anchored_spell_energy_converges() {
+        let energy_needed = 10.0;
+        let mut energy = 4.0;
+        let mut energy_charged = 100.0;
+        let mut energy_used = 0.0;
+        for _ in 0..20 {
+            // The spell's frame: anchoring and decay use energy, then it asks to be charged back up to its shape
+            let energy_at_start = energy;
+            energy = Spell::get_decayed_energy(energy - 0.5, ENERGY_LOSE_RATE, 1.0 / 60.0);
+            energy_used += energy_at_start - energy;
+            let (energy_kept, energy_wanted) = Spell::get_shape_charge(energy, energy_needed);
+            energy = energy_kept;
+
+            // The caster's frame gives only what's missing
+            let energy_given = Spell::get_energy_given(energy, energy_wanted, energy_charged);
+            energy += energy_given;
+            energy_charged -= energy_given;
+
+            assert_eq!(energy, energy_needed);
+        }
+        // The caster only paid for what the spell started without and what it used, never the same energy twice
+        assert!((100.0 - energy_charged - (energy_needed - 4.0 + energy_used)).abs() < 1e-9);
+
+        // Having more than the shape holds loses the extra, and nothing is given past what's wanted or available
+        assert_eq!(Spell::get_shape_charge(12.0, energy_needed), (energy_needed, energy_needed));
+        assert_eq!(Spell::get_energy_given(9.0, 10.0, 0.5), 0.5);
+        assert_eq!(Spell::get_energy_given(11.0, 10.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn previewed_radius_matches_a_cast_spell() {
+        let energy = 25.0;
+        // The natural shape a spell takes when it's ready, as in `update_natural_shape`
+        let natural_sphere = Sphere::from_volume(Spell::get_natural_volume(energy, ENERGY_TO_VOLUME));
+        assert_eq!(Spell::get_natural_radius(energy, ENERGY_TO_VOLUME), natural_sphere.radius);
+        assert_eq!(Spell::get_natural_energy(natural_sphere.get_volume(), ENERGY_TO_VOLUME).round(), energy);
+        assert_eq!(Spell::get_natural_radius(-1.0, ENERGY_TO_VOLUME), 0.0);
+
+        // A shape the same size as the natural one needs no extra control, and bigger ones need more
+        let natural_shape = Shape::from_code(SPHERE, natural_sphere.radius, 0.0, 0.0, MINIMUM_SHAPE_DIMENSION).unwrap();
+        assert!(Spell::get_shape_control(&natural_shape, energy, ENERGY_TO_VOLUME) < 1e-9);
+        let larger_shape = Shape::from_code(SPHERE, natural_sphere.radius * 2.0, 0.0, 0.0, MINIMUM_SHAPE_DIMENSION).unwrap();
+        assert!(Spell::get_shape_control(&larger_shape, energy, ENERGY_TO_VOLUME) > 0.0);
+    }
+
+    #[test]
+    fn anchored_push_moves_heavier_entities_less() {
+        let velocity = Vector3::new(0.0, 0.0, -10.0);
+        let light_push = Spell::get_anchored_push(velocity, 1.0);
+        let heavy_push = Spell::get_anchored_push(velocity, 10.0);
+        assert_eq!(light_push, velocity);
+        assert_eq!(heavy_push, Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(light_push.length(), heavy_push.length() * 10.0);
+
+        // Magical entities without a mass aren't pushed infinitely fast
+        assert_eq!(Spell::get_anchored_push(velocity, 0.0), Spell::get_anchored_push(velocity, DEFAULT_ANCHOR_MASS));
+    }
+
+    #[test]
+    fn hollow_shape_cuts_out_an_inner_shape() {
+        let thickness = Spell::get_clamped_hollow_thickness(0.5);
+        match Shape::Sphere(Sphere { radius: 2.0 }).get_hollow_inner(thickness) {
+            Some(Shape::Sphere(inner)) => assert_eq!(inner.radius, 1.5),
+            _ => panic!("Expected a sphere to be hollowed by a smaller sphere")
+        }
+        match Shape::Cube(Cube { x: 2.0, y: 3.0, z: 4.0 }).get_hollow_inner(thickness) {
+            Some(Shape::Cube(inner)) => assert_eq!((inner.x, inner.y, inner.z), (1.0, 2.0, 3.0)),
+            _ => panic!("Expected a cube to be hollowed by a smaller cube")
+        }
+
+        // A shell as thick as the shape leaves nothing to cut out, and only spheres and cubes can be hollowed
+        assert!(Shape::Sphere(Sphere { radius: 0.5 }).get_hollow_inner(thickness).is_none());
+        assert!(Shape::Cylinder(Cylinder { radius: 2.0, height: 2.0 }).get_hollow_inner(thickness).is_none());
+
+        assert_eq!(Spell::get_clamped_hollow_thickness(0.0), MINIMUM_HOLLOW_THICKNESS);
+        assert_eq!(Spell::get_clamped_hollow_thickness(-1.0), MINIMUM_HOLLOW_THICKNESS);
+    }
+
+    #[test]
+    fn changing_faction_changes_targets() {
+        let spell_faction = 1;
+        let ally_faction = 1;
+        let enemy_faction = 2;
+        assert!(!Spell::is_enemy_faction(spell_faction, ally_faction));
+        assert!(Spell::is_enemy_faction(spell_faction, enemy_faction));
+
+        // Converted to the enemy's faction
+        let spell_faction = 2;
+        assert!(Spell::is_enemy_faction(spell_faction, ally_faction));
+        assert!(!Spell::is_enemy_faction(spell_faction, enemy_faction));
+
+        // No faction
+        assert!(Spell::is_enemy_faction(NO_FACTION, ally_faction));
+        assert!(Spell::is_enemy_faction(spell_faction, NO_FACTION));
+    }
+
+    #[test]
+    fn factions_decide_who_is_damaged_and_healed() {
+        let spell_faction = 1;
+        let ally_faction = 1;
+        let enemy_faction = 2;
+
+        // Magical entities on the same faction as the spell take no damage
+        assert!(!Spell::is_target_of(spell_faction, false, ally_faction));
+        assert!(Spell::is_target_of(spell_faction, false, enemy_faction));
+        assert!(Spell::is_target_of(spell_faction, false, NO_FACTION));
+
+        // And are the only ones it heals
+        assert!(Spell::is_heal_target_of(spell_faction, false, false, ally_faction));
+        assert!(!Spell::is_heal_target_of(spell_faction, false, false, enemy_faction));
+        assert!(!Spell::is_heal_target_of(spell_faction, false, false, NO_FACTION));
+
+        // A spell without a faction damages and heals everyone, and only heals its owner if told to
+        assert!(Spell::is_target_of(NO_FACTION, false, ally_faction));
+        assert!(Spell::is_heal_target_of(NO_FACTION, false, false, enemy_faction));
+        assert!(!Spell::is_heal_target_of(spell_faction, false, true, ally_faction));
+        assert!(Spell::is_heal_target_of(spell_faction, true, true, ally_faction));
+    }
+
+    #[test]
+    fn overlapping_entity_count_skips_owner_and_allies() {
+        let spell_faction = 1;
+        assert_eq!(Spell::count_targets_among(spell_faction, std::iter::empty()), 0);
+
+        // The caster, an ally, two enemies and one without a faction
+        let overlaps = [(true, 2), (false, 1), (false, 2), (false, 3), (false, NO_FACTION)];
+        assert_eq!(Spell::count_targets_among(spell_faction, overlaps.into_iter()), 3);
+        assert_eq!(Spell::count_targets_among(NO_FACTION, overlaps.into_iter()), 4);
+    }
+
+    #[test]
+    fn vm_timer_only_measures_when_enabled() {
+        let mut vm_timer = VmTimer::default();
+        vm_timer.stop(vm_timer.start());
+        vm_timer.finish();
+        assert_eq!(vm_timer.accumulated_time_us, None);
+        assert_eq!(vm_timer.last_time_us, 0);
+
+        vm_timer.enabled = true;
+        let start = vm_timer.start();
+        assert!(start.is_some());
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        vm_timer.stop(start);
+        vm_timer.finish();
+        assert!(vm_timer.last_time_us >= 1000);
+        assert_eq!(vm_timer.accumulated_time_us, None);
+    }
+
+    #[test]
+    fn time_scale_changes_how_fast_time_passes() {
+        let mut clock = SpellClock::new();
+        let mut half_speed_clock = SpellClock::new();
+        half_speed_clock.scale = 0.5;
+        for _ in 0..60 {
+            clock.advance(1.0 / 60.0);
+            half_speed_clock.advance(1.0 / 60.0);
+        }
+        assert!((clock.elapsed - 1.0).abs() < 1e-9);
+        assert!((half_speed_clock.elapsed - clock.elapsed * 0.5).abs() < 1e-9);
+
+        half_speed_clock.reset();
+        half_speed_clock.scale = 0.0;
+        half_speed_clock.advance(1.0);
+        assert_eq!(half_speed_clock.elapsed, 0.0);
+    }
+
+    #[test]
+    fn lifetime_is_unaffected_by_reset_timer() {
+        let spawn_time = 1000;
+        let mut start_time = spawn_time;
+
+        assert_eq!(Spell::get_seconds_between(start_time, 3000), 2.0);
+        assert_eq!(Spell::get_seconds_between(spawn_time, 3000), 2.0);
+
+        // reset_timer
+        start_time = 3000;
+
+        assert_eq!(Spell::get_seconds_between(start_time, 4500), 1.5);
+        assert_eq!(Spell::get_seconds_between(spawn_time, 4500), 3.5);
+    }
+
+    #[test]
+    fn expiry_time_is_start_time_plus_lifetime() {
+        assert_eq!(Spell::get_expiry_time(1500, 2.5), 1500 + 2500);
+        assert_eq!(Spell::get_expiry_time(0, 0.001), 1);
+        assert_eq!(Spell::get_expiry_time(1500, -3.0), 1500);
+    }
+
+    #[test]
+    fn same_seed_gives_same_random_numbers() {
+        let mut rng = SpellRng::new(7);
+        let mut same_seed_rng = SpellRng::new(7);
+        let mut other_seed_rng = SpellRng::new(8);
+
+        let sequence: Vec<f64> = (0..20).map(|_| rng.next_f64()).collect();
+        assert_eq!(sequence, (0..20).map(|_| same_seed_rng.next_f64()).collect::<Vec<f64>>());
+        assert_ne!(sequence, (0..20).map(|_| other_seed_rng.next_f64()).collect::<Vec<f64>>());
+        assert!(sequence.iter().all(|number| (0.0..1.0).contains(number)));
+    }
+
+    #[test]
+    fn chance_ramp_probability_shifts_with_age() {
+        let (start_probability, end_probability, ramp_duration) = (0.2, 0.8, 10.0);
+        let mut rng = SpellRng::new(42);
+
+        let mut count_successes = |age: f64| {
+            let probability = Spell::get_ramped_probability(start_probability, end_probability, ramp_duration, age);
+            (0..1000).filter(|_| rng.next_f64() < probability).count()
+        };
+
+        let young_successes = count_successes(0.0);
+        let old_successes = count_successes(20.0);
+
+        assert!((140..260).contains(&young_successes));
+        assert!((740..860).contains(&old_successes));
+
+        assert!((Spell::get_ramped_probability(start_probability, end_probability, ramp_duration, 5.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn held_spell_stays_in_front_of_where_the_caster_aims() {
+        let caster_position = Vector3::new(1.0, 2.0, 3.0);
+        let (position, direction) = Spell::get_anchor_follow_position(caster_position, Basis::default(), 2.0);
+        assert_eq!(position, Vector3::new(1.0, 2.0, 1.0));
+        assert_eq!(direction, Vector3::FORWARD);
+
+        // Turning to face the right moves the spell round with the caster
+        let turned = Basis::from_axis_angle(Vector3::UP, -std::f32::consts::FRAC_PI_2);
+        let (position, direction) = Spell::get_anchor_follow_position(caster_position, turned, 2.0);
+        assert!((position - Vector3::new(3.0, 2.0, 3.0)).length() < 1e-5);
+        assert!((direction - Vector3::RIGHT).length() < 1e-5);
+
+        // Once let go of, the spell flies the way it was last held out
+        let release_direction = Spell::get_direction_basis(direction).unwrap() * Vector3::FORWARD;
+        assert!((release_direction - direction).length() < 1e-5);
+    }
+
+    #[test]
+    fn familiar_keeps_follow_distance() {
+        let follow_distance = 2.0;
+        let mut caster_position = Vector3::ZERO;
+        let mut familiar_position = Vector3::new(0.0, 0.0, 5.0);
+
+        // Caster walks along the x axis
+        for _ in 0..600 {
+            caster_position += Vector3::new(0.01, 0.0, 0.0);
+            familiar_position = Spell::get_familiar_position(familiar_position, caster_position, follow_distance, FAMILIAR_FOLLOW_RATE / 60.0);
+        }
+
+        let distance = (familiar_position - caster_position).length() as f64;
+        assert!((distance - follow_distance).abs() < 0.25);
+    }
+
+    #[test]
+    fn truncated_instructions_are_errors() {
+        assert_eq!(Spell::skip_component(&mut [GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL].iter()), Err(SpellError::InvalidInstructions("Expected number after number literal opcode")));
+        assert_eq!(Spell::skip_component(&mut [GIVE_VELOCITY, NUMBER_LITERAL, 0].iter()), Err(SpellError::InvalidInstructions("Expected parameter")));
+        assert_eq!(Spell::skip_component(&mut [].iter()), Err(SpellError::InvalidInstructions("Expected component")));
+        assert_eq!(Spell::skip_component(&mut [u64::MAX].iter()), Err(SpellError::UnknownComponent("Component doesn't exist")));
+        assert_eq!(Spell::skip_logic(&mut [NUMBER_LITERAL, 0, COMPONENT, GIVE_VELOCITY].iter()), Err(SpellError::InvalidInstructions("Expected parameter")));
+        assert_eq!(Spell::skip_component(&mut [GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0].iter()), Ok(()));
+    }
+
+    #[test]
+    fn and_and_or_skip_components_they_dont_need() {
+        // Stands in for a spell, where each component cast uses energy and sets the damage
+        struct MockSpell { energy: f64, damage: f64 }
+        let cast = |spell: &mut MockSpell, _component: &[u64]| -> Result<Vec<u64>, SpellError> {
+            spell.energy -= 1.0;
+            spell.damage = 5.0;
+            Ok(vec![TRUE])
+        };
+        let evaluate = |instructions: &[u64]| {
+            let mut spell = MockSpell { energy: 10.0, damage: 0.0 };
+            let tokens = Spell::read_logic(&mut instructions.iter()).unwrap();
+            let result = Spell::evaluate_rpn(&tokens, &mut spell, cast);
+            (result, spell.energy, spell.damage)
+        };
+
+        // false and moving(1)
+        assert_eq!(evaluate(&[FALSE, COMPONENT, MOVING, NUMBER_LITERAL, f64::to_bits(1.0), AND, END_OF_SCOPE]), (Ok(vec![FALSE]), 10.0, 0.0));
+        // true or moving(1)
+        assert_eq!(evaluate(&[TRUE, COMPONENT, MOVING, NUMBER_LITERAL, f64::to_bits(1.0), OR, END_OF_SCOPE]), (Ok(vec![TRUE]), 10.0, 0.0));
+        // true and moving(1) still needs the component
+        assert_eq!(evaluate(&[TRUE, COMPONENT, MOVING, NUMBER_LITERAL, f64::to_bits(1.0), AND, END_OF_SCOPE]), (Ok(vec![TRUE]), 9.0, 5.0));
+        // (false and moving(1)) or moving(1) skips only the first component
+        assert_eq!(evaluate(&[FALSE, COMPONENT, MOVING, NUMBER_LITERAL, 0, AND, COMPONENT, MOVING, NUMBER_LITERAL, 0, OR, END_OF_SCOPE]), (Ok(vec![TRUE]), 9.0, 5.0));
+        // false and (moving(1) or true) skips the whole right side
+        assert_eq!(evaluate(&[FALSE, COMPONENT, MOVING, NUMBER_LITERAL, 0, TRUE, OR, AND, END_OF_SCOPE]), (Ok(vec![FALSE]), 10.0, 0.0));
+        // xor always needs both sides
+        assert_eq!(evaluate(&[FALSE, COMPONENT, MOVING, NUMBER_LITERAL, 0, XOR, END_OF_SCOPE]), (Ok(vec![TRUE]), 9.0, 5.0));
+    }
+
+    #[test]
+    fn short_circuits_find_the_right_side() {
+        // get_time() > 1 and not true, where the right side of the and starts at the second true
+        let tokens = Spell::read_logic(&mut [COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(1.0), GREATER_THAN, TRUE, NOT, AND, END_OF_SCOPE].iter()).unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(Spell::get_short_circuits(&tokens), Ok(vec![None, None, None, Some((5, AND)), None, None]));
+        assert_eq!(Spell::get_short_circuits(&[vec![TRUE], vec![AND]]), Err(SpellError::InvalidInstructions("Expected value to compare")));
+    }
+
+    #[test]
+    fn direction_basis_turns_forward_to_direction() {
+        for direction in [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 3.0, 0.0), Vector3::new(1.0, -2.0, 0.5), Vector3::FORWARD, Vector3::BACK] {
+            let basis = Spell::get_direction_basis(direction).unwrap();
+            assert!((basis * Vector3::FORWARD - direction.normalized()).length() < 1e-5);
+        }
+
+        assert!(Spell::get_direction_basis(Vector3::ZERO).is_none());
+    }
+
+    #[test]
+    fn set_decay_changes_energy_lose_rate() {
+        let delta = 0.5;
+        assert_eq!(Spell::get_clamped_energy_lose_rate(0.2), 0.2);
+        assert_eq!(Spell::get_clamped_energy_lose_rate(-1.0), 0.0);
+        assert_eq!(Spell::get_clamped_energy_lose_rate(5.0), MAX_ENERGY_LOSE_RATE);
+
+        assert_eq!(Spell::get_decayed_energy(10.0, ENERGY_LOSE_RATE, delta), 10.0 - 10.0 * ENERGY_LOSE_RATE * delta);
+        assert_eq!(Spell::get_decayed_energy(10.0, Spell::get_clamped_energy_lose_rate(0.2), delta), 9.0);
+        assert_eq!(Spell::get_decayed_energy(10.0, Spell::get_clamped_energy_lose_rate(-1.0), delta), 10.0);
+    }
+
+    #[test]
+    fn local_forward_undoes_the_spell_direction() {
+        let turned = Basis::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_2);
+        assert!((Spell::get_local_forward(turned, turned) - Vector3::FORWARD).length() < 1e-5);
+        for (original_direction, caster_direction) in [(Basis::IDENTITY, turned), (turned, Basis::IDENTITY)] {
+            let local_forward = Spell::get_local_forward(original_direction, caster_direction);
+            assert!((original_direction * local_forward - caster_direction * Vector3::FORWARD).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn nearest_entity_direction_points_at_the_closer_entity() {
+        let position = Vector3::new(1.0, 0.0, 0.0);
+        let far_entity = Vector3::new(1.0, 0.0, -10.0);
+        let near_entity = Vector3::new(4.0, 0.0, 0.0);
+        assert_eq!(Spell::get_nearest_index(position, &[far_entity, near_entity]), Some(1));
+        assert_eq!(Spell::get_nearest_index(position, &[near_entity, far_entity]), Some(0));
+        assert_eq!(Spell::get_nearest_index(position, &[]), None);
+
+        let turned = Basis::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_2);
+        let direction = Spell::get_local_direction_to(turned, position, near_entity);
+        assert!((turned * direction - Vector3::RIGHT).length() < 1e-5);
+        assert_eq!(Spell::get_local_direction_to(turned, position, position), Vector3::ZERO);
+    }
+
+    #[test]
+    fn detection_radius_only_replaces_the_shape_when_positive() {
+        assert_eq!(Spell::get_detection_area_radius(5.0), Some(5.0));
+        assert_eq!(Spell::get_detection_area_radius(0.0), None);
+        assert_eq!(Spell::get_detection_area_radius(-1.0), None);
+        assert_eq!(Spell::get_detection_area_radius(f32::INFINITY), None);
+        assert_eq!(Spell::get_detection_area_radius(f32::NAN), None);
+    }
+
+    #[test]
+    fn detection_radius_widens_area_effects() {
+        // Magical entities 1, 4 and 8 away from a cleave with a range of 3
+        let distances = [1.0, 4.0, 8.0];
+        let found = |detection_radius: Option<f32>| distances.iter().filter(|&&distance| distance <= Spell::get_area_radius(3.0, detection_radius)).count();
+
+        assert_eq!(found(None), 1);
+        assert_eq!(found(Spell::get_detection_area_radius(5.0)), 2);
+        assert_eq!(found(Spell::get_detection_area_radius(10.0)), 3);
+
+        // A smaller detection radius doesn't shrink what the cleave reaches
+        assert_eq!(found(Spell::get_detection_area_radius(2.0)), 1);
+    }
+
+    #[test]
+    fn vectors_fill_several_parameters() {
+        let instructions = [GIVE_VELOCITY, COMPONENT, CASTER_FORWARD, COMPONENT, PERISH];
+        let mut instructions_iter = instructions.iter();
+        assert_eq!(Spell::skip_component(&mut instructions_iter), Ok(()));
+        assert_eq!(instructions_iter.next(), Some(&COMPONENT));
+
+        let mut component_catalogue = ComponentCatalogue::new();
+        component_catalogue.add_all_components();
+        assert_eq!(Spell::internal_check_allowed_to_cast(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, COMPONENT, CASTER_FORWARD], &component_catalogue), Ok(()));
+
+        let values = Spell::split_return_values(&[NUMBER_LITERAL, 1, NUMBER_LITERAL, 2, NUMBER_LITERAL, 3]).unwrap();
+        assert_eq!(values, vec![vec![NUMBER_LITERAL, 1], vec![NUMBER_LITERAL, 2], vec![NUMBER_LITERAL, 3]]);
+        assert_eq!(Spell::split_return_values(&[NUMBER_LITERAL]), Err(SpellError::InvalidInstructions("Expected number after number literal opcode")));
+    }
+
+    #[test]
+    fn while_loop_counts_down() {
+        let mut countdown = 5;
+        let mut times_run = 0;
+        let result = Spell::run_while_loop(&mut countdown, |countdown| Ok(*countdown > 0), |countdown| {
+            *countdown -= 1;
+            times_run += 1;
+            Ok(())
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(countdown, 0);
+        assert_eq!(times_run, 5);
+    }
+
+    #[test]
+    fn while_loop_stops_at_iteration_cap() {
+        let mut times_run: usize = 0;
+        let result = Spell::run_while_loop(&mut times_run, |_| Ok(true), |times_run| {
+            *times_run += 1;
+            Ok(())
+        });
+
+        assert_eq!(result, Err(SpellError::TooManyIterations));
+        assert_eq!(times_run, MAX_WHILE_ITERATIONS);
+
+        // Errors from the contents, such as running out of energy, end the loop straight away
+        let mut times_run: usize = 0;
+        let result = Spell::run_while_loop(&mut times_run, |_| Ok(true), |times_run| {
+            *times_run += 1;
+            if *times_run == 3 { Err(SpellError::NotEnoughEnergy) } else { Ok(()) }
+        });
+        assert_eq!(result, Err(SpellError::NotEnoughEnergy));
+    }
+
+    #[test]
+    fn repeat_times_runs_its_count_until_energy_runs_out() {
+        // Each time around costs 3 energy
+        let run = |energy: &mut f64, _: &[u64]| {
+            *energy -= 3.0;
+            Ok(())
+        };
+        let has_energy = |energy: &mut f64| *energy >= ENERGY_CONSIDERATION_LEVEL;
+
+        let mut energy = 100.0;
+        let counted_loops = [CountedLoop { iterations: 5, instructions: vec![COMPONENT, PERISH] }];
+        assert_eq!(Spell::run_counted_loops(&mut energy, &counted_loops, has_energy, run), Ok(()));
+        assert_eq!(energy, 85.0);
+
+        // `repeat 0 times` never runs
+        let mut energy = 100.0;
+        let counted_loops = [CountedLoop { iterations: 0, instructions: vec![COMPONENT, PERISH] }];
+        assert_eq!(Spell::run_counted_loops(&mut energy, &counted_loops, has_energy, run), Ok(()));
+        assert_eq!(energy, 100.0);
+
+        // A spell with 10 energy runs out part way through the fourth time around and doesn't start a fifth, or the loop after it
+        let mut energy = 10.0;
+        let counted_loops = [CountedLoop { iterations: 5, instructions: Vec::new() }, CountedLoop { iterations: 5, instructions: Vec::new() }];
+        assert_eq!(Spell::run_counted_loops(&mut energy, &counted_loops, has_energy, run), Ok(()));
+        assert_eq!(energy, -2.0);
+
+        // A failed iteration stops the loops
+        let mut times_run = 0;
+        let result = Spell::run_counted_loops(&mut times_run, &counted_loops, |_| true, |times_run, _| {
+            *times_run += 1;
+            if *times_run == 3 { Err(SpellError::NotEnoughEnergy) } else { Ok(()) }
+        });
+        assert_eq!(result, Err(SpellError::NotEnoughEnergy));
+        assert_eq!(times_run, 3);
+    }
+
+    #[test]
+    fn long_loops_exceed_the_instruction_budget() {
+        // Each time around the loop runs three instructions, so the budget runs out well before the iteration cap
+        let mut instructions_run: usize = 0;
+        let result = Spell::run_while_loop(&mut instructions_run, |_| Ok(true), |instructions_run| {
+            for _ in 0..3 {
+                Spell::count_instruction(instructions_run, 100)?;
+            }
+            Ok(())
+        });
+
+        assert_eq!(result, Err(SpellError::InstructionBudgetExceeded));
+        assert_eq!(instructions_run, 101);
+        assert_eq!(result.unwrap_err().to_string(), "instruction budget exceeded");
+
+        let mut instructions_run: usize = 0;
+        assert_eq!((0..100).try_for_each(|_| Spell::count_instruction(&mut instructions_run, 100)), Ok(()));
+    }
+
+    #[test]
+    fn long_counted_loops_exceed_the_instruction_budget() {
+        // Counted loops share one budget, so an empty loop that goes around enough times still runs out like it does when the spell is ready
+        let counted_loops = [CountedLoop { iterations: 1000, instructions: Vec::new() }];
+        let mut instructions_run: usize = 0;
+        let result = Spell::run_counted_loops(&mut instructions_run, &counted_loops, |_| true, |instructions_run, instructions| {
+            Spell::count_instruction(instructions_run, 100)?;
+            instructions.iter().try_for_each(|_| Spell::count_instruction(instructions_run, 100))
+        });
+
+        assert_eq!(result, Err(SpellError::InstructionBudgetExceeded));
+        assert_eq!(instructions_run, 101);
+
+        // Going around fewer times than the budget is fine
+        let counted_loops = [CountedLoop { iterations: 50, instructions: vec![COMPONENT] }];
+        let mut instructions_run: usize = 0;
+        let result = Spell::run_counted_loops(&mut instructions_run, &counted_loops, |_| true, |instructions_run, instructions| {
+            Spell::count_instruction(instructions_run, 100)?;
+            instructions.iter().try_for_each(|_| Spell::count_instruction(instructions_run, 100))
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(instructions_run, 100);
+    }
+
+    #[test]
+    fn velocity_round_trips_through_the_spells_frame() {
+        let original_direction = Basis::from_axis_angle(Vector3::UP, 0.7) * Basis::from_axis_angle(Vector3::RIGHT, -0.3);
+        let velocity = Vector3::new(1.5, -2.0, 4.0);
+
+        // The spell moves with its velocity turned by the direction it was cast in, and reading that back in its own frame gives the velocity it was set to
+        let direction = (original_direction * velocity).normalized_or_zero();
+        let world_velocity = direction * velocity.length();
+        assert!((Spell::get_local_velocity(original_direction, world_velocity) - velocity).length() < 1e-5);
+
+        // A spell cast straight ahead moves with its velocity unchanged
+        assert_eq!(Spell::get_local_velocity(Basis::IDENTITY, velocity), velocity);
+    }
+
+    #[test]
+    fn estimating_huge_repeat_counts_finishes() {
+        // `repeat 18446744073709551615 times:` stops being estimated once the spell would have run out of energy
+        let mut times_estimated = 0;
+        let energy_needed = Spell::estimate_loop_cost(u64::MAX as usize, 10.0, || {
+            times_estimated += 1;
+            Ok(1.0)
+        });
+        assert_eq!(energy_needed, Ok(11.0));
+        assert_eq!(times_estimated, 11);
+
+        // Or once a time around costs nothing
+        let mut times_estimated = 0;
+        let energy_needed = Spell::estimate_loop_cost(u64::MAX as usize, 10.0, || {
+            times_estimated += 1;
+            Ok(0.0)
+        });
+        assert_eq!(energy_needed, Ok(0.0));
+        assert_eq!(times_estimated, 1);
+
+        // Smaller counts are added up in full
+        assert_eq!(Spell::estimate_loop_cost(3, 10.0, || Ok(2.0)), Ok(6.0));
+        assert_eq!(Spell::estimate_loop_cost(0, 10.0, || Ok(2.0)), Ok(0.0));
+        assert_eq!(Spell::estimate_loop_cost(3, 10.0, || Err(SpellError::NotEnoughEnergy)), Err(SpellError::NotEnoughEnergy));
+    }
+
+    #[test]
+    fn split_conserves_energy_and_fans_out() {
+        let energy = 10.0;
+        let count = Spell::get_split_count(5, energy);
+        assert_eq!(count, 5);
+        assert_eq!(Spell::get_split_cost(5.0), 4.0 * SPLIT_ENERGY_PER_SPELL);
+
+        // Each spell gets an even share of what's left after paying for the split, and none is lost
+        let energies = Spell::get_split_energies(5, energy - Spell::get_split_cost(5.0));
+        assert_eq!(energies, vec![1.6; 5]);
+        assert!((energies.iter().sum::<f64>() - 8.0).abs() < 1e-9);
+
+        // Energy too weak to share between them all makes fewer spells, each with enough to survive
+        let energies = Spell::get_split_energies(5, ENERGY_CONSIDERATION_LEVEL * 3.5);
+        assert_eq!(energies.len(), 3);
+        assert!(energies.iter().all(|&clone_energy| clone_energy >= ENERGY_CONSIDERATION_LEVEL));
+
+        // Huge counts are capped, and don't cost more than what they make
+        assert_eq!(Spell::get_split_count(1_000_000, 1000.0), MAX_SPLIT_COUNT);
+        assert_eq!(Spell::get_split_cost(1e12), (MAX_SPLIT_COUNT - 1) as f64 * SPLIT_ENERGY_PER_SPELL);
+        assert_eq!(Spell::get_split_cost(1.5), 0.0);
+
+        // Clones would be born with less than ENERGY_CONSIDERATION_LEVEL
+        assert_eq!(Spell::get_split_count(5, ENERGY_CONSIDERATION_LEVEL * 3.5), 3);
+        assert_eq!(Spell::get_split_count(5, ENERGY_CONSIDERATION_LEVEL / 2.0), 0);
+
+        let angles = Spell::get_split_angles(3, 0.5);
+        assert_eq!(angles, vec![-0.25, 0.0, 0.25]);
+        assert_eq!(Spell::get_split_angles(1, 0.5), vec![0.0]);
+    }
+
+    #[test]
+    fn parent_spawns_child_with_fraction_of_energy() {
+        let instructions = spelltranslator::parse_spell("when_created:\nspawn_child(0.25)\nchild:\nrepeat:\nperish()", None).unwrap();
+        let (parent_instructions, child_instructions) = Spell::split_child_section(&instructions);
+
+        // The parent keeps its own section, which casts spawn_child, and the child gets the section after `child:` as its whole spell
+        assert_eq!(parent_instructions, &[WHEN_CREATED_SECTION, COMPONENT, SPAWN_CHILD, NUMBER_LITERAL, f64::to_bits(0.25)]);
+        assert_eq!(child_instructions, &[REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, PERISH]);
+
+        let energy_fraction = f64::from_bits(parent_instructions[4]);
+        assert_eq!(Spell::check_child_energy_fraction(energy_fraction), Ok(()));
+        assert_eq!(Spell::get_child_energy(12.0, energy_fraction, 0), Some(3.0));
+
+        // Fractions that would give the child nothing or more than the spell has stop the spell
+        for energy_fraction in [0.0, -0.5, 1.5, f64::NAN] {
+            assert_eq!(Spell::check_child_energy_fraction(energy_fraction), Err(SpellError::BadParameterType("Energy fraction must be more than 0 and no more than 1")));
+        }
+
+        // Children too weak to survive aren't spawned
+        assert_eq!(Spell::get_child_energy(ENERGY_CONSIDERATION_LEVEL, 0.5, 0), None);
+
+        // Children stop being spawned once they're too many generations down
+        assert_eq!(Spell::get_child_energy(12.0, 0.25, MAX_CHILD_GENERATION - 1), Some(3.0));
+        assert_eq!(Spell::get_child_energy(12.0, 0.25, MAX_CHILD_GENERATION), None);
+
+        // A number that has the same bits as the child section opcode doesn't start one
+        let instructions = [WHEN_CREATED_SECTION, COMPONENT, SPAWN_CHILD, NUMBER_LITERAL, CHILD_SECTION];
+        assert_eq!(Spell::split_child_section(&instructions), (&instructions[..], &[][..]));
+    }
+
+    #[test]
+    fn orbit_keeps_the_same_radius() {
+        let orbit = Orbit { center: Vector3::new(1.0, 2.0, 3.0), axis: Vector3::UP, angular_speed: 2.0 };
+        let mut position = Vector3::new(4.0, 5.0, 7.0);
+        let height = position.y;
+        for _ in 0..100 {
+            let next_position = orbit.get_next_position(position, 1.0 / 60.0);
+            assert!(next_position != position);
+            position = next_position;
+            assert!(((position - orbit.center).length() - 34.0_f32.sqrt()).abs() < 1e-4);
+            assert!((position.y - height).abs() < 1e-4);
+        }
+
+        assert_eq!(Orbit::get_radius(Vector3::new(3.0, 10.0, 4.0)), 5.0);
+    }
+
+    #[test]
+    fn radial_force_weakens_with_distance_and_is_never_infinite() {
+        let push = RadialForce { strength: 8.0, radius: 5.0 };
+        let delta = 0.5;
+
+        let near = push.get_velocity(Vector3::new(1.0, 0.0, 0.0), 1, delta);
+        let far = push.get_velocity(Vector3::new(4.0, 0.0, 0.0), 1, delta);
+        assert_eq!(near, Vector3::new(4.0, 0.0, 0.0));
+        assert_eq!(far, Vector3::new(1.0, 0.0, 0.0));
+
+        // The strength is split between targets
+        assert_eq!(push.get_velocity(Vector3::new(1.0, 0.0, 0.0), 2, delta), Vector3::new(2.0, 0.0, 0.0));
+
+        // Negative strength pulls targets towards the center
+        let pull = RadialForce { strength: -8.0, radius: 5.0 };
+        assert_eq!(pull.get_velocity(Vector3::new(0.0, 0.0, 2.0), 1, delta), Vector3::new(0.0, 0.0, -2.0));
+
+        let very_close = push.get_velocity(Vector3::new(0.001, 0.0, 0.0), 1, delta);
+        assert!(very_close.is_finite());
+        assert!((very_close.length() as f64 - 8.0 / MINIMUM_RADIAL_FORCE_DISTANCE * delta).abs() < 1e-4);
+        assert_eq!(push.get_velocity(Vector3::ZERO, 1, delta), Vector3::ZERO);
+    }
+
+    #[test]
+    fn damage_falls_off_between_distances() {
+        let damage_falloff = DamageFalloff { start_distance: 10.0, end_distance: 20.0 };
+        assert_eq!(damage_falloff.get_multiplier(0.0), 1.0);
+        assert_eq!(damage_falloff.get_multiplier(10.0), 1.0);
+        assert_eq!(damage_falloff.get_multiplier(15.0), 0.5);
+        assert_eq!(damage_falloff.get_multiplier(20.0), 0.0);
+        assert_eq!(damage_falloff.get_multiplier(100.0), 0.0);
+
+        let sudden_falloff = DamageFalloff { start_distance: 5.0, end_distance: 5.0 };
+        assert_eq!(sudden_falloff.get_multiplier(4.9), 1.0);
+        assert_eq!(sudden_falloff.get_multiplier(5.1), 0.0);
+
+        // Beams, cleaves and traps go through the same falloff as the spell's own damage
+        let beam = Beam { damage_per_second: 20.0, max_length: 30.0 };
+        assert_eq!(Spell::get_damage_after_falloff(beam.get_frame_damage(0.5, f64::INFINITY), Some(damage_falloff), 15.0), 5.0);
+        assert_eq!(Spell::get_damage_after_falloff(12.0, Some(damage_falloff), 25.0), 0.0);
+        assert_eq!(Spell::get_damage_after_falloff(12.0, None, 25.0), 12.0);
+    }
+
+    #[test]
+    fn equal_spells_annihilate_each_other() {
+        assert_eq!(Spell::get_clash_losses(10.0, 10.0, 10.0, 10.0), (10.0, 10.0));
+        assert_eq!(Spell::get_clash_losses(10.0, 4.0, 10.0, 4.0), (4.0, 4.0));
+
+        // A strong spell breaks through a weak one and keeps most of its energy
+        let (strong_lost, weak_lost) = Spell::get_clash_losses(50.0, 20.0, 5.0, 1.0);
+        assert_eq!((strong_lost, weak_lost), (1.0, 5.0));
+        assert_eq!(Spell::get_clash_losses(5.0, 1.0, 50.0, 20.0), (weak_lost, strong_lost));
+
+        // A spell that doesn't clash takes damage without dealing any back
+        assert_eq!(Spell::get_clash_losses(10.0, 3.0, 10.0, 0.0), (0.0, 3.0));
+    }
+
+    #[test]
+    fn deflected_projectile_reverses_direction() {
+        let original_direction = Basis::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_2);
+        let velocity = Vector3::new(2.0, 0.0, 1.0);
+        let deflected_velocity = Spell::get_deflected_velocity(velocity).unwrap();
+
+        assert_eq!(original_direction * deflected_velocity, -(original_direction * velocity));
+        assert_eq!(deflected_velocity.length(), velocity.length());
+
+        // Spells that aren't moving can't be sent back
+        assert_eq!(Spell::get_deflected_velocity(Vector3::ZERO), None);
+    }
+
+    #[test]
+    fn larger_spell_absorbs_smaller_spell() {
+        let delta = 1.0 / 60.0;
+        let (mut larger, mut smaller) = (100.0, 20.0);
+
+        // Both spells absorb, so the same energy moves whichever spell works it out
+        let absorbed = Spell::get_absorbed_energy(larger, smaller, true, delta);
+        assert!(absorbed > 0.0);
+        assert_eq!(Spell::get_absorbed_energy(smaller, larger, true, delta), -absorbed);
+
+        let mut frames = 0;
+        while smaller >= ENERGY_CONSIDERATION_LEVEL {
+            let absorbed = Spell::get_absorbed_energy(larger, smaller, true, delta);
+            larger += absorbed;
+            smaller -= absorbed;
+            frames += 1;
+            assert!(frames < 10_000);
+        }
+        assert!((larger + smaller - 120.0).abs() < 1e-9);
+
+        // A long frame can't take more than the cap
+        assert_eq!(Spell::get_absorbed_energy(100.0, 20.0, false, 100.0), 20.0 * MAX_ABSORB_FRACTION);
+        assert_eq!(Spell::get_absorbed_energy(20.0, 100.0, true, 100.0), -20.0 * MAX_ABSORB_FRACTION);
+    }
+
+    #[test]
+    fn gust_speeds_up_nearby_projectile() {
+        let delta = 1.0 / 60.0;
+
+        // A gust going along x passes a neutral projectile cast facing along z
+        let gust_direction = Vector3::new(3.0, 0.0, 0.0);
+        let projectile_direction = Basis::from_axis_angle(Vector3::UP, std::f32::consts::FRAC_PI_2);
+        let mut projectile_velocity = Vector3::new(0.0, 0.0, -1.0);
+        let world_velocity = projectile_direction * projectile_velocity;
+
+        let push = Spell::get_spell_push(gust_direction, 6.0, delta);
+        assert!((push - Vector3::new(0.1, 0.0, 0.0)).length() < 1e-6);
+
+        for _ in 0..60 {
+            projectile_velocity = Spell::get_pushed_velocity(projectile_direction, projectile_velocity, push);
+        }
+
+        // After a second the projectile has gained the gust's strength along the gust, and kept the rest of its velocity
+        let gained = projectile_direction * projectile_velocity - world_velocity;
+        assert!((gained - Vector3::new(6.0, 0.0, 0.0)).length() < 1e-4);
+        assert!((projectile_direction * projectile_velocity).length() > world_velocity.length());
+
+        // A gust that isn't going anywhere doesn't push
+        assert_eq!(Spell::get_spell_push(Vector3::ZERO, 6.0, delta), Vector3::ZERO);
+    }
+
+    #[test]
+    fn siphon_feeds_caster_while_enemies_have_energy() {
+        let rate = 4.0;
+        let delta = 0.5;
+        let mut caster_energy_charged = 10.0;
+        let mut enemy_energy = 3.0;
+
+        // Each frame takes rate * delta from the enemy and gives it to the caster
+        let drained = Spell::get_siphoned_energy(rate, delta, enemy_energy);
+        enemy_energy -= drained;
+        caster_energy_charged += drained;
+        assert_eq!(caster_energy_charged, 12.0);
+        assert_eq!(enemy_energy, 1.0);
+
+        // An enemy can't give more energy than it has
+        let drained = Spell::get_siphoned_energy(rate, delta, enemy_energy);
+        enemy_energy -= drained;
+        caster_energy_charged += drained;
+        assert_eq!(caster_energy_charged, 13.0);
+        assert_eq!(enemy_energy, 0.0);
+
+        assert_eq!(Spell::get_siphoned_energy(rate, delta, enemy_energy), 0.0);
+
+        // Each enemy is drained on its own, the caster gets all of it, and the spell pays for every unit moved
+        let (drained, energy_siphoned, energy_cost) = Spell::get_siphon_transfer(rate, delta, &[3.0, 0.5, 0.0]);
+        assert_eq!(drained, vec![2.0, 0.5, 0.0]);
+        assert_eq!(energy_siphoned, 2.5);
+        assert_eq!(energy_cost, 2.5 * SIPHON_ENERGY_COST);
+
+        // Nothing to siphon from costs nothing
+        assert_eq!(Spell::get_siphon_transfer(rate, delta, &[]), (Vec::new(), 0.0, 0.0));
+    }
+
+    #[test]
+    fn trap_only_triggers_once_armed() {
+        let trap = Trap { armed_time: 2000, trigger_radius: 3.0, damage: 10.0 };
+
+        // Not armed yet, even with a target right on top of it
+        assert!(!trap.is_armed(1999));
+        assert!(!trap.should_trigger(0, &[0.0]));
+        assert!(!trap.should_trigger(1999, &[1.0]));
+
+        // Armed but nothing close enough
+        assert!(trap.is_armed(2000));
+        assert!(!trap.should_trigger(2000, &[]));
+        assert!(!trap.should_trigger(2500, &[3.5, 10.0]));
+
+        // Armed and a target within the trigger radius
+        assert!(trap.should_trigger(2000, &[3.0]));
+        assert!(trap.should_trigger(5000, &[10.0, 1.0]));
+    }
+
+    #[test]
+    fn homing_turns_towards_the_nearest_target() {
+        let position = Vector3::ZERO;
+        let target_positions = [Vector3::new(0.0, 0.0, -8.0), Vector3::new(3.0, 0.0, 0.0), Vector3::new(0.0, 5.0, 0.0)];
+        assert_eq!(Spell::get_nearest_position(position, &target_positions), Some(Vector3::new(3.0, 0.0, 0.0)));
+        assert_eq!(Spell::get_nearest_position(position, &[]), None);
+
+        let velocity = Vector3::new(0.0, 0.0, -4.0);
+        let target_direction = Vector3::RIGHT;
+
+        // Turns by no more than the turn rate allows, keeping its speed
+        let (axis, angle) = Spell::get_homing_turn(velocity, target_direction, 0.5).unwrap();
+        assert_eq!(angle, 0.5);
+        let turned = velocity.rotated(axis, angle);
+        assert!((turned.length() - 4.0).abs() < 1e-5);
+        assert!((turned.angle_to(target_direction) - (PI as f32 / 2.0 - 0.5)).abs() < 1e-5);
+        assert_eq!(angle as f64 * HOMING_ENERGY_PER_RADIAN, 0.5);
+
+        // Stops turning once it's facing the target
+        let (axis, angle) = Spell::get_homing_turn(velocity, target_direction, 10.0).unwrap();
+        assert!(velocity.rotated(axis, angle).normalized().is_equal_approx(target_direction));
+
+        // Already heading straight at the target
+        assert_eq!(Spell::get_homing_turn(velocity, Vector3::FORWARD, 0.5), None);
+    }
+
+    #[test]
+    fn cleave_only_hits_inside_arc() {
+        let forward = Vector3::FORWARD;
+        let arc_angle = PI / 2.0;
+
+        // Inside the arc
+        assert!(Spell::is_within_arc(forward, Vector3::new(0.0, 0.0, -3.0), arc_angle));
+        assert!(Spell::is_within_arc(forward, Vector3::new(1.0, 0.0, -2.0), arc_angle));
+        assert!(Spell::is_within_arc(forward, Vector3::ZERO, arc_angle));
+
+        // Outside the angle
+        assert!(!Spell::is_within_arc(forward, Vector3::new(2.0, 0.0, -1.0), arc_angle));
+        assert!(!Spell::is_within_arc(forward, Vector3::new(-1.0, 0.0, 0.0), arc_angle));
+
+        // Behind
+        assert!(!Spell::is_within_arc(forward, Vector3::new(0.0, 0.0, 3.0), arc_angle));
+    }
+
+    #[test]
+    fn beam_damage_is_per_second() {
+        let beam = Beam { damage_per_second: 12.0, max_length: 10.0 };
+
+        // A second of frames deals the damage per second, whatever the frame rate
+        for frames_per_second in [30, 60, 144] {
+            let delta = 1.0 / frames_per_second as f64;
+            let damage_dealt: f64 = (0..frames_per_second).map(|_| beam.get_frame_damage(delta, f64::INFINITY)).sum();
+            assert!((damage_dealt - 12.0).abs() < 1e-9);
+        }
+
+        // A target that's nearly dead only takes what's left
+        assert_eq!(beam.get_frame_damage(0.5, 2.0), 2.0);
+
+        // Casting a longer beam costs more, and a beam that's turned off costs nothing
+        assert_eq!(Beam::get_energy_cost(20.0), 20.0 * BEAM_ENERGY_PER_LENGTH);
+        assert!(Beam::get_energy_cost(40.0) > Beam::get_energy_cost(20.0));
+        assert_eq!(Beam::get_energy_cost(-5.0), 0.0);
+    }
+
+    #[test]
+    fn wall_hit_fragments_spread_and_share_energy() {
+        let count = Spell::get_fragment_count(4, 12.0);
+        assert_eq!(count, 4);
+
+        // Every fragment bounces away from the wall at the same angle, spread evenly around the normal
+        let normal = Vector3::new(0.0, 0.0, 2.0);
+        let directions = Spell::get_fragment_directions(normal, count);
+        assert_eq!(directions.len(), count);
+        for direction in directions.iter() {
+            assert!((direction.length() - 1.0).abs() < 1e-5);
+            assert!((direction.dot(Vector3::BACK) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+        }
+        let sum: Vector3 = directions.iter().fold(Vector3::ZERO, |sum, &direction| sum + direction);
+        assert!((sum.normalized() - Vector3::BACK).length() < 1e-5);
+
+        // Hitting the floor still spreads the fragments
+        assert!(Spell::get_fragment_directions(Vector3::UP, 3).iter().all(|direction| direction.y > 0.0));
+
+        // Spells too weak to share their energy make fewer fragments
+        assert_eq!(Spell::get_fragment_count(4, ENERGY_CONSIDERATION_LEVEL * 2.5), 2);
+        assert_eq!(Spell::get_fragment_count(4, ENERGY_CONSIDERATION_LEVEL * 0.5), 0);
+    }
+
+    #[test]
+    fn stun_costs_energy_for_every_target_held() {
+        let delta = 0.1;
+        let energy_per_stun = STUN_ENERGY_PER_SECOND * delta;
+
+        // Plenty of energy holds every target
+        assert_eq!(Spell::get_affordable_stun_count(100.0, 3, delta), 3);
+
+        // Only enough to stun two targets and stay above the energy the spell perishes at
+        assert_eq!(Spell::get_affordable_stun_count(ENERGY_CONSIDERATION_LEVEL + energy_per_stun * 2.5, 3, delta), 2);
+        assert_eq!(Spell::get_affordable_stun_count(ENERGY_CONSIDERATION_LEVEL * 0.5, 3, delta), 0);
+
+        // Nothing to stun costs nothing
+        assert_eq!(Spell::get_affordable_stun_count(ENERGY_CONSIDERATION_LEVEL * 0.5, 0, delta), 0);
     }
 }
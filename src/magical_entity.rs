@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 
-use crate::{Spell, ENERGY_CONSIDERATION_LEVEL, saver::*, ComponentCatalogue, DEFAULT_COLOR, spelltranslator};
+use crate::{Spell, validator, ENERGY_CONSIDERATION_LEVEL, NO_FACTION, DEFAULT_EFFICIENCY_LEVEL, saver::*, ComponentCatalogue, DEFAULT_COLOR, spelltranslator, spell_error::SpellError};
 
 // Godot imports
 use godot::prelude::*;
@@ -25,8 +25,109 @@ const DEFAULT_PASSIVE_FOCUS_CHANGE_RATE: f64 = 0.001;
 /// Determins how far control can dip to before a spell is freed. Is needed to prevent spells from being freed after casting a spell with no control left
 const CONTROL_DIP_ALLOWANCE: f64 = -0.1;
 
+/// The most spell slots a magical entity can have, which stops a huge count from using up all the memory
+const MAX_LOADED_SLOTS: usize = 64;
+
+/// The team given to `set_team` for a neutral magical entity, which is an enemy of everyone
+const NEUTRAL_TEAM: i64 = -1;
+
+/// Makes a magical entity take `multiplier` times as much damage until `time_remaining` runs out
+struct Vulnerability {
+    multiplier: f64,
+    time_remaining: f64
+}
+
+/// Timed effects on a magical entity, which are counted down in `handle_magic`
+#[derive(Default)]
+struct Statuses {
+    vulnerabilities: Vec<Vulnerability>,
+    /// Seconds until the magical entity can cast spells and move itself again
+    stun_remaining: f64
+}
+
+impl Statuses {
+    /// A spell marks what it hits every frame, so marking again with the same multiplier only refreshes the timer instead of stacking
+    fn add_vulnerability(&mut self, multiplier: f64, duration: f64) {
+        match self.vulnerabilities.iter_mut().find(|vulnerability| vulnerability.multiplier == multiplier) {
+            Some(vulnerability) => vulnerability.time_remaining = vulnerability.time_remaining.max(duration),
+            None => self.vulnerabilities.push(Vulnerability { multiplier, time_remaining: duration })
+        }
+    }
+
+    /// Stuns don't stack. A shorter stun doesn't cut a longer one short
+    fn add_stun(&mut self, duration: f64) {
+        self.stun_remaining = self.stun_remaining.max(duration);
+    }
+
+    fn is_stunned(&self) -> bool {
+        self.stun_remaining > 0.0
+    }
+
+    fn check_can_cast(&self) -> Result<(), SpellError> {
+        if self.is_stunned() {
+            return Err(SpellError::NotAllowed("Magical entity is stunned"))
+        }
+        Ok(())
+    }
+
+    /// Vulnerabilities with different multipliers stack by multiplying together
+    fn get_damage_multiplier(&self) -> f64 {
+        self.vulnerabilities.iter().map(|vulnerability| vulnerability.multiplier).product()
+    }
+
+    fn update(&mut self, delta: f64) {
+        for vulnerability in self.vulnerabilities.iter_mut() {
+            vulnerability.time_remaining -= delta;
+        }
+        self.vulnerabilities.retain(|vulnerability| vulnerability.time_remaining > 0.0);
+        self.stun_remaining = (self.stun_remaining - delta).max(0.0);
+    }
+}
+
+/// Stops a magical entity casting spells that use components it cast too recently
+#[derive(Default)]
+struct Cooldowns {
+    /// How many seconds each component is unavailable for after being cast
+    base_cooldowns: HashMap<u64, f64>,
+    remaining_cooldowns: HashMap<u64, f64>
+}
+
+impl Cooldowns {
+    fn new(base_cooldowns: HashMap<u64, f64>) -> Self {
+        Cooldowns { base_cooldowns, remaining_cooldowns: HashMap::new() }
+    }
+
+    /// Components without a base cooldown can always be cast
+    fn start(&mut self, component_code: u64) {
+        if let Some(&cooldown) = self.base_cooldowns.get(&component_code) {
+            if cooldown > 0.0 {
+                self.remaining_cooldowns.insert(component_code, cooldown);
+            }
+        }
+    }
+
+    fn get_remaining(&self, component_code: u64) -> f64 {
+        self.remaining_cooldowns.get(&component_code).copied().unwrap_or(0.0)
+    }
+
+    fn update(&mut self, delta: f64) {
+        for remaining_cooldown in self.remaining_cooldowns.values_mut() {
+            *remaining_cooldown -= delta;
+        }
+        self.remaining_cooldowns.retain(|_, remaining_cooldown| *remaining_cooldown > 0.0);
+    }
+
+    fn check_instructions(&self, instructions: &[u64]) -> Result<(), SpellError> {
+        if Spell::get_component_codes(instructions).iter().any(|component_code| self.remaining_cooldowns.contains_key(component_code)) {
+            return Err(SpellError::NotAllowed("Component is on cooldown"))
+        }
+        Ok(())
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct SpellCatalogue {
+    #[serde(serialize_with = "godot_json_saver::serialize_sorted")]
     pub spell_catalogue: HashMap<String, String>
 }
 
@@ -56,9 +157,15 @@ pub struct MagicalEntity {
     health: f64,
     #[export]
     max_health: f64,
+    /// Spells cast are given this faction and don't damage magical entities of the same faction
+    #[export]
+    faction: i64,
+    /// The team set with `set_team`, which decides the faction
+    team: i64,
     #[export]
     external_velocity: Vector3,
-    loaded_spell: Vec<u64>,
+    /// The spells ready to be cast, by slot. There's always at least one slot
+    loaded_spells: Vec<Vec<u64>>,
     spells_cast: Vec<Gd<Spell>>,
     #[export]
     energy_charged: f64,
@@ -72,23 +179,42 @@ pub struct MagicalEntity {
     charge_to: f64,
     component_efficiency_levels: HashMap<u64, f64>,
     horizontal_direction_parent: Option<Gd<Node3D>>,
-    vertical_direction_parent: Option<Gd<Node3D>>
+    vertical_direction_parent: Option<Gd<Node3D>>,
+    /// Maps damage types to how much of that type of damage the magical entity takes. Types that aren't here do full damage
+    resistances: HashMap<u64, f64>,
+    statuses: Statuses,
+    cooldowns: Cooldowns,
+    /// The energy lose rate spells are cast with by a caster with a focus of 1, from config.toml
+    spell_energy_lose_rate: f64,
+    focus_stability: f64,
+    /// How much charging slows down as energy charged fills up, from config.toml
+    energy_charge_curve: f64,
+    /// Stops `died` being emitted again if the magical entity takes damage before it's freed
+    has_died: bool,
+    /// How many more spells the magical entity can send back instead of taking damage from
+    deflect_charges: u32
 }
 
 #[godot_api]
 impl ICharacterBody3D for MagicalEntity {
     fn init(base: Base<CharacterBody3D>) -> Self {
+        let config = Config::get_config().unwrap_or_else(|error| {
+            godot_warn!("{}", error);
+            Config::default()
+        });
         Self {
             base,
             save_path: None,
             check_allowed_to_cast: true,
             component_catalogue: ComponentCatalogue::new(),
-            spell_color: DEFAULT_COLOR.into_spell_color(),
+            spell_color: DEFAULT_COLOR.into_spell_color(config.spell_transparency),
             mass: 0.0,
             health: 0.0,
             max_health: 0.0,
+            faction: NO_FACTION,
+            team: NEUTRAL_TEAM,
             external_velocity: Vector3::ZERO,
-            loaded_spell: Vec::new(),
+            loaded_spells: vec![Vec::new()],
             spells_cast: Vec::new(),
             energy_charged: 0.0,
             energy_selected: 1.0,
@@ -98,12 +224,36 @@ impl ICharacterBody3D for MagicalEntity {
             charge_to: 0.0,
             component_efficiency_levels: HashMap::new(),
             horizontal_direction_parent: None,
-            vertical_direction_parent: None
+            vertical_direction_parent: None,
+            resistances: HashMap::new(),
+            statuses: Statuses::default(),
+            cooldowns: Cooldowns::new(config.component_cooldowns),
+            spell_energy_lose_rate: config.energy_lose_rate,
+            focus_stability: config.focus_stability,
+            energy_charge_curve: config.energy_charge_curve,
+            has_died: false,
+            deflect_charges: 0
         }
     }
 }
 
 impl MagicalEntity {
+    /// Returns the energy lose rate of a spell cast with `focus`. Focused casters make steadier spells that lose energy more slowly, and distracted casters make leakier ones.
+    /// Focus goes from 0 to 2, so with a `focus_stability` from 0 to 1 the rate never goes below zero
+    fn get_focused_energy_lose_rate(energy_lose_rate: f64, focus: f64, focus_stability: f64) -> f64 {
+        energy_lose_rate * (1.0 + focus_stability * (1.0 - focus))
+    }
+
+    /// Keeps a slot count from Godot between 1 and `MAX_LOADED_SLOTS`
+    fn get_clamped_slot_count(count: i64) -> usize {
+        count.clamp(1, MAX_LOADED_SLOTS as i64) as usize
+    }
+
+    /// Turns a slot from Godot into an index of `loaded_spells`, or None if there isn't a slot there
+    fn get_slot_index(slot: i64, slot_count: usize) -> Option<usize> {
+        usize::try_from(slot).ok().filter(|&index| index < slot_count)
+    }
+
     fn get_save_path_reference(&self) -> &str {
         match self.save_path {
             Some(ref path) => path,
@@ -120,7 +270,35 @@ impl MagicalEntity {
         return false
     }
 
-    fn get_original_direction(&self) -> Basis {
+    /// Makes the magical entity the owner of a spell it didn't cast directly, such as a fragment of one of its spells
+    pub fn add_spell_cast(&mut self, spell: Gd<Spell>) {
+        self.spells_cast.push(spell);
+    }
+
+    /// Stops the magical entity owning a spell, such as one that was deflected by someone else
+    pub fn remove_spell_cast(&mut self, spell: &Gd<Spell>) {
+        self.spells_cast.retain(|owned_spell| owned_spell != spell);
+    }
+
+    /// Uses up a deflect charge if there's one left. Returns whether one was used
+    pub fn use_deflect_charge(&mut self) -> bool {
+        if self.deflect_charges == 0 {
+            return false
+        }
+        self.deflect_charges -= 1;
+        true
+    }
+
+    fn get_resistance_multiplier(resistances: &HashMap<u64, f64>, damage_type: u64) -> f64 {
+        resistances.get(&damage_type).copied().unwrap_or(1.0)
+    }
+
+    /// Returns how much of `damage` of `damage_type` the magical entity takes, once its vulnerabilities and its resistance to that type are applied
+    fn get_damage_received(damage: f64, damage_multiplier: f64, resistances: &HashMap<u64, f64>, damage_type: u64) -> f64 {
+        damage * damage_multiplier * MagicalEntity::get_resistance_multiplier(resistances, damage_type)
+    }
+
+    pub fn get_original_direction(&self) -> Basis {
         let horizontal_direction = match self.horizontal_direction_parent {
             Some(ref parent) => parent.get_basis(),
             None => Basis::default()
@@ -146,6 +324,12 @@ impl MagicalEntity {
     fn handle_external_and_character_velocity(&mut self, character_velocity: Vector3, gravity: f32, air_resistance: f32, delta: f32) -> Vector3 {
         let mut character_velocity_copy = character_velocity;
 
+        // Stunned magical entities can't move themselves, but still fall and can be pushed
+        if self.statuses.is_stunned() {
+            character_velocity_copy.x = 0.0;
+            character_velocity_copy.z = 0.0;
+        }
+
         let downward = gravity * delta;
         if self.external_velocity.y - downward > 0.0 {
             self.external_velocity.y -= downward;
@@ -201,22 +385,108 @@ impl MagicalEntity {
         self.health + self.energy_charged
     }
 
+    /// `damage_type` is one of the codes in damagetypes.rs. Emits `damaged` with the damage taken from both energy charged and health, and `died` if health runs out
     #[func]
-    pub fn take_damage(&mut self, damage: f64) {
-        if self.energy_charged - damage > 0.0 {
-            self.energy_charged -= damage;
+    pub fn take_damage(&mut self, damage: f64, damage_type: u64) {
+        let damage = MagicalEntity::get_damage_received(damage, self.statuses.get_damage_multiplier(), &self.resistances, damage_type);
+        let is_lethal = damage >= self.get_energy_to_kill();
+        let (energy_charged, health) = MagicalEntity::get_damage_taken(damage, self.energy_charged, self.health);
+        let amount = (self.energy_charged - energy_charged) + (self.health - health);
+        self.energy_charged = energy_charged;
+        self.health = health;
+
+        if amount > 0.0 {
+            self.base_mut().emit_signal("damaged", &[Variant::from(amount), Variant::from(health)]);
+        }
+
+        if is_lethal && !self.has_died {
+            self.has_died = true;
+            self.base_mut().emit_signal("died", &[]);
+            self.perish();
+        }
+    }
+
+    /// Returns the energy charged and health left after taking `damage`. Energy charged is used up first, and health doesn't go below zero
+    fn get_damage_taken(damage: f64, energy_charged: f64, health: f64) -> (f64, f64) {
+        if energy_charged - damage > 0.0 {
+            return (energy_charged - damage, health)
+        }
+
+        let energy_remaining = damage - energy_charged;
+        if health - energy_remaining > 0.0 {
+            (0.0, health - energy_remaining)
         } else {
-            let energy_remaining = damage - self.energy_charged;
-            self.energy_charged = 0.0;
-            if self.health - energy_remaining > 0.0 {
-                self.health -= energy_remaining;
-            } else {
-                self.health = 0.0;
-                self.perish();
-            }
+            (0.0, 0.0)
         }
     }
 
+    /// Each charge sends the next spell that would damage the magical entity back the way it came, and makes the magical entity its owner
+    #[func]
+    fn add_deflect_charges(&mut self, charges: u32) {
+        self.deflect_charges += charges;
+    }
+
+    #[func]
+    fn get_deflect_charges(&self) -> u32 {
+        self.deflect_charges
+    }
+
+    /// Makes the magical entity take `multiplier` times as much damage of `damage_type`. A multiplier of 0.5 halves the damage
+    #[func]
+    fn set_resistance(&mut self, damage_type: u64, multiplier: f64) {
+        self.resistances.insert(damage_type, multiplier);
+    }
+
+    /// Makes the magical entity take `multiplier` times as much damage for `duration` seconds
+    #[func]
+    pub fn mark_vulnerable(&mut self, multiplier: f64, duration: f64) {
+        self.statuses.add_vulnerability(multiplier, duration);
+    }
+
+    /// Puts the magical entity on `team`, so the spells it casts don't damage magical entities on the same team. A team of -1 is neutral and harms everyone
+    #[func]
+    fn set_team(&mut self, team: i64) {
+        self.team = team;
+        self.faction = MagicalEntity::get_team_faction(team);
+    }
+
+    #[func]
+    fn get_team(&self) -> i64 {
+        self.team
+    }
+
+    /// Teams start at 0 but factions start at 1, as faction 0 is neutral
+    fn get_team_faction(team: i64) -> i64 {
+        if team == NEUTRAL_TEAM {
+            return NO_FACTION
+        }
+        team.saturating_add(1)
+    }
+
+    /// Stops the magical entity casting spells or moving itself for `duration` seconds, unless it's already stunned for longer
+    #[func]
+    pub fn stun(&mut self, duration: f64) {
+        self.statuses.add_stun(duration);
+    }
+
+    #[func]
+    fn is_stunned(&self) -> bool {
+        self.statuses.is_stunned()
+    }
+
+    /// Increases health by `amount` without going over `max_health`. Returns the health actually restored
+    #[func]
+    pub fn restore_health(&mut self, amount: f64) -> f64 {
+        let previous_health = self.health;
+        self.health = MagicalEntity::get_restored_health(self.health, self.max_health, amount);
+        self.health - previous_health
+    }
+
+    /// Returns the health after restoring `amount`, which never goes over `max_health` and never goes down
+    fn get_restored_health(health: f64, max_health: f64, amount: f64) -> f64 {
+        (health + amount).min(max_health).max(health)
+    }
+
     #[func]
     fn perish(&mut self) {
         self.base_mut().queue_free();
@@ -225,7 +495,11 @@ impl MagicalEntity {
     /// Focus factors into current power output and current control. Focus ranges from 0 to 2 with the default state being 1
     #[func]
     fn get_focus(&self) -> f64 {
-        2.0 / (1.0 + E.powf(-self.focus_level * FOCUS_LEVEL_TO_FOCUS))
+        MagicalEntity::get_focus_from_level(self.focus_level)
+    }
+
+    fn get_focus_from_level(focus_level: f64) -> f64 {
+        2.0 / (1.0 + E.powf(-focus_level * FOCUS_LEVEL_TO_FOCUS))
     }
 
     #[func]
@@ -314,8 +588,8 @@ impl MagicalEntity {
     fn handle_magic(&mut self, delta: f64) {
         let control = self.get_control();
 
-        let extra_energy = self.get_power() * delta;
         let max_energy = self.max_control * self.charge_to;
+        let extra_energy = MagicalEntity::get_curved_charge(self.get_power() * delta, self.energy_charged, max_energy, self.energy_charge_curve);
 
         if control >= extra_energy {
             self.energy_charged += extra_energy;
@@ -331,6 +605,27 @@ impl MagicalEntity {
         self.reduce_focus(delta);
         self.passive_focus_stabilising(DEFAULT_PASSIVE_FOCUS_CHANGE_RATE, DEFAULT_PASSIVE_FOCUS_CHANGE_RATE, delta); // TODO: Handle changing passive_focus_stabilising_rate
         self.fulfil_recharge_requests();
+        self.statuses.update(delta);
+        self.cooldowns.update(delta);
+    }
+
+    /// Slows charging down as energy charged gets closer to `max_energy`, by more the higher `energy_charge_curve` is. A curve of 0 doesn't change it
+    fn get_curved_charge(extra_energy: f64, energy_charged: f64, max_energy: f64, energy_charge_curve: f64) -> f64 {
+        let fraction_left = 1.0 - MagicalEntity::get_charged_fraction(energy_charged, max_energy);
+        extra_energy * fraction_left.max(0.0).powf(energy_charge_curve)
+    }
+
+    fn get_charged_fraction(energy_charged: f64, max_energy: f64) -> f64 {
+        if max_energy <= 0.0 {
+            return 0.0
+        }
+        (energy_charged / max_energy).clamp(0.0, 1.0)
+    }
+
+    /// Returns how full energy charged is, from 0 to 1, out of the most `charge_to` lets it charge up to. Useful for showing a bar. It's 0 if nothing can be charged
+    #[func]
+    fn get_energy_charged_percent(&self) -> f64 {
+        MagicalEntity::get_charged_fraction(self.energy_charged, self.max_control * self.charge_to)
     }
 
     #[func]
@@ -357,19 +652,15 @@ impl MagicalEntity {
         self.focus_level -= FOCUS_LOSE_FROM_ENERGY * self.spells_cast.iter().map(|x| x.bind().get_control_needed()).sum::<f64>() * delta / self.max_power;
     }
 
+    /// Tops up the spells that asked for energy with `recharge_to` or by being charged to their shape, out of energy charged.
+    /// Each spell's request is cleared once it's fulfilled, so a spell only keeps getting energy if it keeps asking for it
     #[func]
     fn fulfil_recharge_requests(&mut self) {
         self.spells_cast.retain_mut(|spell| {
             if spell.is_instance_valid() {
                 let mut spell_bind = spell.bind_mut();
-                let energy_requested = spell_bind.energy_requested;
-                if energy_requested > self.energy_charged {
-                    spell_bind.energy += self.energy_charged;
-                    self.energy_charged = 0.0;
-                } else {
-                    spell_bind.energy += energy_requested;
-                    self.energy_charged -= energy_requested;
-                }
+                self.energy_charged -= spell_bind.fulfil_energy_request(self.energy_charged);
+                spell_bind.caster_energy_charged = self.energy_charged;
                 true
             } else {
                 false
@@ -377,8 +668,22 @@ impl MagicalEntity {
         });
     }
 
+    /// Casts the spell in slot 0
     #[func]
     fn cast_spell(&mut self) {
+        self.cast_spell_from_slot(0);
+    }
+
+    #[func]
+    fn cast_spell_from_slot(&mut self, slot: i64) {
+        let loaded_spell = match MagicalEntity::get_slot_index(slot, self.loaded_spells.len()) {
+            Some(index) => self.loaded_spells[index].clone(),
+            None => {
+                godot_warn!("Couldn't cast spell: slot {} doesn't exist", slot);
+                return
+            }
+        };
+
         let energy = self.energy_charged * self.energy_selected;
 
         if energy < ENERGY_CONSIDERATION_LEVEL {
@@ -386,23 +691,41 @@ impl MagicalEntity {
         }
 
         if self.check_allowed_to_cast {
-            if Spell::internal_check_allowed_to_cast(self.loaded_spell.clone(), &self.component_catalogue).is_err() {
+            if Spell::internal_check_allowed_to_cast(loaded_spell.clone(), &self.component_catalogue).is_err() {
                 return
             }
         }
 
+        if self.cooldowns.check_instructions(&loaded_spell).and_then(|_| self.statuses.check_can_cast()).is_err() {
+            return
+        }
+
         let mut spell = Spell::new_alloc();
         spell.set_as_top_level(true);
 
-        {
+        let set_instructions_result = {
             let mut spell_bind = spell.bind_mut();
 
             spell_bind.set_energy(energy);
             spell_bind.set_color(self.spell_color);
             spell_bind.connect_player(self.to_gd().upcast());
             spell_bind.internal_set_efficiency_levels(self.component_efficiency_levels.clone());
-            spell_bind.internal_set_instructions(self.loaded_spell.clone());
+            spell_bind.energy_lose_rate = MagicalEntity::get_focused_energy_lose_rate(self.spell_energy_lose_rate, self.get_focus(), self.focus_stability);
+            let set_instructions_result = spell_bind.internal_set_instructions(loaded_spell);
             spell_bind.set_original_direction(self.get_original_direction());
+            spell_bind.caster = Some(self.to_gd());
+            spell_bind.faction = self.faction;
+            spell_bind.caster_energy_selected = self.energy_selected;
+            spell_bind.caster_energy_charged = self.energy_charged - energy;
+            spell_bind.caster_focus = self.get_focus();
+            set_instructions_result
+        };
+
+        // Loaded spells can come from saves, so they might not be valid
+        if let Err(error) = set_instructions_result {
+            godot_warn!("Couldn't cast spell: {}", error);
+            spell.free();
+            return
         }
 
         spell.set_position(self.base().get_global_position());
@@ -431,20 +754,120 @@ impl MagicalEntity {
         }
     }
 
+    /// Takes in a json object mapping component codes to how many seconds they can't be cast for after being cast, replacing the cooldowns from config.toml
+    #[func]
+    fn set_component_cooldowns(&mut self, component_cooldowns_bytecode_json: GString) {
+        let json_string = component_cooldowns_bytecode_json.to_string();
+
+        match serde_json::from_str(&json_string) {
+            Ok(Value::Object(component_cooldowns_object)) => {
+                let mut temp_hashmap: HashMap<u64, f64> = HashMap::new();
+                for (key, value) in component_cooldowns_object {
+                    if let (Ok(parsed_key), Some(parsed_value)) = (key.parse::<u64>(), value.as_f64()) {
+                        temp_hashmap.insert(parsed_key, parsed_value);
+                    }
+                }
+                self.cooldowns.base_cooldowns = temp_hashmap;
+            },
+            Ok(_) => panic!("Invalid Json: Must be object"),
+            Err(_) => panic!("Invalid Json: Incorrect format")
+        }
+    }
+
+    /// Called by spells when they cast a component. The efficiency increase isn't used but is part of the `component_cast` signal
+    #[func]
+    fn start_component_cooldown(&mut self, component: u64, _efficiency_increase: f64) {
+        self.cooldowns.start(component);
+    }
+
+    /// Returns how many seconds are left until spells using the component can be cast again
+    #[func]
+    fn get_remaining_cooldown(&self, component: u64) -> f64 {
+        self.cooldowns.get_remaining(component)
+    }
+
     #[func]
     fn increase_component_efficiency(&mut self, component: u64, efficiency_increase: f64) {
-        let current_efficiency_level = self.component_efficiency_levels.get(&component).unwrap_or(&1.0);
-        self.component_efficiency_levels.insert(component, current_efficiency_level + efficiency_increase);
+        MagicalEntity::increase_efficiency_level(&mut self.component_efficiency_levels, component, efficiency_increase);
+    }
+
+    /// Returns the efficiency level of the component with the name given, which is 1 for components that haven't been cast yet
+    #[func]
+    fn get_component_efficiency(&self, component: GString) -> f64 {
+        match spelltranslator::get_component_num(&component.to_string()) {
+            Some(component_code) => MagicalEntity::get_efficiency_level(&self.component_efficiency_levels, component_code),
+            None => {
+                godot_warn!("Couldn't get efficiency: {} isn't a component", component);
+                DEFAULT_EFFICIENCY_LEVEL
+            }
+        }
+    }
+
+    /// Returns a dictionary mapping the name of every component that has been cast to its efficiency level
+    #[func]
+    fn get_all_efficiencies(&self) -> Dictionary {
+        let mut efficiencies = Dictionary::new();
+        for (component_name, efficiency_level) in MagicalEntity::get_named_efficiency_levels(&self.component_efficiency_levels) {
+            efficiencies.set(GString::from(component_name), efficiency_level);
+        }
+        efficiencies
+    }
+
+    fn increase_efficiency_level(efficiency_levels: &mut HashMap<u64, f64>, component_code: u64, efficiency_increase: f64) {
+        *efficiency_levels.entry(component_code).or_insert(DEFAULT_EFFICIENCY_LEVEL) += efficiency_increase;
+    }
+
+    fn get_efficiency_level(efficiency_levels: &HashMap<u64, f64>, component_code: u64) -> f64 {
+        efficiency_levels.get(&component_code).copied().unwrap_or(DEFAULT_EFFICIENCY_LEVEL)
+    }
+
+    /// Keys efficiency levels by component name instead of code. Codes that aren't components, which could come from `set_efficiency_levels`, are left out
+    fn get_named_efficiency_levels(efficiency_levels: &HashMap<u64, f64>) -> Vec<(String, f64)> {
+        efficiency_levels.iter()
+            .filter_map(|(&component_code, &efficiency_level)| Some((spelltranslator::get_component_name(component_code)?, efficiency_level)))
+            .collect()
     }
 
+    /// Loads a spell into slot 0
     #[func]
     fn set_loaded_spell(&mut self, spell: GString) {
-        self.loaded_spell = Spell::translate_instructions(&spell)
+        self.set_loaded_spell_in_slot(0, spell);
+    }
+
+    #[func]
+    fn set_loaded_spell_in_slot(&mut self, slot: i64, spell: GString) {
+        let index = match MagicalEntity::get_slot_index(slot, self.loaded_spells.len()) {
+            Some(index) => index,
+            None => {
+                godot_warn!("Couldn't load spell: slot {} doesn't exist", slot);
+                return
+            }
+        };
+        let instructions = Spell::translate_instructions(&spell);
+        match validator::validate_instructions(&instructions) {
+            Ok(()) => self.loaded_spells[index] = instructions,
+            Err(error) => godot_warn!("Couldn't load spell: {}", error)
+        }
     }
 
     #[func]
     fn unset_loaded_spell(&mut self) {
-        self.loaded_spell = Vec::new();
+        self.loaded_spells[0] = Vec::new();
+    }
+
+    #[func]
+    fn get_loaded_slot_count(&self) -> i64 {
+        self.loaded_spells.len() as i64
+    }
+
+    /// Changes how many spells can be loaded at once. Slots past the new count are emptied, and there's always at least one slot and at most `MAX_LOADED_SLOTS`
+    #[func]
+    fn set_loaded_slot_count(&mut self, count: i64) {
+        let slot_count = MagicalEntity::get_clamped_slot_count(count);
+        if slot_count as i64 != count {
+            godot_warn!("Slot count {} is out of range, so {} slots are used instead", count, slot_count);
+        }
+        self.loaded_spells.resize(slot_count, Vec::new());
     }
 
     #[func]
@@ -466,7 +889,12 @@ impl MagicalEntity {
             None => return false
         };
 
-        self.loaded_spell = match spelltranslator::parse_spell(spell, None) {
+        let config = Config::get_config().unwrap_or_else(|err| {
+            godot_warn!("Config.toml couldn't be opened, so custom_translation and component_aliases can't be used: {err}");
+            Config::default()
+        });
+
+        self.loaded_spells[0] = match spelltranslator::parse_spell(spell, Some(&config)) {
             Ok(instr) => instr,
             Err(_) => return false
         };
@@ -516,7 +944,7 @@ impl MagicalEntity {
     #[func]
     fn load_saved_data(&mut self) {
         match godot_json_saver::from_path::<PlayerConfig>(&format!("{}/player_config", self.get_save_path_reference())) {
-            Ok(player_config) => self.spell_color = player_config.color.into_spell_color(),
+            Ok(player_config) => self.spell_color = player_config.color.into_spell_color(self.spell_color.a),
             Err(_) => {}
         };
         match godot_json_saver::from_path::<ComponentCatalogue>(&format!("{}/component_catalogue", self.get_save_path_reference())) {
@@ -528,7 +956,7 @@ impl MagicalEntity {
     #[func]
     fn add_component(&mut self, component: GString) {
         let component_code = spelltranslator::get_component_num(&component.to_string()).expect("Component doesn't exist");
-        let number_of_parameters = Spell::get_number_of_component_parameters(&component_code);
+        let number_of_parameters = Spell::get_number_of_component_parameters(&component_code).expect("Component doesn't exist");
         let mut parameter_restrictions: Vec<Vec<&str>> = Vec::new();
         for _ in 0..number_of_parameters {
             parameter_restrictions.push(vec!["ANY"]);
@@ -550,17 +978,288 @@ impl MagicalEntity {
         self.component_catalogue.component_catalogue.remove(&component_code);
     }
 
+    /// Adds every component to the component catalogue without any parameter restrictions
+    #[func]
+    fn add_all_components(&mut self) {
+        self.component_catalogue.add_all_components();
+    }
+
+    /// Returns true if the component is in the component catalogue. Components that don't exist are never allowed
+    #[func]
+    fn is_component_allowed(&self, component: GString) -> bool {
+        spelltranslator::get_component_num(&component.to_string())
+            .is_some_and(|component_code| self.component_catalogue.contains(component_code))
+    }
+
     #[func]
     fn check_allowed_to_cast(&self, instructions_json: GString) -> Dictionary {
-        let (allowed_to_cast, denial_reason) = match Spell::internal_check_allowed_to_cast(Spell::translate_instructions(&instructions_json), &self.component_catalogue) {
+        let instructions = Spell::translate_instructions(&instructions_json);
+        let (allowed_to_cast, denial_reason) = match Spell::internal_check_allowed_to_cast(instructions.clone(), &self.component_catalogue).and_then(|_| self.cooldowns.check_instructions(&instructions)).and_then(|_| self.statuses.check_can_cast()) {
             Ok(_) => (true, ""),
-            Err(error_message) => (false, error_message)
+            Err(error) => (false, error.get_message())
         };
         return dict! {"allowed_to_cast": allowed_to_cast, "denial_reason": denial_reason}
     }
 
+    /// Adds `energy` to the energy charged, without going above the ceiling that `handle_magic` charges up to
     #[func]
-    fn increase_energy_charged(&mut self, energy: f64) {
-        self.energy_charged += energy;
+    pub fn increase_energy_charged(&mut self, energy: f64) {
+        self.energy_charged = MagicalEntity::get_capped_energy_charged(self.energy_charged, energy, self.max_control, self.charge_to);
+    }
+
+    /// Returns the energy charged after adding `energy`, kept between 0 and `max_control * charge_to`.
+    /// The ceiling uses `charge_to` rather than focus as it's the same one `handle_magic` enforces, so energy added here is never taken away again on the next frame
+    fn get_capped_energy_charged(energy_charged: f64, energy: f64, max_control: f64, charge_to: f64) -> f64 {
+        let max_energy = max_control * charge_to;
+        (energy_charged + energy).min(max_energy).max(0.0)
+    }
+
+    /// `amount` counts damage taken from energy charged as well as health
+    #[signal]
+    fn damaged(amount: f64, remaining_health: f64);
+
+    /// Emitted once, when health reaches zero, just before the magical entity is freed
+    #[signal]
+    fn died();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vulnerability_amplifies_damage_until_expiry() {
+        let mut statuses = Statuses::default();
+        let damage = 10.0;
+        assert_eq!(damage * statuses.get_damage_multiplier(), 10.0);
+
+        statuses.add_vulnerability(1.5, 2.0);
+        assert_eq!(damage * statuses.get_damage_multiplier(), 15.0);
+
+        statuses.update(1.0);
+        assert_eq!(damage * statuses.get_damage_multiplier(), 15.0);
+
+        statuses.update(1.5);
+        assert_eq!(damage * statuses.get_damage_multiplier(), 10.0);
+        assert!(statuses.vulnerabilities.is_empty());
+    }
+
+    #[test]
+    fn repeated_vulnerability_mark_does_not_stack() {
+        let mut statuses = Statuses::default();
+
+        // A marked spell overlapping a magical entity marks it again every frame
+        for _ in 0..120 {
+            statuses.add_vulnerability(1.5, 2.0);
+            statuses.update(1.0 / 60.0);
+        }
+        assert_eq!(statuses.get_damage_multiplier(), 1.5);
+        assert_eq!(statuses.vulnerabilities.len(), 1);
+
+        // The timer was refreshed by the last mark
+        statuses.update(1.5);
+        assert_eq!(statuses.get_damage_multiplier(), 1.5);
+
+        // A different mark still stacks with it
+        statuses.add_vulnerability(2.0, 1.0);
+        assert_eq!(statuses.get_damage_multiplier(), 3.0);
+    }
+
+    #[test]
+    fn stunned_magical_entities_cannot_cast() {
+        let mut statuses = Statuses::default();
+        assert_eq!(statuses.check_can_cast(), Ok(()));
+
+        statuses.add_stun(2.0);
+        assert_eq!(statuses.check_can_cast(), Err(SpellError::NotAllowed("Magical entity is stunned")));
+
+        // A shorter stun doesn't cut the longer one short
+        statuses.add_stun(0.5);
+        statuses.update(1.0);
+        assert!(statuses.is_stunned());
+
+        statuses.update(1.0);
+        assert!(!statuses.is_stunned());
+        assert_eq!(statuses.check_can_cast(), Ok(()));
+    }
+
+    #[test]
+    fn efficiency_getters_reflect_increases() {
+        use crate::codes::componentcodes::*;
+        let mut efficiency_levels: HashMap<u64, f64> = HashMap::new();
+        assert_eq!(MagicalEntity::get_efficiency_level(&efficiency_levels, GIVE_VELOCITY), DEFAULT_EFFICIENCY_LEVEL);
+
+        MagicalEntity::increase_efficiency_level(&mut efficiency_levels, GIVE_VELOCITY, 0.5);
+        MagicalEntity::increase_efficiency_level(&mut efficiency_levels, GIVE_VELOCITY, 0.25);
+        assert_eq!(MagicalEntity::get_efficiency_level(&efficiency_levels, GIVE_VELOCITY), 1.75);
+        assert_eq!(MagicalEntity::get_efficiency_level(&efficiency_levels, PERISH), DEFAULT_EFFICIENCY_LEVEL);
+
+        efficiency_levels.insert(999_999, 3.0);
+        assert_eq!(MagicalEntity::get_named_efficiency_levels(&efficiency_levels), vec![(String::from("give_velocity"), 1.75)]);
+    }
+
+    #[test]
+    fn damage_uses_up_energy_charged_before_health() {
+        assert_eq!(MagicalEntity::get_damage_taken(3.0, 5.0, 10.0), (2.0, 10.0));
+        // Partly taken from energy charged, so health only loses what's left over
+        assert_eq!(MagicalEntity::get_damage_taken(8.0, 5.0, 10.0), (0.0, 7.0));
+        assert_eq!(MagicalEntity::get_damage_taken(5.0, 5.0, 10.0), (0.0, 10.0));
+        // Damage past what's needed to kill isn't taken
+        assert_eq!(MagicalEntity::get_damage_taken(40.0, 5.0, 10.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn focus_steadies_spells() {
+        let unfocused = MagicalEntity::get_focused_energy_lose_rate(0.05, 1.0, 0.5);
+        let focused = MagicalEntity::get_focused_energy_lose_rate(0.05, 2.0, 0.5);
+        let distracted = MagicalEntity::get_focused_energy_lose_rate(0.05, 0.0, 0.5);
+        assert_eq!(unfocused, 0.05);
+        assert!(focused < unfocused);
+        assert!(distracted > unfocused);
+        assert!((focused - 0.025).abs() < 1e-12);
+
+        // Without focus_stability, focus makes no difference
+        assert_eq!(MagicalEntity::get_focused_energy_lose_rate(0.05, 2.0, 0.0), 0.05);
+        assert!(MagicalEntity::get_focused_energy_lose_rate(0.05, 2.0, 1.0) >= 0.0);
+    }
+
+    #[test]
+    fn spells_are_cast_with_the_caster_focus() {
+        use crate::codes::componentcodes::*;
+
+        assert_eq!(MagicalEntity::get_focus_from_level(0.0), 1.0);
+
+        // The focus level that gives a focus of 1.5, which `cast_spell` gives the spell for `caster_focus`
+        let focus_level = 3.0_f64.ln() / FOCUS_LEVEL_TO_FOCUS;
+        assert!((MagicalEntity::get_focus_from_level(focus_level) - 1.5).abs() < 1e-12);
+        assert_eq!(spelltranslator::get_component_num("caster_focus"), Some(CASTER_FOCUS));
+    }
+
+    #[test]
+    fn curved_charging_settles_below_full() {
+        let max_energy = 100.0;
+        let delta = 0.1;
+        // Charges like `handle_magic` with 10 power and no spells using control, until energy charged stops changing
+        let charge = |energy_charge_curve: f64| {
+            let mut energy_charged: f64 = 0.0;
+            for _ in 0..100_000 {
+                energy_charged += MagicalEntity::get_curved_charge(10.0 * delta, energy_charged, max_energy, energy_charge_curve);
+                energy_charged = energy_charged.min(max_energy);
+                energy_charged -= energy_charged * ENERGY_CHARGED_LOSE_RATE * delta;
+            }
+            energy_charged
+        };
+
+        let linear = charge(0.0);
+        let curved = charge(2.0);
+        assert!((linear - max_energy * (1.0 - ENERGY_CHARGED_LOSE_RATE * delta)).abs() < 1e-9);
+        assert!(curved < linear);
+        // Where charging and losing energy balance out
+        let fraction_left = 1.0 - curved / max_energy;
+        assert!((10.0 * fraction_left.powi(2) - curved * ENERGY_CHARGED_LOSE_RATE).abs() < 0.1);
+
+        assert_eq!(MagicalEntity::get_charged_fraction(25.0, max_energy), 0.25);
+        assert_eq!(MagicalEntity::get_charged_fraction(25.0, 0.0), 0.0);
+        assert_eq!(MagicalEntity::get_charged_fraction(150.0, max_energy), 1.0);
+        assert_eq!(MagicalEntity::get_charged_fraction(-10.0, max_energy), 0.0);
+    }
+
+    #[test]
+    fn over_charging_is_capped() {
+        assert_eq!(MagicalEntity::get_capped_energy_charged(10.0, 20.0, 100.0, 0.5), 30.0);
+        assert_eq!(MagicalEntity::get_capped_energy_charged(40.0, 20.0, 100.0, 0.5), 50.0);
+        assert_eq!(MagicalEntity::get_capped_energy_charged(0.0, f64::MAX, 100.0, 1.0), 100.0);
+        assert_eq!(MagicalEntity::get_capped_energy_charged(10.0, 5.0, 100.0, 0.0), 0.0);
+        assert_eq!(MagicalEntity::get_capped_energy_charged(10.0, -20.0, 100.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn same_team_entities_take_no_damage() {
+        let team_faction = MagicalEntity::get_team_faction(0);
+        assert_ne!(team_faction, NO_FACTION);
+        assert_ne!(team_faction, MagicalEntity::get_team_faction(1));
+
+        // A spell cast by someone on team 0 doesn't target anyone else on team 0
+        assert!(!Spell::is_target_of(team_faction, false, MagicalEntity::get_team_faction(0)));
+        assert!(Spell::is_target_of(team_faction, false, MagicalEntity::get_team_faction(1)));
+
+        // Neutral magical entities harm everyone and are harmed by everyone
+        assert_eq!(MagicalEntity::get_team_faction(NEUTRAL_TEAM), NO_FACTION);
+        assert!(Spell::is_target_of(NO_FACTION, false, MagicalEntity::get_team_faction(NEUTRAL_TEAM)));
+        assert!(Spell::is_target_of(team_faction, false, MagicalEntity::get_team_faction(NEUTRAL_TEAM)));
+    }
+
+    #[test]
+    fn slot_counts_are_clamped() {
+        assert_eq!(MagicalEntity::get_clamped_slot_count(3), 3);
+        assert_eq!(MagicalEntity::get_clamped_slot_count(0), 1);
+        assert_eq!(MagicalEntity::get_clamped_slot_count(-5), 1);
+        assert_eq!(MagicalEntity::get_clamped_slot_count(i64::MAX), MAX_LOADED_SLOTS);
+    }
+
+    #[test]
+    fn slots_out_of_range_are_none() {
+        assert_eq!(MagicalEntity::get_slot_index(0, 1), Some(0));
+        assert_eq!(MagicalEntity::get_slot_index(2, 3), Some(2));
+        assert_eq!(MagicalEntity::get_slot_index(3, 3), None);
+        assert_eq!(MagicalEntity::get_slot_index(-1, 3), None);
+        assert_eq!(MagicalEntity::get_slot_index(i64::MAX, 3), None);
+    }
+
+    #[test]
+    fn resistances_reduce_damage_of_their_type() {
+        use crate::codes::damagetypes::*;
+
+        let resistances = HashMap::from([(FIRE, 0.5)]);
+        let damage = 10.0;
+        assert_eq!(damage * MagicalEntity::get_resistance_multiplier(&resistances, FIRE), 5.0);
+        assert_eq!(damage * MagicalEntity::get_resistance_multiplier(&resistances, ICE), 10.0);
+        assert_eq!(damage * MagicalEntity::get_resistance_multiplier(&resistances, 99), 10.0);
+    }
+
+    #[test]
+    fn typed_beam_damage_is_resisted() {
+        use crate::codes::damagetypes::*;
+        use crate::Beam;
+
+        // A fire beam hitting something that's vulnerable but resists fire
+        let resistances = HashMap::from([(FIRE, 0.25)]);
+        let beam = Beam { damage_per_second: 20.0, max_length: 10.0 };
+        let frame_damage = beam.get_frame_damage(0.5, f64::INFINITY);
+        assert_eq!(MagicalEntity::get_damage_received(frame_damage, 2.0, &resistances, FIRE), 5.0);
+        assert_eq!(MagicalEntity::get_damage_received(frame_damage, 2.0, &resistances, UNTYPED), 20.0);
+    }
+
+    #[test]
+    fn components_on_cooldown_stop_casting() {
+        use crate::spelltranslator::parse_spell;
+        use crate::codes::componentcodes::*;
+
+        let mut cooldowns = Cooldowns::new(HashMap::from([(GIVE_VELOCITY, 2.0)]));
+        let instructions = parse_spell("when_created:\nif false {\ngive_velocity(1, 0, 0)\n}\nabout:\ncolor = [1, 0, 1]", None).unwrap();
+        assert_eq!(cooldowns.check_instructions(&instructions), Ok(()));
+
+        // Components without a base cooldown never go on cooldown
+        cooldowns.start(PERISH);
+        assert_eq!(cooldowns.get_remaining(PERISH), 0.0);
+
+        cooldowns.start(GIVE_VELOCITY);
+        assert_eq!(cooldowns.check_instructions(&instructions), Err(SpellError::NotAllowed("Component is on cooldown")));
+
+        cooldowns.update(1.5);
+        assert_eq!(cooldowns.get_remaining(GIVE_VELOCITY), 0.5);
+        cooldowns.update(0.5);
+        assert_eq!(cooldowns.check_instructions(&instructions), Ok(()));
+    }
+
+    #[test]
+    fn lifesteal_heals_the_caster() {
+        // A spell with half lifesteal deals 20 damage, so its caster gets 10 health back
+        let health = MagicalEntity::get_restored_health(50.0, 100.0, Spell::get_lifesteal_health(20.0, 0.5));
+        assert_eq!(health, 60.0);
+
+        // But not past their max health
+        assert_eq!(MagicalEntity::get_restored_health(95.0, 100.0, Spell::get_lifesteal_health(20.0, 0.5)), 100.0);
+        assert_eq!(MagicalEntity::get_restored_health(50.0, 100.0, Spell::get_lifesteal_health(20.0, 0.0)), 50.0);
     }
 }
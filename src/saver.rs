@@ -1,14 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // File system imports
 use godot::classes::file_access::ModeFlags;
 use godot::classes::FileAccess;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use serde::de::DeserializeOwned;
 use std::fs;
 use toml;
 
-use crate::{CustomColor, Shape};
+use crate::{CustomColor, Shape, MINIMUM_SHAPE_DIMENSION, ENERGY_LOSE_RATE, FOCUS_STABILITY, EFFICIENCY_INCREASE_RATE, MASS_MOVEMENT_COST, ENERGY_TO_VOLUME, SPELL_TRANSPARENCY, INSTRUCTION_BUDGET, ENERGY_CHARGE_CURVE, HOMING_DETECTION_RADIUS};
 
 const SPELL_CONFIG_PATH: &'static str = "Spell/config.toml";
 const SPELL_SAVE_FOLDER: &'static str = "SpellSave";
@@ -20,10 +20,54 @@ pub struct PlayerConfig {
     pub color: CustomColor
 }
 
-#[derive(Default)]
 pub struct Config {
     pub forms: HashMap<u64, FormConfig>,
-    pub custom_translation: StringCustomTranslation
+    pub custom_translation: StringCustomTranslation,
+    /// Maps extra names for components to the name of the component
+    pub component_aliases: HashMap<String, String>,
+    /// The smallest size `take_shape` accepts for any dimension of a shape
+    pub minimum_shape_dimension: f64,
+    /// Maps component codes to how many seconds a magical entity has to wait after casting them before casting a spell that uses them again
+    pub component_cooldowns: HashMap<u64, f64>,
+    /// The fraction of their energy spells lose every second
+    pub energy_lose_rate: f64,
+    /// How much a caster's focus lowers or raises the energy lose rate of the spells they cast, from 0 to 1
+    pub focus_stability: f64,
+    /// How quickly components become cheaper to cast as they're cast more
+    pub efficiency_increase_rate: f64,
+    /// The energy an anchored spell loses every frame for each unit of mass it holds
+    pub mass_movement_cost: f64,
+    /// The volume of a spell for each unit of energy it has
+    pub energy_to_volume: f64,
+    /// The alpha of spell colors. 0 is fully transparent and 1 is opaque
+    pub spell_transparency: f32,
+    /// The most instructions a section of a spell can run each time it runs before the spell perishes
+    pub instruction_budget: usize,
+    /// How much charging energy slows down as energy charged fills up. 0 charges at the same rate until full
+    pub energy_charge_curve: f64,
+    /// How far homing spells look for targets when they don't have a detection radius
+    pub homing_detection_radius: f32
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            forms: HashMap::new(),
+            custom_translation: HashMap::new(),
+            component_aliases: HashMap::new(),
+            minimum_shape_dimension: MINIMUM_SHAPE_DIMENSION,
+            component_cooldowns: HashMap::new(),
+            energy_lose_rate: ENERGY_LOSE_RATE,
+            focus_stability: FOCUS_STABILITY,
+            efficiency_increase_rate: EFFICIENCY_INCREASE_RATE,
+            mass_movement_cost: MASS_MOVEMENT_COST,
+            energy_to_volume: ENERGY_TO_VOLUME,
+            spell_transparency: SPELL_TRANSPARENCY,
+            instruction_budget: INSTRUCTION_BUDGET,
+            energy_charge_curve: ENERGY_CHARGE_CURVE,
+            homing_detection_radius: HOMING_DETECTION_RADIUS
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -31,7 +75,71 @@ struct StringConfig {
     #[serde(default)]
     forms: HashMap<String, FormConfig>,
     #[serde(default)]
-    custom_translation: StringCustomTranslation
+    custom_translation: StringCustomTranslation,
+    #[serde(default)]
+    component_aliases: HashMap<String, String>,
+    #[serde(default = "default_minimum_shape_dimension")]
+    minimum_shape_dimension: f64,
+    #[serde(default)]
+    component_cooldowns: HashMap<String, f64>,
+    #[serde(default = "default_energy_lose_rate")]
+    energy_lose_rate: f64,
+    #[serde(default = "default_focus_stability")]
+    focus_stability: f64,
+    #[serde(default = "default_efficiency_increase_rate")]
+    efficiency_increase_rate: f64,
+    #[serde(default = "default_mass_movement_cost")]
+    mass_movement_cost: f64,
+    #[serde(default = "default_energy_to_volume")]
+    energy_to_volume: f64,
+    #[serde(default = "default_spell_transparency")]
+    spell_transparency: f32,
+    #[serde(default = "default_instruction_budget")]
+    instruction_budget: usize,
+    #[serde(default = "default_energy_charge_curve")]
+    energy_charge_curve: f64,
+    #[serde(default = "default_homing_detection_radius")]
+    homing_detection_radius: f32
+}
+
+fn default_minimum_shape_dimension() -> f64 {
+    MINIMUM_SHAPE_DIMENSION
+}
+
+fn default_energy_lose_rate() -> f64 {
+    ENERGY_LOSE_RATE
+}
+
+fn default_focus_stability() -> f64 {
+    FOCUS_STABILITY
+}
+
+fn default_efficiency_increase_rate() -> f64 {
+    EFFICIENCY_INCREASE_RATE
+}
+
+fn default_mass_movement_cost() -> f64 {
+    MASS_MOVEMENT_COST
+}
+
+fn default_energy_to_volume() -> f64 {
+    ENERGY_TO_VOLUME
+}
+
+fn default_spell_transparency() -> f32 {
+    SPELL_TRANSPARENCY
+}
+
+fn default_instruction_budget() -> usize {
+    INSTRUCTION_BUDGET
+}
+
+fn default_energy_charge_curve() -> f64 {
+    ENERGY_CHARGE_CURVE
+}
+
+fn default_homing_detection_radius() -> f32 {
+    HOMING_DETECTION_RADIUS
 }
 
 #[derive(Deserialize, Clone)]
@@ -43,9 +151,20 @@ pub struct FormConfig {
 
 pub mod godot_json_saver {
     use godot::classes::DirAccess;
+    use serde_json::Value;
 
     use super::*;
 
+    /// The version of the save format that `save` writes. Saves from before versioning are version 0
+    pub const CURRENT_SAVE_VERSION: u32 = 1;
+
+    /// Saved data is wrapped in this so that old saves can be told apart and migrated
+    #[derive(Serialize)]
+    struct VersionedSave<T> {
+        version: u32,
+        data: T
+    }
+
     pub fn from_path<T>(path: &str) -> Result<T, &'static str>
     where
         T: DeserializeOwned
@@ -53,8 +172,58 @@ pub mod godot_json_saver {
         let mut json_file = FileAccess::open(path, ModeFlags::READ).ok_or("Couldn't open file")?;
         let file_text: String = json_file.get_as_text().into();
         json_file.close();
-        let object: T = serde_json::from_str(&file_text).map_err(|_| "Couldn't parse json")?;
-        Ok(object)
+        from_json(&file_text)
+    }
+
+    /// Reads data written by `save`, or by versions of it from before saves were versioned
+    pub fn from_json<T>(json: &str) -> Result<T, &'static str>
+    where
+        T: DeserializeOwned
+    {
+        let value: Value = serde_json::from_str(json).map_err(|_| "Couldn't parse json")?;
+        let (version, data) = match value {
+            // Unversioned data can have "version" and "data" keys too, such as spells with those names, so it's only a wrapper if the version is a whole number
+            Value::Object(mut object) if object.len() == 2 && object.contains_key("data") && object.get("version").is_some_and(Value::is_u64) => {
+                let version = object.get("version").and_then(Value::as_u64).expect("Checked version is a whole number");
+                (u32::try_from(version).map_err(|_| "Save is from a newer version")?, object.remove("data").expect("Checked data exists"))
+            },
+            // Saves from before versioning are just the data
+            value => (0, value)
+        };
+        serde_json::from_value(migrate(version, data)?).map_err(|_| "Couldn't parse json")
+    }
+
+    /// Upgrades data saved as `version` to the current shape, one version at a time
+    pub fn migrate(version: u32, mut data: Value) -> Result<Value, &'static str> {
+        if version > CURRENT_SAVE_VERSION {
+            return Err("Save is from a newer version")
+        }
+
+        for from_version in version..CURRENT_SAVE_VERSION {
+            data = match from_version {
+                // Version 1 only added the version wrapper, so the data itself is the same
+                0 => data,
+                _ => unreachable!("Every version before the current one has a migration")
+            };
+        }
+        Ok(data)
+    }
+
+    /// Serializes a map with its keys in order, so that saving the same data always writes the same file. Use with `#[serde(serialize_with = "...")]`
+    pub fn serialize_sorted<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+        S: Serializer
+    {
+        map.iter().collect::<BTreeMap<&K, &V>>().serialize(serializer)
+    }
+
+    pub fn to_json<T>(object: T) -> Result<String, &'static str>
+    where
+        T: Serialize
+    {
+        serde_json::to_string(&VersionedSave { version: CURRENT_SAVE_VERSION, data: object }).map_err(|_| "Couldn't serialize data")
     }
 
     pub fn save<T>(object: T, local_path: &str) -> Result<(), &'static str>
@@ -71,12 +240,32 @@ pub mod godot_json_saver {
                 FileAccess::open(&format!("user://{}/{}", SPELL_SAVE_FOLDER, file_path.join("/")), ModeFlags::WRITE).ok_or("Couldn't open file with write access")?
             }
         };
-        json_file.store_string(&serde_json::to_string::<T>(&object).map_err(|_| "Couldn't serialize data")?);
+        json_file.store_string(&to_json(object)?);
         json_file.close();
         Ok(())
     }
 }
 
+/// Turns compiled spells into short codes players can share, and back again
+pub mod spell_code {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    /// Stores each instruction as eight little-endian bytes, so number literals keep their exact bits
+    pub fn encode(instructions: &[u64]) -> String {
+        let bytes: Vec<u8> = instructions.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn decode(code: &str) -> Result<Vec<u64>, &'static str> {
+        let bytes = URL_SAFE_NO_PAD.decode(code.trim()).map_err(|_| "Spell code isn't valid base64")?;
+        if bytes.len() % 8 != 0 {
+            return Err("Spell code has an incomplete instruction")
+        }
+        Ok(bytes.chunks_exact(8).map(|chunk| u64::from_le_bytes(chunk.try_into().expect("Chunks are eight bytes long"))).collect())
+    }
+}
+
 impl Config {
     pub fn get_config() -> Result<Config, String> {
         StringConfig::load_string_config()?.into_config()
@@ -86,15 +275,119 @@ impl Config {
 impl StringConfig {
     /// Consumes self and converts the `StringConfig` into a normal `Config` wrapped in a result
     fn into_config(self) -> Result<Config, String> {
-        let mut config = Config {forms: HashMap::new(), custom_translation: self.custom_translation};
+        if self.energy_to_volume <= 0.0 || self.efficiency_increase_rate < 0.0 {
+            return Err(String::from("Couldn't parse config.toml: energy_to_volume must be above zero and efficiency_increase_rate can't be negative"))
+        }
+        if !(0.0..=1.0).contains(&self.focus_stability) {
+            return Err(String::from("Couldn't parse config.toml: focus_stability must be between 0 and 1"))
+        }
+        if self.instruction_budget == 0 {
+            return Err(String::from("Couldn't parse config.toml: instruction_budget must be above zero"))
+        }
+        if !(self.energy_charge_curve >= 0.0) {
+            return Err(String::from("Couldn't parse config.toml: energy_charge_curve can't be negative"))
+        }
+        if !(self.homing_detection_radius > 0.0) {
+            return Err(String::from("Couldn't parse config.toml: homing_detection_radius must be above zero"))
+        }
+        let mut config = Config {
+            forms: HashMap::new(),
+            custom_translation: self.custom_translation,
+            component_aliases: self.component_aliases,
+            minimum_shape_dimension: self.minimum_shape_dimension,
+            component_cooldowns: HashMap::new(),
+            energy_lose_rate: self.energy_lose_rate,
+            focus_stability: self.focus_stability,
+            efficiency_increase_rate: self.efficiency_increase_rate,
+            mass_movement_cost: self.mass_movement_cost,
+            energy_to_volume: self.energy_to_volume,
+            spell_transparency: self.spell_transparency,
+            instruction_budget: self.instruction_budget,
+            energy_charge_curve: self.energy_charge_curve,
+            homing_detection_radius: self.homing_detection_radius
+        };
         for (key, value) in &self.forms {
             config.forms.insert(key.parse().map_err(|_| "Couldn't parse config.toml: Failed to parse form keys into numbers")?, value.clone());
         }
+        for (key, &value) in &self.component_cooldowns {
+            config.component_cooldowns.insert(key.parse().map_err(|_| "Couldn't parse config.toml: Failed to parse component cooldown keys into numbers")?, value);
+        }
         Ok(config)
     }
 
     fn load_string_config() -> Result<StringConfig, String> {
         let config_file = fs::read_to_string(SPELL_CONFIG_PATH).unwrap_or_default();
-        toml::de::from_str(&config_file).map_err(|err| format!("Couldn't parse config.toml: {}", err.message()))
+        StringConfig::from_toml(&config_file)
+    }
+
+    fn from_toml(config_file: &str) -> Result<StringConfig, String> {
+        toml::de::from_str(config_file).map_err(|err| format!("Couldn't parse config.toml: {}", err.message()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spelltranslator::parse_spell;
+
+    #[test]
+    fn spell_code_round_trips() {
+        let instructions = parse_spell("when_created:\ngive_velocity(1.25, max(get_time(), 0.1), 0.0001)\nif get_energy() >= 3.3 and true {\ntake_shape(\"cylinder\", 0.5, 2, 0)\n}\nrepeat 3 times:\nheal(2, true)\nabout:\ncolor = [0.4, 0, 0.8]", None).unwrap();
+        let code = spell_code::encode(&instructions);
+
+        assert_eq!(spell_code::decode(&code), Ok(instructions));
+        assert!(code.chars().all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_'));
+    }
+
+    #[test]
+    fn config_constants_can_be_overridden() {
+        let config = StringConfig::from_toml("energy_lose_rate = 0.2\nspell_transparency = 0.5").unwrap().into_config().unwrap();
+        assert_eq!(config.energy_lose_rate, 0.2);
+        assert_eq!(config.spell_transparency, 0.5);
+
+        // Constants that aren't in the file keep their defaults
+        let default_config = Config::default();
+        assert_eq!(config.efficiency_increase_rate, default_config.efficiency_increase_rate);
+        assert_eq!(config.mass_movement_cost, default_config.mass_movement_cost);
+        assert_eq!(config.energy_to_volume, default_config.energy_to_volume);
+        assert_eq!(default_config.energy_lose_rate, ENERGY_LOSE_RATE);
+
+        assert!(StringConfig::from_toml("energy_to_volume = 0").unwrap().into_config().is_err());
+        assert!(StringConfig::from_toml("focus_stability = 1.5").unwrap().into_config().is_err());
+        assert!(StringConfig::from_toml("instruction_budget = 0").unwrap().into_config().is_err());
+        assert_eq!(StringConfig::from_toml("instruction_budget = 50").unwrap().into_config().unwrap().instruction_budget, 50);
+        assert!(StringConfig::from_toml("energy_charge_curve = -1").unwrap().into_config().is_err());
+        assert_eq!(default_config.energy_charge_curve, ENERGY_CHARGE_CURVE);
+        assert!(StringConfig::from_toml("homing_detection_radius = 0").unwrap().into_config().is_err());
+        assert_eq!(StringConfig::from_toml("homing_detection_radius = 35").unwrap().into_config().unwrap().homing_detection_radius, 35.0);
+        assert_eq!(default_config.homing_detection_radius, HOMING_DETECTION_RADIUS);
+    }
+
+    #[test]
+    fn unversioned_and_versioned_saves_load() {
+        let unversioned: PlayerConfig = godot_json_saver::from_json(r#"{"color": {"r": 0.1, "g": 0.2, "b": 0.3}}"#).unwrap();
+        assert_eq!((unversioned.color.r, unversioned.color.g, unversioned.color.b), (0.1, 0.2, 0.3));
+
+        let versioned: PlayerConfig = godot_json_saver::from_json(r#"{"version": 1, "data": {"color": {"r": 0.4, "g": 0.5, "b": 0.6}}}"#).unwrap();
+        assert_eq!((versioned.color.r, versioned.color.g, versioned.color.b), (0.4, 0.5, 0.6));
+
+        let saved = godot_json_saver::to_json(HashMap::from([(String::from("fireball"), String::from("when_created:\nperish()"))])).unwrap();
+        assert!(saved.contains(&format!("\"version\":{}", godot_json_saver::CURRENT_SAVE_VERSION)));
+        assert_eq!(godot_json_saver::from_json::<HashMap<String, String>>(&saved).unwrap()["fireball"], "when_created:\nperish()");
+
+        assert_eq!(godot_json_saver::from_json::<PlayerConfig>(r#"{"version": 99, "data": {}}"#).err(), Some("Save is from a newer version"));
+
+        // Unversioned spells named "data" and "version" aren't mistaken for a versioned save
+        let spells: HashMap<String, String> = godot_json_saver::from_json(r#"{"data": "when_created:\nperish()", "fireball": "repeat:\nperish()"}"#).unwrap();
+        assert_eq!(spells["data"], "when_created:\nperish()");
+        let spells: HashMap<String, String> = godot_json_saver::from_json(r#"{"data": "when_created:\nperish()", "version": "repeat:\nperish()"}"#).unwrap();
+        assert_eq!(spells["version"], "repeat:\nperish()");
+    }
+
+    #[test]
+    fn invalid_spell_codes_are_errors() {
+        assert_eq!(spell_code::decode("not base64!"), Err("Spell code isn't valid base64"));
+        assert_eq!(spell_code::decode(&spell_code::encode(&[1, 2])[..12]), Err("Spell code has an incomplete instruction"));
+        assert_eq!(spell_code::decode(""), Ok(Vec::new()));
     }
 }
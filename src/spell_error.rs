@@ -0,0 +1,66 @@
+/// Why spell code couldn't be parsed or spell instructions couldn't be run. Variants that cover more than one problem keep a message saying which
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpellError {
+    /// The component name or code doesn't match any component
+    UnknownComponent(&'static str),
+    /// The component catalogue or a cooldown stops a component being cast
+    NotAllowed(&'static str),
+    /// A parameter, or a value used in logic, is the wrong type or can't be used
+    BadParameterType(&'static str),
+    NotEnoughEnergy,
+    NotEnoughControl,
+    InvalidShape(&'static str),
+    /// Spell code that can't be turned into instructions
+    InvalidSyntax(&'static str),
+    /// Instructions that are cut short or have values where they shouldn't be
+    InvalidInstructions(&'static str),
+    Maths(&'static str),
+    TooManyIterations,
+    /// A section ran more instructions than `instruction_budget` allows
+    InstructionBudgetExceeded,
+    /// A component function didn't give back what it should have
+    Internal(&'static str)
+}
+
+impl SpellError {
+    /// A number for each variant that stays the same when messages change, so that the game can handle errors without reading the message
+    pub fn get_code(&self) -> u64 {
+        match self {
+            SpellError::UnknownComponent(_) => 1,
+            SpellError::NotAllowed(_) => 2,
+            SpellError::BadParameterType(_) => 3,
+            SpellError::NotEnoughEnergy => 4,
+            SpellError::NotEnoughControl => 5,
+            SpellError::InvalidShape(_) => 6,
+            SpellError::InvalidSyntax(_) => 7,
+            SpellError::InvalidInstructions(_) => 8,
+            SpellError::Maths(_) => 9,
+            SpellError::TooManyIterations => 10,
+            SpellError::Internal(_) => 11,
+            SpellError::InstructionBudgetExceeded => 12
+        }
+    }
+
+    pub fn get_message(&self) -> &'static str {
+        match self {
+            SpellError::UnknownComponent(message)
+            | SpellError::NotAllowed(message)
+            | SpellError::BadParameterType(message)
+            | SpellError::InvalidShape(message)
+            | SpellError::InvalidSyntax(message)
+            | SpellError::InvalidInstructions(message)
+            | SpellError::Maths(message)
+            | SpellError::Internal(message) => message,
+            SpellError::NotEnoughEnergy => "Not enough energy",
+            SpellError::NotEnoughControl => "Needs more control than allowed",
+            SpellError::TooManyIterations => "while loop exceeded iteration cap",
+            SpellError::InstructionBudgetExceeded => "instruction budget exceeded"
+        }
+    }
+}
+
+impl std::fmt::Display for SpellError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{}", self.get_message())
+    }
+}
@@ -1,16 +1,23 @@
 use lazy_static::lazy_static;
 use std::collections::HashMap;
-use crate::{boolean_logic, codes::{attributecodes::*, componentcodes::*, opcodes::*, datatypes::*, component_specific_codes::*}, saver::StringCustomTranslation, rpn_operations, ReturnType, Spell, COMPONENT_TO_FUNCTION_MAP};
+use crate::{boolean_logic, codes::{attributecodes::*, componentcodes::*, opcodes::*, datatypes::*, component_specific_codes::*, damagetypes::*}, saver::Config, spell_error::SpellError, rpn_operations, ReturnType, Spell, COMPONENT_TO_FUNCTION_MAP, MAX_REPEAT_TIMES};
 
 use godot::prelude::godot_warn;
 
-const NAME_SIZE: usize = 25;
+const NAME_SIZE: usize = 32;
 
 const WHEN_CREATED_NAME: &'static str = "when_created";
 const REPEAT_NAME: &'static str = "repeat";
 const ABOUT_NAME: &'static str = "about";
-
-type CustomTranslation = HashMap<u64, HashMap<String, u64>>;
+const ON_COLLISION_NAME: &'static str = "on_collision";
+const CHILD_NAME: &'static str = "child";
+
+/// Translations set in config.toml. `strings` maps component codes to the extra strings their parameters accept, and `component_aliases` maps extra component names to component codes
+#[derive(Default)]
+struct CustomTranslation {
+    strings: HashMap<u64, HashMap<String, u64>>,
+    component_aliases: HashMap<String, u64>
+}
 
 lazy_static! {
     static ref COMPONENT_TO_NUM_MAP: HashMap<[Option<char>; NAME_SIZE], u64> = {
@@ -26,13 +33,59 @@ lazy_static! {
         component_map.insert(pad_name("perish"), PERISH);
         component_map.insert(pad_name("take_shape"), TAKE_SHAPE);
         component_map.insert(pad_name("undo_shape"), UNDO_SHAPE);
+        component_map.insert(pad_name("fragment_on_wall"), FRAGMENT_ON_WALL);
+        component_map.insert(pad_name("homing"), HOMING);
+        component_map.insert(pad_name("set_lifetime"), SET_LIFETIME);
+        component_map.insert(pad_name("fade_color_to"), FADE_COLOR_TO);
+        component_map.insert(pad_name("bounce"), BOUNCE);
+        component_map.insert(pad_name("reset_timer"), RESET_TIMER);
+        component_map.insert(pad_name("familiar"), FAMILIAR);
+        component_map.insert(pad_name("split"), SPLIT);
+        component_map.insert(pad_name("set_direction"), SET_DIRECTION);
+        component_map.insert(pad_name("orbit"), ORBIT);
+        component_map.insert(pad_name("set_color"), SET_COLOR);
+        component_map.insert(pad_name("pierce"), PIERCE);
+        component_map.insert(pad_name("delay"), DELAY);
+        component_map.insert(pad_name("set_decay"), SET_DECAY);
+        component_map.insert(pad_name("spawn_child"), SPAWN_CHILD);
+        component_map.insert(pad_name("anchor_follow"), ANCHOR_FOLLOW);
+        component_map.insert(pad_name("hollow_shape"), HOLLOW_SHAPE);
 
         // Logic:
         component_map.insert(pad_name("moving"), MOVING);
         component_map.insert(pad_name("get_time"), GET_TIME);
+        component_map.insert(pad_name("hit_count"), HIT_COUNT);
+        component_map.insert(pad_name("get_energy"), GET_ENERGY);
+        component_map.insert(pad_name("caster_alive"), CASTER_ALIVE);
+        component_map.insert(pad_name("lifetime"), LIFETIME);
+        component_map.insert(pad_name("chance_ramp"), CHANCE_RAMP);
+        component_map.insert(pad_name("caster_energy_selected"), CASTER_ENERGY_SELECTED);
+        component_map.insert(pad_name("caster_energy_charged"), CASTER_ENERGY_CHARGED);
+        component_map.insert(pad_name("caster_focus"), CASTER_FOCUS);
+        component_map.insert(pad_name("get_speed"), GET_SPEED);
+        component_map.insert(pad_name("get_random"), GET_RANDOM);
+        component_map.insert(pad_name("target_health"), TARGET_HEALTH);
+        component_map.insert(pad_name("target_energy"), TARGET_ENERGY);
+        component_map.insert(pad_name("caster_forward"), CASTER_FORWARD);
+        component_map.insert(pad_name("get_nearest_entity_direction"), GET_NEAREST_ENTITY_DIRECTION);
+        component_map.insert(pad_name("get_overlapping_entity_count"), GET_OVERLAPPING_ENTITY_COUNT);
 
         // Power:
         component_map.insert(pad_name("set_damage"), SET_DAMAGE);
+        component_map.insert(pad_name("set_lifesteal"), SET_LIFESTEAL);
+        component_map.insert(pad_name("beam"), BEAM);
+        component_map.insert(pad_name("cleave"), CLEAVE);
+        component_map.insert(pad_name("set_faction"), SET_FACTION);
+        component_map.insert(pad_name("mark_vulnerable"), MARK_VULNERABLE);
+        component_map.insert(pad_name("heal"), HEAL);
+        component_map.insert(pad_name("trap"), TRAP);
+        component_map.insert(pad_name("siphon_to_caster"), SIPHON_TO_CASTER);
+        component_map.insert(pad_name("set_typed_damage"), SET_TYPED_DAMAGE);
+        component_map.insert(pad_name("apply_radial_force"), APPLY_RADIAL_FORCE);
+        component_map.insert(pad_name("absorb"), ABSORB);
+        component_map.insert(pad_name("set_damage_falloff"), SET_DAMAGE_FALLOFF);
+        component_map.insert(pad_name("apply_stun"), APPLY_STUN);
+        component_map.insert(pad_name("push_spells"), PUSH_SPELLS);
 
         component_map
     };
@@ -40,7 +93,9 @@ lazy_static! {
 
 enum Datatype {
     List(List),
-    Boolean
+    Float,
+    Boolean,
+    String
 }
 
 struct List {
@@ -56,6 +111,9 @@ lazy_static! {
         attribute_map.insert(pad_name("color"), (COLOR, Datatype::List(List { datatype: FLOAT, size: 3 })));
         attribute_map.insert(pad_name("colour"), (COLOR, Datatype::List(List { datatype: FLOAT, size: 3 })));
         attribute_map.insert(pad_name("charge_to_shape"), (CHARGE_TO_SHAPE, Datatype::Boolean));
+        attribute_map.insert(pad_name("name"), (NAME, Datatype::String));
+        attribute_map.insert(pad_name("description"), (DESCRIPTION, Datatype::String));
+        attribute_map.insert(pad_name("transparency"), (TRANSPARENCY, Datatype::Float));
         attribute_map
     };
 }
@@ -70,6 +128,22 @@ lazy_static! {
 
             string_map.insert(pad_name("sphere"), SPHERE);
             string_map.insert(pad_name("cube"), CUBE);
+            string_map.insert(pad_name("cylinder"), CYLINDER);
+            string_map.insert(pad_name("cone"), CONE);
+            string_map.insert(pad_name("capsule"), CAPSULE);
+            string_map.insert(pad_name("pyramid"), PYRAMID);
+
+            string_map
+        });
+
+        string_map.insert(SET_TYPED_DAMAGE, {
+            let mut string_map = HashMap::new();
+
+            string_map.insert(pad_name("untyped"), UNTYPED);
+            string_map.insert(pad_name("fire"), FIRE);
+            string_map.insert(pad_name("ice"), ICE);
+            string_map.insert(pad_name("lightning"), LIGHTNING);
+            string_map.insert(pad_name("poison"), POISON);
 
             string_map
         });
@@ -86,6 +160,32 @@ fn pad_name(component_name: &str) -> [Option<char>; NAME_SIZE] {
     padded_name
 }
 
+/// Maps the name of a maths function that can be used in logic and expressions to its opcode
+fn get_maths_function_code(function_name: &str) -> Option<u64> {
+    match function_name {
+        "sqrt" => Some(SQRT),
+        "abs" => Some(ABS),
+        "floor" => Some(FLOOR),
+        "ceil" => Some(CEIL),
+        "round" => Some(ROUND),
+        "sin" => Some(SIN),
+        "cos" => Some(COS),
+        "min" => Some(MIN),
+        "max" => Some(MAX),
+        "clamp" => Some(CLAMP),
+        _ => None
+    }
+}
+
+/// Words in maths that stand for a number
+fn get_maths_constant(constant_name: &str) -> Option<f64> {
+    match constant_name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None
+    }
+}
+
 pub fn get_component_num(component_name: &str) -> Option<u64> {
     COMPONENT_TO_NUM_MAP.get(&pad_name(component_name)).cloned()
 }
@@ -98,85 +198,170 @@ fn get_string_translation(component_num: u64, string: &str) -> Option<u64> {
     STRING_MAP.get(&component_num)?.get(&pad_name(string)).cloned()
 }
 
-pub fn parse_spell(spell_code: &str, string_custom_translation: Option<StringCustomTranslation>) -> Result<Vec<u64>, &'static str> {
-    let custom_translation: HashMap<u64, HashMap<String, u64>> = match string_custom_translation {
-        Some(translation) => {
-            let mut trans_map: HashMap<u64, HashMap<String, u64>> = HashMap::new();
+/// Removes a comment from the end of a line. A `#` only starts a comment when it isn't inside quotes or the brackets of a component
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut bracket_depth: usize = 0;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => bracket_depth += 1,
+            ')' if !in_quotes => bracket_depth = bracket_depth.saturating_sub(1),
+            '#' if !in_quotes && bracket_depth == 0 => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Gets the component code of a built in component name or, failing that, an alias from config.toml. Built in names take precedence over aliases
+fn resolve_component_num(component_name: &str, custom_translation: Option<&CustomTranslation>) -> Option<u64> {
+    get_component_num(component_name).or_else(|| custom_translation?.component_aliases.get(component_name).copied())
+}
 
-            for (component_name, translation_map) in translation {
-                let component_num = match get_component_num(&component_name) {
-                    Some(num) => num,
-                    None => {
-                        godot_warn!("Component \"{}\" in custom_translation in config.toml doesn't exist", component_name);
-                        continue
-                    }
-                };
+fn get_custom_translation(config: &Config) -> CustomTranslation {
+    let mut custom_translation = CustomTranslation::default();
 
-                trans_map.insert(component_num, translation_map);
+    for (component_name, translation_map) in &config.custom_translation {
+        let component_num = match get_component_num(component_name) {
+            Some(num) => num,
+            None => {
+                godot_warn!("Component \"{}\" in custom_translation in config.toml doesn't exist", component_name);
+                continue
             }
+        };
 
-            trans_map
-        },
-        None => HashMap::new()
+        custom_translation.strings.insert(component_num, translation_map.clone());
+    }
+
+    for (alias, component_name) in &config.component_aliases {
+        if get_component_num(alias).is_some() {
+            godot_warn!("Alias \"{}\" in component_aliases in config.toml is already the name of a component, so it is ignored", alias);
+            continue
+        }
+
+        match get_component_num(component_name) {
+            Some(num) => _ = custom_translation.component_aliases.insert(alias.clone(), num),
+            None => godot_warn!("Component \"{}\" in component_aliases in config.toml doesn't exist", component_name)
+        }
+    }
+
+    custom_translation
+}
+
+/// Where in the spell code parsing failed and why. Lines and columns start at 1
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    /// The column the statement, or the condition of the if statement, that couldn't be parsed starts at
+    pub column: Option<usize>,
+    pub message: SpellError
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.column {
+            Some(column) => write!(formatter, "Line {}, column {}: {}", self.line, column, self.message),
+            None => write!(formatter, "Line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+/// Returns the opcode of a line starting an if statement or while loop and how long its keyword is, including the space after it
+fn get_block_opcode(line: &str) -> Option<(u64, usize)> {
+    if !line.ends_with("{") {
+        return None
+    }
+    if line.starts_with("if ") {
+        Some((IF, 3))
+    } else if line.starts_with("while ") {
+        Some((WHILE, 6))
+    } else {
+        None
+    }
+}
+
+pub fn parse_spell(spell_code: &str, config: Option<&Config>) -> Result<Vec<u64>, ParseError> {
+    let custom_translation = match config {
+        Some(config) => get_custom_translation(config),
+        None => CustomTranslation::default()
     };
 
     let mut instructions: Vec<u64> = vec![];
     let mut in_section = None;
-    let mut expected_closing_brackets: usize = 0;
-    let trimmed_spell_code = spell_code.trim();
-    for line in trimmed_spell_code.lines() {
-        let trimmed_line = line.trim();
-        if trimmed_line.ends_with(":") && trimmed_line.chars().take(trimmed_line.len() - 1).all(|character| character.is_alphanumeric() || character == '_' || character == ' ') {
+    // The line and column of each if statement and while loop that hasn't been closed yet
+    let mut unclosed_blocks: Vec<(usize, usize)> = Vec::new();
+    for (line_index, line) in spell_code.lines().enumerate() {
+        let trimmed_line = strip_comment(line).trim();
+        if trimmed_line == "" { // Empty lines and lines that are only comments
+            continue
+        }
+
+        let line_number = line_index + 1;
+        let column = line.chars().take_while(|character| character.is_whitespace()).count() + 1;
+        let error_at = |column: usize| move |message: SpellError| ParseError { line: line_number, column: Some(column), message };
+
+        // Numbers in section names can have decimal points and minus signs, so that invalid ones are reported as invalid values rather than as not being a section
+        if trimmed_line.ends_with(":") && trimmed_line.chars().take(trimmed_line.len() - 1).all(|character| character.is_alphanumeric() || matches!(character, '_' | ' ' | '.' | '-')) {
             match trimmed_line.trim_end_matches(':').split_whitespace().collect::<Vec<&str>>()[..] {
                 [WHEN_CREATED_NAME] => instructions.push(WHEN_CREATED_SECTION),
                 [REPEAT_NAME] => {
                     instructions.extend(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0)]);
                 },
                 [REPEAT_NAME, "every", num] => {
-                    instructions.extend(vec![REPEAT_SECTION, NUMBER_LITERAL, num.parse::<u64>().map(|num| f64::to_bits(num as f64)).map_err(|_| "Invalid value found after keyword \"every\"")?]);
+                    instructions.extend(vec![REPEAT_SECTION, NUMBER_LITERAL, num.parse::<u64>().map(|num| f64::to_bits(num as f64)).map_err(|_| SpellError::InvalidSyntax("Invalid value found after keyword \"every\"")).map_err(error_at(column))?]);
+                },
+                [REPEAT_NAME, "for", seconds] => {
+                    instructions.extend(vec![REPEAT_FOR_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, parse_repeat_duration(seconds).map_err(error_at(column))?]);
+                },
+                [REPEAT_NAME, "every", num, "for", seconds] => {
+                    instructions.extend(vec![REPEAT_FOR_SECTION, NUMBER_LITERAL, num.parse::<u64>().map(|num| f64::to_bits(num as f64)).map_err(|_| SpellError::InvalidSyntax("Invalid value found after keyword \"every\"")).map_err(error_at(column))?, NUMBER_LITERAL, parse_repeat_duration(seconds).map_err(error_at(column))?]);
+                },
+                [REPEAT_NAME, num, "times"] => {
+                    instructions.extend(vec![REPEAT_TIMES_SECTION, NUMBER_LITERAL, parse_repeat_times(num).map_err(error_at(column))?]);
                 },
                 [ABOUT_NAME] => instructions.push(ABOUT_SECTION),
-                _ => return Err("Invalid section name")
+                [ON_COLLISION_NAME] => instructions.push(ON_COLLISION_SECTION),
+                [CHILD_NAME] => instructions.push(CHILD_SECTION),
+                _ => return Err(error_at(column)(SpellError::InvalidSyntax("Invalid section name")))
             };
             in_section = instructions.last().copied();
         } else {
-            if let None = in_section {
-                return Err("Must begin with section statement");
+            // The child section is a whole spell of its own, so it has to start with a section too
+            if let None | Some(CHILD_SECTION) = in_section {
+                return Err(error_at(column)(SpellError::InvalidSyntax("Must begin with section statement")));
             }
 
             if Some(ABOUT_SECTION) == in_section {
                 if trimmed_line.contains('=') { // Indicates an assignment of about section data
-                    instructions.extend(parse_about_line(trimmed_line)?);
-                    continue
-                } else if trimmed_line == "" {
+                    instructions.extend(parse_about_line(trimmed_line).map_err(error_at(column))?);
                     continue
                 } else {
-                    return Err("Expected attribute in about section")
+                    return Err(error_at(column)(SpellError::InvalidSyntax("Expected attribute in about section")))
                 }
             }
             
             // If in section, parse code
             if trimmed_line.ends_with(")") { // Checking to see if component
-                instructions.extend(parse_component(trimmed_line, Some(&custom_translation))?);
-            } else if trimmed_line.starts_with("if ") && trimmed_line.ends_with("{") { // Checking for if statement
-                instructions.push(IF); // Indicates if statement
-                instructions.extend(parse_logic(&trimmed_line[3..trimmed_line.len() - 1], Some(&custom_translation))?);
+                instructions.extend(parse_component(trimmed_line, Some(&custom_translation)).map_err(error_at(column))?);
+            } else if let Some((opcode, keyword_length)) = get_block_opcode(trimmed_line) { // Checking for if statement or while loop
+                let conditions = &trimmed_line[keyword_length..trimmed_line.len() - 1];
+                let conditions_column = column + keyword_length + conditions.chars().take_while(|character| character.is_whitespace()).count();
+                instructions.push(opcode); // Indicates if statement or while loop
+                instructions.extend(parse_condition(conditions, Some(&custom_translation)).map_err(error_at(conditions_column))?);
                 instructions.push(END_OF_SCOPE); // Indicates end of scope for logic
-                expected_closing_brackets += 1;
-            } else if expected_closing_brackets > 0 && trimmed_line == "}" {
+                unclosed_blocks.push((line_number, column));
+            } else if !unclosed_blocks.is_empty() && trimmed_line == "}" {
                 instructions.push(END_OF_SCOPE);
-                expected_closing_brackets -= 1;
-            } else if trimmed_line == "" {
-                continue
+                unclosed_blocks.pop();
             } else {
-                return Err("Not acceptable statement")
+                return Err(error_at(column)(SpellError::InvalidSyntax("Not acceptable statement")))
             }
         }
     }
-    if expected_closing_brackets == 0 {
-        return Ok(instructions)
-    } else {
-        return Err("Expected closing bracket(s)")
+    match unclosed_blocks.last() {
+        None => return Ok(instructions),
+        Some(&(line, column)) => return Err(ParseError { line, column: Some(column), message: SpellError::InvalidSyntax("Expected closing bracket(s)") })
     }
 }
 
@@ -184,9 +369,9 @@ fn get_precedence(operator: &str) -> u64 {
     match operator {
         "(" | ")" => 0,
         "and" | "or" | "xor" => 1,
-        ">" | "<" | "=" | "==" => 2,
+        ">" | "<" | ">=" | "<=" | "=" | "==" => 2,
         "+" | "-" => 3,
-        "*" | "/" => 4,
+        "*" | "/" | "%" => 4,
         "^" => 5,
         "not" => 6,
         _ => panic!("Not valid operator")
@@ -201,7 +386,7 @@ enum Direction {
 
 fn get_associative_direction(operator: &str) -> Direction {
     match operator {
-        "and" | "or" | "xor" | "+" | "-" | "*" | "/" | "^" | "=" | "==" | ">" | "<" => Direction::Left,
+        "and" | "or" | "xor" | "+" | "-" | "*" | "/" | "%" | "^" | "=" | "==" | ">" | "<" | ">=" | "<=" => Direction::Left,
         "not" => Direction::Right,
         _ => panic!("Not valid operator")
     }
@@ -212,11 +397,13 @@ enum Token {
     Number(String),
     Boolean(String),
     Component(String),
+    Function(String),
     OpenBracket,
-    CloseBracket
+    CloseBracket,
+    Comma
 }
 
-fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
+fn tokenise(conditions: &str) -> Result<Vec<Token>, SpellError> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut characters = conditions.chars().peekable();
     let mut last_token_was_value = false;
@@ -230,6 +417,12 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
             },
             '(' => {
                 tokens.push(Token::OpenBracket);
+                last_token_was_value = false;
+                characters.next();
+            },
+            ',' => {
+                tokens.push(Token::Comma);
+                last_token_was_value = false;
                 characters.next();
             },
             ')' => {
@@ -240,13 +433,15 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                 }
                 characters.next();
             },
-            '+' | '*' | '/' | '^' | '=' | '>' | '<' => {
+            '+' | '*' | '/' | '%' | '^' | '=' | '>' | '<' => {
                 let mut opcode = String::new();
                 opcode.push(characters.next().unwrap());
-                if let Some('=') = characters.peek() {
+                // Only comparisons can be two characters long, such as >=
+                if matches!(character, '=' | '>' | '<') && characters.peek() == Some(&'=') {
                     opcode.push(characters.next().unwrap());
                 }
                 tokens.push(Token::Opcode(opcode));
+                last_token_was_value = false;
             },
             '-' => {
                 // Standardises all minus signs to be a subtraction
@@ -299,11 +494,11 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                     } else if next_character == ' '{
                         characters.next();
                     } else {
-                        return Err("Expected valid character after minus sign")
+                        return Err(SpellError::InvalidSyntax("Expected valid character after minus sign"))
                     }
                 }
                 if !at_least_one_loop {
-                    return Err("Expected character after minus sign")
+                    return Err(SpellError::InvalidSyntax("Expected character after minus sign"))
                 }
             },
             'a'..='z' | 'A'..='Z' | '_' => {
@@ -316,7 +511,29 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                         break;
                     }
                 }
-                if let Some('(') = characters.peek() { // Is component
+                let next_is_bracket = characters.peek() == Some(&'(');
+                // Keywords are checked first so that `not(` isn't mistaken for a component
+                if opcode == "true" || opcode == "false" {
+                    tokens.push(Token::Boolean(opcode));
+                    last_token_was_value = true;
+                } else if opcode == "and" || opcode == "or" || opcode == "xor" || opcode == "not" {
+                    tokens.push(Token::Opcode(opcode));
+                    last_token_was_value = false;
+                } else if let (false, Some(constant)) = (next_is_bracket, get_maths_constant(&opcode)) { // Is constant such as pi
+                    tokens.push(Token::Number(constant.to_string()));
+                    last_token_was_value = true;
+                    if close_bracket {
+                        tokens.push(Token::CloseBracket);
+                        close_bracket = false;
+                    }
+                } else if next_is_bracket && get_maths_function_code(&opcode).is_some() { // Is maths function
+                    tokens.push(Token::Function(opcode));
+                    // The brackets of the function are closed instead of the function name
+                    if close_bracket {
+                        close_bracket = false;
+                        close_extra_bracket += 1;
+                    }
+                } else if next_is_bracket { // Is component
                     opcode.push(characters.next().unwrap()); // Push OpenBracket
                     let mut expected_closing_brackets = 1;
                     loop {
@@ -329,7 +546,7 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                             expected_closing_brackets += 1;
                         } else {
                             // Push parameter characters
-                            opcode.push(characters.next().ok_or("Expected closing bracket for component")?);
+                            opcode.push(characters.next().ok_or(SpellError::InvalidSyntax("Expected closing bracket for component"))?);
                         }
                         if expected_closing_brackets == 0 {
                             tokens.push(Token::Component(opcode));
@@ -341,27 +558,40 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                         tokens.push(Token::CloseBracket);
                         close_bracket = false;
                     }
-                } else if opcode == "true" || opcode == "false" {
-                    tokens.push(Token::Boolean(opcode));
                 } else {
-                    tokens.push(Token::Opcode(opcode));
+                    return Err(SpellError::InvalidSyntax("Invalid condition: unknown word"))
                 }
             },
             '0'..='9' => {
                 let mut decimal_point_found = false;
+                let mut exponent_found = false;
                 let mut number = String::new();
                 while let Some(&number_character) = characters.peek() {
                     if number_character.is_numeric() {
                         number.push(number_character);
                         characters.next();
                     } else if number_character == '.' {
-                        if decimal_point_found {
-                            return Err("Cannot have two decimal points in number")
+                        if exponent_found {
+                            return Err(SpellError::InvalidSyntax("Exponent of number must be a whole number"))
+                        } else if decimal_point_found {
+                            return Err(SpellError::InvalidSyntax("Cannot have two decimal points in number"))
                         } else {
                             number.push(number_character);
                             characters.next();
                             decimal_point_found = true;
                         }
+                    } else if matches!(number_character, 'e' | 'E') && !exponent_found {
+                        // Scientific notation such as 1.5e-2. The constant e can't come straight after a number, so an e here is always an exponent
+                        number.push(number_character);
+                        characters.next();
+                        exponent_found = true;
+                        if let Some(&sign) = characters.peek().filter(|&&sign| sign == '-' || sign == '+') {
+                            number.push(sign);
+                            characters.next();
+                        }
+                        if !characters.peek().is_some_and(|character| character.is_ascii_digit()) {
+                            return Err(SpellError::InvalidSyntax("Expected digits after exponent in number"))
+                        }
                     } else {
                         break;
                     }
@@ -373,28 +603,37 @@ fn tokenise(conditions: &str) -> Result<Vec<Token>, &'static str> {
                     close_bracket = false;
                 }
             },
-            _ => return Err("Unexpected character in conditions")
+            _ => return Err(SpellError::InvalidSyntax("Unexpected character in conditions"))
         }
     }
     return Ok(tokens)
 }
 
-fn test_execute_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, &'static str> {
-    let component_code = instructions_iter.next().ok_or("expected component")?;
-    let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code);
+fn test_execute_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, SpellError> {
+    let component_code = instructions_iter.next().ok_or(SpellError::InvalidInstructions("expected component"))?;
+    let number_of_component_parameters = Spell::get_number_of_component_parameters(component_code)?;
     let mut parameters: Vec<u64> = vec![];
-    for _ in 0..number_of_component_parameters {
-        let parameter = *instructions_iter.next().ok_or("expected parameter")?;
+    let mut parameter_number = 0;
+    while parameter_number < number_of_component_parameters {
+        let parameter = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("expected parameter"))?;
 
         match parameter {
             TRUE | FALSE => parameters.push(parameter),
             NUMBER_LITERAL => {
                 parameters.push(parameter);
-                parameters.push(*instructions_iter.next().ok_or("Expected number after number literal opcode")?);
+                parameters.push(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?);
+            },
+            COMPONENT => {
+                let component_return = test_execute_component(instructions_iter)?;
+                // A vector fills one parameter for each of its values
+                parameter_number += Spell::split_return_values(&component_return)?.len();
+                parameters.extend(component_return);
+                continue
             },
-            COMPONENT => parameters.extend(test_execute_component(instructions_iter)?),
-            _ => return Err("Invalid parameter")
+            EXPRESSION => parameters.extend(test_logic(instructions_iter)?),
+            _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
         }
+        parameter_number += 1;
     }
 
     return match COMPONENT_TO_FUNCTION_MAP.get(component_code) {
@@ -402,36 +641,42 @@ fn test_execute_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u
             match *return_type {
                 ReturnType::Float => Ok(vec![NUMBER_LITERAL, 0]),
                 ReturnType::Boolean => Ok(vec![TRUE]),
-                ReturnType::None => return Err("Expected return from component")
+                ReturnType::Vector => Ok(vec![NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]),
+                ReturnType::None => return Err(SpellError::InvalidInstructions("Expected return from component"))
             }
         },
-        None => return Err("Component does not exist")
+        None => return Err(SpellError::UnknownComponent("Component does not exist"))
     };
 }
 
-/// Does a mock execution of the if statement logic where components are all evaulated to default return values and aren't actually run
-fn test_logic(logic: &Vec<u64>) -> Result<(), &'static str> {
-    let mut instructions_iter = logic.iter();
+/// Does a mock execution of the if statement logic where components are all evaulated to default return values and aren't actually run. Returns the value the logic results in
+fn test_logic<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<Vec<u64>, SpellError> {
     let mut rpn_stack: Vec<u64> = Vec::new();
     while let Some(&if_bits) = instructions_iter.next() {
         match if_bits {
             END_OF_SCOPE => break,
             TRUE | FALSE => rpn_stack.push(if_bits), // true and false
-            NUMBER_LITERAL => rpn_stack.extend(vec![NUMBER_LITERAL, *instructions_iter.next().ok_or("Expected following value")?]), // if 102, next bits are a number literal
-            COMPONENT => rpn_stack.extend(test_execute_component(&mut instructions_iter)?), // Component
+            NUMBER_LITERAL => rpn_stack.extend(vec![NUMBER_LITERAL, *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected following value"))?]), // if 102, next bits are a number literal
+            COMPONENT => { // Component
+                let component_return = test_execute_component(instructions_iter)?;
+                if Spell::split_return_values(&component_return)?.len() > 1 {
+                    return Err(SpellError::BadParameterType("Vectors can only be used as the parameters of components"))
+                }
+                rpn_stack.extend(component_return);
+            },
             AND => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::and)?, // And statement
             OR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::or)?, // Or statement
             NOT => { // Not statement
-                let bool_one = rpn_stack.pop().ok_or("Expected value to compare")?;
+                let bool_one = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
                 rpn_stack.push(boolean_logic::not(bool_one)?);
             },
             XOR => rpn_operations::binary_operation(&mut rpn_stack, boolean_logic::xor)?, // Xor statement
             EQUALS => { // Equals statement
-                let argument_two = rpn_stack.pop().ok_or("Expected value to compare")?;
-                let opcode_or_bool = rpn_stack.pop().ok_or("Expected value to compare")?;
+                let argument_two = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                let opcode_or_bool = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
                 if opcode_or_bool == NUMBER_LITERAL {
-                    let argument_one = f64::from_bits(rpn_stack.pop().ok_or("Expected value to compare")?);
-                    let _ = rpn_stack.pop().ok_or("Expected number literal opcode")?;
+                    let argument_one = f64::from_bits(rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?);
+                    let _ = rpn_stack.pop().ok_or(SpellError::InvalidInstructions("Expected number literal opcode"))?;
                     if argument_one == f64::from_bits(argument_two) {
                         rpn_stack.push(TRUE);
                     } else {
@@ -447,18 +692,43 @@ fn test_logic(logic: &Vec<u64>) -> Result<(), &'static str> {
             },
             GREATER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a > b)?, // Greater than
             LESSER_THAN => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a < b)?, // Lesser than
+            GREATER_THAN_OR_EQUAL => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a >= b)?, // Greater than or equal
+            LESSER_THAN_OR_EQUAL => rpn_operations::compare_operation(&mut rpn_stack, |a, b| a <= b)?, // Lesser than or equal
             MULTIPLY => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a * b)?, // Multiply
             DIVIDE => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a / b)?, // Divide
+            MODULO => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a % b)?, // Remainder, which is NaN when dividing by zero
             ADD => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a + b)?, // Add
             SUBTRACT => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a - b)?, // Subtract
             POWER => rpn_operations::maths_operation(&mut rpn_stack, |a, b| a.powf(b))?, // Power
-            _ => return Err("Opcode doesn't exist")
+            SQRT => rpn_operations::unary_maths_operation(&mut rpn_stack, rpn_operations::square_root)?, // Square root
+            ABS => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.abs()))?, // Absolute value
+            FLOOR => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.floor()))?, // Round down
+            CEIL => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.ceil()))?, // Round up
+            ROUND => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.round()))?, // Round to nearest, halves away from zero
+            SIN => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.sin()))?, // Sine, in radians
+            COS => rpn_operations::unary_maths_operation(&mut rpn_stack, |a| Ok(a.cos()))?, // Cosine, in radians
+            MIN => rpn_operations::maths_operation(&mut rpn_stack, f64::min)?, // Minimum
+            MAX => rpn_operations::maths_operation(&mut rpn_stack, f64::max)?, // Maximum
+            CLAMP => rpn_operations::ternary_maths_operation(&mut rpn_stack, |value, low, high| value.max(low).min(high))?, // Clamp, which gives high when low is bigger than high rather than panicking like f64::clamp
+            _ => return Err(SpellError::InvalidInstructions("Opcode doesn't exist"))
         }
     }
-    Ok(())
+    if !matches!(rpn_stack[..], [TRUE] | [FALSE] | [NUMBER_LITERAL, _]) {
+        return Err(SpellError::InvalidSyntax("Invalid condition: Should result in a single value"))
+    }
+    Ok(rpn_stack)
 }
 
-fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, &'static str> {
+/// Parses the condition of an if statement or while loop, which has to come out as true or false rather than a number
+fn parse_condition(conditions: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, SpellError> {
+    let bit_conditions = parse_logic(conditions, custom_translation)?;
+    match test_logic(&mut bit_conditions.iter())?[..] {
+        [TRUE] | [FALSE] => Ok(bit_conditions),
+        _ => Err(SpellError::BadParameterType("Invalid condition: Should be true or false, not a number"))
+    }
+}
+
+fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, SpellError> {
     // Uses the Shunting Yard Algorithm to turn player written infix code into executeable postfix (RPN) code
     let mut holding_stack: Vec<String> = vec![];
     let mut output: Vec<String> = vec![];
@@ -466,7 +736,8 @@ fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>)
     for token in tokenise(conditions)? {
         match token {
             Token::Opcode(opcode) => {
-                while let Some(operator) = holding_stack.last() {
+                // Not is a prefix operator, so nothing before it can be finished yet
+                while let Some(operator) = holding_stack.last().filter(|_| opcode != "not") {
                     if get_precedence(operator) > get_precedence(&opcode) ||
                         (get_precedence(operator) == get_precedence(&opcode) && get_associative_direction(&opcode) == Direction::Left) {
                             output.push(holding_stack.pop().unwrap());
@@ -480,20 +751,40 @@ fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>)
                 holding_stack.push("(".to_string())
             },
             Token::CloseBracket => {
-                let mut operator = holding_stack.pop().ok_or("Expected opening bracket")?;
+                let mut operator = holding_stack.pop().ok_or(SpellError::InvalidSyntax("Expected opening bracket"))?;
                 while operator != "(" {
                     output.push(operator);
-                    operator = holding_stack.pop().ok_or("Expected opening bracket")?;
+                    operator = holding_stack.pop().ok_or(SpellError::InvalidSyntax("Expected opening bracket"))?;
+                }
+                // If the brackets belong to a function, the function comes after its arguments
+                if holding_stack.last().is_some_and(|operator| get_maths_function_code(operator).is_some()) {
+                    output.push(holding_stack.pop().unwrap());
                 }
             },
+            Token::Comma => {
+                // Finishes the current argument of a function
+                while let Some(operator) = holding_stack.last() {
+                    if operator == "(" {
+                        break;
+                    }
+                    output.push(holding_stack.pop().unwrap());
+                }
+                if holding_stack.is_empty() {
+                    return Err(SpellError::InvalidSyntax("Invalid condition: Comma must be inside the brackets of a function"))
+                }
+            },
+            Token::Function(function) => {
+                holding_stack.push(function)
+            },
             Token::Boolean(boolean) => {
                 output.push(boolean);
             },
             Token::Number(num) => {
-                if let Ok(_) = num.parse::<f64>() {
+                // Numbers too big to store, such as 1e999, aren't allowed
+                if num.parse::<f64>().is_ok_and(f64::is_finite) {
                     output.push(num);
                 } else {
-                    return Err("Invalid condition")
+                    return Err(SpellError::InvalidSyntax("Invalid condition"))
                 }
             }
             Token::Component(component) => {
@@ -503,10 +794,14 @@ fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>)
     }
     // Pop remaining operators off holding stack and push to output
     for _ in 0..holding_stack.len() {
-        output.push(holding_stack.pop().ok_or_else(|| "Expected to work: Program logic fault")?);
+        output.push(holding_stack.pop().ok_or_else(|| SpellError::Internal("Expected to work: Program logic fault"))?);
     }
     let mut bit_conditions: Vec<u64> = vec![];
     for condition in output {
+        if let Some(function_code) = get_maths_function_code(&condition) {
+            bit_conditions.push(function_code);
+            continue
+        }
         match condition.as_str() {
             "and" => bit_conditions.push(AND),
             "or" => bit_conditions.push(OR),
@@ -515,8 +810,11 @@ fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>)
             "==" | "=" => bit_conditions.push(EQUALS),
             ">" => bit_conditions.push(GREATER_THAN),
             "<" => bit_conditions.push(LESSER_THAN),
+            ">=" => bit_conditions.push(GREATER_THAN_OR_EQUAL),
+            "<=" => bit_conditions.push(LESSER_THAN_OR_EQUAL),
             "*" => bit_conditions.push(MULTIPLY),
             "/" => bit_conditions.push(DIVIDE),
+            "%" => bit_conditions.push(MODULO),
             "+" => bit_conditions.push(ADD),
             "-" => bit_conditions.push(SUBTRACT),
             "^" => bit_conditions.push(POWER),
@@ -531,23 +829,23 @@ fn parse_logic(conditions: &str, custom_translation: Option<&CustomTranslation>)
                 if possible_component.ends_with(')') && !possible_component.starts_with('(') && possible_component.contains('(') {
                     bit_conditions.extend(parse_component(possible_component, custom_translation)?);
                 } else {
-                    return Err("Invalid condition")
+                    return Err(SpellError::InvalidSyntax("Invalid condition"))
                 }
             }
         }
     }
-    match test_logic(&bit_conditions) {
+    match test_logic(&mut bit_conditions.iter()) {
         Ok(_) => Ok(bit_conditions),
         Err(error) => Err(error)
     }
 }
 
-fn parse_component(component_call: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, &'static str> {
+fn parse_component(component_call: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, SpellError> {
     let mut component_vec: Vec<u64> = vec![COMPONENT];
     let (component_name, parameters) = parse_component_string(component_call, custom_translation)?;
-    let component_num = match get_component_num(&component_name) {
+    let component_num = match resolve_component_num(&component_name, custom_translation) {
         Some(num) => num,
-        None => return Err("Invalid component: mapping doesn't exist")
+        None => return Err(SpellError::UnknownComponent("Invalid component: mapping doesn't exist"))
     };
     component_vec.push(component_num);
     for parameter in parameters {
@@ -556,9 +854,9 @@ fn parse_component(component_call: &str, custom_translation: Option<&CustomTrans
     return Ok(component_vec)
 }
 
-fn parse_component_string(component_call: &str, custom_translation: Option<&CustomTranslation>) -> Result<(String, Vec<Parameter>), &'static str> {
+fn parse_component_string(component_call: &str, custom_translation: Option<&CustomTranslation>) -> Result<(String, Vec<Parameter>), SpellError> {
     if component_call.chars().last() != Some(')') {
-        return Err("Invalid component: Must end with close bracket");
+        return Err(SpellError::InvalidSyntax("Invalid component: Must end with close bracket"));
     }
 
     let mut component_name = String::new();
@@ -572,7 +870,7 @@ fn parse_component_string(component_call: &str, custom_translation: Option<&Cust
             break;
             // Checking if character is alphabetic if not an open bracket.
         } else if !character.is_alphabetic() && character != '_' {
-            return Err("Invalid component: Name must be made up of letters")
+            return Err(SpellError::InvalidSyntax("Invalid component: Name must be made up of letters"))
         }
 
         character_count += 1;
@@ -581,7 +879,7 @@ fn parse_component_string(component_call: &str, custom_translation: Option<&Cust
 
     // There needs to be an opening bracket, if there is none, returns error
     if found_opening_bracket == false {
-        return Err("Invalid component: Must have opening bracket")
+        return Err(SpellError::InvalidSyntax("Invalid component: Must have opening bracket"))
     }
 
     // This line gets the parameters as a string and puts it into the variable parameters_string
@@ -589,56 +887,71 @@ fn parse_component_string(component_call: &str, custom_translation: Option<&Cust
         let parameters = collect_parameters(parameters_string, &component_name, custom_translation)?;
         return Ok((component_name, parameters))
     } else {
-        return Err("Invalid component: Parameters not valid")
+        return Err(SpellError::InvalidSyntax("Invalid component: Parameters not valid"))
     }
 }
 
 enum Parameter {
     Float(f64),
     Boolean(bool),
-    Component(String)
+    Component(String),
+    /// Expressions are stored as the RPN bits created by `parse_logic`
+    Expression(Vec<u64>)
 }
 
 impl Parameter {
-    fn to_bits(&self, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, &'static str> {
+    fn to_bits(&self, custom_translation: Option<&CustomTranslation>) -> Result<Vec<u64>, SpellError> {
         match self {
             Parameter::Float(float) => Ok(vec![NUMBER_LITERAL, float.to_bits()]),
             Parameter::Boolean(boolean) => match boolean {
                 true => Ok(vec![TRUE]),
                 false => Ok(vec![FALSE])
             },
-            Parameter::Component(component) => parse_component(&component, custom_translation)
+            Parameter::Component(component) => parse_component(&component, custom_translation),
+            Parameter::Expression(expression) => {
+                let mut bits = vec![EXPRESSION];
+                bits.extend(expression);
+                bits.push(END_OF_SCOPE);
+                Ok(bits)
+            }
         }
     }
 }
 
-fn collect_parameters(parameters_string: &str, component_name: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<Parameter>, &'static str> {
+fn collect_parameters(parameters_string: &str, component_name: &str, custom_translation: Option<&CustomTranslation>) -> Result<Vec<Parameter>, SpellError> {
     let mut parameter = String::new();
     let mut parameters: Vec<Parameter> = vec![];
 
     let mut index = 0;
 
-    let component_num = get_component_num(component_name).ok_or("Component doesn't exist")?;
+    let component_num = resolve_component_num(component_name, custom_translation).ok_or(SpellError::UnknownComponent("Component doesn't exist"))?;
 
     if let Some((_, encoded_types, _)) = COMPONENT_TO_FUNCTION_MAP.get(&component_num) {
         let encoded_types: &[u64] = encoded_types;
+        let mut bracket_depth: usize = 0;
+        let mut in_quotes = false;
         for character in parameters_string.chars() {
-            if character != ',' {
+            // Commas inside brackets or quotes belong to the parameter
+            match character {
+                '"' => in_quotes = !in_quotes,
+                '(' if !in_quotes => bracket_depth += 1,
+                ')' if !in_quotes => bracket_depth = bracket_depth.saturating_sub(1),
+                _ => {}
+            }
+            if character != ',' || bracket_depth > 0 || in_quotes {
                 parameter.push(character);
                 continue
             }
 
             if parameter.is_empty() {
-                return Err("Invalid parameters: Must have value before bracket")
-            }
-
-            if index >= encoded_types.len() {
-                return Err("Invalid parameters: More parameters than expected");
+                return Err(SpellError::InvalidSyntax("Invalid parameters: Must have value before bracket"))
             }
 
             // Adding parameter to parameters vector
+            let width = get_parameter_width(&parameter, custom_translation);
+            check_parameter_fits(encoded_types, index, width)?;
             parameters.push(parse_parameter(&parameter, encoded_types[index], component_num, custom_translation)?);
-            index += 1;
+            index += width;
 
             // Clear parameter string so next one can be recorded
             parameter.clear();
@@ -646,16 +959,14 @@ fn collect_parameters(parameters_string: &str, component_name: &str, custom_tran
 
         // Adding last parameter
         if !parameter.is_empty() {
-            if index >= encoded_types.len() {
-                return Err("Invalid parameters: More parameters than expected");
-            }
+            let width = get_parameter_width(&parameter, custom_translation);
+            check_parameter_fits(encoded_types, index, width)?;
             parameters.push(parse_parameter(&parameter, encoded_types[index], component_num, custom_translation)?);
+            index += width;
         }
 
-        if parameters.len() < encoded_types.len() {
-            return Err("Invalid parameters: Missing parameters")
-        } else if parameters.len() > encoded_types.len() {
-            return Err("Invalid parameters: More parameters than expected")
+        if index < encoded_types.len() {
+            return Err(SpellError::InvalidSyntax("Invalid parameters: Missing parameters"))
         }
 
     } else {
@@ -665,11 +976,33 @@ fn collect_parameters(parameters_string: &str, component_name: &str, custom_tran
     return Ok(parameters)
 }
 
-fn parse_parameter(parameter_string: &str, parameter_type: u64, component_num: u64, custom_translation: Option<&CustomTranslation>) -> Result<Parameter, &'static str> {
+/// Returns how many parameters a parameter fills, which is more than one for a component that returns a vector
+fn get_parameter_width(parameter_string: &str, custom_translation: Option<&CustomTranslation>) -> usize {
+    let trimmed_parameter_string = parameter_string.trim();
+    if !is_single_component(trimmed_parameter_string) {
+        return 1
+    }
+
+    let component_name = &trimmed_parameter_string[..trimmed_parameter_string.find('(').unwrap_or(trimmed_parameter_string.len())];
+    resolve_component_num(component_name, custom_translation)
+        .and_then(|component_num| Spell::get_parameter_width(&component_num).ok())
+        .unwrap_or(1)
+}
+
+/// Checks there's room for a parameter that fills `width` parameters from `index`. Vectors can only fill float parameters
+fn check_parameter_fits(encoded_types: &[u64], index: usize, width: usize) -> Result<(), SpellError> {
+    match encoded_types.get(index..index + width) {
+        None => Err(SpellError::InvalidSyntax("Invalid parameters: More parameters than expected")),
+        Some(types) if width > 1 && types.iter().any(|&parameter_type| parameter_type != FLOAT) => Err(SpellError::BadParameterType("Invalid parameters: Vectors can only fill float parameters")),
+        Some(_) => Ok(())
+    }
+}
+
+fn parse_parameter(parameter_string: &str, parameter_type: u64, component_num: u64, custom_translation: Option<&CustomTranslation>) -> Result<Parameter, SpellError> {
     let trimmed_parameter_string = parameter_string.trim();
 
     // Check if component
-    if trimmed_parameter_string.ends_with(')') {
+    if is_single_component(trimmed_parameter_string) {
         return Ok(Parameter::Component(trimmed_parameter_string.to_string()))
     }
 
@@ -680,11 +1013,11 @@ fn parse_parameter(parameter_string: &str, parameter_type: u64, component_num: u
             None => {
                 let some_custom_translation = match custom_translation {
                     Some(translation) => translation,
-                    None => return Err("Couldn't parse parameter: string isn't a valid option")
+                    None => return Err(SpellError::BadParameterType("Couldn't parse parameter: string isn't a valid option"))
                 };
 
-                *some_custom_translation.get(&component_num).ok_or("Couldn't parse parameter: string isn't a valid option")?
-                    .get(string).ok_or("Couldn't parse parameter: string isn't a valid option")? as f64
+                *some_custom_translation.strings.get(&component_num).ok_or(SpellError::BadParameterType("Couldn't parse parameter: string isn't a valid option"))?
+                    .get(string).ok_or(SpellError::BadParameterType("Couldn't parse parameter: string isn't a valid option"))? as f64
             }
         };
 
@@ -692,56 +1025,411 @@ fn parse_parameter(parameter_string: &str, parameter_type: u64, component_num: u
     }
 
     match parameter_type {
-        FLOAT => Ok(Parameter::Float(trimmed_parameter_string.parse::<f64>().map_err(|_| "Couldn't parse parameter: should be float")?)),
-        BOOLEAN => Ok(Parameter::Boolean(trimmed_parameter_string.parse::<bool>().map_err(|_| "Couldn't parse parameter: should be boolean")?)),
+        FLOAT => if let Some(float) = parse_signed_number(trimmed_parameter_string) {
+            return Ok(Parameter::Float(float))
+        },
+        BOOLEAN => if let Ok(boolean) = trimmed_parameter_string.parse::<bool>() {
+            return Ok(Parameter::Boolean(boolean))
+        },
         _ => panic!("Parameter type given doesn't exist")
     }
+
+    // If the parameter isn't a value, it's an expression
+    let expression = parse_logic(trimmed_parameter_string, custom_translation)?;
+    match (parameter_type, &test_logic(&mut expression.iter())?[..]) {
+        (FLOAT, [NUMBER_LITERAL, _]) | (BOOLEAN, [TRUE] | [FALSE]) => Ok(Parameter::Expression(expression)),
+        (FLOAT, _) => Err(SpellError::BadParameterType("Couldn't parse parameter: should be float")),
+        _ => Err(SpellError::BadParameterType("Couldn't parse parameter: should be boolean"))
+    }
 }
 
-fn parse_about_line(equation: &str) -> Result<Vec<u64>, &'static str>{
-    let (mut name, mut value) = equation.split_once('=').ok_or_else(|| "There must be an equals sign in an about line")?;
+/// Reads a number with any signs in front of it, such as `-5`, `- 5` or `--5`, treating the signs the same way `tokenise` does.
+/// Words such as `inf` and `nan` aren't numbers, as they aren't in if statements either
+fn parse_signed_number(string: &str) -> Option<f64> {
+    let number = string.trim_start_matches(|character| matches!(character, '-' | '+' | ' '));
+    if !number.starts_with(|character: char| character.is_ascii_digit() || character == '.') {
+        return None
+    }
+    let float = number.parse::<f64>().ok().filter(|float| float.is_finite())?;
+    let minus_count = string[..string.len() - number.len()].matches('-').count();
+    Some(if minus_count % 2 == 1 { -float } else { float })
+}
+
+/// Checks if the string is a single component call such as `get_time()` rather than an expression such as `get_time() * 2`
+fn is_single_component(string: &str) -> bool {
+    let name_length = string.chars().take_while(|character| character.is_ascii_alphabetic() || *character == '_').count();
+    if name_length == 0 || get_maths_function_code(&string[..name_length]).is_some() || !string.ends_with(')') {
+        return false
+    }
+
+    // The bracket opened after the name must be closed by the last character
+    let brackets = &string[name_length..];
+    if !brackets.starts_with('(') {
+        return false
+    }
+    let mut bracket_depth: usize = 0;
+    for (index, character) in brackets.char_indices() {
+        match character {
+            '(' => bracket_depth += 1,
+            ')' => {
+                bracket_depth -= 1;
+                if bracket_depth == 0 {
+                    return index == brackets.len() - 1
+                }
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+fn parse_about_line(equation: &str) -> Result<Vec<u64>, SpellError>{
+    let (mut name, mut value) = equation.split_once('=').ok_or_else(|| SpellError::InvalidSyntax("There must be an equals sign in an about line"))?;
     
     name = name.trim();
     value = value.trim();
 
     let (mut attribute_line, datatype) = match get_attribute_info(name) {
         Some((attribute_num, datatype)) => (vec![*attribute_num], datatype),
-        None => return Err("Invalid attribute: attribute doesn't exist")
+        None => return Err(SpellError::InvalidSyntax("Invalid attribute: attribute doesn't exist"))
     };
 
     match datatype {
         Datatype::List(list) => {
             let str_value_list = value.strip_prefix('[')
                 .and_then(|x| x.strip_suffix(']'))
-                .ok_or("Invalid value: Should be a list starting with [ and ending with ]")?
+                .ok_or(SpellError::InvalidSyntax("Invalid value: Should be a list starting with [ and ending with ]"))?
                 .split(',').map(str::trim).collect::<Vec<&str>>();
 
             if str_value_list.len() > list.size {
-                return Err("Invalid value: List is too long")
+                return Err(SpellError::InvalidSyntax("Invalid value: List is too long"))
             }
             if str_value_list.len() < list.size {
-                return Err("Invalid value: List is too short")
+                return Err(SpellError::InvalidSyntax("Invalid value: List is too short"))
             }
 
             if list.datatype == FLOAT {
                 attribute_line.extend(str_value_list.into_iter()
                     .map(str::parse::<f64>)
                     .collect::<Result<Vec<f64>, _>>()
-                    .map_err(|_| "Invalid value: value in this list is not a float")?.into_iter()
+                    .map_err(|_| SpellError::InvalidSyntax("Invalid value: value in this list is not a float"))?.into_iter()
                     .map(f64::to_bits)
                     .collect::<Vec<u64>>());
                 return Ok(attribute_line)
             }
 
-            Err("The datatype used in the list is not supported")
+            Err(SpellError::InvalidSyntax("The datatype used in the list is not supported"))
+        },
+        Datatype::Float => {
+            match value.parse::<f64>() {
+                Ok(float) if float.is_finite() => attribute_line.push(f64::to_bits(float)),
+                _ => return Err(SpellError::InvalidSyntax("Invalid value: Should be a number"))
+            }
+            Ok(attribute_line)
         },
         Datatype::Boolean => {
-            attribute_line.push(boolean_logic::bool_to_num(value.parse::<bool>().map_err(|_| "Expected boolean value")?));
+            attribute_line.push(boolean_logic::bool_to_num(value.parse::<bool>().map_err(|_| SpellError::InvalidInstructions("Expected boolean value"))?));
+            Ok(attribute_line)
+        },
+        Datatype::String => {
+            let string = value.strip_prefix('"')
+                .and_then(|x| x.strip_suffix('"'))
+                .ok_or(SpellError::InvalidSyntax("Invalid value: Should be a string starting and ending with \""))?;
+            attribute_line.extend(encode_string(string)?);
             Ok(attribute_line)
         }
     }
 }
 
+/// Packs a string into instructions as described in attributecodes.rs
+pub fn encode_string(string: &str) -> Result<Vec<u64>, SpellError> {
+    if string.contains('\0') {
+        return Err(SpellError::InvalidSyntax("Invalid value: Strings can't contain null characters"))
+    }
+
+    let mut packed_string: Vec<u64> = string.as_bytes().chunks(8).map(|chunk| {
+        let mut bytes = [0; 8];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        u64::from_be_bytes(bytes)
+    }).collect();
+    packed_string.push(0);
+    Ok(packed_string)
+}
+
+/// Reads a string packed by `encode_string`, including the 0 at the end of it
+pub fn decode_string(mut instructions_iter: impl Iterator<Item = u64>) -> Result<String, SpellError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    loop {
+        match instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected end of string"))? {
+            0 => break,
+            packed_bytes => bytes.extend(packed_bytes.to_be_bytes().into_iter().filter(|&byte| byte != 0))
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| SpellError::InvalidInstructions("Invalid data: String isn't valid UTF-8"))
+}
+
+/// The precedence of values, components and bracketed expressions when turning RPN back into infix. It's higher than any operator so they never need brackets
+const VALUE_PRECEDENCE: u64 = 7;
+
+pub fn get_component_name(component_code: u64) -> Option<String> {
+    COMPONENT_TO_NUM_MAP.iter()
+        .find(|(_, &code)| code == component_code)
+        .map(|(padded_name, _)| padded_name.iter().flatten().collect())
+}
+
+/// The inverse of `get_maths_function_code`
+fn get_maths_function_name(function_code: u64) -> Option<&'static str> {
+    match function_code {
+        SQRT => Some("sqrt"),
+        ABS => Some("abs"),
+        FLOOR => Some("floor"),
+        CEIL => Some("ceil"),
+        ROUND => Some("round"),
+        SIN => Some("sin"),
+        COS => Some("cos"),
+        MIN => Some("min"),
+        MAX => Some("max"),
+        CLAMP => Some("clamp"),
+        _ => None
+    }
+}
+
+fn get_operator_symbol(opcode: u64) -> Option<&'static str> {
+    match opcode {
+        AND => Some("and"),
+        OR => Some("or"),
+        XOR => Some("xor"),
+        EQUALS => Some("="),
+        GREATER_THAN => Some(">"),
+        LESSER_THAN => Some("<"),
+        GREATER_THAN_OR_EQUAL => Some(">="),
+        LESSER_THAN_OR_EQUAL => Some("<="),
+        MULTIPLY => Some("*"),
+        DIVIDE => Some("/"),
+        MODULO => Some("%"),
+        ADD => Some("+"),
+        SUBTRACT => Some("-"),
+        POWER => Some("^"),
+        _ => None
+    }
+}
+
+fn decompile_number(bits: u64) -> String {
+    f64::from_bits(bits).to_string()
+}
+
+fn decompile_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<String, SpellError> {
+    let component_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+    decompile_component_call(component_code, instructions_iter)
+}
+
+fn decompile_component_call<'a>(component_code: u64, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<String, SpellError> {
+    let component_name = get_component_name(component_code).ok_or(SpellError::UnknownComponent("Component doesn't exist"))?;
+    let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code)?;
+
+    let mut parameters: Vec<String> = Vec::new();
+    let mut parameter_number = 0;
+    while parameter_number < number_of_component_parameters {
+        let parameter = match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
+            TRUE => String::from("true"),
+            FALSE => String::from("false"),
+            NUMBER_LITERAL => decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?),
+            COMPONENT => {
+                // A vector fills one parameter for each of its values
+                let parameter_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+                parameter_number += Spell::get_parameter_width(&parameter_code)? - 1;
+                decompile_component_call(parameter_code, instructions_iter)?
+            },
+            EXPRESSION => {
+                let expression = decompile_logic(instructions_iter)?;
+                // Brackets stop an expression such as `-3` being read back as a value or a component
+                if expression.parse::<f64>().is_ok() || expression.parse::<bool>().is_ok() || is_single_component(&expression) {
+                    format!("({})", expression)
+                } else {
+                    expression
+                }
+            },
+            _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
+        };
+        parameters.push(parameter);
+        parameter_number += 1;
+    }
+
+    Ok(format!("{}({})", component_name, parameters.join(", ")))
+}
+
+/// Turns RPN logic up to the end of scope back into infix, only adding the brackets needed to keep the same order of operations
+fn decompile_logic<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<String, SpellError> {
+    // Each entry is an infix string and the precedence of its lowest operator outside brackets
+    let mut infix_stack: Vec<(String, u64)> = Vec::new();
+    while let Some(&bits) = instructions_iter.next() {
+        match bits {
+            END_OF_SCOPE => break,
+            TRUE => infix_stack.push((String::from("true"), VALUE_PRECEDENCE)),
+            FALSE => infix_stack.push((String::from("false"), VALUE_PRECEDENCE)),
+            NUMBER_LITERAL => infix_stack.push((decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected following value"))?), VALUE_PRECEDENCE)),
+            COMPONENT => infix_stack.push((decompile_component(instructions_iter)?, VALUE_PRECEDENCE)),
+            NOT => {
+                let (operand, operand_precedence) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                let precedence = get_precedence("not");
+                let operand = if operand_precedence < precedence { format!("({})", operand) } else { operand };
+                infix_stack.push((format!("not {}", operand), precedence));
+            },
+            MIN | MAX => {
+                let (argument_two, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                let (argument_one, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                infix_stack.push((format!("{}({}, {})", get_maths_function_name(bits).unwrap(), argument_one, argument_two), VALUE_PRECEDENCE));
+            },
+            CLAMP => {
+                let (high, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                let (low, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                let (value, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                infix_stack.push((format!("clamp({}, {}, {})", value, low, high), VALUE_PRECEDENCE));
+            },
+            SQRT | ABS | FLOOR | CEIL | ROUND | SIN | COS => {
+                let (argument, _) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value for maths function"))?;
+                infix_stack.push((format!("{}({})", get_maths_function_name(bits).unwrap(), argument), VALUE_PRECEDENCE));
+            },
+            operator => {
+                let symbol = get_operator_symbol(operator).ok_or(SpellError::InvalidInstructions("Opcode doesn't exist"))?;
+                let (right, right_precedence) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+                let (left, left_precedence) = infix_stack.pop().ok_or(SpellError::InvalidInstructions("Expected value to compare"))?;
+
+                if operator == SUBTRACT && left == "0" && left_precedence == VALUE_PRECEDENCE {
+                    // Minus signs are parsed as taking away from zero, so this turns them back into minus signs
+                    let right = if right_precedence < VALUE_PRECEDENCE || right.starts_with('-') { format!("({})", right) } else { right };
+                    infix_stack.push((format!("-{}", right), VALUE_PRECEDENCE));
+                    continue
+                }
+
+                // Operators are left associative, so an operator with the same precedence on the right needs brackets
+                let precedence = get_precedence(symbol);
+                let left = if left_precedence < precedence { format!("({})", left) } else { left };
+                let right = if right_precedence <= precedence || (operator == SUBTRACT && right.starts_with('-')) { format!("({})", right) } else { right };
+                infix_stack.push((format!("{} {} {}", left, symbol, right), precedence));
+            }
+        }
+    }
+
+    match infix_stack.pop() {
+        Some((infix, _)) if infix_stack.is_empty() => Ok(infix),
+        _ => Err(SpellError::InvalidInstructions("Logic should result in a single value"))
+    }
+}
+
+fn decompile_about_section<'a>(instructions_iter: &mut std::iter::Peekable<impl Iterator<Item = &'a u64>>) -> Result<Vec<String>, SpellError> {
+    let mut lines: Vec<String> = Vec::new();
+    while let Some(&&attribute) = instructions_iter.peek() {
+        match attribute {
+            COLOR => {
+                instructions_iter.next();
+                let mut colors: Vec<String> = Vec::new();
+                for _ in 0..3 {
+                    colors.push(decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Invalid data: There should be three color values"))?));
+                }
+                lines.push(format!("color = [{}]", colors.join(", ")));
+            },
+            CHARGE_TO_SHAPE => {
+                instructions_iter.next();
+                let charge_to_shape = boolean_logic::num_to_bool(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected boolean after charge_to_shape"))?)?;
+                lines.push(format!("charge_to_shape = {}", charge_to_shape));
+            },
+            TRANSPARENCY => {
+                instructions_iter.next();
+                let transparency = decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after transparency"))?);
+                lines.push(format!("transparency = {}", transparency));
+            },
+            NAME | DESCRIPTION => {
+                instructions_iter.next();
+                let attribute_name = if attribute == NAME { "name" } else { "description" };
+                lines.push(format!("{} = \"{}\"", attribute_name, decode_string(instructions_iter.by_ref().copied())?));
+            },
+            _ => break
+        }
+    }
+    Ok(lines)
+}
+
+/// Parses how many seconds a `repeat for` section runs for into bits, which can be a decimal but not negative
+fn parse_repeat_duration(seconds: &str) -> Result<u64, SpellError> {
+    match seconds.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(f64::to_bits(seconds)),
+        _ => Err(SpellError::InvalidSyntax("Invalid value found after keyword \"for\""))
+    }
+}
+
+/// Parses how many times a `repeat n times` section runs into bits, which can't be more than `MAX_REPEAT_TIMES`
+fn parse_repeat_times(num: &str) -> Result<u64, SpellError> {
+    match num.parse::<u64>() {
+        Ok(num) if num <= MAX_REPEAT_TIMES as u64 => Ok(f64::to_bits(num as f64)),
+        Ok(_) => Err(SpellError::InvalidSyntax("Too many times to repeat")),
+        Err(_) => Err(SpellError::InvalidSyntax("Invalid value found before keyword \"times\""))
+    }
+}
+
+/// The inverse of `parse_spell`. Turns instructions back into spell code, which parses into the same instructions
+pub fn decompile_instructions(instructions: &[u64]) -> Result<String, SpellError> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth: usize = 0;
+    let mut instructions_iter = instructions.iter().peekable();
+    while let Some(&bits) = instructions_iter.next() {
+        let indent = "    ".repeat(depth);
+        match bits {
+            WHEN_CREATED_SECTION => lines.push(format!("{}:", WHEN_CREATED_NAME)),
+            REPEAT_SECTION | REPEAT_TIMES_SECTION => {
+                if instructions_iter.next() != Some(&NUMBER_LITERAL) {
+                    return Err(SpellError::InvalidInstructions("Expected number literal after repeat section"))
+                }
+                let number = decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?);
+                lines.push(match (bits, number.as_str()) {
+                    (REPEAT_SECTION, "1") => format!("{}:", REPEAT_NAME),
+                    (REPEAT_SECTION, _) => format!("{} every {}:", REPEAT_NAME, number),
+                    _ => format!("{} {} times:", REPEAT_NAME, number)
+                });
+            },
+            REPEAT_FOR_SECTION => {
+                let mut numbers: Vec<String> = Vec::new();
+                for _ in 0..2 {
+                    if instructions_iter.next() != Some(&NUMBER_LITERAL) {
+                        return Err(SpellError::InvalidInstructions("Expected number literal after repeat section"))
+                    }
+                    numbers.push(decompile_number(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?));
+                }
+                lines.push(match numbers[0].as_str() {
+                    "1" => format!("{} for {}:", REPEAT_NAME, numbers[1]),
+                    _ => format!("{} every {} for {}:", REPEAT_NAME, numbers[0], numbers[1])
+                });
+            },
+            ON_COLLISION_SECTION => lines.push(format!("{}:", ON_COLLISION_NAME)),
+            CHILD_SECTION => lines.push(format!("{}:", CHILD_NAME)),
+            ABOUT_SECTION => {
+                lines.push(format!("{}:", ABOUT_NAME));
+                lines.extend(decompile_about_section(&mut instructions_iter)?);
+            },
+            COMPONENT => lines.push(format!("{}{}", indent, decompile_component(&mut instructions_iter)?)),
+            IF => {
+                lines.push(format!("{}if {} {{", indent, decompile_logic(&mut instructions_iter)?));
+                depth += 1;
+            },
+            WHILE => {
+                lines.push(format!("{}while {} {{", indent, decompile_logic(&mut instructions_iter)?));
+                depth += 1;
+            },
+            END_OF_SCOPE => {
+                depth = depth.checked_sub(1).ok_or(SpellError::InvalidInstructions("Unexpected end of scope"))?;
+                lines.push(format!("{}}}", "    ".repeat(depth)));
+            },
+            _ => return Err(SpellError::InvalidInstructions("Not valid opcode"))
+        }
+    }
+
+    if depth > 0 {
+        return Err(SpellError::InvalidInstructions("Expected end of scope"))
+    }
+
+    Ok(lines.join("\n"))
+}
+
 // Tests to check that the library is working properly
 #[cfg(test)]
 mod tests {
@@ -772,6 +1460,87 @@ mod tests {
         assert_eq!(parse_spell("repeat every 2:\ngive_velocity(0,0,0)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(2.0), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]))
     }
 
+    #[test]
+    fn decompile_round_trips() {
+        let spells = [
+            "when_created:\ngive_velocity(1, 0.5, -2)\nif get_time() > 5 and not moving(1) {\nperish()\n}",
+            "repeat every 2 for 1.5:\ngive_velocity(1, 0, 0)\nrepeat for 3:\nperish()",
+            "repeat every 3:\nif (get_energy() - 2) * 3 >= 4 or false {\nif true {\nset_damage(max(get_time(), 1) ^ 2)\n}\n}\nabout:\ncolor = [0.4, 0, 0.8]\ncharge_to_shape = false\ntransparency = 0.5",
+            "when_created:\ntake_shape(\"cylinder\", 0.5, 2, 0)\ngive_velocity(-get_time(), sqrt(get_energy()) / (2 + 1), 0)\nrepeat 3 times:\nheal(2, true)\non_collision:\nperish()",
+            "repeat:\ngive_velocity(clamp(get_time(), -1, 2 * 3), 0, 0)",
+            "repeat:\nif not (true and false) xor get_time() - 1 <= 2 ^ (3 ^ 2) {\nperish()\n}",
+            "about:\nname = \"Fire, then ice\"\ndescription = \"\"\ncolor = [1, 0, 0]\nwhen_created:\nperish()",
+            "when_created:\nwhile get_energy() > 5 {\nif get_time() % 2 = 0 {\ngive_velocity(1, 0, 0)\n}\n}",
+            "repeat:\ngive_velocity(caster_forward())\nset_direction(caster_forward())"
+        ];
+
+        for spell in spells {
+            let instructions = parse_spell(spell, None).unwrap();
+            let source = decompile_instructions(&instructions).unwrap();
+            assert_eq!(parse_spell(&source, None), Ok(instructions), "{}", source);
+        }
+    }
+
+    #[test]
+    fn decompile_indents_if_statements() {
+        assert_eq!(decompile_instructions(&parse_spell("when_created:\nif true {\nperish()\n}", None).unwrap()), Ok(String::from("when_created:\nif true {\n    perish()\n}")));
+        assert_eq!(decompile_instructions(&[WHEN_CREATED_SECTION, IF, TRUE, END_OF_SCOPE]), Err(SpellError::InvalidInstructions("Expected end of scope")));
+    }
+
+    #[test]
+    fn parse_errors_report_where_they_are() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(1, 0, 0)\n    launch(1, 0, 0)", None), Err(ParseError { line: 3, column: Some(5), message: SpellError::UnknownComponent("Component doesn't exist") }));
+        // Blank lines and comments still count towards the line number
+        assert_eq!(parse_spell("\n# Comment\nwhen_created:\nif  pie > 1 {\nperish()\n}", None).map_err(|error| error.to_string()), Err(String::from("Line 4, column 5: Invalid condition: unknown word")));
+        assert_eq!(parse_spell("when_created:\nif true {\nif false {\n}", None).map_err(|error| error.line), Err(2));
+        assert_eq!(ParseError { line: 1, column: None, message: SpellError::InvalidSyntax("Invalid section name") }.to_string(), "Line 1: Invalid section name");
+    }
+
+    #[test]
+    fn parse_while_loop() {
+        assert_eq!(parse_spell("when_created:\nwhile get_energy() > 5 {\ngive_velocity(1, 0, 0)\n}", None), Ok(vec![WHEN_CREATED_SECTION, WHILE, COMPONENT, GET_ENERGY, NUMBER_LITERAL, f64::to_bits(5.0), GREATER_THAN, END_OF_SCOPE, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, END_OF_SCOPE]));
+        assert_eq!(parse_spell("when_created:\nwhile true {\nperish()", None).map_err(|error| error.to_string()), Err(String::from("Line 2, column 1: Expected closing bracket(s)")));
+    }
+
+    #[test]
+    fn parse_string_attributes() {
+        let instructions = parse_spell("about:\nname = \"Fireball\"\ndescription = \"Burns, then explodes. # Not a comment\"", None).unwrap();
+        let mut instructions_iter = instructions.iter();
+        assert_eq!(instructions_iter.next(), Some(&ABOUT_SECTION));
+        assert_eq!(instructions_iter.next(), Some(&NAME));
+        assert_eq!(decode_string(instructions_iter.by_ref().copied()), Ok(String::from("Fireball")));
+        assert_eq!(instructions_iter.next(), Some(&DESCRIPTION));
+        assert_eq!(decode_string(instructions_iter.by_ref().copied()), Ok(String::from("Burns, then explodes. # Not a comment")));
+        assert_eq!(instructions_iter.next(), None);
+
+        // Eight bytes fit in one u64, then the string ends with a 0
+        assert_eq!(parse_spell("about:\nname = \"Ice bolt\"", None), Ok(vec![ABOUT_SECTION, NAME, u64::from_be_bytes(*b"Ice bolt"), 0]));
+        assert_eq!(parse_spell("about:\nname = Fireball", None).map_err(|error| error.message), Err(SpellError::InvalidSyntax("Invalid value: Should be a string starting and ending with \"")));
+    }
+
+    #[test]
+    fn parse_on_collision() {
+        assert_eq!(parse_spell("on_collision:\nperish()", None), Ok(vec![ON_COLLISION_SECTION, COMPONENT, PERISH]));
+        assert_eq!(parse_spell("repeat:\ngive_velocity(1, 0, 0)\non_collision:\nset_damage(5)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, ON_COLLISION_SECTION, COMPONENT, SET_DAMAGE, NUMBER_LITERAL, f64::to_bits(5.0)]));
+    }
+
+    #[test]
+    fn parse_repeat_times() {
+        assert_eq!(parse_spell("repeat 5 times:\ngive_velocity(1,0,0)", None), Ok(vec![REPEAT_TIMES_SECTION, NUMBER_LITERAL, f64::to_bits(5.0), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("repeat 0 times:\ngive_velocity(1,0,0)", None), Ok(vec![REPEAT_TIMES_SECTION, NUMBER_LITERAL, 0, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("repeat -1 times:\ngive_velocity(1,0,0)", None).map_err(|error| error.to_string()), Err(String::from("Line 1, column 1: Invalid value found before keyword \"times\"")));
+        assert!(parse_spell(&format!("repeat {} times:\nperish()", MAX_REPEAT_TIMES), None).is_ok());
+        assert_eq!(parse_spell(&format!("repeat {} times:\nperish()", MAX_REPEAT_TIMES + 1), None).map_err(|error| error.to_string()), Err(String::from("Line 1, column 1: Too many times to repeat")));
+    }
+
+    #[test]
+    fn parse_repeat_for() {
+        assert_eq!(parse_spell("repeat for 3:\ngive_velocity(0,0,0)", None), Ok(vec![REPEAT_FOR_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, f64::to_bits(3.0), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("repeat every 2 for 1.5:\ngive_velocity(0,0,0)", None), Ok(vec![REPEAT_FOR_SECTION, NUMBER_LITERAL, f64::to_bits(2.0), NUMBER_LITERAL, f64::to_bits(1.5), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("repeat for 0:\ngive_velocity(0,0,0)", None), Ok(vec![REPEAT_FOR_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("repeat for -1:\ngive_velocity(0,0,0)", None).map_err(|error| error.to_string()), Err(String::from("Line 1, column 1: Invalid value found after keyword \"for\"")));
+    }
+
     #[test]
     fn parse_advanced_repeat_with_irregular_spacing() {
         assert_eq!(parse_spell("repeat  every      3:\ngive_velocity(0,0,0)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(3.0), COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]))
@@ -786,10 +1555,10 @@ mod tests {
     
     #[test]
     fn parse_invalid_spell_color() {
-        assert_eq!(parse_about_line("color = [0.212, 1, 0.3,]"), Err("Invalid value: List is too long"));
-        assert_eq!(parse_about_line("color = 0.4, 0,284]"), Err("Invalid value: Should be a list starting with [ and ending with ]"));
-        assert_eq!(parse_about_line("color = [0.4, 0,284"), Err("Invalid value: Should be a list starting with [ and ending with ]"));
-        assert_eq!(parse_about_line("color = [a, 0,284]"), Err("Invalid value: value in this list is not a float"));
+        assert_eq!(parse_about_line("color = [0.212, 1, 0.3,]"), Err(SpellError::InvalidSyntax("Invalid value: List is too long")));
+        assert_eq!(parse_about_line("color = 0.4, 0,284]"), Err(SpellError::InvalidSyntax("Invalid value: Should be a list starting with [ and ending with ]")));
+        assert_eq!(parse_about_line("color = [0.4, 0,284"), Err(SpellError::InvalidSyntax("Invalid value: Should be a list starting with [ and ending with ]")));
+        assert_eq!(parse_about_line("color = [a, 0,284]"), Err(SpellError::InvalidSyntax("Invalid value: value in this list is not a float")));
     }
 
     #[test]
@@ -802,16 +1571,344 @@ mod tests {
         assert_eq!(parse_spell("about:\ncolour = [0.4, 0, 0.8]", None), Ok(vec![ABOUT_SECTION, COLOR, f64::to_bits(0.4), 0, f64::to_bits(0.8)]))
     }
 
+    #[test]
+    fn parse_transparency() {
+        assert_eq!(parse_spell("about:\ntransparency = 0.5\ncolor = [1, 0, 0]", None), Ok(vec![ABOUT_SECTION, TRANSPARENCY, f64::to_bits(0.5), COLOR, f64::to_bits(1.0), 0, 0]));
+        // Out of range transparencies are clamped when the spell is made, so they still parse
+        assert_eq!(parse_about_line("transparency = 2"), Ok(vec![TRANSPARENCY, f64::to_bits(2.0)]));
+        assert_eq!(parse_about_line("transparency = inf"), Err(SpellError::InvalidSyntax("Invalid value: Should be a number")));
+        assert_eq!(parse_about_line("transparency = [0.5]"), Err(SpellError::InvalidSyntax("Invalid value: Should be a number")));
+    }
+
     #[test]
     fn parse_if_statement_spell() {
         assert_eq!(parse_spell("when_created:\nif false {\ngive_velocity(1, 0, 0)\n}", None), Ok(vec![WHEN_CREATED_SECTION, IF, FALSE, END_OF_SCOPE, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, END_OF_SCOPE]))
     }
 
+    #[test]
+    fn numbers_cant_be_used_as_booleans() {
+        assert_eq!(parse_spell("when_created:\nif 5 and true {\n}", None), Err(ParseError { line: 2, column: Some(4), message: SpellError::BadParameterType("Boolean logic can only compare booleans") }));
+        assert_eq!(parse_spell("when_created:\nif true or get_time() {\n}", None).map_err(|error| error.message), Err(SpellError::BadParameterType("Boolean logic can only compare booleans")));
+        assert_eq!(parse_spell("when_created:\nif not 5 {\n}", None).map_err(|error| error.message), Err(SpellError::BadParameterType("Not can only be used on booleans")));
+        assert_eq!(parse_spell("when_created:\nif 5 {\n}", None).map_err(|error| error.message), Err(SpellError::BadParameterType("Invalid condition: Should be true or false, not a number")));
+        assert_eq!(parse_spell("when_created:\nwhile get_energy() {\n}", None).map_err(|error| error.message), Err(SpellError::BadParameterType("Invalid condition: Should be true or false, not a number")));
+
+        // Expressions are still allowed to be numbers
+        assert!(parse_spell("when_created:\nif get_energy() * 2 > 5 {\n}", None).is_ok());
+    }
+
     #[test]
     fn parse_advanced_if_statement_spell() {
         assert_eq!(parse_spell("when_created:\nif false or get_time() > 5 {\ngive_velocity(1, 0, 0)\n}", None), Ok(vec![WHEN_CREATED_SECTION, IF, FALSE, COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(5.0), GREATER_THAN, OR, END_OF_SCOPE, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, END_OF_SCOPE]))
     }
 
+    #[test]
+    fn parse_hit_count_in_if_statement() {
+        assert_eq!(parse_spell("repeat:\nif hit_count() > 2 {\nperish()\n}", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), IF, COMPONENT, HIT_COUNT, NUMBER_LITERAL, f64::to_bits(2.0), GREATER_THAN, END_OF_SCOPE, COMPONENT, PERISH, END_OF_SCOPE]))
+    }
+
+    #[test]
+    fn parse_comments() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(1,0,0) # push forward", None), parse_spell("when_created:\ngive_velocity(1,0,0)", None));
+        assert_eq!(parse_spell("# A comment\nwhen_created: # section comment\n# full line comment\nif true { # if comment\ngive_velocity(1,0,0)\n} # closing comment", None), parse_spell("when_created:\nif true {\ngive_velocity(1,0,0)\n}", None));
+        assert_eq!(parse_spell("about:\ncolor = [1, 0, 1] # purple", None), parse_spell("about:\ncolor = [1, 0, 1]", None));
+    }
+
+    #[test]
+    fn comment_character_in_parameters_is_not_comment() {
+        assert_eq!(strip_comment("give_velocity(1, 0, #0)"), "give_velocity(1, 0, #0)");
+        assert_eq!(strip_comment("take_form(\"#\") # comment"), "take_form(\"#\") ");
+        assert!(parse_spell("when_created:\ngive_velocity(1, 0, #0)", None).is_err());
+    }
+
+    #[test]
+    fn parse_maths_functions() {
+        assert_eq!(parse_logic("sqrt(4) > 1", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(4.0), SQRT, NUMBER_LITERAL, f64::to_bits(1.0), GREATER_THAN]));
+        assert_eq!(parse_logic("abs(get_time()) > 1", None), Ok(vec![COMPONENT, GET_TIME, ABS, NUMBER_LITERAL, f64::to_bits(1.0), GREATER_THAN]));
+        assert_eq!(parse_logic("max(1, 2) = min(3, 2)", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, f64::to_bits(2.0), MAX, NUMBER_LITERAL, f64::to_bits(3.0), NUMBER_LITERAL, f64::to_bits(2.0), MIN, EQUALS]));
+        assert_eq!(parse_logic("min(1, -2) < 0", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(2.0), SUBTRACT, MIN, NUMBER_LITERAL, 0, LESSER_THAN]));
+    }
+
+    #[test]
+    fn parse_clamp() {
+        assert_eq!(parse_logic("clamp(5, 0, 3) = 3", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(5.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(3.0), CLAMP, NUMBER_LITERAL, f64::to_bits(3.0), EQUALS]));
+        assert_eq!(test_logic(&mut parse_logic("clamp(5, 0, 3) == 3", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("clamp(-1, 0, 3) == 0", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("clamp(2, 0, 3) == 2", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("clamp(get_time() * 0, 1 + 1, max(1, 3)) == 2", None).unwrap().iter()), Ok(vec![TRUE]));
+        // When low is bigger than high, high wins
+        assert_eq!(test_logic(&mut parse_logic("clamp(5, 3, 0) == 0", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("clamp(-1, 3, 0) == 0", None).unwrap().iter()), Ok(vec![TRUE]));
+    }
+
+    #[test]
+    fn not_binds_to_its_operand() {
+        assert_eq!(parse_logic("not true and false", None), Ok(vec![TRUE, NOT, FALSE, AND]));
+        assert_eq!(parse_logic("not(true) and false", None), Ok(vec![TRUE, NOT, FALSE, AND]));
+        assert_eq!(parse_logic("true and not moving(5)", None), Ok(vec![TRUE, COMPONENT, MOVING, NUMBER_LITERAL, f64::to_bits(5.0), NOT, AND]));
+        assert_eq!(test_logic(&mut parse_logic("not true == false", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("not not true == true", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("true and not false", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("not (true and false)", None).unwrap().iter()), Ok(vec![TRUE]));
+    }
+
+    #[test]
+    fn parse_rounding_functions() {
+        assert_eq!(parse_logic("floor(2.9) = 2", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(2.9), FLOOR, NUMBER_LITERAL, f64::to_bits(2.0), EQUALS]));
+        assert_eq!(test_logic(&mut parse_logic("floor(2.9) = 2", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("ceil(2.1) = 3", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("round(2.5) = 3", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("round(2.4) = 2", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(parse_spell("when_created:\ngive_velocity(floor(get_time()), 0, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, EXPRESSION, COMPONENT, GET_TIME, FLOOR, END_OF_SCOPE, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+    }
+
+    #[test]
+    fn square_root_of_negative_is_error() {
+        assert_eq!(parse_logic("sqrt(0 - 4) > 1", None), Err(SpellError::Maths("Cannot take the square root of a negative number")));
+    }
+
+    #[test]
+    fn parse_expression_as_parameter() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(max(1, 2), get_time() * 2, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, EXPRESSION, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, f64::to_bits(2.0), MAX, END_OF_SCOPE, EXPRESSION, COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(2.0), MULTIPLY, END_OF_SCOPE, NUMBER_LITERAL, 0]));
+        assert_eq!(parse_spell("when_created:\ngive_velocity(1 > 2, 0, 0)", None).map_err(|error| error.to_string()), Err(String::from("Line 2, column 1: Couldn't parse parameter: should be float")));
+    }
+
+    #[test]
+    fn parse_get_energy_in_if_statement() {
+        assert_eq!(parse_spell("when_created:\nif get_energy() < 5 {\nperish()\n}", None), Ok(vec![WHEN_CREATED_SECTION, IF, COMPONENT, GET_ENERGY, NUMBER_LITERAL, f64::to_bits(5.0), LESSER_THAN, END_OF_SCOPE, COMPONENT, PERISH, END_OF_SCOPE]))
+    }
+
+    #[test]
+    fn parse_take_shape_cylinder() {
+        assert_eq!(parse_spell("when_created:\ntake_shape(\"cylinder\", 0.5, 2, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, TAKE_SHAPE, NUMBER_LITERAL, f64::to_bits(CYLINDER as f64), NUMBER_LITERAL, f64::to_bits(0.5), NUMBER_LITERAL, f64::to_bits(2.0), NUMBER_LITERAL, 0]))
+    }
+
+    #[test]
+    fn parse_lifetime_and_reset_timer() {
+        assert_eq!(parse_spell("repeat:\nif get_time() > 1 and lifetime() < 10 {\nreset_timer()\n}", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), IF, COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(1.0), GREATER_THAN, COMPONENT, LIFETIME, NUMBER_LITERAL, f64::to_bits(10.0), LESSER_THAN, AND, END_OF_SCOPE, COMPONENT, RESET_TIMER, END_OF_SCOPE]))
+    }
+
+    #[test]
+    fn parse_component_alias_from_config() {
+        let mut config = Config::default();
+        config.component_aliases.insert("launch".to_string(), "give_velocity".to_string());
+        config.component_aliases.insert("perish".to_string(), "give_velocity".to_string());
+
+        assert_eq!(parse_spell("when_created:\nlaunch(1, 0, 0)", Some(&config)), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+
+        // Built in names take precedence over aliases
+        assert_eq!(parse_spell("when_created:\nperish()", Some(&config)), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, PERISH]));
+
+        assert_eq!(parse_spell("when_created:\nlaunch(1, 0, 0)", None).map_err(|error| error.to_string()), Err(String::from("Line 2, column 1: Component doesn't exist")));
+    }
+
+    #[test]
+    fn parse_heal() {
+        assert_eq!(parse_spell("when_created:\nheal(2, true)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, HEAL, NUMBER_LITERAL, f64::to_bits(2.0), TRUE]));
+        assert_eq!(parse_spell("when_created:\nheal(2, 1)", None).map_err(|error| error.to_string()), Err(String::from("Line 2, column 1: Couldn't parse parameter: should be boolean")));
+    }
+
+    #[test]
+    fn parse_errors_say_what_went_wrong() {
+        let unknown_component = parse_spell("when_created:\nlaunch(1, 0, 0)", None).unwrap_err().message;
+        let bad_parameter = parse_spell("when_created:\nheal(2, 1)", None).unwrap_err().message;
+        let bad_syntax = parse_spell("when_created:\nperish(", None).unwrap_err().message;
+
+        assert_eq!(unknown_component, SpellError::UnknownComponent("Component doesn't exist"));
+        assert_eq!(bad_parameter, SpellError::BadParameterType("Couldn't parse parameter: should be boolean"));
+        assert_eq!(bad_syntax.get_code(), SpellError::InvalidSyntax("").get_code());
+        assert_ne!(unknown_component.get_code(), bad_parameter.get_code());
+        assert_eq!(SpellError::NotEnoughEnergy.to_string(), "Not enough energy");
+    }
+
+    #[test]
+    fn parse_get_speed_as_parameter() {
+        assert_eq!(parse_spell("repeat:\nset_damage(get_speed() * 2)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, SET_DAMAGE, EXPRESSION, COMPONENT, GET_SPEED, NUMBER_LITERAL, f64::to_bits(2.0), MULTIPLY, END_OF_SCOPE]));
+    }
+
+    #[test]
+    fn parse_siphon_to_caster() {
+        assert_eq!(parse_spell("when_created:\nanchor()\nsiphon_to_caster(2.5)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, ANCHOR, COMPONENT, SIPHON_TO_CASTER, NUMBER_LITERAL, f64::to_bits(2.5)]));
+    }
+
+    #[test]
+    fn parse_set_decay() {
+        assert_eq!(parse_spell("repeat:\nif hit_count() > 0 {\nset_decay(0)\n}", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), IF, COMPONENT, HIT_COUNT, NUMBER_LITERAL, 0, GREATER_THAN, END_OF_SCOPE, COMPONENT, SET_DECAY, NUMBER_LITERAL, 0, END_OF_SCOPE]));
+    }
+
+    #[test]
+    fn parse_set_damage_falloff() {
+        assert_eq!(parse_spell("when_created:\nset_damage(10)\nset_damage_falloff(5, 15)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, SET_DAMAGE, NUMBER_LITERAL, f64::to_bits(10.0), COMPONENT, SET_DAMAGE_FALLOFF, NUMBER_LITERAL, f64::to_bits(5.0), NUMBER_LITERAL, f64::to_bits(15.0)]));
+    }
+
+    #[test]
+    fn parse_absorb() {
+        assert_eq!(parse_spell("repeat:\nabsorb()", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, ABSORB]));
+    }
+
+    #[test]
+    fn parse_push_spells() {
+        assert_eq!(parse_spell("when_created:\npush_spells(4)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, PUSH_SPELLS, NUMBER_LITERAL, f64::to_bits(4.0)]));
+    }
+
+    #[test]
+    fn parse_delay() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(1, 0, 0)\ndelay(0.5)\nperish()", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, COMPONENT, DELAY, NUMBER_LITERAL, f64::to_bits(0.5), COMPONENT, PERISH]));
+    }
+
+    #[test]
+    fn parse_negative_parameters() {
+        let instructions = Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(-1.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(0.5)]);
+        assert_eq!(parse_spell("when_created:\ngive_velocity(-1, 0, 0.5)", None), instructions);
+        assert_eq!(parse_spell("when_created:\ngive_velocity(- 1, +0, --0.5)", None), instructions);
+        assert_eq!(parse_spell("when_created:\ngive_velocity(---1, 0, -+-0.5)", None), instructions);
+        assert_eq!(parse_spell("when_created:\nset_damage(-5)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, SET_DAMAGE, NUMBER_LITERAL, f64::to_bits(-5.0)]));
+
+        // Minus signs in front of more than a number are part of an expression, the same as in if statements
+        assert_eq!(parse_spell("when_created:\ngive_velocity(-get_time(), 0, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, EXPRESSION, NUMBER_LITERAL, 0, COMPONENT, GET_TIME, SUBTRACT, END_OF_SCOPE, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+        assert!(parse_spell("when_created:\ngive_velocity(-inf, 0, 0)", None).is_err());
+        assert!(parse_spell("when_created:\ngive_velocity(nan, 0, 0)", None).is_err());
+    }
+
+    #[test]
+    fn parse_child_section() {
+        let instructions = vec![WHEN_CREATED_SECTION, COMPONENT, SPAWN_CHILD, NUMBER_LITERAL, f64::to_bits(0.5), CHILD_SECTION, REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, PERISH];
+        assert_eq!(parse_spell("when_created:\nspawn_child(0.5)\nchild:\nrepeat:\nperish()", None), Ok(instructions.clone()));
+        assert_eq!(decompile_instructions(&instructions), Ok(String::from("when_created:\nspawn_child(0.5)\nchild:\nrepeat:\nperish()")));
+        assert_eq!(parse_spell("when_created:\nspawn_child(0.5)\nchild:\nperish()", None), Err(ParseError { line: 4, column: Some(1), message: SpellError::InvalidSyntax("Must begin with section statement") }));
+    }
+
+    #[test]
+    fn parse_get_nearest_entity_direction() {
+        assert_eq!(parse_spell("repeat:\ngive_velocity(get_nearest_entity_direction())", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, GIVE_VELOCITY, COMPONENT, GET_NEAREST_ENTITY_DIRECTION]));
+        assert_eq!(decompile_instructions(&[REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, GIVE_VELOCITY, COMPONENT, GET_NEAREST_ENTITY_DIRECTION]), Ok(String::from("repeat:\ngive_velocity(get_nearest_entity_direction())")));
+    }
+
+    #[test]
+    fn parse_caster_forward() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(caster_forward())", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, COMPONENT, CASTER_FORWARD]));
+        assert_eq!(parse_spell("when_created:\nset_color(1, caster_forward())", None).map_err(|error| error.message), Err(SpellError::InvalidSyntax("Invalid parameters: More parameters than expected")));
+        assert_eq!(parse_spell("when_created:\ngive_velocity(caster_forward(), 1)", None).map_err(|error| error.message), Err(SpellError::InvalidSyntax("Invalid parameters: More parameters than expected")));
+        assert_eq!(check_parameter_fits(&[FLOAT, BOOLEAN, FLOAT], 0, 3), Err(SpellError::BadParameterType("Invalid parameters: Vectors can only fill float parameters")));
+        assert_eq!(parse_spell("when_created:\nif caster_forward() > 0 {\nperish()\n}", None).map_err(|error| error.message), Err(SpellError::BadParameterType("Vectors can only be used as the parameters of components")));
+    }
+
+    #[test]
+    fn parse_pierce() {
+        assert_eq!(parse_spell("when_created:\nset_damage(5)\npierce(2)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, SET_DAMAGE, NUMBER_LITERAL, f64::to_bits(5.0), COMPONENT, PIERCE, NUMBER_LITERAL, f64::to_bits(2.0)]));
+    }
+
+    #[test]
+    fn parse_set_color() {
+        assert_eq!(parse_spell("repeat:\nset_color(sin(get_time()), 0, 1)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, SET_COLOR, EXPRESSION, COMPONENT, GET_TIME, SIN, END_OF_SCOPE, NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(1.0)]));
+    }
+
+    #[test]
+    fn parse_get_random() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(get_random() - 0.5, 0, 1)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, EXPRESSION, COMPONENT, GET_RANDOM, NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(0.5), SUBTRACT, ADD, END_OF_SCOPE, NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(1.0)]));
+        assert_eq!(test_logic(&mut parse_logic("get_random() < 1", None).unwrap().iter()), Ok(vec![TRUE]));
+    }
+
+    #[test]
+    fn parse_target_queries() {
+        assert_eq!(parse_spell("repeat:\nif target_health() < 10 {\nset_damage(target_health())\n}", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), IF, COMPONENT, TARGET_HEALTH, NUMBER_LITERAL, f64::to_bits(10.0), LESSER_THAN, END_OF_SCOPE, COMPONENT, SET_DAMAGE, COMPONENT, TARGET_HEALTH, END_OF_SCOPE]));
+        assert_eq!(parse_logic("target_energy() > 0", None), Ok(vec![COMPONENT, TARGET_ENERGY, NUMBER_LITERAL, 0, GREATER_THAN]));
+        assert_eq!(parse_logic("get_overlapping_entity_count() >= 2", None), Ok(vec![COMPONENT, GET_OVERLAPPING_ENTITY_COUNT, NUMBER_LITERAL, f64::to_bits(2.0), GREATER_THAN_OR_EQUAL]));
+    }
+
+    #[test]
+    fn parse_apply_radial_force() {
+        assert_eq!(parse_spell("repeat:\napply_radial_force(-4, 3)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, APPLY_RADIAL_FORCE, NUMBER_LITERAL, f64::to_bits(-4.0), NUMBER_LITERAL, f64::to_bits(3.0)]));
+    }
+
+    #[test]
+    fn parse_set_direction() {
+        assert_eq!(parse_spell("repeat:\nset_direction(cos(get_time()), 0, sin(get_time()))\ngive_velocity(0, 0, -1)", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), COMPONENT, SET_DIRECTION, EXPRESSION, COMPONENT, GET_TIME, COS, END_OF_SCOPE, NUMBER_LITERAL, 0, EXPRESSION, COMPONENT, GET_TIME, SIN, END_OF_SCOPE, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(-1.0)]));
+    }
+
+    #[test]
+    fn parse_set_typed_damage() {
+        assert_eq!(parse_spell("when_created:\nset_typed_damage(5, \"fire\")", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, SET_TYPED_DAMAGE, NUMBER_LITERAL, f64::to_bits(5.0), NUMBER_LITERAL, f64::to_bits(FIRE as f64)]));
+    }
+
+    #[test]
+    fn parse_split() {
+        assert_eq!(parse_spell("when_created:\ngive_velocity(5, 0, 0)\nsplit(3)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(5.0), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0, COMPONENT, SPLIT, NUMBER_LITERAL, f64::to_bits(3.0)]));
+    }
+
+    #[test]
+    fn parse_trap() {
+        assert_eq!(parse_spell("when_created:\ntrap(1.5, 3, 20)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, TRAP, NUMBER_LITERAL, f64::to_bits(1.5), NUMBER_LITERAL, f64::to_bits(3.0), NUMBER_LITERAL, f64::to_bits(20.0)]));
+    }
+
+    #[test]
+    fn parse_caster_energy_in_if_statement() {
+        assert_eq!(parse_spell("when_created:\nif caster_energy_selected() < 0.5 and caster_energy_charged() > 10 {\nperish()\n}", None), Ok(vec![WHEN_CREATED_SECTION, IF, COMPONENT, CASTER_ENERGY_SELECTED, NUMBER_LITERAL, f64::to_bits(0.5), LESSER_THAN, COMPONENT, CASTER_ENERGY_CHARGED, NUMBER_LITERAL, f64::to_bits(10.0), GREATER_THAN, AND, END_OF_SCOPE, COMPONENT, PERISH, END_OF_SCOPE]))
+    }
+
+    #[test]
+    fn parse_caster_focus() {
+        assert_eq!(parse_logic("caster_focus() >= 1.5", None), Ok(vec![COMPONENT, CASTER_FOCUS, NUMBER_LITERAL, f64::to_bits(1.5), GREATER_THAN_OR_EQUAL]));
+    }
+
+    #[test]
+    fn parse_homing() {
+        assert_eq!(parse_spell("when_created:\nhoming(1.5)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, HOMING, NUMBER_LITERAL, f64::to_bits(1.5)]))
+    }
+
+    #[test]
+    fn parse_caster_alive_in_if_statement() {
+        assert_eq!(parse_spell("repeat:\nif not caster_alive() {\nperish()\n}", None), Ok(vec![REPEAT_SECTION, NUMBER_LITERAL, f64::to_bits(1.0), IF, COMPONENT, CASTER_ALIVE, NOT, END_OF_SCOPE, COMPONENT, PERISH, END_OF_SCOPE]))
+    }
+
+    #[test]
+    fn parse_greater_and_lesser_than_or_equal() {
+        assert_eq!(parse_logic("get_time() >= 5", None), Ok(vec![COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(5.0), GREATER_THAN_OR_EQUAL]));
+        assert_eq!(parse_logic("get_time() <= 5", None), Ok(vec![COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(5.0), LESSER_THAN_OR_EQUAL]));
+    }
+
+    #[test]
+    fn parse_trigonometry() {
+        assert_eq!(parse_logic("sin(pi)", None), Ok(vec![NUMBER_LITERAL, std::f64::consts::PI.to_bits(), SIN]));
+        assert_eq!(parse_logic("e * -pi", None), Ok(vec![NUMBER_LITERAL, std::f64::consts::E.to_bits(), NUMBER_LITERAL, 0, NUMBER_LITERAL, std::f64::consts::PI.to_bits(), SUBTRACT, MULTIPLY]));
+        assert_eq!(test_logic(&mut parse_logic("sin(0) == 0", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("cos(0) == 1", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("abs(sin(pi)) < 0.0001", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(parse_logic("pie > 1", None), Err(SpellError::InvalidSyntax("Invalid condition: unknown word")));
+    }
+
+    #[test]
+    fn parse_scientific_notation() {
+        assert_eq!(parse_logic("get_time() > 1e3", None), Ok(vec![COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(1000.0), GREATER_THAN]));
+        assert_eq!(parse_logic("1.5e-2", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(0.015)]));
+        assert_eq!(parse_logic("-2.5E+6", None), Ok(vec![NUMBER_LITERAL, 0, NUMBER_LITERAL, f64::to_bits(2.5e6), SUBTRACT]));
+        // The constant e still works when it isn't straight after a number
+        assert_eq!(parse_logic("2 * e", None), Ok(vec![NUMBER_LITERAL, f64::to_bits(2.0), NUMBER_LITERAL, std::f64::consts::E.to_bits(), MULTIPLY]));
+        assert_eq!(parse_logic("1e > 0", None), Err(SpellError::InvalidSyntax("Expected digits after exponent in number")));
+        assert_eq!(parse_logic("1e-", None), Err(SpellError::InvalidSyntax("Expected digits after exponent in number")));
+        assert_eq!(parse_logic("1e2.5", None), Err(SpellError::InvalidSyntax("Exponent of number must be a whole number")));
+        assert!(parse_logic("1e999 > 0", None).is_err());
+        assert_eq!(parse_spell("when_created:\ngive_velocity(1e-3, 0, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, f64::to_bits(0.001), NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]));
+    }
+
+    #[test]
+    fn parse_modulo() {
+        assert_eq!(parse_logic("get_time() % 2", None), Ok(vec![COMPONENT, GET_TIME, NUMBER_LITERAL, f64::to_bits(2.0), MODULO]));
+        assert_eq!(test_logic(&mut parse_logic("5 % 3 == 2", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("1 + 5 % 3 = 3", None).unwrap().iter()), Ok(vec![TRUE]));
+        // Like dividing by zero, this isn't an error, and NaN isn't equal to anything
+        assert_eq!(test_logic(&mut parse_logic("5 % 0 = 5 % 0", None).unwrap().iter()), Ok(vec![FALSE]));
+    }
+
+    #[test]
+    fn evaluate_greater_and_lesser_than_or_equal_boundaries() {
+        assert_eq!(test_logic(&mut parse_logic("5 >= 5", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("5 <= 5", None).unwrap().iter()), Ok(vec![TRUE]));
+        assert_eq!(test_logic(&mut parse_logic("4.9 >= 5", None).unwrap().iter()), Ok(vec![FALSE]));
+        assert_eq!(test_logic(&mut parse_logic("5.1 <= 5", None).unwrap().iter()), Ok(vec![FALSE]));
+        assert_eq!(test_logic(&mut parse_logic("1 + 1 >= 2 and 3 <= 2 + 1", None).unwrap().iter()), Ok(vec![TRUE]));
+    }
+
     #[test]
     fn parse_component_as_parameter() {
         assert_eq!(parse_spell("when_created:\ngive_velocity(get_time(), 0, 0)", None), Ok(vec![WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, COMPONENT, GET_TIME, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]))
@@ -0,0 +1,235 @@
+use crate::{Spell, boolean_logic, spelltranslator, spell_error::SpellError, MAX_REPEAT_TIMES};
+use crate::codes::{attributecodes::*, opcodes::*};
+
+/// Walks instructions without running them, checking that every opcode is known, every number literal is followed by its value,
+/// every component has as many parameters as it takes, every logic expression gives a single value and every scope is closed.
+/// Instructions from `parse_spell` always pass, so this is for instructions that come from outside, such as imported spell codes
+pub fn validate_instructions(instructions: &[u64]) -> Result<(), SpellError> {
+    let mut instructions_iter = instructions.iter();
+    let mut in_section = false;
+    let mut depth: usize = 0;
+    while let Some(&bits) = instructions_iter.next() {
+        match bits {
+            WHEN_CREATED_SECTION..=REPEAT_FOR_SECTION | CHILD_SECTION if depth > 0 => return Err(SpellError::InvalidInstructions("Expected end of scope")),
+            WHEN_CREATED_SECTION | ON_COLLISION_SECTION => {},
+            REPEAT_SECTION => validate_section_numbers(&mut instructions_iter, 1)?,
+            REPEAT_TIMES_SECTION => validate_repeat_times(&mut instructions_iter)?,
+            REPEAT_FOR_SECTION => validate_section_numbers(&mut instructions_iter, 2)?,
+            ABOUT_SECTION => {
+                validate_about_section(&mut instructions_iter)?;
+                // Nothing can come after the attributes except another section
+                in_section = false;
+                continue
+            },
+            // Everything after the child section is a spell of its own, which is checked in the same loop so long chains of children can't overflow the stack
+            CHILD_SECTION => {
+                in_section = false;
+                continue
+            },
+            _ if !in_section => return Err(SpellError::InvalidInstructions("Expected section")),
+            COMPONENT => validate_component(&mut instructions_iter)?,
+            IF | WHILE => {
+                validate_logic(&mut instructions_iter)?;
+                depth += 1;
+            },
+            END_OF_SCOPE => depth = depth.checked_sub(1).ok_or(SpellError::InvalidInstructions("Unexpected end of scope"))?,
+            _ => return Err(SpellError::InvalidInstructions("Not valid opcode"))
+        }
+        in_section = true;
+    }
+
+    if depth > 0 {
+        return Err(SpellError::InvalidInstructions("Expected end of scope"))
+    }
+    Ok(())
+}
+
+/// Checks the number literals after a repeat section's opcode, such as how often it runs
+fn validate_section_numbers<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>, count: usize) -> Result<(), SpellError> {
+    for _ in 0..count {
+        if instructions_iter.next() != Some(&NUMBER_LITERAL) {
+            return Err(SpellError::InvalidInstructions("Expected number literal after repeat section"))
+        }
+        instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?;
+    }
+    Ok(())
+}
+
+/// Checks a `repeat n times` section's count, which can't be more than `MAX_REPEAT_TIMES` for spells that didn't come from `parse_spell` either
+fn validate_repeat_times(instructions_iter: &mut std::slice::Iter<u64>) -> Result<(), SpellError> {
+    let iterations = instructions_iter.as_slice().get(1).map(|&bits| f64::from_bits(bits));
+    validate_section_numbers(instructions_iter, 1)?;
+    match iterations {
+        Some(iterations) if iterations <= MAX_REPEAT_TIMES as f64 => Ok(()),
+        _ => Err(SpellError::InvalidInstructions("Too many times to repeat"))
+    }
+}
+
+/// Checks attributes up to the next section, the same way `decompile_instructions` reads them
+fn validate_about_section(instructions_iter: &mut std::slice::Iter<u64>) -> Result<(), SpellError> {
+    while let Some(&attribute) = instructions_iter.as_slice().first() {
+        match attribute {
+            COLOR => {
+                instructions_iter.next();
+                for _ in 0..3 {
+                    instructions_iter.next().ok_or(SpellError::InvalidInstructions("Invalid data: There should be three color values"))?;
+                }
+            },
+            CHARGE_TO_SHAPE => {
+                instructions_iter.next();
+                boolean_logic::num_to_bool(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected boolean after charge_to_shape"))?)?;
+            },
+            NAME | DESCRIPTION => {
+                instructions_iter.next();
+                spelltranslator::decode_string(instructions_iter.by_ref().copied())?;
+            },
+            TRANSPARENCY => {
+                instructions_iter.next();
+                let transparency = f64::from_bits(*instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after transparency"))?);
+                if transparency.is_nan() {
+                    return Err(SpellError::InvalidInstructions("Transparency must be a number"))
+                }
+            },
+            WHEN_CREATED_SECTION..=REPEAT_FOR_SECTION | CHILD_SECTION => break,
+            _ => return Err(SpellError::InvalidInstructions("Invalid attribute"))
+        }
+    }
+    Ok(())
+}
+
+fn validate_component<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+    let component_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+    validate_component_call(component_code, instructions_iter)
+}
+
+fn validate_component_call<'a>(component_code: u64, instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+    let number_of_component_parameters = Spell::get_number_of_component_parameters(&component_code)?;
+
+    let mut parameter_number = 0;
+    while parameter_number < number_of_component_parameters {
+        match *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected parameter"))? {
+            TRUE | FALSE => {},
+            NUMBER_LITERAL => _ = instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected number after number literal opcode"))?,
+            COMPONENT => {
+                // A vector fills one parameter for each of its values
+                let parameter_code = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected component"))?;
+                let width = Spell::get_parameter_width(&parameter_code)?;
+                if parameter_number + width > number_of_component_parameters {
+                    return Err(SpellError::InvalidInstructions("Component returned more values than there are parameters left"))
+                }
+                validate_component_call(parameter_code, instructions_iter)?;
+                parameter_number += width;
+                continue
+            },
+            EXPRESSION => validate_logic(instructions_iter)?,
+            _ => return Err(SpellError::InvalidInstructions("Invalid parameter"))
+        }
+        parameter_number += 1;
+    }
+    Ok(())
+}
+
+/// Checks RPN logic up to and including its end of scope, keeping count of how many values would be on the stack
+fn validate_logic<'a>(instructions_iter: &mut impl Iterator<Item = &'a u64>) -> Result<(), SpellError> {
+    let mut values: usize = 0;
+    loop {
+        let bits = *instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected end of scope"))?;
+        let operand_count = match bits {
+            END_OF_SCOPE => break,
+            TRUE | FALSE => 0,
+            NUMBER_LITERAL => {
+                instructions_iter.next().ok_or(SpellError::InvalidInstructions("Expected following value"))?;
+                0
+            },
+            COMPONENT => {
+                validate_component(instructions_iter)?;
+                0
+            },
+            NOT | SQRT | ABS | FLOOR | CEIL | ROUND | SIN | COS => 1,
+            AND | OR | XOR | EQUALS | GREATER_THAN | LESSER_THAN | GREATER_THAN_OR_EQUAL | LESSER_THAN_OR_EQUAL
+            | MULTIPLY | DIVIDE | MODULO | ADD | SUBTRACT | POWER | MIN | MAX => 2,
+            CLAMP => 3,
+            _ => return Err(SpellError::InvalidInstructions("Opcode doesn't exist"))
+        };
+        values = values.checked_sub(operand_count).ok_or(SpellError::InvalidInstructions("Expected value to compare"))? + 1;
+    }
+
+    if values != 1 {
+        return Err(SpellError::InvalidInstructions("Logic should result in a single value"))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codes::componentcodes::*;
+    use crate::spelltranslator::parse_spell;
+
+    #[test]
+    fn parsed_spells_are_valid() {
+        let spells = [
+            "when_created:\ngive_velocity(1, 0.5, -2)\nif get_time() > 5 and not moving(1) {\nperish()\n}",
+            "repeat every 2 for 1.5:\ngive_velocity(clamp(get_time(), -1, 2 * 3), 0, 0)\nrepeat 3 times:\nheal(2, true)\non_collision:\nperish()",
+            "about:\nname = \"Fireball\"\ncolor = [1, 0, 0]\ncharge_to_shape = false\ntransparency = 0.4\nwhen_created:\nwhile get_energy() > 5 {\nset_direction(caster_forward())\n}",
+            "when_created:\nspawn_child(0.5)\nchild:\nrepeat:\nperish()"
+        ];
+
+        for spell in spells {
+            assert_eq!(validate_instructions(&parse_spell(spell, None).unwrap()), Ok(()), "{}", spell);
+        }
+        assert_eq!(validate_instructions(&[]), Ok(()));
+    }
+
+    #[test]
+    fn truncated_number_literals_are_invalid() {
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, SET_DAMAGE, NUMBER_LITERAL]), Err(SpellError::InvalidInstructions("Expected number after number literal opcode")));
+        assert_eq!(validate_instructions(&[REPEAT_SECTION, NUMBER_LITERAL]), Err(SpellError::InvalidInstructions("Expected number after number literal opcode")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, NUMBER_LITERAL]), Err(SpellError::InvalidInstructions("Expected following value")));
+    }
+
+    #[test]
+    fn wrong_parameter_counts_are_invalid() {
+        // Too few parameters runs out of instructions, and too many leaves a value where an instruction should be
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, NUMBER_LITERAL, 0]), Err(SpellError::InvalidInstructions("Expected parameter")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, PERISH, NUMBER_LITERAL, 0]), Err(SpellError::InvalidInstructions("Not valid opcode")));
+        // A vector can't fill more parameters than are left
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, GIVE_VELOCITY, NUMBER_LITERAL, 0, COMPONENT, CASTER_FORWARD]), Err(SpellError::InvalidInstructions("Component returned more values than there are parameters left")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, 99999]), Err(SpellError::UnknownComponent("Component doesn't exist")));
+    }
+
+    #[test]
+    fn unbalanced_scopes_are_invalid() {
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, TRUE, END_OF_SCOPE, COMPONENT, PERISH]), Err(SpellError::InvalidInstructions("Expected end of scope")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, TRUE, END_OF_SCOPE, REPEAT_SECTION, NUMBER_LITERAL, 0, END_OF_SCOPE]), Err(SpellError::InvalidInstructions("Expected end of scope")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, COMPONENT, PERISH, END_OF_SCOPE]), Err(SpellError::InvalidInstructions("Unexpected end of scope")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, TRUE]), Err(SpellError::InvalidInstructions("Expected end of scope")));
+    }
+
+    #[test]
+    fn long_chains_of_children_are_checked() {
+        let instructions = vec![CHILD_SECTION; 1_000_000];
+        assert_eq!(validate_instructions(&instructions), Ok(()));
+
+        let mut instructions = vec![WHEN_CREATED_SECTION, COMPONENT, PERISH].repeat(100_000);
+        instructions.extend([CHILD_SECTION, COMPONENT, PERISH]);
+        assert_eq!(validate_instructions(&instructions), Err(SpellError::InvalidInstructions("Expected section")));
+    }
+
+    #[test]
+    fn unknown_opcodes_are_invalid() {
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, 12345]), Err(SpellError::InvalidInstructions("Not valid opcode")));
+        assert_eq!(validate_instructions(&[COMPONENT, PERISH]), Err(SpellError::InvalidInstructions("Expected section")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, TRUE, 12345, END_OF_SCOPE, END_OF_SCOPE]), Err(SpellError::InvalidInstructions("Opcode doesn't exist")));
+        assert_eq!(validate_instructions(&[WHEN_CREATED_SECTION, IF, TRUE, TRUE, END_OF_SCOPE, END_OF_SCOPE]), Err(SpellError::InvalidInstructions("Logic should result in a single value")));
+        assert_eq!(validate_instructions(&[ABOUT_SECTION, 12345]), Err(SpellError::InvalidInstructions("Invalid attribute")));
+        assert_eq!(validate_instructions(&[ABOUT_SECTION, TRANSPARENCY, f64::to_bits(f64::NAN)]), Err(SpellError::InvalidInstructions("Transparency must be a number")));
+    }
+
+    #[test]
+    fn repeat_counts_over_the_cap_are_invalid() {
+        assert_eq!(validate_instructions(&[REPEAT_TIMES_SECTION, NUMBER_LITERAL, f64::to_bits(MAX_REPEAT_TIMES as f64), COMPONENT, PERISH]), Ok(()));
+        assert_eq!(validate_instructions(&[REPEAT_TIMES_SECTION, NUMBER_LITERAL, f64::to_bits(1e12), COMPONENT, PERISH]), Err(SpellError::InvalidInstructions("Too many times to repeat")));
+        assert_eq!(validate_instructions(&[REPEAT_TIMES_SECTION, NUMBER_LITERAL, f64::to_bits(f64::NAN), COMPONENT, PERISH]), Err(SpellError::InvalidInstructions("Too many times to repeat")));
+    }
+}